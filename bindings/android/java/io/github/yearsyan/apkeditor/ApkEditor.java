@@ -0,0 +1,125 @@
+package io.github.yearsyan.apkeditor;
+
+import java.io.IOException;
+import java.io.InputStream;
+import java.io.OutputStream;
+
+/**
+ * An apk held in native memory. Every edit is applied right away, so later reads see it; align and
+ * sign belong last since any later edit rewrites the zip unaligned and unsigned.
+ *
+ * <p>Content uris work through the stream methods, e.g.
+ * {@code ApkEditor.open(resolver.openInputStream(uri))}.
+ *
+ * <p>Calls on one instance are synchronized, threads sharing it take turns; close waits for the call in
+ * progress. A stream passed to {@link #writeTo} must not call back into the same instance.
+ */
+public final class ApkEditor implements AutoCloseable {
+    static {
+        System.loadLibrary("apk_editor_android");
+    }
+
+    private long handle;
+
+    private ApkEditor(long handle) {
+        this.handle = handle;
+    }
+
+    public static ApkEditor open(byte[] data) throws IOException {
+        return new ApkEditor(nativeOpen(data));
+    }
+
+    /** Reads the whole stream, it is not closed. */
+    public static ApkEditor open(InputStream stream) throws IOException {
+        return new ApkEditor(nativeOpenStream(stream));
+    }
+
+    /** The DER certificates of the v2 signers, throws when the signature doesn't check out. */
+    public static byte[][] verify(byte[] apk) throws IOException {
+        return nativeVerify(apk);
+    }
+
+    public synchronized String[] listFiles() throws IOException {
+        return nativeListFiles(handle());
+    }
+
+    /** Null when there is no such entry. */
+    public synchronized byte[] getFile(String name) throws IOException {
+        return nativeGetFile(handle(), name);
+    }
+
+    /** Replaces the entry when there is one. */
+    public synchronized void addFile(String name, byte[] data, boolean stored) throws IOException {
+        nativeAddFile(handle(), name, data, stored);
+    }
+
+    public synchronized boolean removeFile(String name) throws IOException {
+        return nativeRemoveFile(handle(), name);
+    }
+
+    public synchronized byte[] getManifest() throws IOException {
+        return nativeGetManifest(handle());
+    }
+
+    /** A binary (compiled) AndroidManifest.xml. */
+    public synchronized void setManifest(byte[] data) throws IOException {
+        nativeSetManifest(handle(), data);
+    }
+
+    public synchronized String getPackageName() throws IOException {
+        return nativePackageName(handle());
+    }
+
+    /** Appended as the next classesN.dex. */
+    public synchronized void addDex(byte[] data) throws IOException {
+        nativeAddDex(handle(), data);
+    }
+
+    public synchronized void align(int alignment) throws IOException {
+        nativeAlign(handle(), alignment);
+    }
+
+    /** APK Signature Scheme v2; key is PKCS#8 or PKCS#1 and the certificate X.509, DER or PEM. */
+    public synchronized void sign(byte[] key, byte[] certificate) throws IOException {
+        nativeSign(handle(), key, certificate);
+    }
+
+    public synchronized byte[] toByteArray() throws IOException {
+        return nativeToByteArray(handle());
+    }
+
+    /** Writes the apk, the stream is flushed but not closed. */
+    public synchronized void writeTo(OutputStream stream) throws IOException {
+        nativeWriteTo(handle(), stream);
+    }
+
+    @Override
+    public synchronized void close() {
+        nativeClose(handle);
+        handle = 0;
+    }
+
+    private long handle() throws IOException {
+        if (handle == 0) {
+            throw new IOException("apk is closed");
+        }
+        return handle;
+    }
+
+    private static native long nativeOpen(byte[] data) throws IOException;
+    private static native long nativeOpenStream(InputStream stream) throws IOException;
+    private static native void nativeClose(long handle);
+    private static native String[] nativeListFiles(long handle) throws IOException;
+    private static native byte[] nativeGetFile(long handle, String name) throws IOException;
+    private static native void nativeAddFile(long handle, String name, byte[] data, boolean stored) throws IOException;
+    private static native boolean nativeRemoveFile(long handle, String name) throws IOException;
+    private static native byte[] nativeGetManifest(long handle) throws IOException;
+    private static native void nativeSetManifest(long handle, byte[] data) throws IOException;
+    private static native String nativePackageName(long handle) throws IOException;
+    private static native void nativeAddDex(long handle, byte[] data) throws IOException;
+    private static native void nativeAlign(long handle, int alignment) throws IOException;
+    private static native void nativeSign(long handle, byte[] key, byte[] certificate) throws IOException;
+    private static native byte[] nativeToByteArray(long handle) throws IOException;
+    private static native void nativeWriteTo(long handle, OutputStream stream) throws IOException;
+    private static native byte[][] nativeVerify(byte[] apk) throws IOException;
+}
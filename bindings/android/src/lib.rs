@@ -0,0 +1,3 @@
+// the JNI entry points live in apk_editor behind its jni feature, this crate only links them into a
+// shared library
+pub use apk_editor;
@@ -0,0 +1,150 @@
+use std::fmt::Display;
+use napi::bindgen_prelude::Buffer;
+use napi::{Error, Result};
+use napi_derive::napi;
+use apk_editor::apk_zip::{self, ApkFile, CompressMethod, SigningKey};
+use apk_editor::manifest::manifest_editor::AndroidManifest;
+
+fn reason<E: Display>(err: E) -> Error {
+    Error::from_reason(err.to_string())
+}
+
+#[napi(object)]
+pub struct FileEntry {
+    pub name: String,
+    pub stored: bool,
+    pub size: u32,
+    pub compressed_size: u32,
+    pub crc32: u32
+}
+
+// an apk held in memory. Every edit is written back right away, so reads see it; align and sign
+// belong last since a later edit rewrites the zip with the default alignment and no signature
+#[napi]
+pub struct Apk {
+    data: Vec<u8>
+}
+
+#[napi]
+impl Apk {
+    #[napi(constructor, catch_unwind)]
+    pub fn new(data: Buffer) -> Result<Apk> {
+        let data = Vec::from(data);
+        ApkFile::from(&data).map_err(reason)?;
+        Ok(Apk{
+            data
+        })
+    }
+
+    fn open(&self) -> Result<ApkFile<'_>> {
+        ApkFile::from(&self.data).map_err(reason)
+    }
+
+    fn edit<F: FnOnce(&mut ApkFile) -> Result<()>>(&mut self, f: F) -> Result<()> {
+        let mut apk = self.open()?;
+        f(&mut apk)?;
+        let mut data = vec![];
+        apk.save(&mut data).map_err(reason)?;
+        self.data = data;
+        Ok(())
+    }
+
+    #[napi(catch_unwind)]
+    pub fn list_files(&self) -> Result<Vec<FileEntry>> {
+        Ok(self.open()?.list_files().into_iter().map(|entry| FileEntry{
+            name: entry.name,
+            stored: entry.compress_method == CompressMethod::Stored,
+            size: entry.size,
+            compressed_size: entry.compressed_size,
+            crc32: entry.crc32
+        }).collect())
+    }
+
+    #[napi(catch_unwind)]
+    pub fn get_file(&self, name: String) -> Result<Option<Buffer>> {
+        Ok(self.open()?.get_file(&name).map(Buffer::from))
+    }
+
+    // replaces the entry when there is one
+    #[napi(catch_unwind)]
+    pub fn add_file(&mut self, name: String, data: Buffer, stored: Option<bool>) -> Result<()> {
+        self.edit(|apk| {
+            let method = if stored.unwrap_or(false) { CompressMethod::Stored } else { CompressMethod::Deflated };
+            apk.add_or_replace_file(&name, data, method);
+            Ok(())
+        })
+    }
+
+    #[napi(catch_unwind)]
+    pub fn remove_file(&mut self, name: String) -> Result<()> {
+        self.edit(|apk| apk.remove_file(&name).map_err(reason))
+    }
+
+    #[napi(catch_unwind)]
+    pub fn get_manifest(&self) -> Result<Buffer> {
+        Ok(self.open()?.get_manifest().map_err(reason)?.into())
+    }
+
+    // a binary (compiled) manifest
+    #[napi(catch_unwind)]
+    pub fn set_manifest(&mut self, data: Buffer) -> Result<()> {
+        let data = Vec::from(data);
+        AndroidManifest::from(&data).map_err(reason)?;
+        self.edit(|apk| apk.set_manifest(&data).map_err(reason))
+    }
+
+    #[napi(catch_unwind)]
+    pub fn package_name(&self) -> Result<Option<String>> {
+        let data = self.open()?.get_manifest().map_err(reason)?;
+        Ok(AndroidManifest::from(&data).map_err(reason)?.package().map(String::from))
+    }
+
+    #[napi(catch_unwind)]
+    pub fn min_sdk_version(&self) -> Result<Option<u32>> {
+        let data = self.open()?.get_manifest().map_err(reason)?;
+        Ok(AndroidManifest::from(&data).map_err(reason)?.min_sdk_version())
+    }
+
+    #[napi(catch_unwind)]
+    pub fn get_app_label(&self, locale: Option<String>) -> Result<Option<String>> {
+        self.open()?.get_app_label(locale.as_deref().unwrap_or("")).map_err(reason)
+    }
+
+    #[napi(catch_unwind)]
+    pub fn set_app_label(&mut self, label: String) -> Result<()> {
+        self.edit(|apk| apk.set_app_label(&label, &[]).map_err(reason))
+    }
+
+    // appended as the next classesN.dex
+    #[napi(catch_unwind)]
+    pub fn add_dex(&mut self, data: Buffer) -> Result<()> {
+        self.edit(|apk| apk.add_dex(data).map_err(reason))
+    }
+
+    #[napi(catch_unwind)]
+    pub fn align(&mut self, alignment: u32) -> Result<()> {
+        let mut data = vec![];
+        self.open()?.save_aligned(&mut data, alignment as usize).map_err(reason)?;
+        self.data = data;
+        Ok(())
+    }
+
+    // APK Signature Scheme v2, see apk_zip::sign_v2
+    #[napi(catch_unwind)]
+    pub fn sign(&mut self, key: Buffer, certificate: Buffer) -> Result<()> {
+        let key = SigningKey::from(&key, &certificate).map_err(reason)?;
+        self.data = apk_zip::sign_v2(&self.data, &key).map_err(reason)?;
+        Ok(())
+    }
+
+    #[napi(catch_unwind)]
+    pub fn to_buffer(&self) -> Buffer {
+        self.data.clone().into()
+    }
+}
+
+// the certificates of the v2 signers, throws when the signature doesn't check out
+#[napi(catch_unwind)]
+pub fn verify(data: Buffer) -> Result<Vec<Buffer>> {
+    Ok(apk_zip::verify_v2(&data).map_err(reason)?.into_iter().map(Buffer::from).collect())
+}
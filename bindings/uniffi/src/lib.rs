@@ -0,0 +1,3 @@
+// the UniFFI interface lives in apk_editor behind its uniffi feature, this crate only links it into
+// a shared (Android, Python) or static (iOS) library
+pub use apk_editor;
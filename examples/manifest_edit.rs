@@ -1,6 +1,5 @@
 use std::fs::{File};
-use std::io::{Read, Write};
-use std::path::Path;
+use std::io::Read;
 use apk_editor::apk_zip;
 use apk_editor::manifest::manifest_editor::{AndroidManifest, Provider};
 
@@ -13,17 +12,18 @@ fn main() {
     f.read_to_end(&mut data).unwrap();
     println!("zip len: {}", data.len());
     let mut zip_file = apk_zip::ApkFile::from(&data).unwrap();
-    let manifest = zip_file.get_manifest();
+    let manifest = zip_file.get_manifest().unwrap();
     println!("manifest len: {}", manifest.len());
     let mut fest = AndroidManifest::from(&manifest).unwrap();
     fest.add_content_provider(Provider{
         class_name: "io.github.yearsyan.hookme.Prov".to_string(),
-        authorities: "io.github.yearsyan.hookme.Provider".to_string()
-    });
+        authorities: "io.github.yearsyan.hookme.Provider".to_string(),
+        ..Default::default()
+    }).unwrap();
     let new_manifest = fest.get_data();
     let ext_file = Vec::from("hello test");
 
-    zip_file.set_manifest(&new_manifest);
+    zip_file.set_manifest(&new_manifest).unwrap();
     zip_file.add_assets("ext.txt", &ext_file);
     zip_file.save(&mut out).unwrap();
     println!("edit done");
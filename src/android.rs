@@ -0,0 +1,302 @@
+// JNI entry points behind io.github.yearsyan.apkeditor.ApkEditor (bindings/android). An apk is opened
+// into a handle the Java side owns and has to close; InputStream and OutputStream are read and written
+// through, so content:// uris from a ContentResolver work without a temp file. Errors are thrown as
+// IOException, panics as RuntimeException
+use std::error::Error;
+use std::io::{Read, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use jni::objects::{JByteArray, JClass, JObject, JObjectArray, JString, JValue};
+use jni::sys::{jboolean, jbyteArray, jint, jlong, jobjectArray, jstring, JNI_FALSE, JNI_TRUE};
+use jni::JNIEnv;
+use crate::apk_zip::{sign_v2, verify_v2, ApkFile, CompressMethod, SigningKey};
+use crate::manifest::manifest_editor::AndroidManifest;
+
+const STREAM_BUFFER_SIZE: i32 = 64 * 1024;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+// the bytes of an open apk, every edit is saved back into them. A handle is a Box<Mutex<Session>>
+struct Session {
+    data: Vec<u8>
+}
+
+impl Session {
+    fn open(&self) -> Result<ApkFile<'_>> {
+        Ok(ApkFile::from(&self.data)?)
+    }
+
+    fn edit<F: FnOnce(&mut ApkFile) -> Result<()>>(&mut self, f: F) -> Result<()> {
+        let mut apk = self.open()?;
+        f(&mut apk)?;
+        let mut data = vec![];
+        apk.save(&mut data)?;
+        self.data = data;
+        Ok(())
+    }
+}
+
+// ApkEditor synchronizes its calls and closes under the same lock, so the handle is valid here. The
+// one way to find the session locked is a stream calling back into the ApkEditor it is being read or
+// written for, which fails rather than deadlocks
+fn session<'a>(handle: jlong) -> Result<MutexGuard<'a, Session>> {
+    let session = unsafe { (handle as *const Mutex<Session>).as_ref() }.ok_or("apk is closed")?;
+    match session.try_lock() {
+        Ok(session) => Ok(session),
+        // a panic is thrown before the session is written, it's as the last call left it
+        Err(TryLockError::Poisoned(err)) => Ok(err.into_inner()),
+        Err(TryLockError::WouldBlock) => Err("apk is in use by the call reading or writing this stream".into())
+    }
+}
+
+fn into_handle(data: Vec<u8>) -> Result<jlong> {
+    ApkFile::from(&data)?;
+    Ok(Box::into_raw(Box::new(Mutex::new(Session{
+        data
+    }))) as jlong)
+}
+
+// runs `f`, turning its error or panic into a pending Java exception and `default`
+fn run<'local, T, F>(env: &mut JNIEnv<'local>, default: T, f: F) -> T where F: FnOnce(&mut JNIEnv<'local>) -> Result<T> {
+    match catch_unwind(AssertUnwindSafe(|| f(env))) {
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => {
+            // a failed Java call already left its exception pending
+            if !env.exception_check().unwrap_or(false) {
+                let _ = env.throw_new("java/io/IOException", err.to_string());
+            }
+            default
+        },
+        Err(panic) => {
+            let message = panic.downcast_ref::<&str>().map(|message| String::from(*message))
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("panic in apk_editor"));
+            let _ = env.throw_new("java/lang/RuntimeException", message);
+            default
+        }
+    }
+}
+
+// java.io.InputStream as Read
+struct JavaInputStream<'a, 'local> {
+    env: &'a mut JNIEnv<'local>,
+    stream: &'a JObject<'local>,
+    buffer: JByteArray<'local>
+}
+
+impl Read for JavaInputStream<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(STREAM_BUFFER_SIZE as usize) as i32;
+        let count = self.env.call_method(self.stream, "read", "([BII)I", &[JValue::Object(&self.buffer), JValue::Int(0), JValue::Int(len)])
+            .and_then(|value| value.i())
+            .map_err(std::io::Error::other)?;
+        if count <= 0 {
+            return Ok(0);
+        }
+        let mut bytes = vec![0i8; count as usize];
+        self.env.get_byte_array_region(&self.buffer, 0, &mut bytes).map_err(std::io::Error::other)?;
+        buf.iter_mut().zip(bytes).for_each(|(byte, value)| *byte = value as u8);
+        Ok(count as usize)
+    }
+}
+
+// java.io.OutputStream as Write
+struct JavaOutputStream<'a, 'local> {
+    env: &'a mut JNIEnv<'local>,
+    stream: &'a JObject<'local>
+}
+
+impl Write for JavaOutputStream<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(STREAM_BUFFER_SIZE as usize);
+        let array = self.env.byte_array_from_slice(&buf[..len]).map_err(std::io::Error::other)?;
+        self.env.call_method(self.stream, "write", "([BII)V", &[JValue::Object(&array), JValue::Int(0), JValue::Int(len as i32)])
+            .map_err(std::io::Error::other)?;
+        self.env.delete_local_ref(array).map_err(std::io::Error::other)?;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.env.call_method(self.stream, "flush", "()V", &[]).map_err(std::io::Error::other)?;
+        Ok(())
+    }
+}
+
+fn byte_array(env: &mut JNIEnv, data: &[u8]) -> Result<jbyteArray> {
+    Ok(env.byte_array_from_slice(data)?.into_raw())
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeOpen<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, data: JByteArray<'local>) -> jlong {
+    run(&mut env, 0, |env| into_handle(env.convert_byte_array(&data)?))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeOpenStream<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, stream: JObject<'local>) -> jlong {
+    run(&mut env, 0, |env| {
+        let buffer = env.new_byte_array(STREAM_BUFFER_SIZE)?;
+        let mut data = vec![];
+        JavaInputStream{
+            env,
+            stream: &stream,
+            buffer
+        }.read_to_end(&mut data)?;
+        into_handle(data)
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeClose<'local>(_env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong) {
+    if handle != 0 {
+        drop(unsafe { Box::from_raw(handle as *mut Mutex<Session>) });
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeListFiles<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong) -> jobjectArray {
+    run(&mut env, std::ptr::null_mut(), |env| {
+        let names: Vec<String> = session(handle)?.open()?.list_files().into_iter().map(|entry| entry.name).collect();
+        let array: JObjectArray = env.new_object_array(names.len() as i32, "java/lang/String", JObject::null())?;
+        for (index, name) in names.iter().enumerate() {
+            let name = env.new_string(name)?;
+            env.set_object_array_element(&array, index as i32, &name)?;
+            env.delete_local_ref(name)?;
+        }
+        Ok(array.into_raw())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeGetFile<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong, name: JString<'local>) -> jbyteArray {
+    run(&mut env, std::ptr::null_mut(), |env| {
+        let name: String = env.get_string(&name)?.into();
+        match session(handle)?.open()?.get_file(&name) {
+            Some(data) => byte_array(env, &data),
+            None => Ok(std::ptr::null_mut())
+        }
+    })
+}
+
+// replaces the entry when there is one
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeAddFile<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong, name: JString<'local>, data: JByteArray<'local>, stored: jboolean) {
+    run(&mut env, (), |env| {
+        let name: String = env.get_string(&name)?.into();
+        let data = env.convert_byte_array(&data)?;
+        session(handle)?.edit(|apk| {
+            let method = if stored == JNI_TRUE { CompressMethod::Stored } else { CompressMethod::Deflated };
+            apk.add_or_replace_file(&name, data, method);
+            Ok(())
+        })
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeRemoveFile<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong, name: JString<'local>) -> jboolean {
+    run(&mut env, JNI_FALSE, |env| {
+        let name: String = env.get_string(&name)?.into();
+        let mut session = session(handle)?;
+        if session.open()?.get_file(&name).is_none() {
+            return Ok(JNI_FALSE);
+        }
+        session.edit(|apk| Ok(apk.remove_file(&name)?))?;
+        Ok(JNI_TRUE)
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeGetManifest<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong) -> jbyteArray {
+    run(&mut env, std::ptr::null_mut(), |env| {
+        let data = session(handle)?.open()?.get_file("AndroidManifest.xml").ok_or("no AndroidManifest.xml")?;
+        byte_array(env, &data)
+    })
+}
+
+// a binary (compiled) manifest
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeSetManifest<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong, data: JByteArray<'local>) {
+    run(&mut env, (), |env| {
+        let data = env.convert_byte_array(&data)?;
+        AndroidManifest::from(&data)?;
+        session(handle)?.edit(|apk| Ok(apk.set_manifest(&data)?))
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativePackageName<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong) -> jstring {
+    run(&mut env, std::ptr::null_mut(), |env| {
+        let data = session(handle)?.open()?.get_file("AndroidManifest.xml").ok_or("no AndroidManifest.xml")?;
+        match AndroidManifest::from(&data)?.package() {
+            Some(package) => Ok(env.new_string(package)?.into_raw()),
+            None => Ok(std::ptr::null_mut())
+        }
+    })
+}
+
+// appended as the next classesN.dex
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeAddDex<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong, data: JByteArray<'local>) {
+    run(&mut env, (), |env| {
+        let data = env.convert_byte_array(&data)?;
+        session(handle)?.edit(|apk| Ok(apk.add_dex(data)?))
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeAlign<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong, alignment: jint) {
+    run(&mut env, (), |_| {
+        let mut session = session(handle)?;
+        let alignment = usize::try_from(alignment).map_err(|_| "negative alignment")?;
+        let mut data = vec![];
+        session.open()?.save_aligned(&mut data, alignment)?;
+        session.data = data;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeSign<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong, key: JByteArray<'local>, certificate: JByteArray<'local>) {
+    run(&mut env, (), |env| {
+        let key = SigningKey::from(&env.convert_byte_array(&key)?, &env.convert_byte_array(&certificate)?)?;
+        let mut session = session(handle)?;
+        session.data = sign_v2(&session.data, &key)?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeToByteArray<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong) -> jbyteArray {
+    run(&mut env, std::ptr::null_mut(), |env| {
+        let session = session(handle)?;
+        byte_array(env, &session.data)
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeWriteTo<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong, stream: JObject<'local>) {
+    run(&mut env, (), |env| {
+        let session = session(handle)?;
+        let mut writer = JavaOutputStream{
+            env,
+            stream: &stream
+        };
+        writer.write_all(&session.data)?;
+        writer.flush()?;
+        Ok(())
+    })
+}
+
+// the certificates of the v2 signers
+#[no_mangle]
+pub extern "system" fn Java_io_github_yearsyan_apkeditor_ApkEditor_nativeVerify<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, data: JByteArray<'local>) -> jobjectArray {
+    run(&mut env, std::ptr::null_mut(), |env| {
+        let certificates = verify_v2(&env.convert_byte_array(&data)?)?;
+        let array = env.new_object_array(certificates.len() as i32, "[B", JObject::null())?;
+        for (index, certificate) in certificates.iter().enumerate() {
+            let certificate = env.byte_array_from_slice(certificate)?;
+            env.set_object_array_element(&array, index as i32, &certificate)?;
+            env.delete_local_ref(certificate)?;
+        }
+        Ok(array.into_raw())
+    })
+}
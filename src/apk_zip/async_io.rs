@@ -0,0 +1,54 @@
+use std::io::SeekFrom;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use crate::apk_zip::ApkFile;
+#[cfg(feature = "sign")]
+use crate::apk_zip::SigningKey;
+use crate::Result;
+
+// the zip is parsed and written from memory, so only the io awaits: reading buffers the whole stream
+// and saving builds the apk before writing it out. Parsing and compressing still run on the calling
+// task, a big apk is worth a spawn_blocking around the edits
+impl ApkFile<'static> {
+    pub async fn from_async_reader<R: AsyncRead + Unpin>(mut reader: R) -> Result<ApkFile<'static>> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data).await?;
+        ApkFile::from_vec(data)
+    }
+
+    // reads from the start whatever the position of `reader`, with the buffer sized up front
+    pub async fn from_async_seekable<R: AsyncRead + AsyncSeek + Unpin>(mut reader: R) -> Result<ApkFile<'static>> {
+        let len = reader.seek(SeekFrom::End(0)).await?;
+        reader.seek(SeekFrom::Start(0)).await?;
+        let mut data = Vec::with_capacity(usize::try_from(len).unwrap_or_default());
+        reader.read_to_end(&mut data).await?;
+        ApkFile::from_vec(data)
+    }
+}
+
+impl ApkFile<'_> {
+    // blocks while building the apk, see save_aligned_async
+    pub async fn save_async<W: AsyncWrite + Unpin>(&mut self, writer: W) -> Result<()> {
+        let align = self.config().align;
+        self.save_aligned_async(writer, align).await
+    }
+
+    // Blocks: the apk is built and compressed in memory on the calling task, only writing it out awaits. A
+    // runtime with other work to do wants the ApkFile moved into spawn_blocking and save_aligned called there
+    pub async fn save_aligned_async<W: AsyncWrite + Unpin>(&mut self, mut writer: W, align: usize) -> Result<()> {
+        let mut data = vec![];
+        self.save_aligned(&mut data, align)?;
+        writer.write_all(&data).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    // blocks while building and signing the apk, like save_aligned_async
+    #[cfg(feature = "sign")]
+    pub async fn save_signed_async<W: AsyncWrite + Unpin>(&mut self, mut writer: W, key: &SigningKey) -> Result<()> {
+        let mut data = vec![];
+        self.save_signed(&mut data, key)?;
+        writer.write_all(&data).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use sha1::{Digest, Sha1};
+
+// SHA-1 of the compressor id and the uncompressed data, and the compression level
+type CacheKey = ([u8; 20], u32);
+
+// a blob on disk starts with its CRC-32 and length, a truncated or damaged file is compressed again
+const BLOB_HEADER_SIZE: usize = 12;
+
+// tells apart the temp files of the threads and caches of one process
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+enum Store {
+    Memory(Mutex<HashMap<CacheKey, Vec<u8>>>),
+    Disk(PathBuf)
+}
+
+// Deflated entries by content, shared through an Arc by the editors of repeated builds (one apk per
+// channel) so only what changed between them is compressed again. Entries kept as is never go
+// through it, they aren't recompressed anyway. Nothing is evicted, a cache lives as long as the build
+pub struct CompressionCache {
+    store: Store,
+    hits: AtomicUsize,
+    misses: AtomicUsize
+}
+
+impl CompressionCache {
+    pub fn in_memory() -> CompressionCache {
+        CompressionCache::with_store(Store::Memory(Mutex::new(HashMap::new())))
+    }
+
+    // one file per blob in `dir`, which is created, so builds in separate processes share it too.
+    // Failing to write a blob only costs the next build the compression
+    pub fn on_disk<P: Into<PathBuf>>(dir: P) -> std::io::Result<CompressionCache> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(CompressionCache::with_store(Store::Disk(dir)))
+    }
+
+    fn with_store(store: Store) -> CompressionCache {
+        CompressionCache{
+            store,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0)
+        }
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    // the cached compression of `data` at `level` by the compressor `id`, else what `compress` makes
+    // of it, which is kept
+    pub(crate) fn get_or_compress<F: FnOnce(&[u8]) -> std::io::Result<Vec<u8>>>(&self, data: &[u8], level: u32, id: &str, compress: F) -> std::io::Result<Vec<u8>> {
+        let mut hasher = Sha1::new();
+        hasher.update(id.as_bytes());
+        hasher.update([0]);
+        hasher.update(data);
+        let key: CacheKey = (hasher.finalize().into(), level);
+        if let Some(blob) = self.get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(blob);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let blob = compress(data)?;
+        self.put(key, &blob);
+        Ok(blob)
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<Vec<u8>> {
+        match &self.store {
+            Store::Memory(blobs) => blobs.lock().ok()?.get(key).cloned(),
+            Store::Disk(dir) => {
+                let data = std::fs::read(dir.join(file_name(key))).ok()?;
+                let blob = data.get(BLOB_HEADER_SIZE..)?;
+                let crc = u32::from_le_bytes(data[0..4].try_into().ok()?);
+                let size = u64::from_le_bytes(data[4..12].try_into().ok()?);
+                if size != blob.len() as u64 || crc != crc32fast::hash(blob) {
+                    return None;
+                }
+                Some(blob.to_vec())
+            }
+        }
+    }
+
+    fn put(&self, key: CacheKey, blob: &[u8]) {
+        match &self.store {
+            Store::Memory(blobs) => {
+                if let Ok(mut blobs) = blobs.lock() {
+                    blobs.insert(key, blob.to_vec());
+                }
+            },
+            Store::Disk(dir) => {
+                // written aside and renamed, a concurrent build never reads half a blob
+                let path = dir.join(file_name(&key));
+                let temp = path.with_extension(format!("{}-{}.tmp", std::process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+                let mut data = Vec::with_capacity(BLOB_HEADER_SIZE + blob.len());
+                data.extend_from_slice(&crc32fast::hash(blob).to_le_bytes());
+                data.extend_from_slice(&(blob.len() as u64).to_le_bytes());
+                data.extend_from_slice(blob);
+                if std::fs::write(&temp, data).and_then(|_| std::fs::rename(&temp, &path)).is_err() {
+                    let _ = std::fs::remove_file(&temp);
+                }
+            }
+        }
+    }
+}
+
+fn file_name(key: &CacheKey) -> String {
+    let hash: String = key.0.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}.deflate", hash, key.1)
+}
@@ -0,0 +1,137 @@
+use std::io::Write;
+use std::sync::Mutex;
+use flate2::{Compress, Compression, FlushCompress, Status};
+
+// What ZipEditor deflates entries with, flate2 unless set_compressor says otherwise: zopfli for a
+// release build, libdeflate for speed. The output is a raw deflate stream, no zlib header
+pub trait Compressor: Send + Sync {
+    // `level` is EditorConfig::compression_level, 0 to 9, a backend maps it to its own scale or ignores it
+    fn deflate(&self, data: &[u8], level: u32) -> std::io::Result<Vec<u8>>;
+
+    // deflate into `out`, which is cleared first. A write hands every entry the same buffer, a backend
+    // that fills it in place saves an allocation per entry
+    fn deflate_into(&self, data: &[u8], level: u32, out: &mut Vec<u8>) -> std::io::Result<()> {
+        *out = self.deflate(data, level)?;
+        Ok(())
+    }
+
+    // Deflate straight into `writer`, how much was written. For the large entries a write streams, a
+    // backend without it holds the whole stream once
+    fn deflate_to(&self, data: &[u8], level: u32, writer: &mut dyn Write) -> std::io::Result<u64> {
+        let res = self.deflate(data, level)?;
+        writer.write_all(&res)?;
+        Ok(res.len() as u64)
+    }
+
+    // tells the blobs of different backends apart in a CompressionCache
+    fn id(&self) -> &str;
+}
+
+// what deflate_to fills before handing it to the writer
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+// Keeps the deflate states it made, reset, for the next entries: a state is a few hundred KB of
+// window and hash tables, allocated once per level and thread rather than once per entry
+#[derive(Default)]
+pub struct Flate2Compressor {
+    states: Mutex<Vec<(u32, Compress)>>
+}
+
+impl Flate2Compressor {
+    pub fn new() -> Flate2Compressor {
+        Flate2Compressor::default()
+    }
+
+    fn take_state(&self, level: u32) -> Compress {
+        let mut states = self.states.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match states.iter().position(|(state_level, _)| *state_level == level) {
+            Some(index) => states.swap_remove(index).1,
+            None => Compress::new(Compression::new(level), false)
+        }
+    }
+
+    fn put_state(&self, level: u32, mut state: Compress) {
+        state.reset();
+        self.states.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push((level, state));
+    }
+}
+
+impl Compressor for Flate2Compressor {
+    fn deflate(&self, data: &[u8], level: u32) -> std::io::Result<Vec<u8>> {
+        let mut res: Vec<u8> = Vec::new();
+        self.deflate_into(data, level, &mut res)?;
+        Ok(res)
+    }
+
+    fn deflate_into(&self, data: &[u8], level: u32, out: &mut Vec<u8>) -> std::io::Result<()> {
+        let level = level.min(9);
+        let mut state = self.take_state(level);
+        out.clear();
+        // compress_vec only fills the spare capacity, it grows when a pass runs out of it
+        out.reserve(data.len() / 2 + 64);
+        loop {
+            let input = &data[state.total_in() as usize..];
+            match state.compress_vec(input, out, FlushCompress::Finish).map_err(std::io::Error::other)? {
+                Status::StreamEnd => break,
+                Status::Ok | Status::BufError => out.reserve(out.capacity().max(64))
+            }
+        }
+        self.put_state(level, state);
+        Ok(())
+    }
+
+    fn deflate_to(&self, data: &[u8], level: u32, writer: &mut dyn Write) -> std::io::Result<u64> {
+        let level = level.min(9);
+        let mut state = self.take_state(level);
+        let mut chunk: Vec<u8> = Vec::with_capacity(STREAM_CHUNK_SIZE);
+        loop {
+            chunk.clear();
+            let input = &data[state.total_in() as usize..];
+            let status = state.compress_vec(input, &mut chunk, FlushCompress::Finish).map_err(std::io::Error::other)?;
+            writer.write_all(&chunk)?;
+            if status == Status::StreamEnd {
+                break;
+            }
+        }
+        let written = state.total_out();
+        self.put_state(level, state);
+        Ok(written)
+    }
+
+    fn id(&self) -> &str {
+        "flate2"
+    }
+}
+
+// stored deflate blocks, the data as is: entries stay deflated in the zip but cost no cpu to write
+#[derive(Default)]
+pub struct PassThroughCompressor;
+
+impl Compressor for PassThroughCompressor {
+    fn deflate(&self, data: &[u8], level: u32) -> std::io::Result<Vec<u8>> {
+        let mut res: Vec<u8> = Vec::new();
+        self.deflate_into(data, level, &mut res)?;
+        Ok(res)
+    }
+
+    fn deflate_into(&self, data: &[u8], _level: u32, out: &mut Vec<u8>) -> std::io::Result<()> {
+        out.clear();
+        out.reserve(data.len() + 5 * (data.len() / 0xffff + 1));
+        let mut blocks = data.chunks(0xffff).peekable();
+        if blocks.peek().is_none() {
+            out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+        }
+        while let Some(block) = blocks.next() {
+            // BFINAL on the last block, BTYPE 00, then LEN and its complement
+            out.push(u8::from(blocks.peek().is_none()));
+            out.extend_from_slice(&(block.len() as u16).to_le_bytes());
+            out.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+            out.extend_from_slice(block);
+        }
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        "stored"
+    }
+}
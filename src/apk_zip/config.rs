@@ -0,0 +1,50 @@
+use crate::apk_zip::CompressMethod;
+use crate::options::ParseOptions;
+
+// Settings of an ApkFile, from parsing to the written zip:
+// - align: stored entries start on a multiple of it, save_aligned overrides it for one save
+// - compression_level: 0 to 9 for the entries the editor deflates, entries kept as is aren't recompressed
+// - no_compress: name suffixes (".so", "resources.arsc") written stored even when added as deflated,
+//   like aapt's -0
+// - deterministic: drops the extra fields of kept entries (timestamps, uid/gid) and writes added
+//   entries in name order, so the output only depends on the content
+// - max_entries, max_entry_size: an apk declaring more entries, or an entry larger than this
+//   uncompressed, is rejected when opened
+// - threads: how many threads compress the entries of a save while it's written, 0 for one per core.
+//   1 keeps it all on the calling thread, the output is the same either way
+#[derive(Clone, Debug, PartialEq)]
+pub struct EditorConfig {
+    pub options: ParseOptions,
+    pub align: usize,
+    pub compression_level: u32,
+    pub no_compress: Vec<String>,
+    pub deterministic: bool,
+    pub max_entries: Option<usize>,
+    pub max_entry_size: Option<u32>,
+    pub threads: usize
+}
+
+impl Default for EditorConfig {
+    fn default() -> Self {
+        EditorConfig{
+            options: ParseOptions::default(),
+            align: 4, // what zipalign does
+            compression_level: 6, // zlib's default
+            no_compress: vec![],
+            deterministic: false,
+            max_entries: None,
+            max_entry_size: None,
+            threads: 0
+        }
+    }
+}
+
+impl EditorConfig {
+    // the method an entry the editor writes ends up with
+    pub(crate) fn method_for(&self, name: &str, method: &CompressMethod) -> CompressMethod {
+        if self.no_compress.iter().any(|suffix| name.ends_with(suffix.as_str())) {
+            return CompressMethod::Stored;
+        }
+        method.clone()
+    }
+}
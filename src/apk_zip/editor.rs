@@ -1,29 +1,90 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::error::Error;
-use std::io::Write;
+use std::fmt::{Display, Formatter};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
 use byteorder::{LittleEndian, WriteBytesExt};
-use flate2::Compression;
-use flate2::write::DeflateEncoder;
-use crate::apk_zip::{CENTRAL_DIRECTORY, CENTRAL_DIRECTORY_END, CompressMethod, LOCAL_FILE_HEADER};
-use crate::apk_zip::zip::{LocalFileHeader, ZipEntry, ZipFile};
-use crate::utils::{get_leu16_value};
+use crate::apk_zip::{CENTRAL_DIRECTORY, CENTRAL_DIRECTORY_END, CompressMethod, DATA_DESCRIPTOR, EditorConfig, LOCAL_FILE_HEADER};
+use crate::apk_zip::cache::CompressionCache;
+use crate::apk_zip::compress::{Compressor, Flate2Compressor};
+use crate::apk_zip::hooks::{EditEvent, Hooks};
+use crate::apk_zip::pipeline::{with_pipeline, Pipeline};
+use crate::apk_zip::save_plan::{EntryChange, PlannedEntry};
+use crate::apk_zip::transform::{EntryMeta, EntryTransformer};
+use crate::apk_zip::zip::{inflate, LocalFileHeader, ZipEntry, ZipFile, FLAG_DATA_DESCRIPTOR};
+use crate::utils::{read_leu16, read_leu32};
+
+// why an edit couldn't be made: the entry isn't there, or no transaction is open
+#[derive(Debug)]
+pub struct ZipError {
+    reason: String
+}
+
+impl ZipError {
+    pub(crate) fn new(reason: String) -> ZipError {
+        ZipError{
+            reason
+        }
+    }
+
+    fn missing(name: &str) -> ZipError {
+        ZipError::new(format!("{} is not in the zip", name))
+    }
+}
+
+impl Display for ZipError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "zip edit error: {}", self.reason)
+    }
+}
+
+impl Error for ZipError {}
 
+// a count, size or offset that doesn't fit its field. There is no zip64, so it's an error rather than a
+// truncated value
+fn zip32<T: TryFrom<usize>>(value: usize, field: &str) -> Result<T, std::io::Error> {
+    T::try_from(value).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} {} needs zip64, which isn't written", field, value)))
+}
+
+pub(crate) fn no_transaction() -> ZipError {
+    ZipError::new(String::from("no transaction is open"))
+}
+
+#[derive(Clone)]
 struct AppendZipEntry {
     data: Vec<u8>,
     compress_method: CompressMethod,
-    file_name: String,
-    modify_time: u32
+    file_name: String
 }
 
+// what finish buffers the writer with, data larger than it skips the buffer
+const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+// Added entries from this size on are deflated straight into the writer, behind a data descriptor,
+// rather than into a buffer as large as they are
+const STREAM_THRESHOLD: usize = 4 * 1024 * 1024;
+
+#[derive(Clone)]
 struct EditZipEntry {
     origin_entry: ZipEntry,
     remove: bool,
-    edit: Option<Vec<u8>>
+    edit: Option<Vec<u8>>,
+    method: Option<CompressMethod>, // overrides the original method of an edited entry
+    rename: Option<String>
 }
 
 pub struct ZipEditor {
     // origin_zip: Option<&'a ZipFile<'a>>,
     editable_entries: Vec<EditZipEntry>,
-    append_entries: Vec<AppendZipEntry>
+    append_entries: Vec<AppendZipEntry>,
+    checkpoints: Vec<(Vec<EditZipEntry>, Vec<AppendZipEntry>)>, // one per open transaction, innermost last
+    config: EditorConfig,
+    hooks: Hooks,
+    transformers: Vec<Box<dyn EntryTransformer>>, // run by finish, in order
+    cache: Option<Arc<CompressionCache>>,
+    compressor: Box<dyn Compressor>
 }
 
 struct FileHeaderBuilder<'a> {
@@ -32,28 +93,21 @@ struct FileHeaderBuilder<'a> {
     origin_size: u32,
     compress_size: u32,
     crc32: u32,
-    lfd_ext: Option<&'a [u8]>
+    lfd_ext: Option<Cow<'a, [u8]>>,
+    streamed: bool // crc and sizes in a data descriptor after the data, the local header has zeros
 }
 
 impl<'a> FileHeaderBuilder<'a> {
 
-    fn from_entry(zip: &'a ZipFile, entry: &'a ZipEntry) -> FileHeaderBuilder<'a> {
-        let lfh_offset = entry.local_file_header_offset;
-        let file_name_len = get_leu16_value(zip.data, lfh_offset as usize + 26);
-        let ext_start = lfh_offset as usize + 30 + file_name_len as usize;
-        let ext_len = get_leu16_value(zip.data, lfh_offset as usize + 28);
-        let ext_end = ext_start + ext_len as usize;
+    fn from_entry(entry: &'a ZipEntry, lfd_ext: Cow<'a, [u8]>) -> FileHeaderBuilder<'a> {
         FileHeaderBuilder {
             file_name: entry.file_name.as_str(),
             compress_method: entry.compress_method.clone(),
             origin_size: entry.origin_size,
             compress_size: entry.compressed_size,
             crc32: entry.crc_32,
-            lfd_ext: if ext_len == 0 {
-                None
-            } else {
-                Some(&zip.data[ext_start..ext_end])
-            }
+            lfd_ext: Some(lfd_ext).filter(|ext| !ext.is_empty()),
+            streamed: false
         }
     }
 
@@ -64,7 +118,8 @@ impl<'a> FileHeaderBuilder<'a> {
             origin_size,
             compress_size,
             crc32,
-            lfd_ext: None
+            lfd_ext: None,
+            streamed: false
         }
     }
 
@@ -72,21 +127,21 @@ impl<'a> FileHeaderBuilder<'a> {
         self.compress_size = size;
     }
 
-    pub fn set_ldf_ext(&mut self, value: &'a [u8]) {
-        self.lfd_ext = Some(value);
+    fn flag(&self) -> u16 {
+        if self.streamed { FLAG_DATA_DESCRIPTOR } else { 0 }
     }
 
     pub fn write_cd<W: Write>(&self, mut writer: W, lfh_offset: u32) -> Result<usize, std::io::Error> {
         writer.write_u32::<LittleEndian>(CENTRAL_DIRECTORY)?;
         writer.write_u16::<LittleEndian>(0)?;
         writer.write_u16::<LittleEndian>(0)?;
-        writer.write_u16::<LittleEndian>(0)?; // flag
+        writer.write_u16::<LittleEndian>(self.flag())?; // flag
         writer.write_u16::<LittleEndian>(self.compress_method.value())?; // method
         writer.write_u32::<LittleEndian>(0)?; // modify
         writer.write_u32::<LittleEndian>(self.crc32)?;
         writer.write_u32::<LittleEndian>(self.compress_size)?;
         writer.write_u32::<LittleEndian>(self.origin_size)?;
-        writer.write_u16::<LittleEndian>(self.file_name.len() as u16)?;
+        writer.write_u16::<LittleEndian>(zip32(self.file_name.len(), "file name length")?)?;
         writer.write_u16::<LittleEndian>(0)?; // ext len
         writer.write_u16::<LittleEndian>(0)?; // comment
         writer.write_u16::<LittleEndian>(0)?;
@@ -98,7 +153,7 @@ impl<'a> FileHeaderBuilder<'a> {
     }
 
     pub fn write_lfh<W: Write>(&self, mut writer: W, offset: usize, align: usize) -> Result<usize, std::io::Error> {
-        let origin_ext_len = match self.lfd_ext {
+        let origin_ext_len = match &self.lfd_ext {
             Some(v) => v.len(),
             None => 0
         };
@@ -109,28 +164,165 @@ impl<'a> FileHeaderBuilder<'a> {
             (align - ((offset + origin_lfd_len) % align)) % align
         };
         let new_ext_len = origin_ext_len + align_count;
+        // a streamed entry's sizes aren't known yet, write_descriptor has them
+        let (crc32, compress_size, origin_size) = match self.streamed {
+            true => (0, 0, 0),
+            false => (self.crc32, self.compress_size, self.origin_size)
+        };
         writer.write_u32::<LittleEndian>(LOCAL_FILE_HEADER)?;
         writer.write_u16::<LittleEndian>(0)?;
-        writer.write_u16::<LittleEndian>(0)?;
+        writer.write_u16::<LittleEndian>(self.flag())?;
         writer.write_u16::<LittleEndian>(self.compress_method.value())?;
         writer.write_u32::<LittleEndian>(0)?;
-        writer.write_u32::<LittleEndian>(self.crc32)?;
-        writer.write_u32::<LittleEndian>(self.compress_size)?;
-        writer.write_u32::<LittleEndian>(self.origin_size)?;
-        writer.write_u16::<LittleEndian>(self.file_name.len() as u16)?;
-        writer.write_u16::<LittleEndian>(new_ext_len as u16)?;
+        writer.write_u32::<LittleEndian>(crc32)?;
+        writer.write_u32::<LittleEndian>(compress_size)?;
+        writer.write_u32::<LittleEndian>(origin_size)?;
+        writer.write_u16::<LittleEndian>(zip32(self.file_name.len(), "file name length")?)?;
+        writer.write_u16::<LittleEndian>(zip32(new_ext_len, "extra field length")?)?;
         writer.write_all(self.file_name.as_bytes())?;
-        match self.lfd_ext {
-            Some(ext_data) => writer.write_all(ext_data)?,
-            _ => {}
-        };
+        if let Some(ext_data) = &self.lfd_ext {
+            writer.write_all(ext_data)?;
+        }
         for _ in 0.. align_count {
             writer.write_u8(0)?;
         }
         Ok(30 + self.file_name.len() + new_ext_len)
     }
+
+    // after the data of a streamed entry
+    pub fn write_descriptor<W: Write>(&self, mut writer: W) -> Result<usize, std::io::Error> {
+        writer.write_u32::<LittleEndian>(DATA_DESCRIPTOR)?;
+        writer.write_u32::<LittleEndian>(self.crc32)?;
+        writer.write_u32::<LittleEndian>(self.compress_size)?;
+        writer.write_u32::<LittleEndian>(self.origin_size)?;
+        Ok(16)
+    }
+}
+
+
+// where write copies the entries kept from the zip as read: the zip in memory, or the reader of
+// finish_from, which nothing is loaded from but the entries a transformer reads
+trait Origin {
+    // the extra field of the entry's local header and where its data starts
+    fn locate(&self, entry: &ZipEntry) -> Result<(Cow<'_, [u8]>, u64), std::io::Error>;
+
+    // the stored bytes of the entry, from `data_offset`
+    fn copy_data(&self, entry: &ZipEntry, data_offset: u64, writer: &mut dyn Write) -> Result<(), std::io::Error>;
+
+    // uncompressed
+    fn load(&self, entry: &ZipEntry) -> Option<Vec<u8>>;
+}
+
+fn bad_local_header(entry: &ZipEntry) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("bad local file header of {}", entry.file_name))
 }
 
+fn data_past_end(entry: &ZipEntry) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::UnexpectedEof, format!("data of {} past the end of the file", entry.file_name))
+}
+
+impl Origin for ZipFile<'_> {
+    fn locate(&self, entry: &ZipEntry) -> Result<(Cow<'_, [u8]>, u64), std::io::Error> {
+        // ZipFile::from checked every local header, this only fails on a zip it didn't parse
+        let lfh = LocalFileHeader::of(&self.data, entry).ok_or_else(|| bad_local_header(entry))?;
+        let extra = lfh.extra(&self.data).ok_or_else(|| bad_local_header(entry))?;
+        Ok((Cow::Borrowed(extra), lfh.get_data_offset() as u64))
+    }
+
+    fn copy_data(&self, entry: &ZipEntry, data_offset: u64, writer: &mut dyn Write) -> Result<(), std::io::Error> {
+        let start = data_offset as usize;
+        let data = start.checked_add(entry.compressed_size as usize).and_then(|end| self.data.get(start..end))
+            .ok_or_else(|| data_past_end(entry))?;
+        writer.write_all(data)
+    }
+
+    fn load(&self, entry: &ZipEntry) -> Option<Vec<u8>> {
+        self.get_uncompress_data(&entry.file_name)
+    }
+}
+
+struct ReaderOrigin<R: Read + Seek> {
+    reader: RefCell<R>
+}
+
+impl<R: Read + Seek> Origin for ReaderOrigin<R> {
+    fn locate(&self, entry: &ZipEntry) -> Result<(Cow<'_, [u8]>, u64), std::io::Error> {
+        let mut reader = self.reader.borrow_mut();
+        let offset = entry.local_file_header_offset as u64;
+        let mut header = [0u8; 30];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut header)?;
+        if read_leu32(&header, 0) != Some(LOCAL_FILE_HEADER) {
+            return Err(bad_local_header(entry));
+        }
+        // where the data starts hangs on the name length, so the name has to be the entry's. With a data
+        // descriptor the sizes come after the data
+        let has_descriptor = read_leu16(&header, 6).unwrap_or(0) & FLAG_DATA_DESCRIPTOR != 0;
+        let sizes_match = read_leu32(&header, 18) == Some(entry.compressed_size) && read_leu32(&header, 22) == Some(entry.origin_size);
+        if !has_descriptor && !sizes_match {
+            return Err(bad_local_header(entry));
+        }
+        let mut name = vec![0u8; read_leu16(&header, 26).unwrap_or(0) as usize];
+        let mut extra = vec![0u8; read_leu16(&header, 28).unwrap_or(0) as usize];
+        reader.read_exact(&mut name)?;
+        if name != entry.file_name.as_bytes() {
+            return Err(bad_local_header(entry));
+        }
+        reader.read_exact(&mut extra)?;
+        let data_offset = offset + 30 + name.len() as u64 + extra.len() as u64;
+        Ok((Cow::Owned(extra), data_offset))
+    }
+
+    // in io::copy's chunks, the entry is never in memory whole
+    fn copy_data(&self, entry: &ZipEntry, data_offset: u64, writer: &mut dyn Write) -> Result<(), std::io::Error> {
+        let mut reader = self.reader.borrow_mut();
+        reader.seek(SeekFrom::Start(data_offset))?;
+        let copied = std::io::copy(&mut (&mut *reader).take(entry.compressed_size as u64), writer)?;
+        if copied != entry.compressed_size as u64 {
+            return Err(data_past_end(entry));
+        }
+        Ok(())
+    }
+
+    fn load(&self, entry: &ZipEntry) -> Option<Vec<u8>> {
+        let (_, data_offset) = self.locate(entry).ok()?;
+        let mut raw = vec![];
+        self.copy_data(entry, data_offset, &mut raw).ok()?;
+        inflate(&raw, entry)
+    }
+}
+
+// an entry prepared by ZipEditor::prepare, waiting for its turn to be written
+enum Pending<'a> {
+    Removed(PlannedEntry),
+    Written {
+        header: FileHeaderBuilder<'a>,
+        align: usize,
+        body: Body<'a>,
+        change: EntryChange,
+        renamed_from: Option<String>
+    }
+}
+
+enum Body<'a> {
+    // taken back from the pipeline, crc and sizes go into the header then
+    Encoded(usize),
+    // the stored bytes of an entry of the apk as read, where they start in it
+    Copied(&'a ZipEntry, u64),
+    // deflated as it's written, crc included
+    Streamed(Cow<'a, [u8]>)
+}
+
+// the content of an entry of the apk as read, for a transformer
+fn load_entry(name: &str, load: &dyn Fn() -> Option<Vec<u8>>) -> Result<Vec<u8>, std::io::Error> {
+    load().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("can't inflate {}", name)))
+}
+
+impl Default for ZipEditor {
+    fn default() -> Self {
+        ZipEditor::new()
+    }
+}
 
 impl ZipEditor {
 
@@ -138,150 +330,478 @@ impl ZipEditor {
         ZipEditor{
             // origin_zip: None,
             editable_entries: vec![],
-            append_entries: vec![]
+            append_entries: vec![],
+            checkpoints: vec![],
+            config: EditorConfig::default(),
+            hooks: Hooks::default(),
+            transformers: vec![],
+            cache: None,
+            compressor: Box::new(Flate2Compressor::new())
         }
     }
 
     pub fn from(zip_file: & ZipFile) -> ZipEditor {
+        ZipEditor::with_config(zip_file, EditorConfig::default())
+    }
+
+    pub fn with_config(zip_file: & ZipFile, config: EditorConfig) -> ZipEditor {
         let mut res = ZipEditor{
             // origin_zip: Some(zip_file),
             editable_entries: vec![],
-            append_entries: vec![]
+            append_entries: vec![],
+            checkpoints: vec![],
+            config,
+            hooks: Hooks::default(),
+            transformers: vec![],
+            cache: None,
+            compressor: Box::new(Flate2Compressor::new())
         };
         for entry in &zip_file.entries {
             res.editable_entries.push(EditZipEntry{
                 origin_entry: entry.clone(),
                 remove: false,
-                edit: None
+                edit: None,
+                method: None,
+                rename: None
             });
         }
         res
     }
 
+    // `callback` sees every later change, see EditEvent
+    pub fn on_event<F: Fn(&EditEvent) + Send + Sync + 'static>(&mut self, callback: F) {
+        self.hooks.add(Box::new(callback));
+    }
+
+    // `transformer` sees every entry finish writes, after the ones added before it
+    pub fn add_transformer<T: EntryTransformer + 'static>(&mut self, transformer: T) {
+        self.transformers.push(Box::new(transformer));
+    }
+
+    // what deflates the entries the editor writes, see Compressor
+    pub fn set_compressor<C: Compressor + 'static>(&mut self, compressor: C) {
+        self.compressor = Box::new(compressor);
+    }
+
+    // deflated entries are looked up in `cache` before compressing them, and added to it
+    pub fn set_compression_cache(&mut self, cache: Arc<CompressionCache>) {
+        self.cache = Some(cache);
+    }
+
+    pub(crate) fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
     pub fn append_file(&mut self, data: Vec<u8>, file_name: String, method: CompressMethod) {
+        self.hooks.emit(EditEvent::FileAdded{name: &file_name, size: data.len()});
         self.append_entries.push(AppendZipEntry{
             data,
             compress_method: method,
-            file_name,
-            modify_time: 0
+            file_name
         });
     }
 
-    pub fn edit_file(&mut self, origin_zip: &ZipFile, name: &str, data: Vec<u8>) -> Option<()> {
-        let idx = origin_zip.get_file_index(name)?;
-        let mut item = self.editable_entries.get_mut(idx)?;
+    pub fn edit_file(&mut self, origin_zip: &ZipFile, name: &str, data: Vec<u8>) -> Result<(), ZipError> {
+        let item = origin_zip.get_file_index(name)
+            .and_then(|idx| self.editable_entries.get_mut(idx))
+            .ok_or_else(|| ZipError::missing(name))?;
+        self.hooks.emit(EditEvent::FileEdited{name, size: data.len()});
         item.edit = Some(data);
-        Some(())
+        Ok(())
+    }
+
+    pub fn edit_file_with_method(&mut self, origin_zip: &ZipFile, name: &str, data: Vec<u8>, method: CompressMethod) -> Result<(), ZipError> {
+        let item = origin_zip.get_file_index(name)
+            .and_then(|idx| self.editable_entries.get_mut(idx))
+            .ok_or_else(|| ZipError::missing(name))?;
+        self.hooks.emit(EditEvent::FileEdited{name, size: data.len()});
+        item.edit = Some(data);
+        item.method = Some(method);
+        Ok(())
     }
 
-    pub fn remove_file(&mut self, origin_zip: &ZipFile, name: &str) -> Option<()> {
-        let idx = origin_zip.get_file_index(name)?;
-        let mut item = self.editable_entries.get_mut(idx)?;
+    pub fn rename_file(&mut self, origin_zip: &ZipFile, name: &str, new_name: &str) -> Result<(), ZipError> {
+        let item = origin_zip.get_file_index(name)
+            .and_then(|idx| self.editable_entries.get_mut(idx))
+            .ok_or_else(|| ZipError::missing(name))?;
+        self.hooks.emit(EditEvent::FileRenamed{from: name, to: new_name});
+        item.rename = Some(String::from(new_name));
+        Ok(())
+    }
+
+    pub fn remove_file(&mut self, origin_zip: &ZipFile, name: &str) -> Result<(), ZipError> {
+        let item = origin_zip.get_file_index(name)
+            .and_then(|idx| self.editable_entries.get_mut(idx))
+            .ok_or_else(|| ZipError::missing(name))?;
+        self.hooks.emit(EditEvent::FileRemoved{name});
         item.remove = true;
-        Some(())
+        Ok(())
     }
 
-    pub fn finish<W: Write>(&self, origin_zip: Option<&ZipFile>, mut writer: W, align: usize) -> Result<(), Box<dyn Error>> {
-        let mut central_directory_data: Vec<u8> = Vec::new();
-        let mut current_offset: usize = 0;
-        let mut file_count: u16 = 0;
+    // renames the entry currently called `name`, which may be appended or renamed already
+    pub fn rename_current_file(&mut self, name: &str, new_name: &str) -> Result<(), ZipError> {
+        if let Some(item) = self.append_entries.iter_mut().find(|item| item.file_name == name) {
+            self.hooks.emit(EditEvent::FileRenamed{from: name, to: new_name});
+            item.file_name = String::from(new_name);
+            return Ok(());
+        }
+        let item = self.editable_entries.iter_mut().find(|item| {
+            !item.remove && item.rename.as_deref().unwrap_or(item.origin_entry.file_name.as_str()) == name
+        }).ok_or_else(|| ZipError::missing(name))?;
+        self.hooks.emit(EditEvent::FileRenamed{from: name, to: new_name});
+        item.rename = Some(String::from(new_name));
+        Ok(())
+    }
 
-        if origin_zip.is_some() {
-            let origin_zip = origin_zip.unwrap();
-            for entry in &self.editable_entries {
-                if entry.remove {
-                    continue;
-                }
+    // removes the entry currently called `name`, an appended one is dropped
+    pub fn remove_current_file(&mut self, name: &str) -> Result<(), ZipError> {
+        if let Some(index) = self.append_entries.iter().position(|item| item.file_name == name) {
+            self.hooks.emit(EditEvent::FileRemoved{name});
+            self.append_entries.remove(index);
+            return Ok(());
+        }
+        let item = self.editable_entries.iter_mut().find(|item| {
+            !item.remove && item.rename.as_deref().unwrap_or(item.origin_entry.file_name.as_str()) == name
+        }).ok_or_else(|| ZipError::missing(name))?;
+        self.hooks.emit(EditEvent::FileRemoved{name});
+        item.remove = true;
+        Ok(())
+    }
 
-                file_count += 1;
-                let lfh = LocalFileHeader::from_slice(origin_zip.data.as_slice(), entry.origin_entry.local_file_header_offset as usize);
-                let mut header_build = FileHeaderBuilder::from_entry(origin_zip, &entry.origin_entry);
-                let new_local_file_header_offset = current_offset as u32;
-                if entry.edit.is_none() {
-                    current_offset += header_build.write_lfh(&mut writer, current_offset, align)?;
-                    let data_start = lfh.get_data_offset();
-                    let data = &origin_zip.data[data_start..(data_start + lfh.get_data_len() as usize)];
-                    writer.write_all(data)?;
-                    current_offset += data.len();
-                } else {
-                    let new_file = entry.edit.as_ref().unwrap();
-                    if entry.origin_entry.compress_method == CompressMethod::Stored {
-                        header_build.set_compressed_size(new_file.len() as u32);
-                        current_offset += header_build.write_lfh(&mut writer, current_offset, align)?;
-                        writer.write_all(new_file.as_slice())?;
-                        current_offset += new_file.len();
-                    } else {
-                        let mut hasher = crc32fast::Hasher::new();
-                        hasher.update(entry.edit.as_ref().unwrap().as_slice());
-                        let crc32 = hasher.finalize();
-
-                        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
-                        encoder.write_all(entry.edit.as_ref().unwrap().as_slice())?;
-                        let compress_data = encoder.finish()?;
-
-                        header_build.origin_size = entry.edit.as_ref().unwrap().len() as u32;
-                        header_build.set_compressed_size(compress_data.len() as u32);
-                        header_build.crc32 = crc32;
-
-                        current_offset += header_build.write_lfh(&mut writer, current_offset, align)?;
-                        writer.write_all(compress_data.as_slice())?;
-                        current_offset += compress_data.as_slice().len();
-                    }
+    // new data for the entry currently called `name`, keeping its compression method
+    pub fn replace_current_file(&mut self, name: &str, data: Vec<u8>) -> Result<(), ZipError> {
+        if let Some(item) = self.append_entries.iter_mut().find(|item| item.file_name == name) {
+            self.hooks.emit(EditEvent::FileEdited{name, size: data.len()});
+            item.data = data;
+            return Ok(());
+        }
+        let item = self.editable_entries.iter_mut().find(|item| {
+            !item.remove && item.rename.as_deref().unwrap_or(item.origin_entry.file_name.as_str()) == name
+        }).ok_or_else(|| ZipError::missing(name))?;
+        self.hooks.emit(EditEvent::FileEdited{name, size: data.len()});
+        item.edit = Some(data);
+        Ok(())
+    }
 
-                }
-                header_build.write_cd(&mut central_directory_data, new_local_file_header_offset)?;
+    pub fn config(&self) -> &EditorConfig {
+        &self.config
+    }
+
+    // whether `name` will be in the written zip, after renames, removals and appends
+    pub fn has_file(&self, name: &str) -> bool {
+        self.append_entries.iter().any(|item| item.file_name == name) || self.editable_entries.iter().any(|item| {
+            !item.remove && item.rename.as_deref().unwrap_or(item.origin_entry.file_name.as_str()) == name
+        })
+    }
+
+    // Starts a transaction, the edits from here on can be undone together with rollback. They nest,
+    // each commit or rollback closes the innermost. The pending edits are copied, data included
+    pub fn begin(&mut self) {
+        self.checkpoints.push((self.editable_entries.clone(), self.append_entries.clone()));
+    }
+
+    // keeps the edits of the innermost transaction, an error when no transaction is open
+    pub fn commit(&mut self) -> Result<(), ZipError> {
+        self.checkpoints.pop().map(|_| ()).ok_or_else(no_transaction)
+    }
+
+    // back to the state at the matching begin
+    pub fn rollback(&mut self) -> Result<(), ZipError> {
+        let (editable_entries, append_entries) = self.checkpoints.pop().ok_or_else(no_transaction)?;
+        self.editable_entries = editable_entries;
+        self.append_entries = append_entries;
+        self.hooks.emit(EditEvent::RolledBack);
+        Ok(())
+    }
+
+    // the data the entry currently called `name` will be written with, if it was edited or added
+    pub fn pending_data(&self, name: &str) -> Option<&[u8]> {
+        if let Some(item) = self.append_entries.iter().find(|item| item.file_name == name) {
+            return Some(&item.data);
+        }
+        self.editable_entries.iter().find(|item| {
+            !item.remove && item.rename.as_deref().unwrap_or(item.origin_entry.file_name.as_str()) == name
+        })?.edit.as_deref()
+    }
+
+    // the names the written zip will have, after renames, removals and appends
+    pub fn current_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.editable_entries.iter()
+            .filter(|item| !item.remove)
+            .map(|item| item.rename.as_deref().unwrap_or(item.origin_entry.file_name.as_str()))
+            .chain(self.append_entries.iter().map(|item| item.file_name.as_str()))
+    }
+
+    // the content the entry currently called `name` will be written with: its pending data, or what
+    // `origin_zip` has for it under the name it was read with
+    pub fn current_data(&self, origin_zip: &ZipFile, name: &str) -> Option<Vec<u8>> {
+        if let Some(item) = self.append_entries.iter().find(|item| item.file_name == name) {
+            return Some(item.data.clone());
+        }
+        let item = self.editable_entries.iter().find(|item| {
+            !item.remove && item.rename.as_deref().unwrap_or(item.origin_entry.file_name.as_str()) == name
+        })?;
+        match &item.edit {
+            Some(data) => Some(data.clone()),
+            None => origin_zip.get_uncompress_data(&item.origin_entry.file_name)
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "zip_write", level = "debug", skip_all,
+        fields(align = align, entries = tracing::field::Empty, bytes = tracing::field::Empty)))]
+    // Headers go out a field at a time, so `writer` is written through a buffer of its own: a File
+    // needs no BufWriter around it
+    pub fn finish<W: Write>(&self, origin_zip: Option<&ZipFile>, writer: W, align: usize) -> Result<(), std::io::Error> {
+        self.hooks.emit(EditEvent::SaveStarted{align});
+        let mut writer = BufWriter::with_capacity(WRITE_BUFFER_SIZE, writer);
+        let size = self.write(origin_zip.map(|zip| zip as &dyn Origin), &mut writer, align, &mut vec![])?;
+        writer.flush()?;
+        record!("bytes", size);
+        self.hooks.emit(EditEvent::SaveFinished{size});
+        Ok(())
+    }
+
+    // finish for an editor made from ZipFile::read_central_directory, `reader` being what it read. The
+    // entries kept as they are stream from it to `writer`, a 2 GB apk is saved without being loaded
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "zip_write", level = "debug", skip_all,
+        fields(align = align, entries = tracing::field::Empty, bytes = tracing::field::Empty)))]
+    pub fn finish_from<R: Read + Seek, W: Write>(&self, reader: R, writer: W, align: usize) -> Result<(), std::io::Error> {
+        self.hooks.emit(EditEvent::SaveStarted{align});
+        let origin = ReaderOrigin{
+            reader: RefCell::new(reader)
+        };
+        let mut writer = BufWriter::with_capacity(WRITE_BUFFER_SIZE, writer);
+        let size = self.write(Some(&origin), &mut writer, align, &mut vec![])?;
+        writer.flush()?;
+        record!("bytes", size);
+        self.hooks.emit(EditEvent::SaveFinished{size});
+        Ok(())
+    }
+
+    // what finish would write and its size, without writing it
+    pub(crate) fn layout(&self, origin_zip: Option<&ZipFile>, align: usize) -> Result<(Vec<PlannedEntry>, usize), std::io::Error> {
+        let mut entries = vec![];
+        let size = self.write(origin_zip.map(|zip| zip as &dyn Origin), std::io::sink(), align, &mut entries)?;
+        Ok((entries, size))
+    }
+
+    // into `out`, the scratch buffer of the write, unless the cache hands a blob over
+    fn deflate(&self, data: &[u8], level: u32, out: &mut Vec<u8>) -> Result<(), std::io::Error> {
+        match &self.cache {
+            Some(cache) => {
+                *out = cache.get_or_compress(data, level, self.compressor.id(), |data| self.compressor.deflate(data, level))?;
+                Ok(())
+            },
+            None => self.compressor.deflate_into(data, level, out)
+        }
+    }
+
+    // What the transformers make of one entry. `data` is loaded from the apk as read when one of them
+    // reads it or the method changed, so the entry has to be encoded again. Returns whether the
+    // content changed
+    fn transform<'d>(&self, meta: &mut EntryMeta, data: &mut Option<Cow<'d, [u8]>>, load: &dyn Fn() -> Option<Vec<u8>>) -> Result<bool, std::io::Error> {
+        let original_method = meta.compress_method.clone();
+        let mut changed = false;
+        for transformer in &self.transformers {
+            if data.is_none() && transformer.reads(meta.name()) {
+                *data = Some(Cow::Owned(load_entry(meta.name(), load)?));
             }
+            if let Some(new_data) = transformer.transform(meta, data.as_deref()) {
+                *data = Some(Cow::Owned(new_data));
+                changed = true;
+            }
+        }
+        if data.is_none() && meta.compress_method != original_method {
+            *data = Some(Cow::Owned(load_entry(meta.name(), load)?));
         }
+        Ok(changed)
+    }
 
-        for new_entry in &self.append_entries {
-            file_count += 1;
+    // What becomes of an entry on its way into the zip, in write order: how it's written, or that it's
+    // left out. Encoded entries are submitted to `pipeline` here, write() takes them back
+    fn prepare<'a>(&'a self, origin_zip: &'a dyn Origin, entry: &'a EditZipEntry, align: usize, pipeline: &mut Pipeline<'a, '_>) -> Result<Pending<'a>, std::io::Error> {
+        if entry.remove {
+            return Ok(Pending::Removed(PlannedEntry{
+                name: entry.origin_entry.file_name.clone(),
+                change: EntryChange::Removed,
+                renamed_from: None,
+                compress_method: entry.origin_entry.compress_method.clone(),
+                size: entry.origin_entry.origin_size,
+                compressed_size: entry.origin_entry.compressed_size,
+                offset: None
+            }));
+        }
+        let (lfd_ext, data_offset) = origin_zip.locate(&entry.origin_entry)?;
+        let mut header_build = FileHeaderBuilder::from_entry(&entry.origin_entry, lfd_ext);
+        if self.config.deterministic {
+            header_build.lfd_ext = None;
+        }
+        if let Some(new_name) = &entry.rename {
+            header_build.file_name = new_name.as_str();
+        }
+        if entry.edit.is_some() {
+            if let Some(method) = &entry.method {
+                header_build.compress_method = method.clone();
+            }
+            header_build.compress_method = self.config.method_for(header_build.file_name, &header_build.compress_method);
+        }
+        let mut meta = EntryMeta::new(header_build.file_name,
+            if entry.edit.is_some() { EntryChange::Edited } else { EntryChange::Kept },
+            header_build.compress_method.clone(), align);
+        let mut data: Option<Cow<[u8]>> = entry.edit.as_deref().map(Cow::Borrowed);
+        let changed = self.transform(&mut meta, &mut data, &|| origin_zip.load(&entry.origin_entry))?;
+        let encode = entry.edit.is_some() || changed || meta.compress_method != entry.origin_entry.compress_method;
+        header_build.compress_method = meta.compress_method.clone();
+        let body = match data.filter(|_| encode) {
+            Some(new_file) => Body::Encoded(pipeline.submit(new_file, header_build.compress_method != CompressMethod::Stored)?),
+            None => Body::Copied(&entry.origin_entry, data_offset)
+        };
+        Ok(Pending::Written{
+            header: header_build,
+            align: meta.align,
+            body,
+            change: if entry.edit.is_some() || changed { EntryChange::Edited } else { EntryChange::Kept },
+            renamed_from: entry.rename.as_ref().map(|_| entry.origin_entry.file_name.clone())
+        })
+    }
 
-            let mut hash = crc32fast::Hasher::new();
-            hash.update(new_entry.data.as_slice());
-            let crc32_hash = hash.finalize();
-
-            let mut compress_data_opt: Option<Vec<u8>> = None;
-            if new_entry.compress_method != CompressMethod::Stored {
-                let mut compress_data: Vec<u8> = Vec::new();
-                let mut encoder = DeflateEncoder::new(&mut compress_data, Compression::default());
-                encoder.write_all(new_entry.data.as_slice())?;
-                encoder.finish()?;
-                compress_data_opt = Some(compress_data);
+    fn prepare_added<'a>(&'a self, new_entry: &'a AppendZipEntry, align: usize, pipeline: &mut Pipeline<'a, '_>) -> Result<Pending<'a>, std::io::Error> {
+        let mut meta = EntryMeta::new(&new_entry.file_name, EntryChange::Added,
+            self.config.method_for(&new_entry.file_name, &new_entry.compress_method), align);
+        let mut data = Some(Cow::Borrowed(new_entry.data.as_slice()));
+        self.transform(&mut meta, &mut data, &|| None)?;
+        let compress_method = meta.compress_method.clone();
+        let data = data.unwrap_or_default();
+        // a cached blob is whole anyway
+        let body = if compress_method != CompressMethod::Stored && data.len() >= STREAM_THRESHOLD && self.cache.is_none() {
+            Body::Streamed(data)
+        } else {
+            Body::Encoded(pipeline.submit(data, compress_method != CompressMethod::Stored)?)
+        };
+        Ok(Pending::Written{
+            header: FileHeaderBuilder::new(new_entry.file_name.as_str(), compress_method, 0, 0, 0),
+            align: meta.align,
+            body,
+            change: EntryChange::Added,
+            renamed_from: None
+        })
+    }
+
+    // the worker threads of a write, config.threads resolved
+    fn threads(&self) -> usize {
+        match self.config.threads {
+            0 => std::thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1),
+            threads => threads
+        }
+    }
+
+    fn write<W: Write>(&self, origin_zip: Option<&dyn Origin>, writer: W, align: usize, planned: &mut Vec<PlannedEntry>) -> Result<usize, std::io::Error> {
+        let level = self.config.compression_level.min(9);
+        let deflate = |data: &[u8], out: &mut Vec<u8>| self.deflate(data, level, out);
+        with_pipeline(self.threads(), &deflate, |pipeline| self.write_entries(origin_zip, writer, align, planned, pipeline))
+    }
+
+    // Prepares the entries up to pipeline.window() ahead of the one being written, so their crc and
+    // deflate run while it's written. Transformers still see them one by one, in write order
+    fn write_entries<'a, W: Write>(&'a self, origin_zip: Option<&'a dyn Origin>, mut writer: W, align: usize, planned: &mut Vec<PlannedEntry>,
+                                   pipeline: &mut Pipeline<'a, '_>) -> Result<usize, std::io::Error> {
+        let mut central_directory_data: Vec<u8> = Vec::new();
+        let mut current_offset: usize = 0;
+        let mut file_count: usize = 0;
+        for transformer in &self.transformers {
+            transformer.start();
+        }
+
+        let editable_entries = match origin_zip {
+            Some(_) => self.editable_entries.as_slice(),
+            None => &[]
+        };
+        let mut append_entries: Vec<&AppendZipEntry> = self.append_entries.iter().collect();
+        if self.config.deterministic {
+            append_entries.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        }
+        let entry_count = editable_entries.len() + append_entries.len();
+        let mut queue: VecDeque<Pending> = VecDeque::new();
+        let mut next = 0;
+        loop {
+            while next < entry_count && queue.len() < pipeline.window() {
+                queue.push_back(match (editable_entries.get(next), origin_zip) {
+                    (Some(entry), Some(origin_zip)) => self.prepare(origin_zip, entry, align, pipeline)?,
+                    _ => self.prepare_added(append_entries[next - editable_entries.len()], align, pipeline)?
+                });
+                next += 1;
             }
+            let (mut header_build, entry_align, body, change, renamed_from) = match queue.pop_front() {
+                Some(Pending::Removed(entry)) => {
+                    planned.push(entry);
+                    continue;
+                },
+                Some(Pending::Written{header, align, body, change, renamed_from}) => (header, align, body, change, renamed_from),
+                None => break
+            };
 
-            let file_header = FileHeaderBuilder::new(
-                new_entry.file_name.as_str(),
-                new_entry.compress_method.clone(),
-                new_entry.data.len() as u32,
-                match &compress_data_opt {
-                    Some(data) => data.len(),
-                    None => new_entry.data.len()
-                } as u32,
-                crc32_hash
-            );
-
-            file_header.write_cd(&mut central_directory_data, current_offset as u32)?;
-            current_offset += file_header.write_lfh(&mut writer, current_offset, align)?;
-
-            if new_entry.compress_method == CompressMethod::Stored {
-                writer.write_all(new_entry.data.as_slice())?;
-                current_offset += new_entry.data.len();
-            } else {
-                writer.write_all(compress_data_opt.as_ref().unwrap().as_slice())?;
-                current_offset += compress_data_opt.unwrap().len();
+            file_count += 1;
+            let new_local_file_header_offset: u32 = zip32(current_offset, "entry offset")?;
+            match body {
+                Body::Encoded(ticket) => {
+                    let encoded = pipeline.take(ticket)?;
+                    header_build.crc32 = encoded.crc32;
+                    header_build.origin_size = zip32(encoded.data.len(), "entry size")?;
+                    let written: &[u8] = encoded.compressed.as_deref().unwrap_or(&encoded.data);
+                    if encoded.compressed.is_some() {
+                        trace_event!(name = header_build.file_name, size = encoded.data.len(), compressed = written.len(), "deflated");
+                    }
+                    header_build.set_compressed_size(zip32(written.len(), "compressed size")?);
+                    current_offset += header_build.write_lfh(&mut writer, current_offset, entry_align)?;
+                    writer.write_all(written)?;
+                    current_offset += written.len();
+                    if let Some(buffer) = encoded.compressed {
+                        pipeline.recycle(buffer);
+                    }
+                },
+                Body::Streamed(data) => {
+                    header_build.streamed = true;
+                    header_build.crc32 = crc32fast::hash(&data);
+                    header_build.origin_size = zip32(data.len(), "entry size")?;
+                    current_offset += header_build.write_lfh(&mut writer, current_offset, entry_align)?;
+                    let written = self.compressor.deflate_to(&data, self.config.compression_level.min(9), &mut writer)?;
+                    trace_event!(name = header_build.file_name, size = data.len(), compressed = written, "deflated");
+                    header_build.set_compressed_size(zip32(written as usize, "compressed size")?);
+                    current_offset += written as usize;
+                    current_offset += header_build.write_descriptor(&mut writer)?;
+                },
+                Body::Copied(entry, data_offset) => {
+                    current_offset += header_build.write_lfh(&mut writer, current_offset, entry_align)?;
+                    if let Some(origin_zip) = origin_zip {
+                        origin_zip.copy_data(entry, data_offset, &mut writer)?;
+                    }
+                    current_offset += entry.compressed_size as usize;
+                }
             }
+            header_build.write_cd(&mut central_directory_data, new_local_file_header_offset)?;
+            planned.push(PlannedEntry{
+                name: String::from(header_build.file_name),
+                change,
+                renamed_from,
+                compress_method: header_build.compress_method.clone(),
+                size: header_build.origin_size,
+                compressed_size: header_build.compress_size,
+                offset: Some(new_local_file_header_offset)
+            });
         }
 
-        let central_directory_offset = current_offset as u32;
+        let central_directory_offset: u32 = zip32(current_offset, "central directory offset")?;
+        let entries: u16 = zip32(file_count, "entry count")?;
         writer.write_all(central_directory_data.as_slice())?;
         writer.write_u32::<LittleEndian>(CENTRAL_DIRECTORY_END)?;
         writer.write_u16::<LittleEndian>(0)?;
         writer.write_u16::<LittleEndian>(0)?;
-        writer.write_u16::<LittleEndian>(file_count)?;
-        writer.write_u16::<LittleEndian>(file_count)?;
-        writer.write_u32::<LittleEndian>(central_directory_data.len() as u32)?;
+        writer.write_u16::<LittleEndian>(entries)?;
+        writer.write_u16::<LittleEndian>(entries)?;
+        writer.write_u32::<LittleEndian>(zip32(central_directory_data.len(), "central directory size")?)?;
         writer.write_u32::<LittleEndian>(central_directory_offset)?;
         writer.write_u16::<LittleEndian>(0)?;
-        Ok(())
+        record!("entries", file_count);
+        Ok(current_offset + central_directory_data.len() + 22)
     }
 }
@@ -0,0 +1,41 @@
+use crate::manifest::diff::ManifestChange;
+
+// A change made through ZipEditor or ApkFile, passed to the callbacks registered with on_event as it
+// happens. Sizes are uncompressed. Edits that fail (a missing entry) aren't reported
+#[derive(Debug)]
+pub enum EditEvent<'a> {
+    FileAdded { name: &'a str, size: usize },
+    FileEdited { name: &'a str, size: usize },
+    FileRenamed { from: &'a str, to: &'a str },
+    FileRemoved { name: &'a str },
+    // from set_manifest, after the FileEdited of AndroidManifest.xml. Empty when either side doesn't parse
+    ManifestChanged { changes: &'a [ManifestChange] },
+    // the edits reported since the matching begin are undone
+    RolledBack,
+    SaveStarted { align: usize },
+    SaveFinished { size: usize },
+    Signed { size: usize }
+}
+
+type Callback = Box<dyn Fn(&EditEvent) + Send + Sync>;
+
+#[derive(Default)]
+pub(crate) struct Hooks {
+    callbacks: Vec<Callback>
+}
+
+impl Hooks {
+    pub(crate) fn add(&mut self, callback: Callback) {
+        self.callbacks.push(callback);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+
+    pub(crate) fn emit(&self, event: EditEvent) {
+        for callback in &self.callbacks {
+            callback(&event);
+        }
+    }
+}
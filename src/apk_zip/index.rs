@@ -0,0 +1,171 @@
+use alloc::format;
+use alloc::vec::Vec;
+use crate::apk_zip::CENTRAL_DIRECTORY;
+use crate::apk_zip::zip::{next_entry_offset, parse_end, ZipEntry, ZipFile, ZipFormatError};
+#[cfg(feature = "zip")]
+use crate::apk_zip::zip::inflate;
+use crate::options::{ParseContext, ParseOptions, ParseWarning};
+use crate::utils::{read_leu16, read_leu32};
+
+// The names of the entries of a zip and where their central directory records are, nothing more: an
+// entry is parsed, its local header checked, when it's asked for. Reading the manifest out of an apk
+// with tens of thousands of entries costs one pass over the central directory, not a ZipEntry and a
+// map key each. ZipFile parses and checks everything up front
+pub struct ZipIndex<'a> {
+    data: &'a [u8],
+    records: Vec<(&'a str, usize)>, // name and central directory offset, in central directory order
+    names: NameIndex,
+    options: ParseOptions,
+    warnings: Vec<ParseWarning>
+}
+
+// Looks the entries of a zip up by name without a map key each: their positions, sorted by their
+// names. Two entries of one name are an error however lenient the parse: readers don't agree on which
+// one counts, the signature could be checked over one and the other installed
+pub(crate) struct NameIndex {
+    by_name: Vec<usize>
+}
+
+impl NameIndex {
+    // of `count` entries, `name` and `offset` telling their names and central directory offsets
+    pub(crate) fn new<'n, F, O>(count: usize, name: F, offset: O) -> Result<NameIndex, ZipFormatError>
+        where F: Fn(usize) -> &'n str, O: Fn(usize) -> usize {
+        // a stable sort keeps entries of the same name in central directory order, the error is at the
+        // first entry reusing a name
+        let mut by_name: Vec<usize> = (0..count).collect();
+        by_name.sort_by(|a, b| name(*a).cmp(name(*b)));
+        match by_name.windows(2).filter(|pair| name(pair[0]) == name(pair[1])).map(|pair| pair[1]).min() {
+            Some(index) => Err(ZipFormatError{
+                offset: offset(index),
+                reason: "duplicate entry name"
+            }),
+            None => Ok(NameIndex{
+                by_name
+            })
+        }
+    }
+
+    pub(crate) fn find<'n, F: Fn(usize) -> &'n str>(&self, key: &str, name: F) -> Option<usize> {
+        let position = self.by_name.binary_search_by(|index| name(*index).cmp(key)).ok()?;
+        Some(self.by_name[position])
+    }
+}
+
+impl<'a> ZipIndex<'a> {
+    pub fn from<T: AsRef<[u8]> + ?Sized>(data: &'a T) -> Result<ZipIndex<'a>, ZipFormatError> {
+        ZipIndex::from_with_options(data, ParseOptions::default())
+    }
+
+    pub fn from_with_options<T: AsRef<[u8]> + ?Sized>(data: &'a T, options: ParseOptions) -> Result<ZipIndex<'a>, ZipFormatError> {
+        let data = data.as_ref();
+        let mut context = ParseContext::new(options);
+        let (central_directory_offset, central_directory_end_offset, dir_count) = parse_end(data, 0, &mut context)?;
+        let mut records: Vec<(&'a str, usize)> = Vec::with_capacity(dir_count as usize);
+        let mut current_offset = central_directory_offset;
+        for _ in 0..dir_count {
+            let (name, size) = match ZipIndex::read_record(data, current_offset) {
+                Ok(record) => record,
+                Err(err) if context.recover(err.offset, &format!("skipped a central directory entry, {}", err.reason)) => {
+                    match next_entry_offset(data, current_offset, central_directory_end_offset) {
+                        Some(offset) => {
+                            current_offset = offset;
+                            continue;
+                        },
+                        None => break
+                    }
+                },
+                Err(err) => return Err(err)
+            };
+            records.push((name, current_offset));
+            current_offset += size;
+        }
+
+        let names = NameIndex::new(records.len(), |index| records[index].0, |index| records[index].1)?;
+        Ok(ZipIndex{
+            data,
+            records,
+            names,
+            options,
+            warnings: context.warnings
+        })
+    }
+
+    // the name of the record at `offset` and the record's size, the rest is left for entry()
+    fn read_record(data: &'a [u8], offset: usize) -> Result<(&'a str, usize), ZipFormatError> {
+        let out_of_file = || ZipFormatError{
+            offset,
+            reason: "central directory entry out of the file"
+        };
+        if read_leu32(data, offset).ok_or_else(out_of_file)? != CENTRAL_DIRECTORY {
+            return Err(ZipFormatError{
+                offset,
+                reason: "magic of central directory error"
+            });
+        }
+        let u16_at = |at: usize| read_leu16(data, offset + at).map(|value| value as usize).ok_or_else(out_of_file);
+        let file_name_len = u16_at(28)?;
+        let name = data.get(offset + 46..offset + 46 + file_name_len).ok_or_else(out_of_file)?;
+        let name = core::str::from_utf8(name).map_err(|_| ZipFormatError{
+            offset,
+            reason: "convert string fail"
+        })?;
+        Ok((name, 46 + file_name_len + u16_at(30)? + u16_at(32)?))
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // in central directory order
+    pub fn names(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.records.iter().map(|(name, _)| *name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        let index = self.names.find(name, |index| self.records[index].0)?;
+        Some(self.records[index].1)
+    }
+
+    // Parses the entry called `name`, none when there's no such entry. It's checked as ZipFile::from
+    // would, what a lenient parse lets through isn't recorded though, warnings() are the index's
+    pub fn entry(&self, name: &str) -> Result<Option<ZipEntry>, ZipFormatError> {
+        let offset = match self.find(name) {
+            Some(offset) => offset,
+            None => return Ok(None)
+        };
+        let mut context = ParseContext::new(self.options);
+        let entry = ZipFile::parse_entry(self.data, offset, &mut context)?;
+        entry.check_data(self.data, &mut context)?;
+        Ok(Some(entry))
+    }
+
+    // the stored (possibly compressed) bytes of `name`
+    pub fn get_compress_data(&self, name: &str) -> Result<Option<&'a [u8]>, ZipFormatError> {
+        Ok(self.entry(name)?.and_then(|entry| entry.data_in(self.data)))
+    }
+
+    // none when there's no such entry or it doesn't parse or inflate, as ZipFile::get_uncompress_data
+    #[cfg(feature = "zip")]
+    pub fn get_uncompress_data(&self, name: &str) -> Option<Vec<u8>> {
+        let entry = self.entry(name).ok()??;
+        inflate(entry.data_in(self.data)?, &entry)
+    }
+
+    // what the lenient indexing let through, the entries parsed later aren't looked at
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    // all of it, parsed and checked
+    pub fn to_zip_file(&self) -> Result<ZipFile<'a>, ZipFormatError> {
+        ZipFile::from_with_options(self.data, self.options)
+    }
+}
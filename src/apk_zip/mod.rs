@@ -1,10 +1,54 @@
-pub(in crate::apk_zip) mod zip;
-pub(in crate::apk_zip) mod editor;
+// zip.rs and index.rs parse with alloc alone (zip-headers), the rest reads entries and writes zips
+mod zip;
+mod index;
+#[cfg(feature = "zip")]
+mod editor;
+#[cfg(feature = "zip")]
+mod cache;
+#[cfg(feature = "zip")]
+mod compress;
+#[cfg(feature = "zip")]
+mod config;
+#[cfg(feature = "zip")]
+mod hooks;
+#[cfg(feature = "zip")]
+mod pipeline;
+#[cfg(feature = "zip")]
+mod save_plan;
+#[cfg(feature = "zip")]
+mod transform;
+#[cfg(feature = "zip")]
 mod wrap;
+#[cfg(feature = "tokio")]
+mod async_io;
+#[cfg(feature = "sign")]
+mod sign;
 
-pub use wrap::ApkFile;
+#[cfg(feature = "zip")]
+pub use cache::CompressionCache;
+#[cfg(feature = "zip")]
+pub use compress::{Compressor, Flate2Compressor, PassThroughCompressor};
+#[cfg(feature = "zip")]
+pub use config::EditorConfig;
+#[cfg(feature = "zip")]
+pub use editor::{ZipEditor, ZipError};
+#[cfg(feature = "zip")]
+pub use hooks::EditEvent;
+#[cfg(feature = "zip")]
+pub use save_plan::{EntryChange, PlannedEntry, SavePlan, SignatureImpact};
+#[cfg(feature = "zip")]
+pub use transform::{AutoAlign, EntryMeta, EntryTransformer, PngCrunch};
+#[cfg(feature = "sign")]
+pub use transform::DigestCollector;
+#[cfg(feature = "zip")]
+pub use wrap::{ApkFile, FileInfo};
+pub use zip::{ZipEntry, ZipFile, ZipFormatError};
+pub use index::ZipIndex;
+#[cfg(feature = "sign")]
+pub use sign::{sign_v2, verify_v2, SignError, SigningKey};
 
 #[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum CompressMethod {
     Stored = 0,
     Deflated = 8
@@ -37,3 +81,8 @@ impl CompressMethod {
 const LOCAL_FILE_HEADER: u32 = 0x4034b50;
 const CENTRAL_DIRECTORY_END: u32 = 0x6054b50;
 const CENTRAL_DIRECTORY: u32 = 0x2014b50;
+// after the data of an entry flagged with it, its crc and sizes
+#[cfg(feature = "zip")]
+const DATA_DESCRIPTOR: u32 = 0x08074b50;
+// ends the APK signing block, right before the central directory
+const SIGNING_BLOCK_MAGIC: &[u8; 16] = b"APK Sig Block 42";
@@ -0,0 +1,154 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::Mutex;
+
+type Deflate<'f> = dyn Fn(&[u8], &mut Vec<u8>) -> std::io::Result<()> + Sync + 'f;
+
+// an entry's content with its crc, and deflated unless it's written stored
+pub(crate) struct Encoded<'a> {
+    pub(crate) data: Cow<'a, [u8]>,
+    pub(crate) crc32: u32,
+    pub(crate) compressed: Option<Vec<u8>>
+}
+
+struct Job<'a> {
+    ticket: usize,
+    data: Cow<'a, [u8]>,
+    deflate: bool
+}
+
+// Crc and deflate of the entries ZipEditor::write encodes, on worker threads while the caller writes
+// the entries before them. Results are taken by ticket, so the output is the same whatever order the
+// workers finish in. Without workers (one thread, or none to be had as on wasm32) an entry is encoded
+// when it's submitted
+pub(crate) struct Pipeline<'a, 'f> {
+    deflate: &'f Deflate<'f>,
+    jobs: Option<SyncSender<Job<'a>>>,
+    results: Option<Receiver<(usize, std::io::Result<Encoded<'a>>)>>,
+    ready: BTreeMap<usize, std::io::Result<Encoded<'a>>>,
+    buffers: &'f Mutex<Vec<Vec<u8>>>, // deflate outputs written out, for the next entries to fill
+    workers: usize,
+    submitted: usize
+}
+
+fn encode<'a>(data: Cow<'a, [u8]>, deflate: bool, deflate_fn: &Deflate, buffers: &Mutex<Vec<Vec<u8>>>) -> std::io::Result<Encoded<'a>> {
+    let crc32 = crc32fast::hash(&data);
+    let compressed = if deflate {
+        let mut out = buffers.lock().ok().and_then(|mut buffers| buffers.pop()).unwrap_or_default();
+        deflate_fn(&data, &mut out)?;
+        Some(out)
+    } else {
+        None
+    };
+    Ok(Encoded{
+        data,
+        crc32,
+        compressed
+    })
+}
+
+fn work<'a>(jobs: &Mutex<Receiver<Job<'a>>>, results: Sender<(usize, std::io::Result<Encoded<'a>>)>, deflate: &Deflate, buffers: &Mutex<Vec<Vec<u8>>>) {
+    loop {
+        // the lock is only held while waiting, the sender hanging up ends every worker
+        let job = match jobs.lock().map(|jobs| jobs.recv()) {
+            Ok(Ok(job)) => job,
+            _ => break
+        };
+        // a panicking compressor would leave the writer waiting for its ticket forever
+        let res = catch_unwind(AssertUnwindSafe(|| encode(job.data, job.deflate, deflate, buffers)))
+            .unwrap_or_else(|_| Err(std::io::Error::other("the compressor panicked")));
+        if results.send((job.ticket, res)).is_err() {
+            break;
+        }
+    }
+}
+
+// runs `f` with a pipeline of `threads` workers, which are joined before it returns
+pub(crate) fn with_pipeline<'a, T, F>(threads: usize, deflate: &Deflate, f: F) -> T
+    where F: for<'f> FnOnce(&mut Pipeline<'a, 'f>) -> T {
+    let buffers: Mutex<Vec<Vec<u8>>> = Mutex::new(vec![]);
+    let mut pipeline = Pipeline{
+        deflate,
+        jobs: None,
+        results: None,
+        ready: BTreeMap::new(),
+        buffers: &buffers,
+        workers: 0,
+        submitted: 0
+    };
+    if threads <= 1 {
+        return f(&mut pipeline);
+    }
+    // room for a job per worker waiting behind the ones being encoded
+    let (job_sender, job_receiver) = sync_channel::<Job<'a>>(threads);
+    let (result_sender, result_receiver) = channel();
+    let job_receiver = Mutex::new(job_receiver);
+    std::thread::scope(|scope| {
+        let job_receiver = &job_receiver;
+        let buffers = &buffers;
+        for _ in 0..threads {
+            let result_sender = result_sender.clone();
+            let spawned = std::thread::Builder::new().name(String::from("apk-editor-deflate"))
+                .spawn_scoped(scope, move || work(job_receiver, result_sender, deflate, buffers));
+            if spawned.is_err() {
+                break;
+            }
+            pipeline.workers += 1;
+        }
+        drop(result_sender);
+        if pipeline.workers > 0 {
+            pipeline.jobs = Some(job_sender);
+            pipeline.results = Some(result_receiver);
+        }
+        let res = f(&mut pipeline);
+        // hangs up on the workers, the scope joins them
+        pipeline.jobs = None;
+        res
+    })
+}
+
+impl<'a> Pipeline<'a, '_> {
+    // how many entries the writer prepares ahead of the one it writes
+    pub(crate) fn window(&self) -> usize {
+        (2 * self.workers).max(1)
+    }
+
+    // queues `data` for its crc, and deflate, the ticket takes the result
+    pub(crate) fn submit(&mut self, data: Cow<'a, [u8]>, deflate: bool) -> std::io::Result<usize> {
+        let ticket = self.submitted;
+        self.submitted += 1;
+        match &self.jobs {
+            Some(jobs) => jobs.send(Job{
+                ticket,
+                data,
+                deflate
+            }).map_err(|_| std::io::Error::other("the compression workers stopped"))?,
+            None => {
+                let res = encode(data, deflate, self.deflate, self.buffers);
+                self.ready.insert(ticket, res);
+            }
+        }
+        Ok(ticket)
+    }
+
+    // waits for the entry of `ticket`
+    pub(crate) fn take(&mut self, ticket: usize) -> std::io::Result<Encoded<'a>> {
+        loop {
+            if let Some(res) = self.ready.remove(&ticket) {
+                return res;
+            }
+            let results = self.results.as_ref().ok_or_else(|| std::io::Error::other("entry never submitted"))?;
+            let (done, res) = results.recv().map_err(|_| std::io::Error::other("the compression workers stopped"))?;
+            self.ready.insert(done, res);
+        }
+    }
+
+    // a deflate output that was written, its allocation goes to a later entry
+    pub(crate) fn recycle(&self, buffer: Vec<u8>) {
+        if let Ok(mut buffers) = self.buffers.lock() {
+            buffers.push(buffer);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt::{Display, Formatter};
+use crate::apk_zip::CompressMethod;
+use crate::manifest::diff::ManifestChange;
+
+// what save() would write, from ApkFile::plan
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SavePlan {
+    pub entries: Vec<PlannedEntry>, // in the order they'd be written, removed ones where they were
+    pub manifest_changes: Vec<ManifestChange>,
+    pub signature: SignatureImpact,
+    pub size: usize // of the whole apk
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PlannedEntry {
+    pub name: String,
+    pub change: EntryChange,
+    pub renamed_from: Option<String>,
+    pub compress_method: CompressMethod,
+    pub size: u32,
+    pub compressed_size: u32,
+    pub offset: Option<u32> // of the local header, none for a removed entry
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum EntryChange {
+    Kept, // copied as is, a renamed entry too
+    Edited,
+    Removed,
+    Added
+}
+
+// save() never writes a signature, so what the apk as read had is lost or left stale
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SignatureImpact {
+    pub v2_removed: bool, // the signing block isn't carried over, save_signed signs again
+    pub v1_stale: bool // META-INF signature files are kept but entries they cover changed
+}
+
+impl SavePlan {
+    pub fn has_changes(&self) -> bool {
+        self.entries.iter().any(|entry| entry.change != EntryChange::Kept || entry.renamed_from.is_some())
+    }
+}
+
+impl Display for SavePlan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for entry in &self.entries {
+            let mark = match entry.change {
+                EntryChange::Kept if entry.renamed_from.is_none() => continue,
+                EntryChange::Kept | EntryChange::Edited => "~",
+                EntryChange::Removed => "-",
+                EntryChange::Added => "+"
+            };
+            write!(f, "{} {}", mark, entry.name)?;
+            if let Some(from) = &entry.renamed_from {
+                write!(f, " (from {})", from)?;
+            }
+            match entry.offset {
+                Some(offset) => writeln!(f, ", {} bytes ({} in the zip) at {}", entry.size, entry.compressed_size, offset)?,
+                None => writeln!(f)?
+            }
+        }
+        for change in &self.manifest_changes {
+            writeln!(f, "manifest {}", change)?;
+        }
+        if self.signature.v2_removed {
+            writeln!(f, "v2 signature removed")?;
+        }
+        if self.signature.v1_stale {
+            writeln!(f, "v1 signature no longer matches")?;
+        }
+        write!(f, "{} bytes", self.size)
+    }
+}
+
+pub(in crate::apk_zip) fn is_v1_signature_file(name: &str) -> bool {
+    name.strip_prefix("META-INF/").is_some_and(|name| {
+        !name.contains('/') && [".SF", ".RSA", ".DSA", ".EC"].iter().any(|suffix| name.ends_with(suffix))
+    })
+}
@@ -0,0 +1,314 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::{der, DecodePrivateKey, DecodePublicKey, EncodePublicKey};
+use rsa::{Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256};
+use crate::apk_zip::{CENTRAL_DIRECTORY_END, SIGNING_BLOCK_MAGIC};
+
+const V2_BLOCK_ID: u32 = 0x7109871a;
+const RSA_PKCS1_V1_5_WITH_SHA256: u32 = 0x0103;
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct SignError {
+    reason: String
+}
+
+impl SignError {
+    fn new(reason: String) -> SignError {
+        SignError{
+            reason
+        }
+    }
+}
+
+impl Display for SignError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sign error: {}", self.reason)
+    }
+}
+
+impl Error for SignError {}
+
+// an RSA key and the X.509 certificate for it
+pub struct SigningKey {
+    key: RsaPrivateKey,
+    public_key: Vec<u8>, // SubjectPublicKeyInfo
+    certificate: Vec<u8>
+}
+
+fn is_pem(data: &[u8]) -> bool {
+    data.trim_ascii_start().starts_with(b"-----BEGIN")
+}
+
+impl SigningKey {
+    // `key` is PKCS#8 or PKCS#1, `certificate` X.509, both either DER or PEM
+    pub fn from<K: AsRef<[u8]> + ?Sized, C: AsRef<[u8]> + ?Sized>(key: &K, certificate: &C) -> Result<SigningKey, SignError> {
+        SigningKey::read(key.as_ref(), certificate.as_ref())
+    }
+
+    fn read(key: &[u8], certificate: &[u8]) -> Result<SigningKey, SignError> {
+        let key = if is_pem(key) {
+            let pem = std::str::from_utf8(key).map_err(|_| SignError::new(String::from("key is not valid PEM")))?;
+            RsaPrivateKey::from_pkcs8_pem(pem).or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem))
+        } else {
+            RsaPrivateKey::from_pkcs8_der(key).or_else(|_| RsaPrivateKey::from_pkcs1_der(key))
+        }.map_err(|err| SignError::new(format!("can't read the RSA key, {}", err)))?;
+        let certificate = if is_pem(certificate) {
+            der::pem::decode_vec(certificate).map_err(|err| SignError::new(format!("can't read the certificate, {}", err)))?.1
+        } else {
+            Vec::from(certificate)
+        };
+        let public_key = RsaPublicKey::from(&key).to_public_key_der()
+            .map_err(|err| SignError::new(format!("can't encode the public key, {}", err)))?
+            .into_vec();
+        // a certificate embeds the SubjectPublicKeyInfo as is
+        if !contains(&certificate, &public_key) {
+            return Err(SignError::new(String::from("the certificate is not for this key")));
+        }
+        Ok(SigningKey{
+            key,
+            public_key,
+            certificate
+        })
+    }
+}
+
+fn contains(data: &[u8], part: &[u8]) -> bool {
+    data.windows(part.len()).any(|window| window == part)
+}
+
+fn push_prefixed(data: &mut Vec<u8>, value: &[u8]) {
+    data.extend((value.len() as u32).to_le_bytes());
+    data.extend(value);
+}
+
+// the length prefixed sequences the signing block is made of
+struct BlockReader<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> BlockReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SignError> {
+        if len > self.data.len() {
+            return Err(SignError::new(String::from("signing block runs past its end")));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, SignError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn prefixed(&mut self) -> Result<BlockReader<'a>, SignError> {
+        let len = self.u32()? as usize;
+        Ok(BlockReader{
+            data: self.bytes(len)?
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+// offsets of the signing block (where the entries end), the central directory and its end record
+struct ZipLayout {
+    entries_end: usize,
+    central_directory: usize,
+    central_directory_end: usize
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn zip_layout(apk: &[u8]) -> Result<ZipLayout, SignError> {
+    if apk.len() < 22 {
+        return Err(SignError::new(String::from("too short for a zip")));
+    }
+    let central_directory_end = (0..=apk.len() - 22).rev().take(65536)
+        .find(|offset| u32_at(apk, *offset) == CENTRAL_DIRECTORY_END && offset + 22 + u16::from_le_bytes([apk[offset + 20], apk[offset + 21]]) as usize == apk.len())
+        .ok_or_else(|| SignError::new(String::from("central directory end not found")))?;
+    let central_directory = u32_at(apk, central_directory_end + 16) as usize;
+    if central_directory + u32_at(apk, central_directory_end + 12) as usize != central_directory_end {
+        return Err(SignError::new(String::from("central directory doesn't end where its end record starts")));
+    }
+    let mut entries_end = central_directory;
+    if central_directory >= 32 && &apk[central_directory - 16..central_directory] == SIGNING_BLOCK_MAGIC {
+        // usize is 32 bit on wasm32, a corrupt size must not overflow it. The size covers at least
+        // itself and the magic at the end
+        let size = u64_at(apk, central_directory - 24);
+        entries_end = usize::try_from(size).ok()
+            .filter(|size| *size >= 24)
+            .and_then(|size| size.checked_add(8))
+            .and_then(|len| central_directory.checked_sub(len))
+            .filter(|start| u64_at(apk, *start) == size)
+            .ok_or_else(|| SignError::new(String::from("malformed APK Signing Block")))?;
+    }
+    Ok(ZipLayout{
+        entries_end,
+        central_directory,
+        central_directory_end
+    })
+}
+
+// chunked SHA-256 over the entries, the central directory and its end record, the latter pointing
+// at the signing block as the central directory offset
+fn content_digest(apk: &[u8], layout: &ZipLayout) -> Vec<u8> {
+    let mut central_directory_end = Vec::from(&apk[layout.central_directory_end..]);
+    central_directory_end[16..20].copy_from_slice(&(layout.entries_end as u32).to_le_bytes());
+    let sections = [&apk[..layout.entries_end], &apk[layout.central_directory..layout.central_directory_end], &central_directory_end];
+    let mut digests = vec![];
+    let mut count: u32 = 0;
+    for chunk in sections.iter().flat_map(|section| section.chunks(CHUNK_SIZE)) {
+        digests.extend(Sha256::new().chain_update([0xa5]).chain_update((chunk.len() as u32).to_le_bytes()).chain_update(chunk).finalize());
+        count += 1;
+    }
+    Sha256::new().chain_update([0x5a]).chain_update(count.to_le_bytes()).chain_update(&digests).finalize().to_vec()
+}
+
+// `apk` signed with APK Signature Scheme v2 (RSASSA-PKCS1-v1_5, SHA-256), replacing any signing
+// block it had. Entries stay where they are, so it goes after the last edit and alignment. There is
+// no v1 (JAR) signature, which devices before API 24 need
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all,
+    fields(bytes = apk.len(), block = tracing::field::Empty)))]
+pub fn sign_v2(apk: &[u8], key: &SigningKey) -> Result<Vec<u8>, SignError> {
+    let mut layout = zip_layout(apk)?;
+    let entries = &apk[..layout.entries_end];
+    let central_directory = &apk[layout.central_directory..layout.central_directory_end];
+    let mut unsigned = Vec::with_capacity(apk.len());
+    unsigned.extend(entries);
+    unsigned.extend(central_directory);
+    unsigned.extend(&apk[layout.central_directory_end..]);
+    layout = ZipLayout{
+        entries_end: entries.len(),
+        central_directory: entries.len(),
+        central_directory_end: entries.len() + central_directory.len()
+    };
+
+    let mut digest = RSA_PKCS1_V1_5_WITH_SHA256.to_le_bytes().to_vec();
+    push_prefixed(&mut digest, &content_digest(&unsigned, &layout));
+    let mut digests = vec![];
+    push_prefixed(&mut digests, &digest);
+    let mut certificates = vec![];
+    push_prefixed(&mut certificates, &key.certificate);
+    let mut signed_data = vec![];
+    push_prefixed(&mut signed_data, &digests);
+    push_prefixed(&mut signed_data, &certificates);
+    push_prefixed(&mut signed_data, &[]); // no additional attributes
+
+    let signature_bytes = key.key.sign(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(&signed_data))
+        .map_err(|err| SignError::new(format!("signing failed, {}", err)))?;
+    let mut signature = RSA_PKCS1_V1_5_WITH_SHA256.to_le_bytes().to_vec();
+    push_prefixed(&mut signature, &signature_bytes);
+    let mut signatures = vec![];
+    push_prefixed(&mut signatures, &signature);
+    let mut signer = vec![];
+    push_prefixed(&mut signer, &signed_data);
+    push_prefixed(&mut signer, &signatures);
+    push_prefixed(&mut signer, &key.public_key);
+    let mut signers = vec![];
+    push_prefixed(&mut signers, &signer);
+    let mut value = vec![];
+    push_prefixed(&mut value, &signers);
+
+    // size counts everything but the leading size field
+    let size = (8 + 4 + value.len() + 8 + SIGNING_BLOCK_MAGIC.len()) as u64;
+    let mut block = Vec::from(size.to_le_bytes());
+    block.extend(((4 + value.len()) as u64).to_le_bytes());
+    block.extend(V2_BLOCK_ID.to_le_bytes());
+    block.extend(value);
+    block.extend(size.to_le_bytes());
+    block.extend(SIGNING_BLOCK_MAGIC);
+    record!("block", block.len());
+
+    let mut signed = Vec::with_capacity(unsigned.len() + block.len());
+    signed.extend(entries);
+    signed.extend(&block);
+    signed.extend(central_directory);
+    let central_directory_end = signed.len();
+    signed.extend(&unsigned[layout.central_directory_end..]);
+    signed[central_directory_end + 16..central_directory_end + 20].copy_from_slice(&((entries.len() + block.len()) as u32).to_le_bytes());
+    Ok(signed)
+}
+
+// checks the v2 signature of `apk`, returns the certificate (DER) of every signer. Only
+// RSASSA-PKCS1-v1_5 with SHA-256 signatures are understood
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(bytes = apk.len())))]
+pub fn verify_v2(apk: &[u8]) -> Result<Vec<Vec<u8>>, SignError> {
+    let layout = zip_layout(apk)?;
+    if layout.entries_end == layout.central_directory {
+        return Err(SignError::new(String::from("no APK Signing Block")));
+    }
+    let mut pairs = BlockReader{
+        data: &apk[layout.entries_end + 8..layout.central_directory - 24]
+    };
+    let mut value = None;
+    while !pairs.is_empty() {
+        let len = u64::from_le_bytes(pairs.bytes(8)?.try_into().unwrap()) as usize;
+        let mut pair = BlockReader{
+            data: pairs.bytes(len)?
+        };
+        if pair.u32()? == V2_BLOCK_ID {
+            value = Some(pair);
+        }
+    }
+    let mut signers = value.ok_or_else(|| SignError::new(String::from("no v2 signature in the signing block")))?.prefixed()?;
+    let digest = content_digest(apk, &layout);
+    let mut certificates = vec![];
+    while !signers.is_empty() {
+        let mut signer = signers.prefixed()?;
+        let signed_data = signer.prefixed()?.data;
+        let mut signatures = signer.prefixed()?;
+        let public_key = signer.prefixed()?.data;
+
+        let mut signature = None;
+        while !signatures.is_empty() {
+            let mut item = signatures.prefixed()?;
+            if item.u32()? == RSA_PKCS1_V1_5_WITH_SHA256 {
+                signature = Some(item.prefixed()?.data);
+            }
+        }
+        let signature = signature.ok_or_else(|| SignError::new(String::from("signer has no RSA SHA-256 signature")))?;
+        RsaPublicKey::from_public_key_der(public_key)
+            .map_err(|err| SignError::new(format!("can't read the signer's public key, {}", err)))?
+            .verify(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(signed_data), signature)
+            .map_err(|_| SignError::new(String::from("signature doesn't match the signed data")))?;
+
+        let mut signed = BlockReader{
+            data: signed_data
+        };
+        let mut digests = signed.prefixed()?;
+        let mut signed_certificates = signed.prefixed()?;
+        let mut found = false;
+        while !digests.is_empty() {
+            let mut item = digests.prefixed()?;
+            if item.u32()? == RSA_PKCS1_V1_5_WITH_SHA256 {
+                if item.prefixed()?.data != digest.as_slice() {
+                    return Err(SignError::new(String::from("the apk was changed after signing")));
+                }
+                found = true;
+            }
+        }
+        if !found {
+            return Err(SignError::new(String::from("signed data has no SHA-256 content digest")));
+        }
+        let certificate = signed_certificates.prefixed()?.data;
+        if !contains(certificate, public_key) {
+            return Err(SignError::new(String::from("the public key is not the certificate's")));
+        }
+        certificates.push(Vec::from(certificate));
+    }
+    if certificates.is_empty() {
+        return Err(SignError::new(String::from("v2 signature has no signers")));
+    }
+    Ok(certificates)
+}
@@ -0,0 +1,159 @@
+use std::sync::Arc;
+#[cfg(feature = "sign")]
+use std::sync::Mutex;
+use crate::apk_zip::{CompressMethod, EntryChange};
+
+// an entry on its way into the zip, what a transformer sees and may change of it
+pub struct EntryMeta<'a> {
+    name: &'a str,
+    change: EntryChange,
+    pub compress_method: CompressMethod,
+    pub align: usize // of the data of a stored entry
+}
+
+// A stage of ZipEditor::finish, run over every entry in write order, in the order the transformers
+// were added. plan() runs them too, so a transformer can see the same entries more than once
+pub trait EntryTransformer: Send + Sync {
+    // before the first entry of a write
+    fn start(&self) {}
+
+    // whether transform wants the content of `name`. An entry nothing wants and nobody edited is
+    // copied from the apk as read, without inflating it
+    fn reads(&self, _name: &str) -> bool {
+        true
+    }
+
+    // `data` is uncompressed, none when reads said no and the entry wasn't edited. Returns the new
+    // content, or none to keep it
+    fn transform(&self, entry: &mut EntryMeta, data: Option<&[u8]>) -> Option<Vec<u8>>;
+}
+
+impl<T: EntryTransformer + ?Sized> EntryTransformer for Arc<T> {
+    fn start(&self) {
+        (**self).start()
+    }
+
+    fn reads(&self, name: &str) -> bool {
+        (**self).reads(name)
+    }
+
+    fn transform(&self, entry: &mut EntryMeta, data: Option<&[u8]>) -> Option<Vec<u8>> {
+        (**self).transform(entry, data)
+    }
+}
+
+impl<'a> EntryMeta<'a> {
+    pub(crate) fn new(name: &'a str, change: EntryChange, compress_method: CompressMethod, align: usize) -> EntryMeta<'a> {
+        EntryMeta{
+            name,
+            change,
+            compress_method,
+            align
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    // Kept for an entry copied from the apk as read, a renamed one too
+    pub fn change(&self) -> &EntryChange {
+        &self.change
+    }
+}
+
+// Stores native libraries page aligned so they load straight from the apk (extractNativeLibs=false),
+// and resources.arsc stored, which targeting R requires
+pub struct AutoAlign {
+    page_size: usize
+}
+
+impl AutoAlign {
+    // 4096, 16384 for devices with 16k pages
+    pub fn new(page_size: usize) -> AutoAlign {
+        AutoAlign{ page_size }
+    }
+}
+
+impl Default for AutoAlign {
+    fn default() -> Self {
+        AutoAlign::new(4096)
+    }
+}
+
+impl EntryTransformer for AutoAlign {
+    fn reads(&self, _name: &str) -> bool {
+        false
+    }
+
+    fn transform(&self, entry: &mut EntryMeta, _data: Option<&[u8]>) -> Option<Vec<u8>> {
+        if entry.name.starts_with("lib/") && entry.name.ends_with(".so") {
+            entry.compress_method = CompressMethod::Stored;
+            entry.align = self.page_size;
+        } else if entry.name == "resources.arsc" {
+            entry.compress_method = CompressMethod::Stored;
+        }
+        None
+    }
+}
+
+// hands the .png entries to `crunch` (an optimizer of the caller's choice) and keeps what it returns
+// when it's smaller. Compiled 9-patches are left alone, their chunks must survive
+pub struct PngCrunch<F: Fn(&str, &[u8]) -> Option<Vec<u8>> + Send + Sync> {
+    crunch: F
+}
+
+impl<F: Fn(&str, &[u8]) -> Option<Vec<u8>> + Send + Sync> PngCrunch<F> {
+    pub fn new(crunch: F) -> PngCrunch<F> {
+        PngCrunch{ crunch }
+    }
+}
+
+impl<F: Fn(&str, &[u8]) -> Option<Vec<u8>> + Send + Sync> EntryTransformer for PngCrunch<F> {
+    fn reads(&self, name: &str) -> bool {
+        name.ends_with(".png") && !name.ends_with(".9.png")
+    }
+
+    fn transform(&self, entry: &mut EntryMeta, data: Option<&[u8]>) -> Option<Vec<u8>> {
+        let data = data.filter(|_| self.reads(entry.name))?;
+        (self.crunch)(entry.name, data).filter(|crunched| crunched.len() < data.len())
+    }
+}
+
+// SHA-256 of the content of every written entry, as transformed by the stages before it: the
+// digests a v1 MANIFEST.MF lists. Added through an Arc to read them after the save
+#[cfg(feature = "sign")]
+#[derive(Default)]
+pub struct DigestCollector {
+    digests: Mutex<Vec<(String, [u8; 32])>>
+}
+
+#[cfg(feature = "sign")]
+impl DigestCollector {
+    pub fn new() -> DigestCollector {
+        DigestCollector::default()
+    }
+
+    // of the last write, in write order
+    pub fn digests(&self) -> Vec<(String, [u8; 32])> {
+        self.digests.lock().map(|digests| digests.clone()).unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "sign")]
+impl EntryTransformer for DigestCollector {
+    fn start(&self) {
+        if let Ok(mut digests) = self.digests.lock() {
+            digests.clear();
+        }
+    }
+
+    fn transform(&self, entry: &mut EntryMeta, data: Option<&[u8]>) -> Option<Vec<u8>> {
+        use sha2::{Digest, Sha256};
+        let digest: [u8; 32] = Sha256::digest(data?).into();
+        if let Ok(mut digests) = self.digests.lock() {
+            digests.push((String::from(entry.name), digest));
+        }
+        None
+    }
+}
@@ -1,50 +1,766 @@
-use std::error::Error;
-use std::io::{Read, Write};
-use crate::apk_zip::zip::{ZipFile, ZipFormatError};
-use crate::apk_zip::editor::ZipEditor;
-use crate::apk_zip::CompressMethod;
+use std::collections::HashSet;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use crate::apk_zip::zip::ZipFile;
+use crate::apk_zip::editor::{self, ZipEditor};
+use crate::apk_zip::{CompressMethod, CompressionCache, Compressor, EditEvent, EditorConfig, EntryTransformer};
+use crate::apk_zip::save_plan::{is_v1_signature_file, EntryChange, SavePlan, SignatureImpact};
+#[cfg(feature = "sign")]
+use crate::apk_zip::sign::{sign_v2, SigningKey};
+use crate::dex::{self, ConstString, DexError, DexFile, DexHeader, ProguardMapping, RefCounts, ScanMatch, StripReport};
+use crate::manifest::typed_value::TypedValue;
+use crate::manifest::axml::AndroidXml;
+use crate::manifest::chunks::{self, ChunkVisitor};
+use crate::manifest::manifest_editor::AndroidManifest;
+use crate::manifest::components::ComponentKind;
+use crate::manifest::validator::{Severity, ValidationIssue};
+use crate::manifest::attrs::{ICON, LABEL};
+use crate::resources::nine_patch;
+use crate::resources::remap::{remap_id, remap_value};
+use crate::resources::{ResourceConfig, ResourceError, ResourceTable, ShrinkReport, UsageReport};
+use crate::options::{ParseOptions, ParseWarning};
+use crate::{Error, Result};
+
+// 1 for classes.dex, N for classesN.dex
+fn dex_index(name: &str) -> Option<usize> {
+    match name.strip_prefix("classes")?.strip_suffix(".dex")? {
+        "" => Some(1),
+        number => number.parse().ok().filter(|index| *index > 1)
+    }
+}
+
+// there is no classes1.dex
+fn dex_name(index: usize) -> String {
+    match index {
+        1 => String::from("classes.dex"),
+        index => format!("classes{}.dex", index)
+    }
+}
+
+fn no_transaction() -> Error {
+    editor::no_transaction().into()
+}
+
+// an entry of the apk as read, edits don't show up here
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FileInfo {
+    pub name: String,
+    pub compress_method: CompressMethod,
+    pub size: u32,
+    pub compressed_size: u32,
+    pub crc32: u32
+}
 
 pub struct ApkFile<'a> {
-    data: &'a Vec<u8>,
     zip: ZipFile<'a>,
     editor: ZipEditor,
-    dex_count: usize
+    dex_count: usize,
+    mapping: Option<ProguardMapping>,
+    checkpoints: Vec<(usize, Option<ProguardMapping>)>, // what the editor's transactions don't cover
+    options: ParseOptions // for the manifest, resources and xml entries read later too, a copy of the editor's
 }
 
 impl<'a> ApkFile<'a> {
 
-    pub fn from(data: &'a Vec<u8>) -> Result<ApkFile<'a>, ZipFormatError> {
-        let zip = ZipFile::from(data)?;
-        let editor = ZipEditor::from(&zip);
+    // borrows anything that derefs to bytes: a slice, Vec, Bytes or an mmap
+    pub fn from<T: AsRef<[u8]> + ?Sized>(data: &'a T) -> Result<ApkFile<'a>> {
+        ApkFile::from_with_config(data, EditorConfig::default())
+    }
+
+    pub fn from_with_options<T: AsRef<[u8]> + ?Sized>(data: &'a T, options: ParseOptions) -> Result<ApkFile<'a>> {
+        ApkFile::from_with_config(data, EditorConfig{
+            options,
+            ..EditorConfig::default()
+        })
+    }
+
+    pub fn from_with_config<T: AsRef<[u8]> + ?Sized>(data: &'a T, config: EditorConfig) -> Result<ApkFile<'a>> {
+        ApkFile::with_zip(ZipFile::from_with_options(data, config.options)?, config)
+    }
+
+    // takes the buffer, so the ApkFile can be stored and outlive whatever it was read from
+    pub fn from_vec(data: Vec<u8>) -> Result<ApkFile<'static>> {
+        ApkFile::from_vec_with_config(data, EditorConfig::default())
+    }
+
+    pub fn from_vec_with_options(data: Vec<u8>, options: ParseOptions) -> Result<ApkFile<'static>> {
+        ApkFile::from_vec_with_config(data, EditorConfig{
+            options,
+            ..EditorConfig::default()
+        })
+    }
+
+    pub fn from_vec_with_config(data: Vec<u8>, config: EditorConfig) -> Result<ApkFile<'static>> {
+        ApkFile::with_zip(ZipFile::from_vec_with_options(data, config.options)?, config)
+    }
+
+    // reads `reader` to the end, the zip is parsed from memory
+    pub fn from_reader<R: Read>(reader: R) -> Result<ApkFile<'static>> {
+        ApkFile::from_reader_with_config(reader, EditorConfig::default())
+    }
+
+    pub fn from_reader_with_config<R: Read>(mut reader: R, config: EditorConfig) -> Result<ApkFile<'static>> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        ApkFile::from_vec_with_config(data, config)
+    }
+
+    // reads from the start whatever the position of `reader`, with the buffer sized up front
+    pub fn from_seekable<R: Read + Seek>(reader: R) -> Result<ApkFile<'static>> {
+        ApkFile::from_seekable_with_config(reader, EditorConfig::default())
+    }
+
+    pub fn from_seekable_with_config<R: Read + Seek>(mut reader: R, config: EditorConfig) -> Result<ApkFile<'static>> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut data = Vec::with_capacity(usize::try_from(len).unwrap_or_default());
+        reader.read_to_end(&mut data)?;
+        ApkFile::from_vec_with_config(data, config)
+    }
+
+    fn with_zip(zip: ZipFile<'a>, config: EditorConfig) -> Result<ApkFile<'a>> {
+        zip.check_limits(&config)?;
+        let options = config.options;
+        let editor = ZipEditor::with_config(&zip, config);
         let mut dex_count = 0;
-        for (name, index) in &zip.file_name_map {
+        for name in zip.names() {
             if name.starts_with("classes") && name.ends_with(".dex") {
                 dex_count += 1;
             }
         }
         Ok(ApkFile {
-            data,
             zip,
             editor,
-            dex_count
+            dex_count,
+            mapping: None,
+            checkpoints: vec![],
+            options
         })
     }
 
+    pub fn config(&self) -> &EditorConfig {
+        self.editor.config()
+    }
+
+    // What the lenient parse of the zip let through, then the same for the manifest and resources.arsc,
+    // with their offsets into the entry, and deflated entries whose crc doesn't match (which inflates
+    // all of them, strict parses included). Edits aren't looked at
+    pub fn warnings(&self) -> Vec<ParseWarning> {
+        let mut res = Vec::from(self.zip.warnings());
+        for entry in self.zip.entries().iter().filter(|entry| entry.compress_method == CompressMethod::Deflated) {
+            let message = match self.zip.get_uncompress_data(&entry.file_name) {
+                Some(data) if crc32fast::hash(&data) == entry.crc_32 => continue,
+                Some(_) => format!("crc of {} doesn't match its data", entry.file_name),
+                None => format!("{} doesn't inflate", entry.file_name)
+            };
+            res.push(ParseWarning{
+                offset: entry.local_file_header_offset as usize,
+                message
+            });
+        }
+        let entry_warnings = |name: &str, warnings: std::result::Result<Vec<ParseWarning>, String>| match warnings {
+            Ok(warnings) => warnings.into_iter().map(|warning| ParseWarning{
+                offset: warning.offset,
+                message: format!("{}: {}", name, warning.message)
+            }).collect(),
+            Err(err) => vec![ParseWarning{
+                offset: 0,
+                message: format!("{}: {}", name, err)
+            }]
+        };
+        if let Ok(manifest) = self.get_manifest() {
+            let warnings = AndroidXml::from_data_with_options(&manifest, self.options)
+                .map(|xml| Vec::from(xml.warnings()))
+                .map_err(|err| err.to_string());
+            res.extend(entry_warnings("AndroidManifest.xml", warnings));
+        }
+        if let Some(resources) = self.get_resources() {
+            let warnings = ResourceTable::from_with_options(&resources, self.options)
+                .map(|table| Vec::from(table.warnings()))
+                .map_err(|err| err.to_string());
+            res.extend(entry_warnings("resources.arsc", warnings));
+        }
+        res
+    }
+
+    // Transactions over the edits, see ZipEditor::begin. For a multi-step patch that should leave
+    // the apk as it was when a step fails, transaction() does the bookkeeping
+    pub fn begin(&mut self) {
+        self.editor.begin();
+        self.checkpoints.push((self.dex_count, self.mapping.clone()));
+    }
+
+    pub fn commit(&mut self) -> Result<()> {
+        self.checkpoints.pop().ok_or_else(no_transaction)?;
+        self.editor.commit()?;
+        Ok(())
+    }
+
+    pub fn rollback(&mut self) -> Result<()> {
+        (self.dex_count, self.mapping) = self.checkpoints.pop().ok_or_else(no_transaction)?;
+        self.editor.rollback()?;
+        Ok(())
+    }
 
-    pub fn add_dex<T: AsRef<[u8]>>(&mut self, data: T) {
-        let mut file_name = String::from("classes");
-        file_name.push_str(self.dex_count.clone().to_string().as_str());
+    // runs `f` in a transaction, committed when it succeeds and rolled back when it fails
+    pub fn transaction<T, F: FnOnce(&mut Self) -> Result<T>>(&mut self, f: F) -> Result<T> {
+        self.begin();
+        match f(self) {
+            Ok(value) => {
+                self.commit()?;
+                Ok(value)
+            },
+            Err(err) => {
+                self.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    // `callback` is called on every later edit and save, e.g. to audit log them. See EditEvent
+    pub fn on_event<F: Fn(&EditEvent) + Send + Sync + 'static>(&mut self, callback: F) {
+        self.editor.on_event(callback);
+    }
+
+    // a stage of every later save, see EntryTransformer
+    pub fn add_transformer<T: EntryTransformer + 'static>(&mut self, transformer: T) {
+        self.editor.add_transformer(transformer);
+    }
+
+    // deflates with `compressor` instead of flate2 from the next save on
+    pub fn set_compressor<C: Compressor + 'static>(&mut self, compressor: C) {
+        self.editor.set_compressor(compressor);
+    }
+
+    // shares deflated entries with other saves using `cache`, see CompressionCache
+    pub fn set_compression_cache(&mut self, cache: Arc<CompressionCache>) {
+        self.editor.set_compression_cache(cache);
+    }
+
+    // copies a borrowed buffer, pending edits and the mapping are kept
+    pub fn into_owned(self) -> ApkFile<'static> {
+        ApkFile {
+            zip: self.zip.into_owned(),
+            editor: self.editor,
+            dex_count: self.dex_count,
+            mapping: self.mapping,
+            checkpoints: self.checkpoints,
+            options: self.options
+        }
+    }
+
+
+    // the dex is validated first, garbage is rejected instead of ending up in the apk
+    pub fn add_dex<T: AsRef<[u8]>>(&mut self, data: T) -> Result<()> {
+        dex::validate(data.as_ref())?;
+        self.dex_count += 1;
+        self.editor.append_file(Vec::from(data.as_ref()), dex_name(self.dex_count), CompressMethod::Deflated);
+        Ok(())
+    }
+
+    // puts the dex at `index` (1 for classes.dex) so it loads before the ones after it, which move one
+    // index up. Those are only renamed, their data is copied as is
+    pub fn insert_dex<T: AsRef<[u8]>>(&mut self, index: usize, data: T) -> Result<()> {
+        if !(1..=self.dex_count + 1).contains(&index) {
+            return Err(DexError::new(format!("dex index {} out of 1..={}", index, self.dex_count + 1)).into());
+        }
+        dex::validate(data.as_ref())?;
+        if let Some(missing) = (index..=self.dex_count).map(dex_name).find(|name| !self.editor.has_file(name)) {
+            return Err(DexError::new(format!("{} is missing, the dexes aren't numbered in order", missing)).into());
+        }
+        // from the last one down so no two entries share a name on the way
+        for current in (index..=self.dex_count).rev() {
+            self.editor.rename_current_file(&dex_name(current), &dex_name(current + 1))?;
+        }
         self.dex_count += 1;
-        file_name.push_str(".dex");
-        self.editor.append_file(Vec::from(data.as_ref()), file_name, CompressMethod::Deflated);
+        self.editor.append_file(Vec::from(data.as_ref()), dex_name(index), CompressMethod::Deflated);
+        Ok(())
+    }
+
+    // runs `patch` over the bytes of `name` (classes2.dex...), then fixes the checksums and validates
+    // the result before writing it back
+    pub fn patch_dex<F: FnOnce(&mut Vec<u8>)>(&mut self, name: &str, patch: F) -> Result<()> {
+        let mut data = match self.current_data(name) {
+            Some(data) if dex_index(name).is_some() => data,
+            _ => return Err(DexError::new(format!("{} is not a dex in the apk", name)).into())
+        };
+        patch(&mut data);
+        dex::fix_checksums(&mut data)?;
+        dex::validate(&data)?;
+        self.editor.replace_current_file(name, data).map_err(|_| Error::MissingEntry(name.to_string()))
+    }
+
+    // the content of `name` with the edits made so far, the methods rewriting entries start from these
+    // rather than the apk as read so one doesn't undo another
+    fn current_data(&self, name: &str) -> Option<Vec<u8>> {
+        self.editor.current_data(&self.zip, name)
+    }
+
+    // classes.dex, classes2.dex... in load order, with their index, added and renumbered ones included
+    fn dex_names(&self) -> Vec<(usize, &str)> {
+        let mut names: Vec<(usize, &str)> = self.editor.current_names()
+            .filter_map(|name| Some((dex_index(name)?, name)))
+            .collect();
+        names.sort();
+        names
+    }
+
+    // swaps the contents of `name`, which may have been added or renumbered since the apk was read.
+    // With `fix_checksums` the new dex gets its checksums recomputed instead of having to match them;
+    // it is validated either way. The entry keeps its compression, so a stored dex stays aligned
+    pub fn replace_dex<T: AsRef<[u8]>>(&mut self, name: &str, data: T, fix_checksums: bool) -> Result<()> {
+        if dex_index(name).is_none() || !self.editor.has_file(name) {
+            return Err(DexError::new(format!("{} is not a dex in the apk", name)).into());
+        }
+        let mut data = Vec::from(data.as_ref());
+        if fix_checksums {
+            dex::fix_checksums(&mut data)?;
+        }
+        dex::validate(&data)?;
+        self.editor.replace_current_file(name, data).map_err(|_| Error::MissingEntry(name.to_string()))
+    }
+
+    // header of every classes*.dex, for reporting versions and spotting broken ones
+    pub fn get_dex_headers(&self) -> Vec<(String, Result<DexHeader, DexError>)> {
+        self.dex_names().into_iter().map(|(_, name)| {
+            let header = match self.current_data(name) {
+                Some(data) => dex::validate(&data),
+                None => Err(DexError::new(format!("can't read {}", name)))
+            };
+            (String::from(name), header)
+        }).collect()
+    }
+
+    // id counts of every dex, to see how close each one is to the 64K method and field limits
+    pub fn dex_ref_counts(&self) -> Vec<(String, Result<RefCounts, DexError>)> {
+        self.dex_names().into_iter().map(|(_, name)| {
+            let counts = match self.current_data(name) {
+                Some(data) => dex::ref_counts(&data),
+                None => Err(DexError::new(format!("can't read {}", name)))
+            };
+            (String::from(name), counts)
+        }).collect()
+    }
+
+    // the mapping.txt of a shrunken build, lets find_class and check_components take the original
+    // class names
+    pub fn set_proguard_mapping(&mut self, mapping: Option<ProguardMapping>) {
+        self.mapping = mapping;
+    }
+
+    pub fn proguard_mapping(&self) -> Option<&ProguardMapping> {
+        self.mapping.as_ref()
+    }
+
+    // the first dex, in load order, defining `class` ("Lcom/foo/Bar;"), as its name and index (1 for
+    // classes.dex). Dexes that can't be read are skipped. With a mapping set an original name is
+    // looked up under its obfuscated one when the dexes don't define it as is
+    pub fn find_class(&self, class: &str) -> Option<(String, usize)> {
+        let find = |class: &str| self.dex_names().into_iter().find_map(|(index, name)| {
+            let data = self.current_data(name)?;
+            let classes = dex::class_names(&data).ok()?;
+            classes.iter().any(|item| item == class).then(|| (String::from(name), index))
+        });
+        find(class).or_else(|| find(&self.mapping.as_ref()?.obfuscate(class)?))
+    }
+
+    // an error for every activity, service, receiver and provider of the manifest no dex defines, and a
+    // warning for every dex that can't be read to tell. Aliases have no class of their own
+    pub fn check_components(&self) -> Result<Vec<ValidationIssue>> {
+        let manifest_data = self.get_manifest()?;
+        let manifest = AndroidManifest::from_with_options(&manifest_data, self.options)?;
+        let package = manifest.package().unwrap_or_default();
+        let mut issues = vec![];
+        let mut classes: HashSet<String> = HashSet::new();
+        for (_, name) in self.dex_names() {
+            match self.current_data(name).ok_or_else(|| DexError::new(format!("can't read {}", name))).and_then(|data| dex::class_names(&data)) {
+                Ok(names) => classes.extend(names),
+                Err(err) => issues.push(ValidationIssue{
+                    severity: Severity::Warning,
+                    element: String::from(name),
+                    message: format!("classes not checked against it, {}", err)
+                })
+            }
+        }
+        for component in manifest.components() {
+            let tag = match component.kind {
+                ComponentKind::Activity => "activity",
+                ComponentKind::Service => "service",
+                ComponentKind::Receiver => "receiver",
+                ComponentKind::Provider => "provider",
+                ComponentKind::ActivityAlias => continue
+            };
+            // ".Main" and "Main" are relative to the package
+            let class_name = match component.name.find('.') {
+                Some(0) => format!("{}{}", package, component.name),
+                None => format!("{}.{}", package, component.name),
+                Some(_) => component.name.clone()
+            };
+            if classes.contains(&format!("L{};", class_name.replace('.', "/"))) {
+                continue;
+            }
+            // the shrinker renamed a class the manifest still names, the platform won't find it either
+            let message = match self.mapping.as_ref().and_then(|mapping| mapping.obfuscate(&class_name)) {
+                Some(obfuscated) if classes.contains(&format!("L{};", obfuscated.replace('.', "/"))) => {
+                    format!("{} is renamed to {} by the mapping", class_name, obfuscated)
+                },
+                _ => format!("no dex defines {}", class_name)
+            };
+            issues.push(ValidationIssue{
+                severity: Severity::Error,
+                element: format!("<{} android:name=\"{}\">", tag, component.name),
+                message
+            });
+        }
+        Ok(issues)
+    }
+
+    // an error for every dex newer than the runtime of the manifest's minSdkVersion can load, which
+    // installs fine and then fails on the older devices
+    pub fn check_dex_versions(&self) -> Result<Vec<ValidationIssue>> {
+        let manifest_data = self.get_manifest()?;
+        let min_sdk = match AndroidManifest::from_with_options(&manifest_data, self.options)?.min_sdk_version() {
+            Some(min_sdk) => min_sdk,
+            None => return Ok(vec![]) // preview codename, no released runtime to compare with
+        };
+        Ok(self.get_dex_headers().into_iter().filter_map(|(name, header)| match header {
+            Ok(header) if header.min_api_level() > min_sdk => Some(ValidationIssue{
+                severity: Severity::Error,
+                element: name,
+                message: format!("dex version {} needs API {}, minSdkVersion is {}", header.version_string(), header.min_api_level(), min_sdk)
+            }),
+            Ok(_) => None,
+            Err(err) => Some(ValidationIssue{
+                severity: Severity::Warning,
+                element: name,
+                message: format!("version not checked, {}", err)
+            })
+        }).collect())
+    }
+
+    // const-string instructions loading a string containing `pattern`, in every dex
+    pub fn find_const_strings(&self, pattern: &str) -> Vec<(String, ConstString)> {
+        self.dex_names().into_iter()
+            .filter_map(|(_, name)| Some((name, DexFile::parse(&self.current_data(name)?).ok()?)))
+            .flat_map(|(name, file)| file.find_const_strings(pattern).into_iter().map(move |item| (String::from(name), item)))
+            .collect()
+    }
+
+    // strings and method references containing any of `patterns` (urls, class names, api signatures) in
+    // every dex, with where the code uses them, see DexFile::scan. Dexes that can't be read are skipped
+    pub fn scan_dex(&self, patterns: &[&str]) -> Vec<(String, ScanMatch)> {
+        self.dex_names().into_iter()
+            .filter_map(|(_, name)| Some((name, DexFile::parse(&self.current_data(name)?).ok()?)))
+            .flat_map(|(name, file)| file.scan(patterns).into_iter().map(move |item| (String::from(name), item)))
+            .collect()
+    }
+
+    // replaces `from` with `to` in the const-strings of every dex, see DexFile::replace_const_strings.
+    // Only the dexes with a match are rewritten
+    pub fn replace_const_strings(&mut self, from: &str, to: &str) -> Result<usize> {
+        let mut count = 0;
+        let names: Vec<String> = self.dex_names().into_iter().map(|(_, name)| String::from(name)).collect();
+        for name in names {
+            let data = self.current_data(&name).ok_or_else(|| DexError::new(format!("can't read {}", name)))?;
+            let mut file = DexFile::parse(&data)?;
+            let replaced = file.replace_const_strings(from, to)?;
+            if replaced > 0 {
+                self.editor.replace_current_file(&name, file.to_bytes()?)?;
+                count += replaced;
+            }
+        }
+        Ok(count)
+    }
+
+    // strips the debug info of every dex, see DexFile::strip_debug_info, and reports what each one
+    // saved. Dexes with nothing to strip are left as they are
+    pub fn strip_debug_info(&mut self) -> Result<Vec<(String, StripReport)>> {
+        let mut reports = vec![];
+        let names: Vec<String> = self.dex_names().into_iter().map(|(_, name)| String::from(name)).collect();
+        for name in names {
+            let data = self.current_data(&name).ok_or_else(|| DexError::new(format!("can't read {}", name)))?;
+            let (stripped, mut report) = dex::strip_debug_info(&data)?;
+            if report.is_empty() {
+                report.stripped_size = report.original_size;
+            } else {
+                self.editor.replace_current_file(&name, stripped)?;
+            }
+            reports.push((name, report));
+        }
+        Ok(reports)
+    }
+
+    // runs `visitor` over the chunks of a compiled xml entry as edited so far (a layout, a drawable,
+    // the manifest) and writes back what it made of them
+    pub fn patch_xml_chunks<V: ChunkVisitor + ?Sized>(&mut self, name: &str, visitor: &mut V) -> Result<()> {
+        let data = self.current_data(name).ok_or_else(|| Error::MissingEntry(name.to_string()))?;
+        let data = chunks::visit_chunks(&data, visitor)?;
+        self.editor.replace_current_file(name, data).map_err(|_| Error::MissingEntry(name.to_string()))
+    }
+
+    pub fn get_manifest(&self) -> Result<Vec<u8>> {
+        self.zip.get_uncompress_data("AndroidManifest.xml").ok_or_else(|| Error::MissingEntry(String::from("AndroidManifest.xml")))
     }
 
-    pub fn get_manifest(&self) -> Vec<u8> {
-        self.zip.get_uncompress_data("AndroidManifest.xml").unwrap()
+    pub fn set_manifest<T: AsRef<[u8]>>(&mut self, data: T) -> Result<()> {
+        // the diff is only worth parsing both manifests for when someone listens
+        let previous = if self.editor.hooks().is_empty() {
+            None
+        } else {
+            self.editor.pending_data("AndroidManifest.xml").map(Vec::from).or_else(|| self.get_manifest().ok())
+        };
+        self.editor.edit_file(&self.zip, "AndroidManifest.xml", Vec::from(data.as_ref()))
+            .map_err(|_| Error::MissingEntry(String::from("AndroidManifest.xml")))?;
+        if let Some(previous) = previous {
+            let old = AndroidManifest::from_with_options(&previous, self.options);
+            let changes = match (old, AndroidManifest::from_with_options(data.as_ref(), self.options)) {
+                (Ok(old), Ok(new)) => old.diff(&new),
+                _ => vec![]
+            };
+            self.editor.hooks().emit(EditEvent::ManifestChanged{changes: &changes});
+        }
+        Ok(())
+    }
+
+    pub fn get_resources(&self) -> Option<Vec<u8>> {
+        self.zip.get_uncompress_data("resources.arsc")
+    }
+
+    // resources.arsc must stay stored (and aligned) so it can be mapped directly on API 30+
+    pub fn set_resources<T: AsRef<[u8]>>(&mut self, data: T) -> Result<()> {
+        self.editor.edit_file_with_method(&self.zip, "resources.arsc", Vec::from(data.as_ref()), CompressMethod::Stored)
+            .map_err(|_| Error::MissingEntry(String::from("resources.arsc")))
+    }
+
+    // adds res/`res_type`/`file_name` to the zip and registers it in `table`, which still has to be
+    // written back with set_resources. xml files must already be compiled to binary xml
+    pub fn add_resource_file<T: AsRef<[u8]>>(&mut self, table: &mut ResourceTable, res_type: &str, file_name: &str, data: T) -> Result<u32> {
+        if self.zip.get_file(&format!("res/{}/{}", res_type, file_name)).is_some() {
+            return Err(ResourceError::new(format!("res/{}/{} is already in the apk", res_type, file_name)).into());
+        }
+        let (id, path) = table.add_file(res_type, file_name)?;
+        let compress_method = match file_name.rsplit('.').next() {
+            Some("png" | "jpg" | "jpeg" | "gif" | "webp" | "ogg" | "mp3" | "mp4") => CompressMethod::Stored,
+            _ => CompressMethod::Deflated
+        };
+        self.editor.append_file(Vec::from(data.as_ref()), path, compress_method);
+        Ok(id)
+    }
+
+    // shortens the res/ paths in `table` and renames the entries to match, added ones included, returns
+    // (old, new) for a mapping file. The new paths go around the r/ entries the apk has already. `table`
+    // still has to be written back with set_resources
+    pub fn obfuscate_resource_paths(&mut self, table: &mut ResourceTable) -> Vec<(String, String)> {
+        let renames = table.obfuscate_paths(|path| self.editor.has_file(path));
+        for (path, new_path) in &renames {
+            // a path the table has but the apk doesn't only changes in the table
+            self.editor.rename_current_file(path, new_path).ok();
+        }
+        renames
     }
 
-    pub fn set_manifest<T: AsRef<[u8]>>(&mut self, data: T) {
-        self.editor.edit_file(&self.zip, "AndroidManifest.xml", Vec::from(data.as_ref()));
+    // ids referenced from the attributes of a binary xml entry, as edited so far
+    fn xml_references(&self, path: &str) -> Vec<u32> {
+        let data = match self.current_data(path) {
+            Some(data) if data.len() >= 8 && data.starts_with(&[0x03, 0x00, 0x08, 0x00]) => data, // binary xml magic
+            _ => return vec![]
+        };
+        let xml = match AndroidXml::from_data_with_options(&data, self.options) {
+            Ok(xml) => xml,
+            Err(_) => return vec![]
+        };
+        xml.content.root_node.iter()
+            .flat_map(|(_, node)| node.attrs().iter())
+            .filter_map(|attr| match attr.typed_value() {
+                TypedValue::Reference(id) | TypedValue::Attribute(id)
+                | TypedValue::DynamicReference(id) | TypedValue::DynamicAttribute(id) => Some(id),
+                _ => None
+            })
+            .collect()
+    }
+
+    // removes the resources not reachable from `keep_ids` or the manifest, and their files. `table`
+    // still has to be written back with set_resources
+    pub fn shrink_resources(&mut self, table: &mut ResourceTable, keep_ids: &[u32]) -> ShrinkReport {
+        let mut roots = self.xml_references("AndroidManifest.xml");
+        roots.extend_from_slice(keep_ids);
+        let mut report = table.shrink(&roots, |path| self.xml_references(path));
+        report.reclaimed_bytes += self.remove_files(&report.removed_files);
+        report
+    }
+
+    // drops the drawable/mipmap variants not needed on `densities` ("xxhdpi"...) from `table` and the
+    // zip, returns the removed files. `table` still has to be written back with set_resources
+    pub fn keep_resource_densities(&mut self, table: &mut ResourceTable, densities: &[&str]) -> Vec<String> {
+        let removed_files = table.keep_densities(densities);
+        self.remove_files(&removed_files);
+        removed_files
+    }
+
+    // ResourceTable::validate against the files the apk will hold once saved
+    pub fn validate_resources(&self, table: &ResourceTable) -> Vec<ValidationIssue> {
+        table.validate(|path| self.editor.has_file(path))
+    }
+
+    // UsageReport of `table` with the stored sizes and crcs of the files in the apk
+    pub fn resource_usage_report(&self, table: &ResourceTable) -> UsageReport {
+        table.usage_report(|path| self.zip.get_file(path).map(|entry| (entry.compressed_size as usize, entry.crc_32)))
+    }
+
+    // moves the app resources from package id `from` to `to` in `table` and in every compiled xml of the
+    // apk as edited so far, added ones included: attribute values and the resource map of custom
+    // attributes. `table` still has to be written back with set_resources
+    pub fn remap_package_id(&mut self, table: &mut ResourceTable, from: u32, to: u32) -> Result<()> {
+        table.remap_package_id(from, to)?;
+        let paths: Vec<String> = self.editor.current_names()
+            .filter(|path| *path == "AndroidManifest.xml" || (path.starts_with("res/") && path.ends_with(".xml")))
+            .map(String::from)
+            .collect();
+        for path in paths {
+            let data = match self.current_data(&path) {
+                Some(data) if data.len() >= 8 && data.starts_with(&[0x03, 0x00, 0x08, 0x00]) => data,
+                _ => continue
+            };
+            let mut xml = AndroidXml::from_data_with_options(&data, self.options)?;
+            let mut changed = false;
+            for (_, element) in xml.content.root_node.iter_mut() {
+                for attr in element.attrs.iter_mut() {
+                    let resource_id = remap_id(attr.resource_id(), from, to);
+                    let value = remap_value(attr.typed_value(), from, to);
+                    if resource_id != attr.resource_id() {
+                        attr.resource_id = resource_id;
+                        changed = true;
+                    }
+                    if value != attr.typed_value() {
+                        attr.set_typed_value(value);
+                        changed = true;
+                    }
+                }
+            }
+            if changed {
+                let new_data = xml.regenerate();
+                self.editor.replace_current_file(&path, new_data)?;
+            }
+        }
+        Ok(())
+    }
+
+    // removes what is currently called `paths`, added entries included. Bytes freed: the compressed size
+    // of an entry as read, the data of an added or edited one
+    fn remove_files(&mut self, paths: &[String]) -> usize {
+        let mut res = 0;
+        for path in paths {
+            let size = match self.editor.pending_data(path) {
+                Some(data) => data.len(),
+                None => self.zip.get_file(path).map_or(0, |entry| entry.compressed_size as usize)
+            };
+            if self.editor.remove_current_file(path).is_ok() {
+                res += size;
+            }
+        }
+        res
+    }
+
+    // an attribute of <application>, straight from the manifest as edited so far
+    fn application_attr(&self, resource_id: u32) -> Option<(Option<String>, TypedValue)> {
+        let data = self.current_data("AndroidManifest.xml")?;
+        let xml = AndroidXml::from_data_with_options(&data, self.options).ok()?;
+        let attr = xml.content.root_node.find_child("application")?.get_attr_by_id(resource_id)?;
+        Some((attr.string_value().map(String::from), attr.typed_value()))
+    }
+
+    // resources.arsc as edited so far
+    fn resource_table(&self) -> Option<ResourceTable> {
+        ResourceTable::from_with_options(&self.current_data("resources.arsc")?, self.options).ok()
+    }
+
+    // android:label of the application for `locale` ("zh-rCN", "" for the default), looked up in
+    // resources.arsc when the manifest only holds a reference. An error when `locale` doesn't parse
+    pub fn get_app_label(&self, locale: &str) -> Result<Option<String>> {
+        let config = ResourceConfig::from_qualifiers(locale)
+            .ok_or_else(|| ResourceError::new(format!("bad locale {}", locale)))?;
+        let (literal, value) = match self.application_attr(LABEL) {
+            Some(attr) => attr,
+            None => return Ok(None)
+        };
+        let id = match value {
+            TypedValue::Reference(id) => id,
+            _ => return Ok(literal)
+        };
+        Ok(self.resource_table().and_then(|table| {
+            let value = table.resolve_value(id, &config)?;
+            table.string_value(&value).map(String::from)
+        }))
+    }
+
+    // renames the app: every configuration of the string android:label points to becomes `value`, then
+    // `per_locale_overrides` ("fr", "Mon app") apply. A literal, framework or dangling label is replaced
+    // by a new string resource. Writes back both the manifest and resources.arsc
+    pub fn set_app_label(&mut self, value: &str, per_locale_overrides: &[(&str, &str)]) -> Result<()> {
+        let mut table = self.resource_table().ok_or_else(|| ResourceError::new(String::from("no readable resources.arsc")))?;
+        let app_package = table.packages().first().map(|package| package.id());
+        let label = match self.application_attr(LABEL) {
+            Some((_, TypedValue::Reference(id))) if Some(id >> 24) == app_package && !table.variants(id).is_empty() => Some(id),
+            _ => None
+        };
+        let id = match label {
+            Some(id) => {
+                let configs: Vec<ResourceConfig> = table.variants(id).into_iter().map(|(config, _)| config).collect();
+                for config in &configs {
+                    table.set_string(id, value, config)?;
+                }
+                id
+            },
+            None => {
+                let name = (0..).map(|i| if i == 0 { String::from("app_label") } else { format!("app_label_{}", i) })
+                    .find(|name| table.resource_id(&format!("string/{}", name)).is_none())
+                    .unwrap_or_default();
+                let id = table.add_string(&name, value, &[])?;
+                let manifest_data = self.current_data("AndroidManifest.xml")
+                    .ok_or_else(|| Error::MissingEntry(String::from("AndroidManifest.xml")))?;
+                let mut manifest = AndroidManifest::from_with_options(&manifest_data, self.options)?;
+                manifest.set_label(id)?;
+                self.set_manifest(manifest.get_data())?;
+                id
+            }
+        };
+        for (locale, text) in per_locale_overrides {
+            let config = ResourceConfig::from_qualifiers(locale)
+                .ok_or_else(|| ResourceError::new(format!("bad locale {}", locale)))?;
+            table.set_string(id, text, &config)?;
+        }
+        self.set_resources(table.to_data()?)
+    }
+
+    // every file android:icon resolves to across configurations, e.g. the per density pngs and the
+    // adaptive icon xml
+    pub fn get_app_icon_paths(&self) -> Vec<String> {
+        let id = match self.application_attr(ICON) {
+            Some((_, TypedValue::Reference(id))) => id,
+            _ => return vec![]
+        };
+        let table = match self.resource_table() {
+            Some(table) => table,
+            None => return vec![]
+        };
+        let mut res: Vec<String> = Vec::new();
+        let mut pending: Vec<u32> = vec![id];
+        let mut visited: Vec<u32> = Vec::new();
+        while let Some(id) = pending.pop() {
+            if visited.contains(&id) {
+                continue;
+            }
+            visited.push(id);
+            for (_, entry) in table.variants(id) {
+                match (entry.typed_value(), table.entry_file(entry)) {
+                    (Some(TypedValue::Reference(next)), _) => pending.push(next),
+                    (_, Some(path)) if !res.iter().any(|item| item == path) => res.push(path.to_string()),
+                    _ => {}
+                }
+            }
+        }
+        res
     }
 
     pub fn add_assets<T: AsRef<[u8]>>(&mut self, name: &str, data: T) {
@@ -53,7 +769,7 @@ impl<'a> ApkFile<'a> {
         self.editor.append_file(Vec::from(data.as_ref()), path, CompressMethod::Deflated);
     }
 
-    pub fn add_assets_from_reader<T: Read>(&mut self, name: &str, mut data: T) -> Result<(),std::io::Error> {
+    pub fn add_assets_from_reader<T: Read>(&mut self, name: &str, mut data: T) -> Result<()> {
         let mut content: Vec<u8> = Vec::new();
         data.read_to_end(&mut content)?;
         let mut path = String::from("assets/");
@@ -62,21 +778,122 @@ impl<'a> ApkFile<'a> {
         Ok(())
     }
 
+    // entries in central directory order
+    pub fn list_files(&self) -> Vec<FileInfo> {
+        self.zip.entries.iter().map(|entry| FileInfo{
+            name: entry.file_name.clone(),
+            compress_method: entry.compress_method.clone(),
+            size: entry.origin_size,
+            compressed_size: entry.compressed_size,
+            crc32: entry.crc_32
+        }).collect()
+    }
+
+    // uncompressed content of an entry of the apk as read
+    pub fn get_file(&self, path: &str) -> Option<Vec<u8>> {
+        self.zip.get_uncompress_data(path)
+    }
+
     pub fn add_file<T: AsRef<[u8]>>(&mut self, path: &str, data: T, compress_method: CompressMethod) {
         self.editor.append_file(Vec::from(data.as_ref()), String::from(path), compress_method);
     }
 
-    pub fn edit_file<T: AsRef<[u8]>>(&mut self, path: &str, data: T) -> Option<()> {
-        let raw = Vec::from(data.as_ref());
-        self.editor.edit_file(&self.zip, path, raw)
+    // add_file in place of what is currently called `path`, if anything: an entry of the apk, an added
+    // one or one renamed to it
+    pub fn add_or_replace_file<T: AsRef<[u8]>>(&mut self, path: &str, data: T, compress_method: CompressMethod) {
+        self.editor.remove_current_file(path).ok();
+        self.add_file(path, data, compress_method);
+    }
+
+    // a plain png written over a .9.png entry is compiled first. A dex gets its checksums fixed
+    pub fn edit_file<T: AsRef<[u8]>>(&mut self, path: &str, data: T) -> Result<()> {
+        if path.ends_with(".9.png") {
+            return self.replace_nine_patch(path, data);
+        }
+        let mut raw = Vec::from(data.as_ref());
+        if dex_index(path).is_some() {
+            dex::fix_checksums(&mut raw)?;
+        }
+        self.editor.edit_file(&self.zip, path, raw).map_err(|_| Error::MissingEntry(path.to_string()))
+    }
+
+    // replaces a .9.png entry with either a compiled 9-patch, which is checked, or a source one with
+    // its 1px frame, which is compiled
+    pub fn replace_nine_patch<T: AsRef<[u8]>>(&mut self, path: &str, data: T) -> Result<()> {
+        if !path.ends_with(".9.png") {
+            return Err(ResourceError::new(format!("{} is not a 9-patch", path)).into());
+        }
+        let data = data.as_ref();
+        let data = if nine_patch::is_compiled(data) {
+            nine_patch::validate(data)?;
+            data.to_vec()
+        } else {
+            nine_patch::compile(data)?
+        };
+        self.editor.edit_file(&self.zip, path, data).map_err(|_| Error::MissingEntry(path.to_string()))
+    }
+
+    pub fn remove_file(&mut self, path: &str) -> Result<()> {
+        self.editor.remove_file(&self.zip, path).map_err(|_| Error::MissingEntry(path.to_string()))
+    }
+
+    pub fn save<W: Write>(&mut self, writer: W) -> Result<()> {
+        self.save_aligned(writer, self.config().align)
+    }
+
+    // stored entries start on a multiple of `align` instead of the configured one, 4096 lets native
+    // libraries be mapped in place
+    pub fn save_aligned<W: Write>(&mut self, writer: W, align: usize) -> Result<()> {
+        if align == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "alignment of 0").into());
+        }
+        Ok(self.editor.finish(Some(&self.zip), writer, align)?)
     }
 
-    pub fn remove_file(&mut self, path: &str) -> Option<()> {
-        self.editor.remove_file(&self.zip, path)
+    // A dry run of save(): the layout is computed, entries compressed included, but nothing is
+    // written. Manifest changes compare the manifest as read with the one set since
+    pub fn plan(&self) -> Result<SavePlan> {
+        self.plan_aligned(self.config().align)
     }
 
-    pub fn save<W: Write>(&mut self, writer: W) -> Result<(), Box<dyn Error>> {
-        self.editor.finish(Some(&self.zip), writer, 4)
+    // of save_aligned
+    pub fn plan_aligned(&self, align: usize) -> Result<SavePlan> {
+        if align == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "alignment of 0").into());
+        }
+        let (entries, size) = self.editor.layout(Some(&self.zip), align)?;
+        let manifest_changes = match self.editor.pending_data("AndroidManifest.xml") {
+            Some(data) => {
+                let manifest = self.get_manifest()?;
+                AndroidManifest::from_with_options(&manifest, self.options)?.diff(&AndroidManifest::from_with_options(data, self.options)?)
+            },
+            None => vec![]
+        };
+        let changed = entries.iter().any(|entry| {
+            !entry.name.starts_with("META-INF/") && (entry.change != EntryChange::Kept || entry.renamed_from.is_some())
+        });
+        let signature = SignatureImpact{
+            v2_removed: self.zip.has_signing_block(),
+            v1_stale: changed && entries.iter().any(|entry| entry.change != EntryChange::Removed && is_v1_signature_file(&entry.name))
+        };
+        Ok(SavePlan{
+            entries,
+            manifest_changes,
+            signature,
+            size
+        })
+    }
+
+    // save, then sign_v2
+    #[cfg(feature = "sign")]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn save_signed<W: Write>(&mut self, mut writer: W, key: &SigningKey) -> Result<()> {
+        let mut data = vec![];
+        self.save(&mut data)?;
+        let signed = sign_v2(&data, key)?;
+        self.editor.hooks().emit(EditEvent::Signed{size: signed.len()});
+        writer.write_all(&signed)?;
+        Ok(())
     }
 
 }
@@ -1,16 +1,29 @@
-use std::collections::HashMap;
-use std::error::Error;
-use std::fmt::{Display, Formatter};
-use std::io::Write;
-use byteorder::{LittleEndian, WriteBytesExt};
-use flate2::write::DeflateDecoder;
-use crate::utils::{get_leu32_value, get_leu16_value};
-use crate::apk_zip::{CENTRAL_DIRECTORY, CENTRAL_DIRECTORY_END, CompressMethod, LOCAL_FILE_HEADER};
+use alloc::vec;
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+#[cfg(feature = "zip")]
+use std::io::{Read, Seek, SeekFrom};
+#[cfg(feature = "zip")]
+use flate2::read::DeflateDecoder;
+use crate::utils::{read_leu16, read_leu32};
+use crate::apk_zip::index::NameIndex;
+use crate::apk_zip::{CENTRAL_DIRECTORY, CENTRAL_DIRECTORY_END, CompressMethod, LOCAL_FILE_HEADER, SIGNING_BLOCK_MAGIC};
+#[cfg(feature = "zip")]
+use crate::apk_zip::EditorConfig;
+use crate::options::{ParseContext, ParseOptions, ParseWarning};
+
+// general purpose flags
+const FLAG_ENCRYPTED: u16 = 0x0001;
+pub(crate) const FLAG_DATA_DESCRIPTOR: u16 = 0x0008;
 
 #[derive(Debug)]
 pub struct ZipFormatError{
-    offset: usize,
-    reason: &'static str,
+    pub(crate) offset: usize,
+    pub(crate) reason: &'static str,
 }
 
 pub struct ZipEntry {
@@ -27,30 +40,22 @@ pub struct ZipEntry {
 }
 
 pub struct ZipFile<'a> {
-    pub(crate) data: &'a Vec<u8>,
-    central_directory_offset: u32,
+    pub(crate) data: Cow<'a, [u8]>,
     pub(crate) entries: Vec<ZipEntry>,
-    pub(crate) file_name_map: HashMap<String,usize>
+    pub(crate) names: NameIndex,
+    warnings: Vec<ParseWarning>
 }
 
 pub(crate) struct LocalFileHeader {
     global_offset: usize,
-    compress_version: u16,
-    flags: u16,
-    compress_method: CompressMethod,
-    modify_time: u32,
-    crc_32: u32,
     compressed_size: u32,
-    origin_size: u32,
     file_name_len: u16,
-    ext_len: u16,
-    file_name: String,
-    ext_data: Vec<u8>
+    ext_len: u16
 }
 
 
 impl Display for ZipFormatError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(f, "zip format error at: {}, reason: {}", self.offset, self.reason)
     }
 }
@@ -74,50 +79,188 @@ impl Clone for ZipEntry {
     }
 }
 
-impl LocalFileHeader {
-    pub(crate) fn from_slice(data: &[u8], offset: usize) -> LocalFileHeader {
-        // TODO unwrap
-        let file_name_len = get_leu16_value(data, offset + 26);
-        let ext_len = get_leu16_value(data, offset + 28);
-        let file_name = String::from_utf8(data[(offset + 30)..(offset + 30 + file_name_len as usize)].to_vec()).unwrap();
-        LocalFileHeader{
-            global_offset: offset,
-            compress_version: get_leu16_value(data, offset + 4),
-            flags: get_leu16_value(data, offset + 6),
-            compress_method: CompressMethod::convert_from_u16(get_leu16_value(data, offset + 8)).unwrap(),
-            modify_time: get_leu32_value(data, offset + 10),
-            crc_32: get_leu32_value(data, offset + 14),
-            compressed_size: get_leu32_value(data, offset + 18),
-            origin_size: get_leu32_value(data, offset + 22),
-            file_name_len,
-            ext_len,
-            file_name,
-            ext_data: data[(offset + 30 + file_name_len as usize)..(offset + 30 + (file_name_len + ext_len) as usize)].to_vec()
+// MS-DOS date and time, 0 is what zip writers leave when they don't set it (this editor too)
+fn is_valid_dos_time(value: u32) -> bool {
+    let (time, date) = (value & 0xffff, value >> 16);
+    let (seconds, minutes, hours) = ((time & 0x1f) * 2, (time >> 5) & 0x3f, time >> 11);
+    let (day, month) = (date & 0x1f, (date >> 5) & 0xf);
+    value == 0 || (seconds < 60 && minutes < 60 && hours < 24 && (1..=31).contains(&day) && (1..=12).contains(&month))
+}
+
+// the end record is 22 bytes plus a comment of up to 65535
+const MAX_END_SIZE: usize = 22 + 65535;
+
+fn find_end(data: &[u8]) -> Result<usize, ZipFormatError> {
+    let lowest = data.len().saturating_sub(MAX_END_SIZE);
+    (lowest..=data.len().saturating_sub(22)).rev()
+        .find(|offset| read_leu32(data, *offset) == Some(CENTRAL_DIRECTORY_END))
+        .ok_or(ZipFormatError{offset: lowest, reason: "Central directory end not found"})
+}
+
+// Where the central directory starts and ends in `data` (the zip from `base` to its end), and how
+// many entries it declares
+pub(crate) fn parse_end(data: &[u8], base: usize, context: &mut ParseContext) -> Result<(usize, usize, u16), ZipFormatError> {
+    if data.len() < 22 {
+        return Err(ZipFormatError{offset: 0, reason: "too short for a zip"});
+    }
+    let central_directory_end_offset = find_end(data)?;
+
+    let end_u16_at = |offset: usize| read_leu16(data, central_directory_end_offset + offset)
+        .ok_or(ZipFormatError{offset: central_directory_end_offset, reason: "central directory end out of the file"});
+    let central_directory_offset = read_leu32(data, central_directory_end_offset + 16)
+        .and_then(|offset| (offset as usize).checked_sub(base))
+        .ok_or(ZipFormatError{offset: central_directory_end_offset, reason: "central directory end out of the file"})?;
+    let dir_count = end_u16_at(10)?;
+    // disk numbers and the entry count of this disk, an apk is a single disk
+    if (end_u16_at(4)? != 0 || end_u16_at(6)? != 0 || end_u16_at(8)? != dir_count)
+        && !context.tolerate(central_directory_end_offset, "multi-disk fields set in the central directory end") {
+        return Err(ZipFormatError{offset: central_directory_end_offset, reason: "multi-disk zip"});
+    }
+    if central_directory_end_offset + 22 + end_u16_at(20)? as usize != data.len()
+        && !context.tolerate(central_directory_end_offset, "zip comment doesn't reach the end of the file") {
+        return Err(ZipFormatError{offset: central_directory_end_offset, reason: "data after the zip comment"});
+    }
+    Ok((central_directory_offset, central_directory_end_offset, dir_count))
+}
+
+// None when the data doesn't inflate, or inflates past the size the entry declares: a few kB can
+// deflate gigabytes, that's where it stops rather than when memory runs out
+#[cfg(feature = "zip")]
+pub(crate) fn inflate(raw: &[u8], entry: &ZipEntry) -> Option<Vec<u8>> {
+    match entry.compress_method {
+        CompressMethod::Stored => Some(Vec::from(raw)),
+        CompressMethod::Deflated => {
+            let limit = entry.origin_size as u64;
+            let mut data: Vec<u8> = Vec::new();
+            DeflateDecoder::new(raw).take(limit + 1).read_to_end(&mut data).ok()?;
+            (data.len() as u64 <= limit).then_some(data)
+        }
+    }
+}
+
+// where the entry after a broken one at `offset` starts: past it when its header still reads and
+// another entry or the end record follows, else at the next central directory magic
+pub(crate) fn next_entry_offset(data: &[u8], offset: usize, end_offset: usize) -> Option<usize> {
+    let declared = (|| {
+        if read_leu32(data, offset)? != CENTRAL_DIRECTORY {
+            return None;
+        }
+        let lengths = read_leu16(data, offset + 28)? as usize + read_leu16(data, offset + 30)? as usize + read_leu16(data, offset + 32)? as usize;
+        Some(offset + 46 + lengths)
+    })();
+    if let Some(next) = declared.filter(|next| *next == end_offset || read_leu32(data, *next) == Some(CENTRAL_DIRECTORY)) {
+        return Some(next);
+    }
+    let magic = CENTRAL_DIRECTORY.to_le_bytes();
+    data.get(offset + 1..end_offset)?.windows(4).position(|window| window == magic).map(|position| offset + 1 + position)
+}
+
+impl ZipEntry {
+    pub fn name(&self) -> &str {
+        &self.file_name
+    }
+
+    // uncompressed
+    pub fn size(&self) -> u32 {
+        self.origin_size
+    }
+
+    pub fn compressed_size(&self) -> u32 {
+        self.compressed_size
+    }
+
+    pub fn crc32(&self) -> u32 {
+        self.crc_32
+    }
+
+    pub fn compress_method(&self) -> &CompressMethod {
+        &self.compress_method
+    }
+
+    // MS-DOS time in the low half, date in the high half, as stored
+    pub fn modify_time(&self) -> u32 {
+        self.modify_time
+    }
+
+    pub fn local_header_offset(&self) -> u32 {
+        self.local_file_header_offset
+    }
+
+    // the local header repeats name and sizes, Android only reads the central directory so packers
+    // zero the copies or make them up
+    fn check_local_header(&self, data: &[u8], header: &LocalFileHeader, context: &mut ParseContext) -> Result<(), ZipFormatError> {
+        let offset = header.global_offset;
+        let name_start = offset + 30;
+        if data.get(name_start..name_start + header.file_name_len as usize) != Some(self.file_name.as_bytes())
+            && !context.tolerate(offset, &format!("local header name of {} differs from the central directory", self.file_name)) {
+            return Err(ZipFormatError{offset, reason: "local header name mismatch"});
+        }
+        // with a data descriptor the sizes come after the data instead
+        let has_descriptor = read_leu16(data, offset + 6).unwrap_or(0) & FLAG_DATA_DESCRIPTOR != 0;
+        let sizes_match = read_leu32(data, offset + 18) == Some(self.compressed_size) && read_leu32(data, offset + 22) == Some(self.origin_size);
+        if !has_descriptor && !sizes_match
+            && !context.tolerate(offset, &format!("local header sizes of {} differ from the central directory", self.file_name)) {
+            return Err(ZipFormatError{offset, reason: "local header size mismatch"});
         }
+        Ok(())
     }
 
-    pub(crate) fn write<W: Write>(&self, mut writer: W) -> Result<usize,std::io::Error> {
-        writer.write_u32::<LittleEndian>(LOCAL_FILE_HEADER)?;
-        writer.write_u16::<LittleEndian>(self.compress_version)?;
-        writer.write_u16::<LittleEndian>(self.flags)?;
-        writer.write_u16::<LittleEndian>(self.compress_method.value())?;
-        writer.write_u32::<LittleEndian>(self.modify_time)?;
-        writer.write_u32::<LittleEndian>(self.crc_32)?;
-        writer.write_u32::<LittleEndian>(self.compressed_size)?;
-        writer.write_u32::<LittleEndian>(self.origin_size)?;
-        writer.write_u16::<LittleEndian>(self.file_name_len)?;
-        writer.write_u16::<LittleEndian>(self.ext_len)?;
-        writer.write_all(self.file_name.as_bytes())?;
-        writer.write_all(self.ext_data.as_slice())?;
-        Ok((self.file_name_len + self.ext_len + 30) as usize)
+    // the stored bytes of the entry, None unless its local header and they are inside `data`
+    pub(crate) fn data_in<'d>(&self, data: &'d [u8]) -> Option<&'d [u8]> {
+        LocalFileHeader::of(data, self)?.data(data)
+    }
+
+    // everything after reads through the local header, it has to hold together
+    pub(crate) fn check_data(&self, data: &[u8], context: &mut ParseContext) -> Result<(), ZipFormatError> {
+        let local_header = LocalFileHeader::of(data, self).ok_or(ZipFormatError{
+            offset: self.local_file_header_offset as usize,
+            reason: "bad local file header"
+        })?;
+        self.check_local_header(data, &local_header, context)?;
+        // deflated entries would have to be inflated for this, ApkFile::warnings does it
+        if self.compress_method == CompressMethod::Stored {
+            let crc_matches = local_header.data(data).is_some_and(|stored| crc32fast::hash(stored) == self.crc_32);
+            if !crc_matches && !context.tolerate(self.central_directory_header_offset as usize + 16, &format!("crc of {} doesn't match its data", self.file_name)) {
+                return Err(ZipFormatError{offset: self.central_directory_header_offset as usize, reason: "crc mismatch"});
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LocalFileHeader {
+    // the local header of `entry`, None unless it and the entry's data are inside `data`. The size
+    // is the one of the central directory, as Android reads it, the local copy may be zeroed or made up
+    pub(crate) fn of(data: &[u8], entry: &ZipEntry) -> Option<LocalFileHeader> {
+        let offset = entry.local_file_header_offset as usize;
+        if read_leu32(data, offset)? != LOCAL_FILE_HEADER {
+            return None;
+        }
+        let res = LocalFileHeader{
+            global_offset: offset,
+            compressed_size: entry.compressed_size,
+            file_name_len: read_leu16(data, offset.checked_add(26)?)?,
+            ext_len: read_leu16(data, offset.checked_add(28)?)?
+        };
+        res.data(data)?;
+        Some(res)
     }
 
     pub(crate) fn get_data_offset(&self) -> usize {
         self.global_offset + self.file_name_len as usize + self.ext_len as usize + 30
     }
 
-    pub(crate) fn get_data_len(&self) -> u32 {
-        self.compressed_size
+    // the extra field of the header
+    #[cfg(feature = "zip")]
+    pub(crate) fn extra<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        let start = self.global_offset + 30 + self.file_name_len as usize;
+        data.get(start..start + self.ext_len as usize)
+    }
+
+    // the stored (possibly compressed) bytes of the entry
+    pub(crate) fn data<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        let start = self.get_data_offset();
+        data.get(start..start.checked_add(self.compressed_size as usize)?)
     }
 
 }
@@ -125,36 +268,18 @@ impl LocalFileHeader {
 impl<'a> ZipFile<'a> {
 
     pub fn get_file_compress_data(&self, idx: usize) -> Option<&[u8]> {
-        let header_offset = self.get_header_offset(idx)?;
-        let file_name_len = get_leu16_value(self.data, (header_offset + 26) as usize) as u32;
-        let ext_len = get_leu16_value(self.data, (header_offset + 28) as usize) as u32;
-        let compress_size = get_leu32_value(self.data, (header_offset + 18) as usize);
-        let file_start_offset = (header_offset + 30 + file_name_len + ext_len) as usize;
-        Some(&self.data[file_start_offset..(file_start_offset + compress_size as usize)])
+        self.entries.get(idx)?.data_in(&self.data)
     }
 
+    #[cfg(feature = "zip")]
     pub fn get_uncompress_data(&self, name: &str) -> Option<Vec<u8>> {
-        let idx = *self.file_name_map.get(name)?;
-        let compress_method = self.entries.get(idx)?.compress_method.clone();
-        let raw = self.get_file_compress_data(idx)?;
-        match compress_method {
-            CompressMethod::Stored => Some(Vec::from(raw)),
-            CompressMethod::Deflated => {
-                let mut data: Vec<u8> = Vec::new();
-                let mut decoder = DeflateDecoder::new(&mut data);
-                decoder.write_all(raw);
-                decoder.finish();
-                Some(data)
-            }
-        }
+        let idx = self.get_file_index(name)?;
+        inflate(self.get_file_compress_data(idx)?, self.entries.get(idx)?)
     }
 
     pub fn get_entry_header_data(&self, idx: usize) -> Option<&[u8]> {
-        let header_offset = self.get_header_offset(idx)?;
-        let file_name_len = get_leu16_value(self.data, (header_offset + 26) as usize) as u32;
-        let ext_len = get_leu16_value(self.data, (header_offset + 28) as usize) as u32;
-        let end = (header_offset + 30 + file_name_len + ext_len) as usize;
-        Some(&self.data[(header_offset as usize)..end])
+        let header = LocalFileHeader::of(&self.data, self.entries.get(idx)?)?;
+        self.data.get(header.global_offset..header.get_data_offset())
     }
 
     pub fn get_header_offset(&self, idx: usize) -> Option<u32> {
@@ -171,78 +296,249 @@ impl<'a> ZipFile<'a> {
     }
 
     pub fn get_file(&self, name: &str) -> Option<&ZipEntry> {
-        let idx = self.file_name_map.get(name)?;
-        self.get_entry(*idx)
+        self.get_entry(self.get_file_index(name)?)
+    }
+
+    // in central directory order
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    // in central directory order
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.entries.iter().map(|entry| entry.file_name.as_str())
+    }
+
+    // whether an APK signing block (v2 and later signatures) sits before the central directory
+    pub fn has_signing_block(&self) -> bool {
+        self.entries.first().is_some_and(|entry| {
+            let central_directory = entry.central_directory_header_offset as usize;
+            central_directory.checked_sub(16).and_then(|start| self.data.get(start..central_directory)) == Some(SIGNING_BLOCK_MAGIC)
+        })
+    }
+
+    // the whole zip as read
+    pub fn data(&self) -> &[u8] {
+        &self.data
     }
 
     pub(crate) fn get_file_index(&self, name: &str) -> Option<usize> {
-        Some(*(self.file_name_map.get(name)?))
+        self.names.find(name, |idx| self.entries[idx].file_name.as_str())
     }
 
-    pub fn from(data: &Vec<u8>) -> Result<ZipFile,ZipFormatError> {
-        let mut res = ZipFile{
-            data,
-            central_directory_offset: 0,
-            entries: vec![],
-            file_name_map: HashMap::new()
-        };
+    // anything that derefs to bytes without a copy, a slice, Vec, Bytes or an mmap
+    pub fn from<T: AsRef<[u8]> + ?Sized>(data: &T) -> Result<ZipFile<'_>,ZipFormatError> {
+        ZipFile::parse(Cow::Borrowed(data.as_ref()), ParseOptions::default())
+    }
 
-        let mut seek_index: usize = 0;
-        let central_directory_end_offset = loop {
-            let magic = get_leu32_value(data, data.len() - 22 - seek_index);
-            if magic == CENTRAL_DIRECTORY_END {
-                break data.len() - 22 - seek_index;
-            }
-            seek_index += 1;
-            if (data.len() - 22 - seek_index < 4) || seek_index > 65535 {
-                return Err(ZipFormatError{offset: data.len() - 22 - seek_index, reason: "Central directory end not found"})
-            }
-        };
+    pub fn from_with_options<T: AsRef<[u8]> + ?Sized>(data: &T, options: ParseOptions) -> Result<ZipFile<'_>,ZipFormatError> {
+        ZipFile::parse(Cow::Borrowed(data.as_ref()), options)
+    }
 
-        res.central_directory_offset = get_leu32_value(data, central_directory_end_offset + 16);
-        let dir_count = get_leu16_value(data, central_directory_end_offset + 10);
-        let mut current_offset = res.central_directory_offset as usize;
-        let mut parse_count = 0;
-        while parse_count < dir_count {
+    // takes the buffer, the result borrows nothing
+    pub fn from_vec(data: Vec<u8>) -> Result<ZipFile<'static>,ZipFormatError> {
+        ZipFile::parse(Cow::Owned(data), ParseOptions::default())
+    }
+
+    pub fn from_vec_with_options(data: Vec<u8>, options: ParseOptions) -> Result<ZipFile<'static>,ZipFormatError> {
+        ZipFile::parse(Cow::Owned(data), options)
+    }
 
-            if get_leu32_value(data, current_offset) != CENTRAL_DIRECTORY {
-                return Err(ZipFormatError{
-                    offset: current_offset,
-                    reason: "magic of central directory error"
-                });
+    // Only the central directory of the zip `reader` reads, the data of the entries stays in it: data()
+    // is empty, and so is what's read through it. For ZipEditor::finish_from, which copies what's kept
+    // out of the reader, to edit an apk too large to load. Local headers are checked as they're copied
+    #[cfg(feature = "zip")]
+    pub fn read_central_directory<R: Read + Seek>(mut reader: R, options: ParseOptions) -> crate::Result<ZipFile<'static>> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        let tail_start = len.saturating_sub(MAX_END_SIZE as u64);
+        let mut tail: Vec<u8> = vec![];
+        reader.seek(SeekFrom::Start(tail_start))?;
+        reader.read_to_end(&mut tail)?;
+        let end = find_end(&tail).map_err(|err| ZipFormatError{
+            offset: err.offset + tail_start as usize,
+            reason: err.reason
+        })?;
+        let central_directory_offset = read_leu32(&tail, end + 16).ok_or(ZipFormatError{
+            offset: end + tail_start as usize,
+            reason: "central directory end out of the file"
+        })? as u64;
+        if central_directory_offset > tail_start + end as u64 {
+            return Err(ZipFormatError{offset: end + tail_start as usize, reason: "central directory end out of the file"}.into());
+        }
+        let mut data: Vec<u8> = vec![];
+        reader.seek(SeekFrom::Start(central_directory_offset))?;
+        reader.read_to_end(&mut data)?;
+        let base = central_directory_offset as usize;
+        let mut context = ParseContext::new(options);
+        let (entries, names) = ZipFile::parse_directory(&data, base, &mut context, |entry, _| {
+            // the data has to end before the central directory, its local header is read later
+            let data_end = (entry.local_file_header_offset as usize).checked_add(30 + entry.compressed_size as usize);
+            match data_end {
+                Some(data_end) if data_end <= base => Ok(()),
+                _ => Err(ZipFormatError{offset: entry.local_file_header_offset as usize, reason: "bad local file header"})
             }
+        })?;
+        Ok(ZipFile{
+            data: Cow::Borrowed(&[]),
+            entries,
+            names,
+            warnings: context.warnings
+        })
+    }
 
-            let file_name_len = get_leu16_value(data, current_offset + 28);
-            let ext_len = get_leu16_value(data, current_offset + 30);
-            let comment_len = get_leu16_value(data, current_offset + 32);
-            let file_name_data = data.as_slice()[(current_offset + 46)..(current_offset + 46 + file_name_len as usize)].to_vec();
-            let file_name = match String::from_utf8(file_name_data){
-                Ok(v) => v,
-                Err(_) => return Err(ZipFormatError{
-                    offset: current_offset,
-                    reason: "convert string fail"
-                })
-            };
-            res.file_name_map.insert(file_name.clone(), res.entries.len());
-
-            let entry = ZipEntry{
-                origin_size: get_leu32_value(data, current_offset + 24),
-                compressed_size: get_leu32_value(data, current_offset + 20),
-                file_name,
-                crc_32: get_leu32_value(data, current_offset + 16),
-                compress_method: CompressMethod::convert_from_u16(get_leu16_value(data, current_offset + 10)).unwrap(),
-                modify_time: get_leu32_value(data, current_offset + 12),
-                local_file_header_offset: get_leu32_value(data, current_offset + 42),
-                central_directory_header_offset: current_offset as u32,
-                entry_size: 46 + file_name_len as u32 + ext_len as u32 + comment_len as u32,
-                ext_len
-            };
+    pub fn into_owned(self) -> ZipFile<'static> {
+        ZipFile{
+            data: Cow::Owned(self.data.into_owned()),
+            entries: self.entries,
+            names: self.names,
+            warnings: self.warnings
+        }
+    }
 
-            current_offset += entry.entry_size as usize;
+    // what a lenient parse let through, always empty for a strict one
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    // the sizes are the declared ones, from the central directory
+    #[cfg(feature = "zip")]
+    pub(crate) fn check_limits(&self, config: &EditorConfig) -> Result<(), ZipFormatError> {
+        if config.max_entries.is_some_and(|max_entries| self.entries.len() > max_entries) {
+            return Err(ZipFormatError{offset: 0, reason: "more entries than max_entries"});
+        }
+        match config.max_entry_size {
+            Some(max_entry_size) => match self.entries.iter().find(|entry| entry.origin_size > max_entry_size) {
+                Some(entry) => Err(ZipFormatError{
+                    offset: entry.central_directory_header_offset as usize,
+                    reason: "entry larger than max_entry_size"
+                }),
+                None => Ok(())
+            },
+            None => Ok(())
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "zip_parse", level = "debug", skip_all,
+        fields(bytes = buffer.len(), entries = tracing::field::Empty)))]
+    fn parse(buffer: Cow<'a, [u8]>, options: ParseOptions) -> Result<ZipFile<'a>,ZipFormatError> {
+        let mut context = ParseContext::new(options);
+        let (entries, names) = ZipFile::parse_directory(&buffer, 0, &mut context, |entry, context| entry.check_data(&buffer, context))?;
+        record!("entries", entries.len());
+        Ok(ZipFile{
+            data: buffer,
+            entries,
+            names,
+            warnings: context.warnings
+        })
+    }
+
+    // The entries of the central directory of a zip, `data` being the zip from `base` to its end.
+    // Offsets (entries, errors and warnings) are into the whole zip. `check` sees every entry before
+    // it's kept
+    fn parse_directory<F>(data: &[u8], base: usize, context: &mut ParseContext, check: F) -> Result<(Vec<ZipEntry>, NameIndex), ZipFormatError>
+        where F: Fn(&ZipEntry, &mut ParseContext) -> Result<(), ZipFormatError> {
+        let first_warning = context.warnings.len();
+        let res = ZipFile::parse_entries(data, base, context, check).map_err(|err| ZipFormatError{
+            offset: err.offset + base,
+            reason: err.reason
+        });
+        for warning in &mut context.warnings[first_warning..] {
+            warning.offset += base;
+        }
+        res
+    }
+
+    fn parse_entries<F>(data: &[u8], base: usize, context: &mut ParseContext, check: F) -> Result<(Vec<ZipEntry>, NameIndex), ZipFormatError>
+        where F: Fn(&ZipEntry, &mut ParseContext) -> Result<(), ZipFormatError> {
+        let (central_directory_offset, central_directory_end_offset, dir_count) = parse_end(data, base, context)?;
+        let mut entries: Vec<ZipEntry> = vec![];
+        let mut current_offset = central_directory_offset;
+        let mut parse_count = 0;
+        while parse_count < dir_count {
             parse_count += 1;
-            res.entries.push(entry);
+            let parsed = ZipFile::parse_entry(data, current_offset, context).and_then(|mut entry| {
+                entry.central_directory_header_offset += base as u32;
+                check(&entry, context).map(|_| entry)
+            });
+            let entry = match parsed {
+                Ok(entry) => entry,
+                Err(err) if context.recover(err.offset, &format!("skipped a central directory entry, {}", err.reason)) => {
+                    match next_entry_offset(data, current_offset, central_directory_end_offset) {
+                        Some(offset) => {
+                            current_offset = offset;
+                            continue;
+                        },
+                        None => break
+                    }
+                },
+                Err(err) => return Err(err)
+            };
+            current_offset += entry.entry_size as usize;
+            entries.push(entry);
         }
-        Ok(res)
+        let names = NameIndex::new(entries.len(), |idx| entries[idx].file_name.as_str(),
+            |idx| entries[idx].central_directory_header_offset as usize - base)?;
+        Ok((entries, names))
     }
 
+    // the central directory entry at `current_offset`
+    pub(crate) fn parse_entry(data: &[u8], current_offset: usize, context: &mut ParseContext) -> Result<ZipEntry, ZipFormatError> {
+        let out_of_file = || ZipFormatError{
+            offset: current_offset,
+            reason: "central directory entry out of the file"
+        };
+        let u16_at = |offset: usize| read_leu16(data, current_offset + offset).ok_or_else(out_of_file);
+        let u32_at = |offset: usize| read_leu32(data, current_offset + offset).ok_or_else(out_of_file);
+
+        if u32_at(0)? != CENTRAL_DIRECTORY {
+            return Err(ZipFormatError{
+                offset: current_offset,
+                reason: "magic of central directory error"
+            });
+        }
+
+        let file_name_len = u16_at(28)?;
+        let ext_len = u16_at(30)?;
+        let comment_len = u16_at(32)?;
+        let file_name_data = data.get((current_offset + 46)..(current_offset + 46 + file_name_len as usize)).ok_or_else(out_of_file)?.to_vec();
+        let file_name = match String::from_utf8(file_name_data){
+            Ok(v) => v,
+            Err(_) => return Err(ZipFormatError{
+                offset: current_offset,
+                reason: "convert string fail"
+            })
+        };
+        // packers set the encryption bit on plain entries, Android ignores it
+        if u16_at(8)? & FLAG_ENCRYPTED != 0 && !context.tolerate(current_offset, "entry flagged as encrypted") {
+            return Err(ZipFormatError{
+                offset: current_offset,
+                reason: "encrypted entry"
+            });
+        }
+        let compress_method = match CompressMethod::convert_from_u16(u16_at(10)?) {
+            Some(method) => method,
+            // Android inflates whatever isn't stored
+            None if context.tolerate(current_offset, "unknown compression method, read as deflated") => CompressMethod::Deflated,
+            None => return Err(ZipFormatError{
+                offset: current_offset,
+                reason: "unsupported compression method"
+            })
+        };
+        let entry = ZipEntry{
+            origin_size: u32_at(24)?,
+            compressed_size: u32_at(20)?,
+            file_name,
+            crc_32: u32_at(16)?,
+            compress_method,
+            modify_time: u32_at(12)?,
+            local_file_header_offset: u32_at(42)?,
+            central_directory_header_offset: current_offset as u32,
+            entry_size: 46 + file_name_len as u32 + ext_len as u32 + comment_len as u32,
+            ext_len
+        };
+        if !is_valid_dos_time(entry.modify_time) {
+            context.note(current_offset + 12, &format!("invalid modification time of {}", entry.file_name));
+        }
+        Ok(entry)
+    }
 }
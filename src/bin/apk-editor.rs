@@ -0,0 +1,346 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
+use clap::{Parser, Subcommand};
+use sha2::{Digest, Sha256};
+use apk_editor::apk_zip::{sign_v2, verify_v2, ApkFile, CompressMethod, SigningKey};
+use apk_editor::manifest::attrs::ANDROID_NAMESPACE;
+use apk_editor::manifest::manifest_editor::AndroidManifest;
+use apk_editor::manifest::XmlNode;
+use apk_editor::plan::{self, apply, PatchPlan};
+
+#[derive(Parser)]
+#[command(name = "apk-editor", version, about = "Inspect and edit Android APKs")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Package, versions, dex files and signature of an apk
+    Info {
+        apk: PathBuf
+    },
+    /// List the entries of an apk
+    List {
+        apk: PathBuf
+    },
+    /// Extract entries, all of them when none is named
+    Extract {
+        apk: PathBuf,
+        names: Vec<String>,
+        /// Directory to extract into
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf
+    },
+    /// Add a file to an apk, or replace the entry of that name
+    Add {
+        apk: PathBuf,
+        file: PathBuf,
+        /// Entry name, e.g. assets/config.json
+        name: String,
+        /// Store the entry uncompressed
+        #[arg(long)]
+        store: bool,
+        /// Where to write the apk, the input is overwritten by default
+        #[arg(short, long)]
+        output: Option<PathBuf>
+    },
+    /// Remove entries from an apk
+    Remove {
+        apk: PathBuf,
+        #[arg(required = true)]
+        names: Vec<String>,
+        #[arg(short, long)]
+        output: Option<PathBuf>
+    },
+    /// Read or replace AndroidManifest.xml
+    Manifest {
+        #[command(subcommand)]
+        command: ManifestCommand
+    },
+    /// Rewrite an apk with its stored entries aligned
+    Align {
+        apk: PathBuf,
+        /// Alignment in bytes, 4096 to map native libraries in place
+        #[arg(long, default_value_t = 4)]
+        align: usize,
+        #[arg(short, long)]
+        output: Option<PathBuf>
+    },
+    /// Sign an apk with APK Signature Scheme v2
+    Sign {
+        apk: PathBuf,
+        /// RSA private key, PKCS#8 or PKCS#1, DER or PEM
+        #[arg(long)]
+        key: PathBuf,
+        /// X.509 certificate of the key, DER or PEM
+        #[arg(long)]
+        cert: PathBuf,
+        #[arg(short, long)]
+        output: Option<PathBuf>
+    },
+    /// Check the v2 signature of an apk
+    Verify {
+        apk: PathBuf
+    },
+    /// Run a patch plan (JSON, or TOML by extension) on an apk
+    Apply {
+        apk: PathBuf,
+        plan: PathBuf,
+        /// Print the entries, manifest changes and signature the result would have, write nothing
+        #[arg(long)]
+        dry_run: bool,
+        #[arg(short, long)]
+        output: Option<PathBuf>
+    }
+}
+
+#[derive(Subcommand)]
+enum ManifestCommand {
+    /// Print the manifest as xml
+    Get {
+        apk: PathBuf,
+        /// Write the binary manifest to this file instead
+        #[arg(long)]
+        raw: Option<PathBuf>
+    },
+    /// Replace the manifest with a binary (compiled) one
+    Set {
+        apk: PathBuf,
+        file: PathBuf,
+        #[arg(short, long)]
+        output: Option<PathBuf>
+    }
+}
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|err| format!("{}: {}", path.display(), err).into())
+}
+
+fn open(data: &[u8]) -> Result<ApkFile<'_>> {
+    Ok(ApkFile::from(data)?)
+}
+
+// edits `apk` with `f` and writes it to `output`, or back over `apk`
+fn edit<F: FnOnce(&mut ApkFile) -> Result<()>>(apk: &Path, output: Option<&Path>, f: F) -> Result<()> {
+    let data = read(apk)?;
+    let mut file = open(&data)?;
+    f(&mut file)?;
+    let mut out = vec![];
+    file.save(&mut out)?;
+    fs::write(output.unwrap_or(apk), out)?;
+    Ok(())
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn attribute_value(node: &XmlNode, name: &str) -> Option<String> {
+    let attr = node.get_attr(Some(ANDROID_NAMESPACE), name)?;
+    Some(attr.string_value().map(String::from).unwrap_or_else(|| attr.typed_value().to_string()))
+}
+
+fn write_xml(node: &XmlNode, depth: usize, prefixes: &mut Vec<(String, String)>, out: &mut String) {
+    let indent = "    ".repeat(depth);
+    out.push_str(&format!("{}<{}", indent, node.tag_name()));
+    for namespace in node.namespaces() {
+        prefixes.push((String::from(namespace.uri()), String::from(namespace.prefix())));
+        out.push_str(&format!(" xmlns:{}=\"{}\"", namespace.prefix(), escape(namespace.uri())));
+    }
+    for attr in node.attrs() {
+        let name = match attr.namespace_uri() {
+            Some(uri) => {
+                let prefix = prefixes.iter().rev().find(|(item, _)| item == uri).map(|(_, prefix)| prefix.as_str()).unwrap_or(uri);
+                format!("{}:{}", prefix, attr.name())
+            },
+            None => String::from(attr.name())
+        };
+        let value = attr.string_value().map(String::from).unwrap_or_else(|| attr.typed_value().to_string());
+        out.push_str(&format!("\n{}    {}=\"{}\"", indent, name, escape(&value)));
+    }
+    if node.children().is_empty() {
+        out.push_str(" />\n");
+    } else {
+        out.push_str(">\n");
+        for child in node.children() {
+            write_xml(child, depth + 1, prefixes, out);
+        }
+        out.push_str(&format!("{}</{}>\n", indent, node.tag_name()));
+    }
+    prefixes.truncate(prefixes.len() - node.namespaces().len());
+}
+
+fn info(apk: &Path) -> Result<()> {
+    let data = read(apk)?;
+    let file = open(&data)?;
+    let manifest_data = file.get_file("AndroidManifest.xml").ok_or("no AndroidManifest.xml")?;
+    let manifest = AndroidManifest::from(&manifest_data)?;
+    let root = manifest.root_node();
+    println!("package: {}", manifest.package().unwrap_or("?"));
+    println!("versionCode: {}", attribute_value(root, "versionCode").unwrap_or_default());
+    println!("versionName: {}", attribute_value(root, "versionName").unwrap_or_default());
+    match manifest.min_sdk_version() {
+        Some(min_sdk) => println!("minSdkVersion: {}", min_sdk),
+        None => println!("minSdkVersion: preview")
+    }
+    println!("entries: {}", file.list_files().len());
+    for ((name, header), (_, counts)) in file.get_dex_headers().into_iter().zip(file.dex_ref_counts()) {
+        match (header, counts) {
+            (Ok(header), Ok(counts)) => println!("{}: version {}, {} classes, {} methods, {} fields",
+                name, header.version_string(), counts.classes, counts.methods, counts.fields),
+            (Err(err), _) | (_, Err(err)) => println!("{}: {}", name, err)
+        }
+    }
+    match verify_v2(&data) {
+        Ok(certificates) => println!("signature: v2, {} signer(s)", certificates.len()),
+        Err(err) => println!("signature: none ({})", err)
+    }
+    Ok(())
+}
+
+fn list(apk: &Path) -> Result<()> {
+    let data = read(apk)?;
+    for entry in open(&data)?.list_files() {
+        let method = match entry.compress_method {
+            CompressMethod::Stored => "stored",
+            CompressMethod::Deflated => "deflated"
+        };
+        println!("{:>10} {:>10} {:<8} {:08x} {}", entry.size, entry.compressed_size, method, entry.crc32, entry.name);
+    }
+    Ok(())
+}
+
+fn extract(apk: &Path, names: &[String], output: &Path) -> Result<()> {
+    let data = read(apk)?;
+    let file = open(&data)?;
+    let names: Vec<String> = if names.is_empty() {
+        file.list_files().into_iter().map(|entry| entry.name).filter(|name| !name.ends_with('/')).collect()
+    } else {
+        names.to_vec()
+    };
+    for name in names {
+        // entry names come from the apk, nothing may land outside `output`
+        let relative = Path::new(&name);
+        if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+            return Err(format!("{}: refusing to extract outside the output directory", name).into());
+        }
+        let content = file.get_file(&name).ok_or_else(|| format!("{}: not in the apk", name))?;
+        let path = output.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+fn manifest_command(command: ManifestCommand) -> Result<()> {
+    match command {
+        ManifestCommand::Get { apk, raw } => {
+            let data = read(&apk)?;
+            let manifest_data = open(&data)?.get_file("AndroidManifest.xml").ok_or("no AndroidManifest.xml")?;
+            match raw {
+                Some(path) => fs::write(path, manifest_data)?,
+                None => {
+                    let mut xml = String::new();
+                    write_xml(AndroidManifest::from(&manifest_data)?.root_node(), 0, &mut vec![], &mut xml);
+                    print!("{}", xml);
+                }
+            }
+            Ok(())
+        },
+        ManifestCommand::Set { apk, file, output } => {
+            let manifest_data = read(&file)?;
+            AndroidManifest::from(&manifest_data).map_err(|err| format!("{}: not a binary manifest, {}", file.display(), err))?;
+            edit(&apk, output.as_deref(), |apk| Ok(apk.set_manifest(&manifest_data)?))
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        Command::Info { apk } => info(&apk),
+        Command::List { apk } => list(&apk),
+        Command::Extract { apk, names, output } => extract(&apk, &names, &output),
+        Command::Add { apk, file, name, store, output } => {
+            let content = read(&file)?;
+            edit(&apk, output.as_deref(), |apk| {
+                if apk.get_file(&name).is_some() {
+                    apk.remove_file(&name)?;
+                }
+                let method = if store { CompressMethod::Stored } else { CompressMethod::Deflated };
+                apk.add_file(&name, content, method);
+                Ok(())
+            })
+        },
+        Command::Remove { apk, names, output } => edit(&apk, output.as_deref(), |apk| {
+            for name in &names {
+                apk.remove_file(name)?;
+            }
+            Ok(())
+        }),
+        Command::Manifest { command } => manifest_command(command),
+        Command::Align { apk, align, output } => {
+            let data = read(&apk)?;
+            let mut out = vec![];
+            open(&data)?.save_aligned(&mut out, align)?;
+            fs::write(output.as_deref().unwrap_or(&apk), out)?;
+            Ok(())
+        },
+        Command::Sign { apk, key, cert, output } => {
+            let key = SigningKey::from(&read(&key)?, &read(&cert)?)?;
+            let data = read(&apk)?;
+            let manifest_data = open(&data)?.get_file("AndroidManifest.xml").unwrap_or_default();
+            if let Some(min_sdk) = AndroidManifest::from(&manifest_data).ok().and_then(|manifest| manifest.min_sdk_version()) {
+                if min_sdk < 24 {
+                    eprintln!("warning: minSdkVersion {} is below 24, those devices need a v1 signature this tool doesn't write", min_sdk);
+                }
+            }
+            fs::write(output.as_deref().unwrap_or(&apk), sign_v2(&data, &key)?)?;
+            Ok(())
+        },
+        Command::Verify { apk } => {
+            for certificate in verify_v2(&read(&apk)?)? {
+                println!("signer certificate SHA-256: {}", hex(&Sha256::digest(&certificate)));
+            }
+            println!("verified");
+            Ok(())
+        },
+        Command::Apply { apk, plan, dry_run, output } => {
+            let patch_plan = PatchPlan::from_file(&plan)?;
+            let data = read(&apk)?;
+            if dry_run {
+                println!("{}", plan::dry_run(&patch_plan, &mut open(&data)?)?);
+                if patch_plan.sign.is_some() {
+                    println!("signed with v2");
+                }
+                return Ok(());
+            }
+            let mut out = vec![];
+            apply(&patch_plan, &mut open(&data)?, &mut out)?;
+            fs::write(output.as_deref().unwrap_or(&apk), out)?;
+            Ok(())
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("apk-editor: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
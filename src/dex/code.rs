@@ -0,0 +1,361 @@
+use crate::dex::reader::{push_sleb128, push_uleb128, push_uleb128p1, DexReader};
+use crate::dex::{DexError, IndexKind};
+use crate::utils::{push_leu16, push_leu32};
+
+const CONST_STRING: u16 = 0x1a;
+const CONST_STRING_JUMBO: u16 = 0x1b;
+
+const DBG_END_SEQUENCE: u8 = 0x00;
+const DBG_ADVANCE_PC: u8 = 0x01;
+const DBG_ADVANCE_LINE: u8 = 0x02;
+const DBG_START_LOCAL: u8 = 0x03;
+const DBG_START_LOCAL_EXTENDED: u8 = 0x04;
+const DBG_END_LOCAL: u8 = 0x05;
+const DBG_RESTART_LOCAL: u8 = 0x06;
+const DBG_SET_PROLOGUE_END: u8 = 0x07;
+const DBG_SET_EPILOGUE_BEGIN: u8 = 0x08;
+const DBG_SET_FILE: u8 = 0x09;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum DebugOp {
+    AdvancePc(u32),
+    AdvanceLine(i32),
+    StartLocal(u32, u32, u32), // register, name, type
+    StartLocalExtended(u32, u32, u32, u32), // register, name, type, signature
+    EndLocal(u32),
+    RestartLocal(u32),
+    SetPrologueEnd,
+    SetEpilogueBegin,
+    SetFile(u32),
+    Special(u8)
+}
+
+// debug_info_item, names and types are NO_INDEX when absent
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct DebugInfo {
+    pub(crate) line_start: u32,
+    pub(crate) parameter_names: Vec<u32>,
+    pub(crate) ops: Vec<DebugOp>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TryItem {
+    pub(crate) start_addr: u32,
+    pub(crate) insn_count: u16,
+    pub(crate) handler: usize // into Code::handlers
+}
+
+// encoded_catch_handler
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct CatchHandler {
+    pub(crate) catches: Vec<(u32, u32)>, // exception type, handler address
+    pub(crate) catch_all: Option<u32>
+}
+
+// code_item
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Code {
+    pub(crate) registers: u16,
+    pub(crate) ins: u16,
+    pub(crate) outs: u16,
+    pub(crate) debug_info: Option<DebugInfo>,
+    pub(crate) insns: Vec<u16>,
+    pub(crate) tries: Vec<TryItem>,
+    pub(crate) handlers: Vec<CatchHandler>
+}
+
+// the id operands of an opcode: what they index, which code unit they start at and whether they are 32 bit
+fn index_operands(opcode: u16) -> &'static [(IndexKind, usize, bool)] {
+    match opcode {
+        CONST_STRING => &[(IndexKind::String, 1, false)],
+        CONST_STRING_JUMBO => &[(IndexKind::String, 1, true)],
+        0x1c | 0x1f | 0x20 | 0x22..=0x25 => &[(IndexKind::Type, 1, false)],
+        0x52..=0x6d => &[(IndexKind::Field, 1, false)],
+        0x6e..=0x72 | 0x74..=0x78 => &[(IndexKind::Method, 1, false)],
+        0xfa | 0xfb => &[(IndexKind::Method, 1, false), (IndexKind::Proto, 3, false)],
+        0xfc | 0xfd => &[(IndexKind::CallSite, 1, false)],
+        0xfe => &[(IndexKind::MethodHandle, 1, false)],
+        0xff => &[(IndexKind::Proto, 1, false)],
+        _ => &[]
+    }
+}
+
+// size in code units of the instruction, or payload, at `pc`
+pub(crate) fn instruction_width(insns: &[u16], pc: usize) -> Result<usize, DexError> {
+    let unit = |offset: usize| insns.get(pc + offset).copied()
+        .ok_or_else(|| DexError::new(format!("instruction at {} runs past the code", pc)));
+    let width = match unit(0)? {
+        0x0100 => 4 + unit(1)? as usize * 2, // packed-switch-payload
+        0x0200 => 2 + unit(1)? as usize * 4, // sparse-switch-payload
+        0x0300 => { // fill-array-data-payload
+            let count = unit(2)? as usize | ((unit(3)? as usize) << 16);
+            4 + (count * unit(1)? as usize).div_ceil(2)
+        },
+        opcode => match opcode & 0xff {
+            0x00 | 0x01 | 0x04 | 0x07 | 0x0a..=0x12 | 0x1d | 0x1e | 0x21 | 0x27 | 0x28 | 0x3e..=0x43 | 0x73 | 0x79 | 0x7a
+            | 0x7b..=0x8f | 0xb0..=0xcf | 0xe3..=0xf9 => 1,
+            0x02 | 0x05 | 0x08 | 0x13 | 0x15 | 0x16 | 0x19 | 0x1a | 0x1c | 0x1f | 0x20 | 0x22 | 0x23 | 0x29 | 0x2d..=0x3d
+            | 0x44..=0x6d | 0x90..=0xaf | 0xd0..=0xe2 | 0xfe | 0xff => 2,
+            0x03 | 0x06 | 0x09 | 0x14 | 0x17 | 0x1b | 0x24..=0x26 | 0x2a..=0x2c | 0x6e..=0x72 | 0x74..=0x78 | 0xfc | 0xfd => 3,
+            0xfa | 0xfb => 4,
+            _ => 5 // const-wide
+        }
+    };
+    if pc + width > insns.len() {
+        return Err(DexError::new(format!("instruction at {} runs past the code", pc)));
+    }
+    Ok(width)
+}
+
+// calls `f` with the pc, opcode and code units of every instruction, payloads included
+pub(crate) fn for_each_instruction<F: FnMut(usize, u16, &[u16])>(insns: &[u16], mut f: F) -> Result<(), DexError> {
+    let mut pc = 0;
+    while pc < insns.len() {
+        let width = instruction_width(insns, pc)?;
+        let opcode = match insns[pc] {
+            0x0100 | 0x0200 | 0x0300 => insns[pc],
+            unit => unit & 0xff
+        };
+        f(pc, opcode, &insns[pc..pc + width]);
+        pc += width;
+    }
+    Ok(())
+}
+
+// the string a const-string or const-string/jumbo loads
+pub(crate) fn const_string_index(opcode: u16, units: &[u16]) -> Option<u32> {
+    match opcode {
+        CONST_STRING => Some(units[1] as u32),
+        CONST_STRING_JUMBO => Some(units[1] as u32 | ((units[2] as u32) << 16)),
+        _ => None
+    }
+}
+
+// the method an invoke-* calls
+pub(crate) fn invoked_method_index(opcode: u16, units: &[u16]) -> Option<u32> {
+    match opcode {
+        0x6e..=0x72 | 0x74..=0x78 | 0xfa | 0xfb => Some(units[1] as u32),
+        _ => None
+    }
+}
+
+impl DebugInfo {
+    fn parse(data: &[u8], offset: u32) -> Result<DebugInfo, DexError> {
+        let mut reader = DexReader::at(data, offset as usize);
+        let line_start = reader.uleb128()?;
+        let parameter_count = reader.uleb128()?;
+        let parameter_names = (0..parameter_count).map(|_| reader.uleb128p1()).collect::<Result<_, DexError>>()?;
+        let mut ops = vec![];
+        loop {
+            let op = match reader.u8()? {
+                DBG_END_SEQUENCE => break,
+                DBG_ADVANCE_PC => DebugOp::AdvancePc(reader.uleb128()?),
+                DBG_ADVANCE_LINE => DebugOp::AdvanceLine(reader.sleb128()?),
+                DBG_START_LOCAL => DebugOp::StartLocal(reader.uleb128()?, reader.uleb128p1()?, reader.uleb128p1()?),
+                DBG_START_LOCAL_EXTENDED => DebugOp::StartLocalExtended(reader.uleb128()?, reader.uleb128p1()?, reader.uleb128p1()?, reader.uleb128p1()?),
+                DBG_END_LOCAL => DebugOp::EndLocal(reader.uleb128()?),
+                DBG_RESTART_LOCAL => DebugOp::RestartLocal(reader.uleb128()?),
+                DBG_SET_PROLOGUE_END => DebugOp::SetPrologueEnd,
+                DBG_SET_EPILOGUE_BEGIN => DebugOp::SetEpilogueBegin,
+                DBG_SET_FILE => DebugOp::SetFile(reader.uleb128p1()?),
+                special => DebugOp::Special(special)
+            };
+            ops.push(op);
+        }
+        Ok(DebugInfo{
+            line_start,
+            parameter_names,
+            ops
+        })
+    }
+
+    pub(crate) fn write(&self, data: &mut Vec<u8>) {
+        push_uleb128(data, self.line_start);
+        push_uleb128(data, self.parameter_names.len() as u32);
+        self.parameter_names.iter().for_each(|name| push_uleb128p1(data, *name));
+        for op in &self.ops {
+            match op {
+                DebugOp::AdvancePc(addr_diff) => {
+                    data.push(DBG_ADVANCE_PC);
+                    push_uleb128(data, *addr_diff);
+                },
+                DebugOp::AdvanceLine(line_diff) => {
+                    data.push(DBG_ADVANCE_LINE);
+                    push_sleb128(data, *line_diff);
+                },
+                DebugOp::StartLocal(register, name, local_type) => {
+                    data.push(DBG_START_LOCAL);
+                    push_uleb128(data, *register);
+                    push_uleb128p1(data, *name);
+                    push_uleb128p1(data, *local_type);
+                },
+                DebugOp::StartLocalExtended(register, name, local_type, signature) => {
+                    data.push(DBG_START_LOCAL_EXTENDED);
+                    push_uleb128(data, *register);
+                    push_uleb128p1(data, *name);
+                    push_uleb128p1(data, *local_type);
+                    push_uleb128p1(data, *signature);
+                },
+                DebugOp::EndLocal(register) => {
+                    data.push(DBG_END_LOCAL);
+                    push_uleb128(data, *register);
+                },
+                DebugOp::RestartLocal(register) => {
+                    data.push(DBG_RESTART_LOCAL);
+                    push_uleb128(data, *register);
+                },
+                DebugOp::SetPrologueEnd => data.push(DBG_SET_PROLOGUE_END),
+                DebugOp::SetEpilogueBegin => data.push(DBG_SET_EPILOGUE_BEGIN),
+                DebugOp::SetFile(name) => {
+                    data.push(DBG_SET_FILE);
+                    push_uleb128p1(data, *name);
+                },
+                DebugOp::Special(op) => data.push(*op)
+            }
+        }
+        data.push(DBG_END_SEQUENCE);
+    }
+
+    fn visit_indices<F: FnMut(IndexKind, &mut u32)>(&mut self, f: &mut F) {
+        let mut visit = |kind: IndexKind, index: &mut u32| {
+            if *index != crate::dex::reader::NO_INDEX {
+                f(kind, index);
+            }
+        };
+        self.parameter_names.iter_mut().for_each(|name| visit(IndexKind::String, name));
+        for op in &mut self.ops {
+            match op {
+                DebugOp::StartLocal(_, name, local_type) => {
+                    visit(IndexKind::String, name);
+                    visit(IndexKind::Type, local_type);
+                },
+                DebugOp::StartLocalExtended(_, name, local_type, signature) => {
+                    visit(IndexKind::String, name);
+                    visit(IndexKind::Type, local_type);
+                    visit(IndexKind::String, signature);
+                },
+                DebugOp::SetFile(name) => visit(IndexKind::String, name),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Code {
+    pub(crate) fn parse(data: &[u8], offset: u32) -> Result<Code, DexError> {
+        let mut reader = DexReader::at(data, offset as usize);
+        let (registers, ins, outs, tries_size) = (reader.u16()?, reader.u16()?, reader.u16()?, reader.u16()?);
+        let debug_info = match reader.u32()? {
+            0 => None,
+            offset => Some(DebugInfo::parse(data, offset)?)
+        };
+        let insns_size = reader.u32()?;
+        let insns = (0..insns_size).map(|_| reader.u16()).collect::<Result<Vec<u16>, DexError>>()?;
+        if tries_size > 0 && insns_size % 2 == 1 {
+            reader.u16()?;
+        }
+        let tries = (0..tries_size).map(|_| Ok((reader.u32()?, reader.u16()?, reader.u16()?))).collect::<Result<Vec<_>, DexError>>()?;
+        // handler_off of a try is the byte offset of its handler from the start of the list
+        let mut handlers = vec![];
+        let mut handler_offsets = vec![];
+        if tries_size > 0 {
+            let list_start = reader.offset;
+            let size = reader.uleb128()?;
+            for _ in 0..size {
+                handler_offsets.push(reader.offset - list_start);
+                let catch_count = reader.sleb128()?;
+                let catches = (0..catch_count.unsigned_abs()).map(|_| Ok((reader.uleb128()?, reader.uleb128()?))).collect::<Result<_, DexError>>()?;
+                let catch_all = if catch_count <= 0 { Some(reader.uleb128()?) } else { None };
+                handlers.push(CatchHandler{
+                    catches,
+                    catch_all
+                });
+            }
+        }
+        let tries = tries.into_iter().map(|(start_addr, insn_count, handler_offset)| Ok(TryItem{
+            start_addr,
+            insn_count,
+            handler: handler_offsets.iter().position(|offset| *offset == handler_offset as usize)
+                .ok_or_else(|| DexError::new(format!("try at {} has no handler at 0x{:x}", start_addr, handler_offset)))?
+        })).collect::<Result<_, DexError>>()?;
+        Ok(Code{
+            registers,
+            ins,
+            outs,
+            debug_info,
+            insns,
+            tries,
+            handlers
+        })
+    }
+
+    // the code_item without the debug info, which is written to its own section first
+    pub(crate) fn write(&self, data: &mut Vec<u8>, debug_info_offset: u32) {
+        push_leu16(data, self.registers);
+        push_leu16(data, self.ins);
+        push_leu16(data, self.outs);
+        push_leu16(data, self.tries.len() as u16);
+        push_leu32(data, debug_info_offset);
+        push_leu32(data, self.insns.len() as u32);
+        self.insns.iter().for_each(|unit| push_leu16(data, *unit));
+        if self.tries.is_empty() {
+            return;
+        }
+        if self.insns.len() % 2 == 1 {
+            push_leu16(data, 0);
+        }
+        let mut list = vec![];
+        push_uleb128(&mut list, self.handlers.len() as u32);
+        let mut handler_offsets = vec![];
+        for handler in &self.handlers {
+            handler_offsets.push(list.len() as u16);
+            let count = handler.catches.len() as i32;
+            push_sleb128(&mut list, if handler.catch_all.is_some() { -count } else { count });
+            for (exception_type, addr) in &handler.catches {
+                push_uleb128(&mut list, *exception_type);
+                push_uleb128(&mut list, *addr);
+            }
+            if let Some(addr) = handler.catch_all {
+                push_uleb128(&mut list, addr);
+            }
+        }
+        for item in &self.tries {
+            push_leu32(data, item.start_addr);
+            push_leu16(data, item.insn_count);
+            push_leu16(data, handler_offsets[item.handler]);
+        }
+        data.extend_from_slice(&list);
+    }
+
+    // 16 bit operands that no longer fit are an error, widening an instruction would move every branch
+    // after it
+    pub(crate) fn visit_indices<F: FnMut(IndexKind, &mut u32)>(&mut self, f: &mut F) -> Result<(), DexError> {
+        let mut operands = vec![];
+        for_each_instruction(&self.insns, |pc, opcode, _| {
+            for (kind, unit, wide) in index_operands(opcode) {
+                operands.push((*kind, pc, pc + unit, *wide));
+            }
+        })?;
+        for (kind, pc, position, wide) in operands {
+            let mut index = self.insns[position] as u32;
+            if wide {
+                index |= (self.insns[position + 1] as u32) << 16;
+            }
+            f(kind, &mut index);
+            if !wide && index > 0xffff {
+                return Err(DexError::new(format!("{:?} index {} doesn't fit the instruction at {}", kind, index, pc)));
+            }
+            self.insns[position] = index as u16;
+            if wide {
+                self.insns[position + 1] = (index >> 16) as u16;
+            }
+        }
+        for handler in &mut self.handlers {
+            handler.catches.iter_mut().for_each(|(exception_type, _)| f(IndexKind::Type, exception_type));
+        }
+        if let Some(debug_info) = &mut self.debug_info {
+            debug_info.visit_indices(f);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,837 @@
+use std::collections::HashMap;
+use crate::dex::code::Code;
+use crate::dex::header::{header_size, HEADER_SIZE};
+use crate::dex::reader::{decode_mutf8, encode_mutf8, push_uleb128, DexReader, NO_INDEX};
+use crate::dex::value::{parse_array, write_array, AnnotationsDirectory, EncodedValue};
+use crate::dex::{fix_checksums, DexError, IndexKind};
+use crate::utils::{push_leu16, push_leu32};
+
+const ENDIAN_CONSTANT: u32 = 0x12345678;
+
+// map_list item types
+const TYPE_HEADER_ITEM: u16 = 0x0000;
+const TYPE_STRING_ID_ITEM: u16 = 0x0001;
+const TYPE_TYPE_ID_ITEM: u16 = 0x0002;
+const TYPE_PROTO_ID_ITEM: u16 = 0x0003;
+const TYPE_FIELD_ID_ITEM: u16 = 0x0004;
+const TYPE_METHOD_ID_ITEM: u16 = 0x0005;
+const TYPE_CLASS_DEF_ITEM: u16 = 0x0006;
+const TYPE_CALL_SITE_ID_ITEM: u16 = 0x0007;
+const TYPE_METHOD_HANDLE_ITEM: u16 = 0x0008;
+const TYPE_MAP_LIST: u16 = 0x1000;
+const TYPE_TYPE_LIST: u16 = 0x1001;
+const TYPE_ANNOTATION_SET_REF_LIST: u16 = 0x1002;
+const TYPE_ANNOTATION_SET_ITEM: u16 = 0x1003;
+const TYPE_CLASS_DATA_ITEM: u16 = 0x2000;
+const TYPE_CODE_ITEM: u16 = 0x2001;
+const TYPE_STRING_DATA_ITEM: u16 = 0x2002;
+const TYPE_DEBUG_INFO_ITEM: u16 = 0x2003;
+const TYPE_ANNOTATION_ITEM: u16 = 0x2004;
+const TYPE_ENCODED_ARRAY_ITEM: u16 = 0x2005;
+const TYPE_ANNOTATIONS_DIRECTORY_ITEM: u16 = 0x2006;
+const TYPE_HIDDENAPI_CLASS_DATA_ITEM: u16 = 0xf000;
+
+// fields are in sort order: return type, then parameters
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct ProtoId {
+    pub(crate) return_type: u32,
+    pub(crate) parameters: Vec<u32>,
+    pub(crate) shorty: u32
+}
+
+// class, then name, then type
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct FieldId {
+    pub(crate) class: u32,
+    pub(crate) name: u32,
+    pub(crate) field_type: u32
+}
+
+// class, then name, then proto
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct MethodId {
+    pub(crate) class: u32,
+    pub(crate) name: u32,
+    pub(crate) proto: u32
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EncodedField {
+    pub(crate) field: u32,
+    pub(crate) access_flags: u32
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EncodedMethod {
+    pub(crate) method: u32,
+    pub(crate) access_flags: u32,
+    pub(crate) code: Option<Code>
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ClassData {
+    pub(crate) static_fields: Vec<EncodedField>,
+    pub(crate) instance_fields: Vec<EncodedField>,
+    pub(crate) direct_methods: Vec<EncodedMethod>,
+    pub(crate) virtual_methods: Vec<EncodedMethod>
+}
+
+// superclass and source_file are NO_INDEX when absent
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ClassDef {
+    pub(crate) class: u32,
+    pub(crate) access_flags: u32,
+    pub(crate) superclass: u32,
+    pub(crate) interfaces: Vec<u32>,
+    pub(crate) source_file: u32,
+    pub(crate) annotations: Option<AnnotationsDirectory>,
+    pub(crate) class_data: Option<ClassData>,
+    pub(crate) static_values: Option<Vec<EncodedValue>>
+}
+
+// a whole dex read into id tables and class definitions. Indices stay plain numbers; after the tables
+// are edited, to_bytes sorts them again, drops what nothing references and renumbers every use
+#[derive(Clone, Debug, PartialEq)]
+pub struct DexFile {
+    pub(crate) version: u32,
+    pub(crate) strings: Vec<Vec<u16>>, // utf-16, which is also the sort order
+    pub(crate) types: Vec<u32>,
+    pub(crate) protos: Vec<ProtoId>,
+    pub(crate) fields: Vec<FieldId>,
+    pub(crate) methods: Vec<MethodId>,
+    pub(crate) classes: Vec<ClassDef>
+}
+
+fn parse_type_list(data: &[u8], offset: u32) -> Result<Vec<u32>, DexError> {
+    if offset == 0 {
+        return Ok(vec![]);
+    }
+    let mut reader = DexReader::at(data, offset as usize);
+    let size = reader.u32()?;
+    (0..size).map(|_| Ok(reader.u16()? as u32)).collect()
+}
+
+fn parse_class_data(data: &[u8], offset: u32) -> Result<ClassData, DexError> {
+    let mut reader = DexReader::at(data, offset as usize);
+    let sizes = [reader.uleb128()?, reader.uleb128()?, reader.uleb128()?, reader.uleb128()?];
+    let fields = |reader: &mut DexReader, count: u32| {
+        let mut index = 0u32;
+        (0..count).map(|_| {
+            index = index.wrapping_add(reader.uleb128()?);
+            Ok(EncodedField{
+                field: index,
+                access_flags: reader.uleb128()?
+            })
+        }).collect::<Result<Vec<_>, DexError>>()
+    };
+    let static_fields = fields(&mut reader, sizes[0])?;
+    let instance_fields = fields(&mut reader, sizes[1])?;
+    let methods = |reader: &mut DexReader, count: u32| {
+        let mut index = 0u32;
+        (0..count).map(|_| {
+            index = index.wrapping_add(reader.uleb128()?);
+            let access_flags = reader.uleb128()?;
+            let code = match reader.uleb128()? {
+                0 => None,
+                offset => Some(Code::parse(data, offset)?)
+            };
+            Ok(EncodedMethod{
+                method: index,
+                access_flags,
+                code
+            })
+        }).collect::<Result<Vec<_>, DexError>>()
+    };
+    let direct_methods = methods(&mut reader, sizes[2])?;
+    let virtual_methods = methods(&mut reader, sizes[3])?;
+    Ok(ClassData{
+        static_fields,
+        instance_fields,
+        direct_methods,
+        virtual_methods
+    })
+}
+
+// the old -> new index of every table, NO_INDEX for dropped entries
+struct IndexMap {
+    strings: Vec<u32>,
+    types: Vec<u32>,
+    protos: Vec<u32>,
+    fields: Vec<u32>,
+    methods: Vec<u32>
+}
+
+impl IndexMap {
+    fn table(&self, kind: IndexKind) -> &[u32] {
+        match kind {
+            IndexKind::String => &self.strings,
+            IndexKind::Type => &self.types,
+            IndexKind::Proto => &self.protos,
+            IndexKind::Field => &self.fields,
+            IndexKind::Method => &self.methods,
+            IndexKind::CallSite | IndexKind::MethodHandle => &[]
+        }
+    }
+}
+
+// sorted, deduplicated copy of `items` and where each old one went
+fn sorted_table<T: Clone + Ord>(items: &[T], used: &[bool]) -> (Vec<T>, Vec<u32>) {
+    let mut table: Vec<T> = items.iter().zip(used).filter(|(_, used)| **used).map(|(item, _)| item.clone()).collect();
+    table.sort();
+    table.dedup();
+    let map = items.iter().zip(used).map(|(item, used)| match used {
+        true => table.binary_search(item).map(|index| index as u32).unwrap_or(NO_INDEX),
+        false => NO_INDEX
+    }).collect();
+    (table, map)
+}
+
+// one item type of the data area as it is laid out, for the map list
+struct Section {
+    item_type: u16,
+    count: u32,
+    offset: u32
+}
+
+// the file being written, with the data sections appended one after another
+struct DexWriter {
+    data: Vec<u8>,
+    sections: Vec<Section>
+}
+
+impl DexWriter {
+    fn begin(&mut self, item_type: u16) {
+        self.sections.push(Section{
+            item_type,
+            count: 0,
+            offset: 0
+        });
+    }
+
+    fn item(&mut self, align: usize, bytes: &[u8]) -> u32 {
+        self.data.resize(self.data.len().next_multiple_of(align), 0);
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(bytes);
+        let section = self.sections.last_mut().expect("item outside a section");
+        if section.count == 0 {
+            section.offset = offset;
+        }
+        section.count += 1;
+        offset
+    }
+
+    // identical items are written once, for type lists and annotations that many places share
+    fn shared_item(&mut self, seen: &mut HashMap<Vec<u8>, u32>, align: usize, bytes: Vec<u8>) -> u32 {
+        if let Some(offset) = seen.get(&bytes) {
+            return *offset;
+        }
+        let offset = self.item(align, &bytes);
+        seen.insert(bytes, offset);
+        offset
+    }
+}
+
+// offsets of a directory's class set, field sets, method sets and parameter ref lists
+type DirectorySets = (u32, Vec<u32>, Vec<u32>, Vec<u32>);
+
+fn set_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+impl ClassDef {
+    pub(crate) fn methods_mut(&mut self) -> impl Iterator<Item = &mut EncodedMethod> {
+        self.class_data.iter_mut().flat_map(|class_data| class_data.direct_methods.iter_mut().chain(class_data.virtual_methods.iter_mut()))
+    }
+
+    pub(crate) fn methods(&self) -> impl Iterator<Item = &EncodedMethod> {
+        self.class_data.iter().flat_map(|class_data| class_data.direct_methods.iter().chain(class_data.virtual_methods.iter()))
+    }
+
+    fn visit_indices<F: FnMut(IndexKind, &mut u32)>(&mut self, f: &mut F) -> Result<(), DexError> {
+        f(IndexKind::Type, &mut self.class);
+        if self.superclass != NO_INDEX {
+            f(IndexKind::Type, &mut self.superclass);
+        }
+        self.interfaces.iter_mut().for_each(|interface| f(IndexKind::Type, interface));
+        if self.source_file != NO_INDEX {
+            f(IndexKind::String, &mut self.source_file);
+        }
+        if let Some(annotations) = &mut self.annotations {
+            annotations.visit_indices(f);
+        }
+        if let Some(class_data) = &mut self.class_data {
+            for field in class_data.static_fields.iter_mut().chain(class_data.instance_fields.iter_mut()) {
+                f(IndexKind::Field, &mut field.field);
+            }
+        }
+        for method in self.methods_mut() {
+            f(IndexKind::Method, &mut method.method);
+            if let Some(code) = &mut method.code {
+                code.visit_indices(f)?;
+            }
+        }
+        if let Some(values) = &mut self.static_values {
+            values.iter_mut().for_each(|value| value.visit_indices(f));
+        }
+        Ok(())
+    }
+}
+
+impl DexFile {
+    pub fn parse<T: AsRef<[u8]> + ?Sized>(data: &T) -> Result<DexFile, DexError> {
+        DexFile::parse_data(data.as_ref())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "dex_parse", level = "debug", skip_all,
+        fields(bytes = data.len(), classes = tracing::field::Empty)))]
+    fn parse_data(data: &[u8]) -> Result<DexFile, DexError> {
+        let header = crate::dex::validate(data)?;
+        record!("classes", header.class_defs.size);
+        let mut map = DexReader::at(data, header.map_offset as usize);
+        for _ in 0..map.u32()? {
+            let item_type = map.u16()?;
+            map.bytes(10)?;
+            if let TYPE_CALL_SITE_ID_ITEM | TYPE_METHOD_HANDLE_ITEM | TYPE_HIDDENAPI_CLASS_DATA_ITEM = item_type {
+                return Err(DexError::new(format!("map item type 0x{:04x} is not supported", item_type)));
+            }
+        }
+        if header.link.size != 0 {
+            return Err(DexError::new(String::from("linked dex files are not supported")));
+        }
+        let mut ids = DexReader::at(data, header.string_ids.offset as usize);
+        let strings = (0..header.string_ids.size).map(|_| {
+            let mut reader = DexReader::at(data, ids.u32()? as usize);
+            reader.uleb128()?;
+            let start = reader.offset;
+            while reader.u8()? != 0 {}
+            Ok(decode_mutf8(&data[start..reader.offset - 1]))
+        }).collect::<Result<_, DexError>>()?;
+        let mut ids = DexReader::at(data, header.type_ids.offset as usize);
+        let types = (0..header.type_ids.size).map(|_| ids.u32()).collect::<Result<_, DexError>>()?;
+        let mut ids = DexReader::at(data, header.proto_ids.offset as usize);
+        let protos = (0..header.proto_ids.size).map(|_| {
+            let (shorty, return_type, parameters_offset) = (ids.u32()?, ids.u32()?, ids.u32()?);
+            Ok(ProtoId{
+                return_type,
+                parameters: parse_type_list(data, parameters_offset)?,
+                shorty
+            })
+        }).collect::<Result<_, DexError>>()?;
+        let mut ids = DexReader::at(data, header.field_ids.offset as usize);
+        let fields = (0..header.field_ids.size).map(|_| {
+            let (class, field_type, name) = (ids.u16()? as u32, ids.u16()? as u32, ids.u32()?);
+            Ok(FieldId{
+                class,
+                name,
+                field_type
+            })
+        }).collect::<Result<_, DexError>>()?;
+        let mut ids = DexReader::at(data, header.method_ids.offset as usize);
+        let methods = (0..header.method_ids.size).map(|_| {
+            let (class, proto, name) = (ids.u16()? as u32, ids.u16()? as u32, ids.u32()?);
+            Ok(MethodId{
+                class,
+                name,
+                proto
+            })
+        }).collect::<Result<_, DexError>>()?;
+        let mut ids = DexReader::at(data, header.class_defs.offset as usize);
+        let classes = (0..header.class_defs.size).map(|_| {
+            let (class, access_flags, superclass, interfaces_offset) = (ids.u32()?, ids.u32()?, ids.u32()?, ids.u32()?);
+            let (source_file, annotations_offset, class_data_offset, static_values_offset) = (ids.u32()?, ids.u32()?, ids.u32()?, ids.u32()?);
+            Ok(ClassDef{
+                class,
+                access_flags,
+                superclass,
+                interfaces: parse_type_list(data, interfaces_offset)?,
+                source_file,
+                annotations: match annotations_offset {
+                    0 => None,
+                    offset => Some(AnnotationsDirectory::parse(data, offset)?)
+                },
+                class_data: match class_data_offset {
+                    0 => None,
+                    offset => Some(parse_class_data(data, offset)?)
+                },
+                static_values: match static_values_offset {
+                    0 => None,
+                    offset => Some(parse_array(&mut DexReader::at(data, offset as usize))?)
+                }
+            })
+        }).collect::<Result<_, DexError>>()?;
+        Ok(DexFile{
+            version: header.version,
+            strings,
+            types,
+            protos,
+            fields,
+            methods,
+            classes
+        })
+    }
+
+    pub(crate) fn string(&self, index: u32) -> Option<String> {
+        self.strings.get(index as usize).map(|units| String::from_utf16_lossy(units))
+    }
+
+    // "Lcom/foo/Bar;"
+    pub(crate) fn type_descriptor(&self, index: u32) -> Option<String> {
+        self.string(*self.types.get(index as usize)?)
+    }
+
+    // "Lcom/foo/Bar;->run(I)V"
+    pub(crate) fn method_descriptor(&self, index: u32) -> Option<String> {
+        let method = self.methods.get(index as usize)?;
+        let proto = self.protos.get(method.proto as usize)?;
+        let parameters = proto.parameters.iter().map(|parameter| self.type_descriptor(*parameter)).collect::<Option<String>>()?;
+        Some(format!("{}->{}({}){}", self.type_descriptor(method.class)?, self.string(method.name)?, parameters, self.type_descriptor(proto.return_type)?))
+    }
+
+    // index of `value` in the string table. A new string goes where it sorts and the strings after it
+    // move up one, so its index is final and fits the same instructions the others do
+    pub(crate) fn insert_string(&mut self, value: &str) -> Result<u32, DexError> {
+        let units: Vec<u16> = value.encode_utf16().collect();
+        if let Some(index) = self.strings.iter().position(|item| *item == units) {
+            return Ok(index as u32);
+        }
+        let index = self.strings.iter().position(|item| *item > units).unwrap_or(self.strings.len()) as u32;
+        let shift = |string: &mut u32| if *string >= index { *string += 1 };
+        self.visit_class_indices(&mut |kind, string| if kind == IndexKind::String { shift(string) })?;
+        self.types.iter_mut().for_each(shift);
+        self.protos.iter_mut().for_each(|proto| shift(&mut proto.shorty));
+        self.fields.iter_mut().for_each(|field| shift(&mut field.name));
+        self.methods.iter_mut().for_each(|method| shift(&mut method.name));
+        self.strings.insert(index as usize, units);
+        Ok(index)
+    }
+
+    // calls `f` on every index held by the class definitions, not the ones the id tables hold
+    pub(crate) fn visit_class_indices<F: FnMut(IndexKind, &mut u32)>(&mut self, f: &mut F) -> Result<(), DexError> {
+        for class in &mut self.classes {
+            class.visit_indices(f)?;
+        }
+        Ok(())
+    }
+
+    // which entries of each table are reachable from the class definitions
+    fn used(&mut self) -> Result<[Vec<bool>; 5], DexError> {
+        let mut used = [
+            vec![false; self.strings.len()],
+            vec![false; self.types.len()],
+            vec![false; self.protos.len()],
+            vec![false; self.fields.len()],
+            vec![false; self.methods.len()]
+        ];
+        let mut bad: Option<(IndexKind, u32)> = None;
+        let mut mark = |used: &mut [Vec<bool>; 5], kind: IndexKind, index: u32| {
+            let table = match kind {
+                IndexKind::String => &mut used[0],
+                IndexKind::Type => &mut used[1],
+                IndexKind::Proto => &mut used[2],
+                IndexKind::Field => &mut used[3],
+                IndexKind::Method => &mut used[4],
+                IndexKind::CallSite | IndexKind::MethodHandle => {
+                    bad.get_or_insert((kind, index));
+                    return;
+                }
+            };
+            match table.get_mut(index as usize) {
+                Some(item) => *item = true,
+                None => {
+                    bad.get_or_insert((kind, index));
+                }
+            }
+        };
+        self.visit_class_indices(&mut |kind, index| mark(&mut used, kind, *index))?;
+        for (method, _) in self.methods.iter().zip(used[4].clone()).filter(|(_, used)| *used) {
+            mark(&mut used, IndexKind::Type, method.class);
+            mark(&mut used, IndexKind::String, method.name);
+            mark(&mut used, IndexKind::Proto, method.proto);
+        }
+        for (field, _) in self.fields.iter().zip(used[3].clone()).filter(|(_, used)| *used) {
+            mark(&mut used, IndexKind::Type, field.class);
+            mark(&mut used, IndexKind::String, field.name);
+            mark(&mut used, IndexKind::Type, field.field_type);
+        }
+        for (proto, _) in self.protos.iter().zip(used[2].clone()).filter(|(_, used)| *used) {
+            mark(&mut used, IndexKind::String, proto.shorty);
+            mark(&mut used, IndexKind::Type, proto.return_type);
+            proto.parameters.iter().for_each(|parameter| mark(&mut used, IndexKind::Type, *parameter));
+        }
+        for (string, _) in self.types.iter().zip(used[1].clone()).filter(|(_, used)| *used) {
+            mark(&mut used, IndexKind::String, *string);
+        }
+        match bad {
+            Some((kind, index)) => Err(DexError::new(format!("{:?} index {} out of range", kind, index))),
+            None => Ok(used)
+        }
+    }
+
+    // puts the tables in the order the format requires, drops unreferenced and duplicate entries, and
+    // renumbers every use to match
+    pub(crate) fn normalize(&mut self) -> Result<(), DexError> {
+        let [used_strings, used_types, used_protos, used_fields, used_methods] = self.used()?;
+        let (strings, string_map) = sorted_table(&self.strings, &used_strings);
+        let types: Vec<u32> = self.types.iter().map(|string| string_map[*string as usize]).collect();
+        let (types, type_map) = sorted_table(&types, &used_types);
+        let protos: Vec<ProtoId> = self.protos.iter().map(|proto| ProtoId{
+            return_type: type_map[proto.return_type as usize],
+            parameters: proto.parameters.iter().map(|parameter| type_map[*parameter as usize]).collect(),
+            shorty: string_map[proto.shorty as usize]
+        }).collect();
+        let (protos, proto_map) = sorted_table(&protos, &used_protos);
+        let fields: Vec<FieldId> = self.fields.iter().map(|field| FieldId{
+            class: type_map[field.class as usize],
+            name: string_map[field.name as usize],
+            field_type: type_map[field.field_type as usize]
+        }).collect();
+        let (fields, field_map) = sorted_table(&fields, &used_fields);
+        let methods: Vec<MethodId> = self.methods.iter().map(|method| MethodId{
+            class: type_map[method.class as usize],
+            name: string_map[method.name as usize],
+            proto: proto_map[method.proto as usize]
+        }).collect();
+        let (methods, method_map) = sorted_table(&methods, &used_methods);
+
+        let map = IndexMap{
+            strings: string_map,
+            types: type_map,
+            protos: proto_map,
+            fields: field_map,
+            methods: method_map
+        };
+        self.visit_class_indices(&mut |kind, index| *index = map.table(kind)[*index as usize])?;
+        (self.strings, self.types, self.protos, self.fields, self.methods) = (strings, types, protos, fields, methods);
+        for (name, count) in [("type", self.types.len()), ("proto", self.protos.len()), ("field", self.fields.len()), ("method", self.methods.len())] {
+            if count > 0x10000 {
+                return Err(DexError::new(format!("{} {} references, at most 65536 fit in a dex", count, name)));
+            }
+        }
+
+        for class in &mut self.classes {
+            if let Some(class_data) = &mut class.class_data {
+                class_data.static_fields.sort_by_key(|field| field.field);
+                class_data.instance_fields.sort_by_key(|field| field.field);
+                class_data.direct_methods.sort_by_key(|method| method.method);
+                class_data.virtual_methods.sort_by_key(|method| method.method);
+            }
+            if let Some(annotations) = &mut class.annotations {
+                annotations.sort();
+            }
+        }
+        self.sort_classes()
+    }
+
+    // a superclass or interface defined in the same dex has to come before the classes using it
+    fn sort_classes(&mut self) -> Result<(), DexError> {
+        let mut defined: HashMap<u32, usize> = HashMap::new();
+        for (index, class) in self.classes.iter().enumerate() {
+            if defined.insert(class.class, index).is_some() {
+                return Err(DexError::new(format!("{} is defined twice", self.type_descriptor(class.class).unwrap_or_default())));
+            }
+        }
+        let mut order: Vec<usize> = Vec::with_capacity(self.classes.len());
+        let mut state = vec![0u8; self.classes.len()]; // 0 new, 1 in progress, 2 placed
+        fn place(classes: &[ClassDef], defined: &HashMap<u32, usize>, state: &mut [u8], order: &mut Vec<usize>, index: usize) -> bool {
+            match state[index] {
+                2 => return true,
+                1 => return false,
+                _ => state[index] = 1
+            }
+            let class = &classes[index];
+            for parent in std::iter::once(&class.superclass).chain(class.interfaces.iter()) {
+                if let Some(parent) = defined.get(parent) {
+                    if !place(classes, defined, state, order, *parent) {
+                        return false;
+                    }
+                }
+            }
+            state[index] = 2;
+            order.push(index);
+            true
+        }
+        for index in 0..self.classes.len() {
+            if !place(&self.classes, &defined, &mut state, &mut order, index) {
+                return Err(DexError::new(format!("{} inherits from itself", self.type_descriptor(self.classes[index].class).unwrap_or_default())));
+            }
+        }
+        let mut classes: Vec<Option<ClassDef>> = std::mem::take(&mut self.classes).into_iter().map(Some).collect();
+        self.classes = order.into_iter().filter_map(|index| classes[index].take()).collect();
+        Ok(())
+    }
+
+    // writes a normalized copy of the dex with fresh checksums
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DexError> {
+        let mut file = self.clone();
+        file.normalize()?;
+        file.write()
+    }
+
+    fn write(&self) -> Result<Vec<u8>, DexError> {
+        let header_size = header_size(self.version);
+        let string_ids_offset = header_size;
+        let type_ids_offset = string_ids_offset + self.strings.len() * 4;
+        let proto_ids_offset = type_ids_offset + self.types.len() * 4;
+        let field_ids_offset = proto_ids_offset + self.protos.len() * 12;
+        let method_ids_offset = field_ids_offset + self.fields.len() * 8;
+        let class_defs_offset = method_ids_offset + self.methods.len() * 8;
+        let data_offset = class_defs_offset + self.classes.len() * 32;
+        let mut writer = DexWriter{
+            data: vec![0u8; data_offset],
+            sections: vec![]
+        };
+
+        writer.begin(TYPE_STRING_DATA_ITEM);
+        let string_offsets: Vec<u32> = self.strings.iter().map(|units| {
+            let mut item = vec![];
+            push_uleb128(&mut item, units.len() as u32);
+            item.extend(encode_mutf8(units));
+            item.push(0);
+            writer.item(1, &item)
+        }).collect();
+
+        writer.begin(TYPE_TYPE_LIST);
+        let mut type_lists = HashMap::new();
+        let mut type_list = |writer: &mut DexWriter, types: &[u32]| {
+            if types.is_empty() {
+                return 0;
+            }
+            let mut item = vec![];
+            push_leu32(&mut item, types.len() as u32);
+            types.iter().for_each(|item_type| push_leu16(&mut item, *item_type as u16));
+            writer.shared_item(&mut type_lists, 4, item)
+        };
+        let parameter_offsets: Vec<u32> = self.protos.iter().map(|proto| type_list(&mut writer, &proto.parameters)).collect();
+        let interface_offsets: Vec<u32> = self.classes.iter().map(|class| type_list(&mut writer, &class.interfaces)).collect();
+
+        writer.begin(TYPE_DEBUG_INFO_ITEM);
+        let debug_offsets: Vec<u32> = self.classes.iter().flat_map(|class| class.methods())
+            .filter_map(|method| method.code.as_ref())
+            .map(|code| match &code.debug_info {
+                Some(debug_info) => {
+                    let mut item = vec![];
+                    debug_info.write(&mut item);
+                    writer.item(1, &item)
+                },
+                None => 0
+            })
+            .collect();
+
+        writer.begin(TYPE_CODE_ITEM);
+        let code_offsets: Vec<u32> = self.classes.iter().flat_map(|class| class.methods())
+            .filter_map(|method| method.code.as_ref())
+            .zip(debug_offsets)
+            .map(|(code, debug_offset)| {
+                let mut item = vec![];
+                code.write(&mut item, debug_offset);
+                writer.item(4, &item)
+            })
+            .collect();
+
+        writer.begin(TYPE_ANNOTATION_ITEM);
+        let mut annotation_items = HashMap::new();
+        let mut annotation_offsets: HashMap<Vec<u8>, u32> = HashMap::new();
+        let directories: Vec<&AnnotationsDirectory> = self.classes.iter().filter_map(|class| class.annotations.as_ref()).collect();
+        let all_sets = directories.iter().flat_map(|directory| directory.class_annotations.iter()
+            .chain(directory.fields.iter().map(|(_, set)| set))
+            .chain(directory.methods.iter().map(|(_, set)| set))
+            .chain(directory.parameters.iter().flat_map(|(_, sets)| sets.iter().flatten())));
+        for annotation in all_sets.clone().flatten() {
+            let mut item = vec![];
+            annotation.write(&mut item);
+            let offset = writer.shared_item(&mut annotation_items, 1, item.clone());
+            annotation_offsets.insert(item, offset);
+        }
+
+        writer.begin(TYPE_ANNOTATION_SET_ITEM);
+        let mut annotation_sets = HashMap::new();
+        let mut set_offsets: Vec<u32> = vec![];
+        for set in all_sets {
+            let mut item = vec![];
+            push_leu32(&mut item, set.len() as u32);
+            for annotation in set {
+                let mut bytes = vec![];
+                annotation.write(&mut bytes);
+                push_leu32(&mut item, annotation_offsets[&bytes]);
+            }
+            set_offsets.push(writer.shared_item(&mut annotation_sets, 4, item));
+        }
+
+        // sets were written in directory order, class, fields, methods then parameters
+        let mut next_set = set_offsets.into_iter();
+        writer.begin(TYPE_ANNOTATION_SET_REF_LIST);
+        let mut directory_sets: Vec<DirectorySets> = vec![];
+        for directory in &directories {
+            let class_set = match directory.class_annotations {
+                Some(_) => next_set.next().unwrap_or(0),
+                None => 0
+            };
+            let field_sets = directory.fields.iter().map(|_| next_set.next().unwrap_or(0)).collect();
+            let method_sets = directory.methods.iter().map(|_| next_set.next().unwrap_or(0)).collect();
+            let ref_lists = directory.parameters.iter().map(|(_, sets)| {
+                let mut item = vec![];
+                push_leu32(&mut item, sets.len() as u32);
+                for set in sets {
+                    push_leu32(&mut item, if set.is_some() { next_set.next().unwrap_or(0) } else { 0 });
+                }
+                writer.item(4, &item)
+            }).collect();
+            directory_sets.push((class_set, field_sets, method_sets, ref_lists));
+        }
+
+        writer.begin(TYPE_ANNOTATIONS_DIRECTORY_ITEM);
+        let mut directory_offsets = directories.iter().zip(directory_sets).map(|(directory, (class_set, field_sets, method_sets, ref_lists))| {
+            let mut item = vec![];
+            push_leu32(&mut item, class_set);
+            push_leu32(&mut item, directory.fields.len() as u32);
+            push_leu32(&mut item, directory.methods.len() as u32);
+            push_leu32(&mut item, directory.parameters.len() as u32);
+            for ((field, _), set) in directory.fields.iter().zip(field_sets) {
+                push_leu32(&mut item, *field);
+                push_leu32(&mut item, set);
+            }
+            for ((method, _), set) in directory.methods.iter().zip(method_sets) {
+                push_leu32(&mut item, *method);
+                push_leu32(&mut item, set);
+            }
+            for ((method, _), list) in directory.parameters.iter().zip(ref_lists) {
+                push_leu32(&mut item, *method);
+                push_leu32(&mut item, list);
+            }
+            writer.item(4, &item)
+        }).collect::<Vec<u32>>().into_iter();
+
+        writer.begin(TYPE_ENCODED_ARRAY_ITEM);
+        let mut arrays = HashMap::new();
+        let static_value_offsets: Vec<u32> = self.classes.iter().map(|class| match &class.static_values {
+            Some(values) => {
+                let mut item = vec![];
+                write_array(values, &mut item);
+                writer.shared_item(&mut arrays, 1, item)
+            },
+            None => 0
+        }).collect();
+
+        writer.begin(TYPE_CLASS_DATA_ITEM);
+        let mut next_code = code_offsets.into_iter();
+        let class_data_offsets: Vec<u32> = self.classes.iter().map(|class| {
+            let class_data = match &class.class_data {
+                Some(class_data) => class_data,
+                None => return 0
+            };
+            let mut item = vec![];
+            push_uleb128(&mut item, class_data.static_fields.len() as u32);
+            push_uleb128(&mut item, class_data.instance_fields.len() as u32);
+            push_uleb128(&mut item, class_data.direct_methods.len() as u32);
+            push_uleb128(&mut item, class_data.virtual_methods.len() as u32);
+            for fields in [&class_data.static_fields, &class_data.instance_fields] {
+                let mut previous = 0;
+                for field in fields {
+                    push_uleb128(&mut item, field.field - previous);
+                    push_uleb128(&mut item, field.access_flags);
+                    previous = field.field;
+                }
+            }
+            for methods in [&class_data.direct_methods, &class_data.virtual_methods] {
+                let mut previous = 0;
+                for method in methods {
+                    push_uleb128(&mut item, method.method - previous);
+                    push_uleb128(&mut item, method.access_flags);
+                    push_uleb128(&mut item, if method.code.is_some() { next_code.next().unwrap_or(0) } else { 0 });
+                    previous = method.method;
+                }
+            }
+            writer.item(1, &item)
+        }).collect();
+
+        writer.begin(TYPE_MAP_LIST);
+        let map_offset = writer.data.len().next_multiple_of(4) as u32;
+        let mut sections = vec![
+            Section{ item_type: TYPE_HEADER_ITEM, count: 1, offset: 0 },
+            Section{ item_type: TYPE_STRING_ID_ITEM, count: self.strings.len() as u32, offset: string_ids_offset as u32 },
+            Section{ item_type: TYPE_TYPE_ID_ITEM, count: self.types.len() as u32, offset: type_ids_offset as u32 },
+            Section{ item_type: TYPE_PROTO_ID_ITEM, count: self.protos.len() as u32, offset: proto_ids_offset as u32 },
+            Section{ item_type: TYPE_FIELD_ID_ITEM, count: self.fields.len() as u32, offset: field_ids_offset as u32 },
+            Section{ item_type: TYPE_METHOD_ID_ITEM, count: self.methods.len() as u32, offset: method_ids_offset as u32 },
+            Section{ item_type: TYPE_CLASS_DEF_ITEM, count: self.classes.len() as u32, offset: class_defs_offset as u32 }
+        ];
+        sections.append(&mut writer.sections);
+        sections.retain(|section| section.count > 0 || section.item_type == TYPE_MAP_LIST);
+        let mut map = vec![];
+        push_leu32(&mut map, sections.len() as u32);
+        for section in &sections {
+            push_leu16(&mut map, section.item_type);
+            push_leu16(&mut map, 0);
+            push_leu32(&mut map, if section.item_type == TYPE_MAP_LIST { 1 } else { section.count });
+            push_leu32(&mut map, if section.item_type == TYPE_MAP_LIST { map_offset } else { section.offset });
+        }
+        writer.sections = vec![];
+        writer.begin(TYPE_MAP_LIST);
+        writer.item(4, &map);
+
+        let mut data = writer.data;
+        let id_offset = |size: usize, offset: usize| if size == 0 { 0 } else { offset as u32 };
+        data[0..8].copy_from_slice(format!("dex\n{:03}\0", self.version).as_bytes());
+        let header_fields = [
+            (32, data.len() as u32),
+            (36, header_size as u32),
+            (40, ENDIAN_CONSTANT),
+            (52, map_offset),
+            (56, self.strings.len() as u32),
+            (60, id_offset(self.strings.len(), string_ids_offset)),
+            (64, self.types.len() as u32),
+            (68, id_offset(self.types.len(), type_ids_offset)),
+            (72, self.protos.len() as u32),
+            (76, id_offset(self.protos.len(), proto_ids_offset)),
+            (80, self.fields.len() as u32),
+            (84, id_offset(self.fields.len(), field_ids_offset)),
+            (88, self.methods.len() as u32),
+            (92, id_offset(self.methods.len(), method_ids_offset)),
+            (96, self.classes.len() as u32),
+            (100, id_offset(self.classes.len(), class_defs_offset)),
+            (104, (data.len() - data_offset) as u32),
+            (108, data_offset as u32)
+        ];
+        for (offset, value) in header_fields {
+            set_u32(&mut data, offset, value);
+        }
+        if header_size > HEADER_SIZE {
+            // a container of just this dex
+            let container_size = data.len() as u32;
+            set_u32(&mut data, 112, container_size);
+        }
+
+        for (index, offset) in string_offsets.into_iter().enumerate() {
+            set_u32(&mut data, string_ids_offset + index * 4, offset);
+        }
+        for (index, string) in self.types.iter().enumerate() {
+            set_u32(&mut data, type_ids_offset + index * 4, *string);
+        }
+        for (index, (proto, parameters)) in self.protos.iter().zip(parameter_offsets).enumerate() {
+            let offset = proto_ids_offset + index * 12;
+            set_u32(&mut data, offset, proto.shorty);
+            set_u32(&mut data, offset + 4, proto.return_type);
+            set_u32(&mut data, offset + 8, parameters);
+        }
+        for (index, field) in self.fields.iter().enumerate() {
+            let offset = field_ids_offset + index * 8;
+            set_u32(&mut data, offset, field.class | (field.field_type << 16));
+            set_u32(&mut data, offset + 4, field.name);
+        }
+        for (index, method) in self.methods.iter().enumerate() {
+            let offset = method_ids_offset + index * 8;
+            set_u32(&mut data, offset, method.class | (method.proto << 16));
+            set_u32(&mut data, offset + 4, method.name);
+        }
+        let class_items = self.classes.iter().zip(interface_offsets).zip(static_value_offsets).zip(class_data_offsets);
+        for (index, (((class, interfaces), static_values), class_data)) in class_items.enumerate() {
+            let annotations = if class.annotations.is_some() { directory_offsets.next().unwrap_or(0) } else { 0 };
+            let values = [class.class, class.access_flags, class.superclass, interfaces, class.source_file, annotations, class_data, static_values];
+            for (field, value) in values.into_iter().enumerate() {
+                set_u32(&mut data, class_defs_offset + index * 32 + field * 4, value);
+            }
+        }
+        fix_checksums(&mut data)?;
+        Ok(data)
+    }
+}
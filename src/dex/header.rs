@@ -0,0 +1,190 @@
+use crate::dex::{adler32, DexError};
+use crate::utils::{get_leu16_value, get_leu32_value};
+
+pub(crate) const HEADER_SIZE: usize = 0x70;
+// 041 adds container_size and header_offset after the data section
+const CONTAINER_HEADER_SIZE: usize = 0x78;
+const CONTAINER_VERSION: u32 = 41;
+const ENDIAN_CONSTANT: u32 = 0x12345678;
+const SUPPORTED_VERSIONS: [u32; 6] = [35, 37, 38, 39, 40, 41];
+
+// map_list item types whose counts the header repeats
+const TYPE_HEADER_ITEM: u16 = 0x0000;
+const TYPE_STRING_ID_ITEM: u16 = 0x0001;
+const TYPE_TYPE_ID_ITEM: u16 = 0x0002;
+const TYPE_PROTO_ID_ITEM: u16 = 0x0003;
+const TYPE_FIELD_ID_ITEM: u16 = 0x0004;
+const TYPE_METHOD_ID_ITEM: u16 = 0x0005;
+const TYPE_CLASS_DEF_ITEM: u16 = 0x0006;
+
+// size and offset pair of the header
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DexSection {
+    pub size: u32,
+    pub offset: u32
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DexHeader {
+    pub version: u32, // 35 for "dex\n035\0"
+    pub checksum: u32, // adler32 of everything after this field
+    pub signature: [u8; 20], // sha-1 of everything after this field
+    pub file_size: u32,
+    pub header_size: u32,
+    pub endian_tag: u32,
+    pub link: DexSection,
+    pub map_offset: u32,
+    pub string_ids: DexSection,
+    pub type_ids: DexSection,
+    pub proto_ids: DexSection,
+    pub field_ids: DexSection,
+    pub method_ids: DexSection,
+    pub class_defs: DexSection,
+    pub data: DexSection,
+    pub container_size: u32, // 041 and later, 0 before
+    pub header_offset: u32 // 041 and later, where this header sits in the container
+}
+
+fn section(data: &[u8], offset: usize) -> DexSection {
+    DexSection{
+        size: get_leu32_value(data, offset),
+        offset: get_leu32_value(data, offset + 4)
+    }
+}
+
+impl DexHeader {
+    // just the header fields, see validate for checking them against the file
+    pub fn parse(data: &[u8]) -> Result<DexHeader, DexError> {
+        if data.len() < HEADER_SIZE {
+            return Err(DexError::new(format!("{} bytes is too short for a dex header", data.len())));
+        }
+        let magic = &data[0..8];
+        if &magic[0..4] != b"dex\n" || magic[7] != 0 || !magic[4..7].iter().all(|c| c.is_ascii_digit()) {
+            return Err(DexError::new(String::from("bad magic")));
+        }
+        let version = magic[4..7].iter().fold(0, |res, c| res * 10 + (c - b'0') as u32);
+        let mut signature = [0u8; 20];
+        signature.copy_from_slice(&data[12..32]);
+        Ok(DexHeader{
+            version,
+            checksum: get_leu32_value(data, 8),
+            signature,
+            file_size: get_leu32_value(data, 32),
+            header_size: get_leu32_value(data, 36),
+            endian_tag: get_leu32_value(data, 40),
+            link: section(data, 44),
+            map_offset: get_leu32_value(data, 52),
+            string_ids: section(data, 56),
+            type_ids: section(data, 64),
+            proto_ids: section(data, 72),
+            field_ids: section(data, 80),
+            method_ids: section(data, 88),
+            class_defs: section(data, 96),
+            data: section(data, 104),
+            container_size: if version >= CONTAINER_VERSION { get_leu32_value(data, 112) } else { 0 },
+            header_offset: if version >= CONTAINER_VERSION { get_leu32_value(data, 116) } else { 0 }
+        })
+    }
+
+    // "035"
+    pub fn version_string(&self) -> String {
+        format!("{:03}", self.version)
+    }
+
+    // the first API level whose runtime loads this version: 037 came with 7.0, 038 with 8.0, 039 with
+    // 9.0, 040 with 10 and 041 (dex containers) with 15
+    pub fn min_api_level(&self) -> u32 {
+        match self.version {
+            0..=36 => 1,
+            37 => 24,
+            38 => 26,
+            39 => 28,
+            40 => 29,
+            _ => 35
+        }
+    }
+}
+
+// the header size a dex of `version` has, 0x78 from 041 on
+pub(crate) fn header_size(version: u32) -> usize {
+    if version >= CONTAINER_VERSION { CONTAINER_HEADER_SIZE } else { HEADER_SIZE }
+}
+
+// parses the header and checks the invariants ART checks before anything else: version, sizes, the
+// checksum, id sections inside the file and agreeing with the map list
+pub fn validate(data: &[u8]) -> Result<DexHeader, DexError> {
+    let header = DexHeader::parse(data)?;
+    if !SUPPORTED_VERSIONS.contains(&header.version) {
+        return Err(DexError::new(format!("unknown dex version {}", header.version_string())));
+    }
+    if header.endian_tag == ENDIAN_CONSTANT.swap_bytes() {
+        return Err(DexError::new(String::from("byte swapped dex files are not supported")));
+    }
+    if header.endian_tag != ENDIAN_CONSTANT {
+        return Err(DexError::new(format!("bad endian tag 0x{:08x}", header.endian_tag)));
+    }
+    let header_size = header_size(header.version);
+    if header.header_size as usize != header_size || data.len() < header_size {
+        return Err(DexError::new(format!("header size 0x{:x}", header.header_size)));
+    }
+    if header.version >= CONTAINER_VERSION && (header.header_offset != 0 || header.container_size != header.file_size) {
+        return Err(DexError::new(String::from("containers of more than one dex are not supported")));
+    }
+    if header.file_size as usize != data.len() {
+        return Err(DexError::new(format!("file size {} in the header, {} bytes given", header.file_size, data.len())));
+    }
+    if adler32(&data[12..]) != header.checksum {
+        return Err(DexError::new(String::from("checksum mismatch")));
+    }
+    if header.type_ids.size > 0x10000 || header.proto_ids.size > 0x10000 {
+        return Err(DexError::new(String::from("more than 65536 type or proto ids")));
+    }
+
+    let id_sections = [
+        ("string_ids", header.string_ids, 4, TYPE_STRING_ID_ITEM),
+        ("type_ids", header.type_ids, 4, TYPE_TYPE_ID_ITEM),
+        ("proto_ids", header.proto_ids, 12, TYPE_PROTO_ID_ITEM),
+        ("field_ids", header.field_ids, 8, TYPE_FIELD_ID_ITEM),
+        ("method_ids", header.method_ids, 8, TYPE_METHOD_ID_ITEM),
+        ("class_defs", header.class_defs, 32, TYPE_CLASS_DEF_ITEM)
+    ];
+    for (name, section, item_size, _) in id_sections {
+        if section.size == 0 {
+            continue;
+        }
+        let end = section.offset as u64 + section.size as u64 * item_size;
+        if (section.offset as usize) < header_size || !section.offset.is_multiple_of(4) || end > data.len() as u64 {
+            return Err(DexError::new(format!("{} section out of the file", name)));
+        }
+    }
+    if header.data.offset as u64 + header.data.size as u64 > data.len() as u64 {
+        return Err(DexError::new(String::from("data section out of the file")));
+    }
+
+    let map_offset = header.map_offset as usize;
+    if map_offset < header_size || !map_offset.is_multiple_of(4) || map_offset + 4 > data.len() {
+        return Err(DexError::new(String::from("map list out of the file")));
+    }
+    let map_size = get_leu32_value(data, map_offset) as usize;
+    if map_offset + 4 + map_size * 12 > data.len() {
+        return Err(DexError::new(String::from("map list out of the file")));
+    }
+    let map_items: Vec<(u16, u32, u32)> = (0..map_size).map(|i| {
+        let offset = map_offset + 4 + i * 12;
+        (get_leu16_value(data, offset), get_leu32_value(data, offset + 4), get_leu32_value(data, offset + 8))
+    }).collect();
+    if map_items.first().map(|(item_type, _, offset)| (*item_type, *offset)) != Some((TYPE_HEADER_ITEM, 0)) {
+        return Err(DexError::new(String::from("map list doesn't start with the header")));
+    }
+    for (name, section, _, item_type) in id_sections {
+        let mapped = map_items.iter().find(|(map_type, _, _)| *map_type == item_type)
+            .map(|(_, size, offset)| DexSection{ size: *size, offset: *offset })
+            .unwrap_or_default();
+        if mapped.size != section.size || (section.size > 0 && mapped.offset != section.offset) {
+            return Err(DexError::new(format!("{} in the header and the map list disagree", name)));
+        }
+    }
+    Ok(header)
+}
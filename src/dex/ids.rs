@@ -0,0 +1,41 @@
+use crate::dex::reader::{decode_mutf8, DexReader, NO_INDEX};
+use crate::dex::{DexError, DexHeader};
+
+// offset of id item `index` of a section with `item_size` byte items, bounds checked
+fn id_item(data: &[u8], size: u32, offset: u32, item_size: usize, index: u32, name: &str) -> Result<usize, DexError> {
+    let item = offset as usize + index as usize * item_size;
+    if index >= size || item + item_size > data.len() {
+        return Err(DexError::new(format!("{} index {} out of range", name, index)));
+    }
+    Ok(item)
+}
+
+// string_ids[`index`]
+pub(crate) fn string_at(data: &[u8], header: &DexHeader, index: u32) -> Result<String, DexError> {
+    let item = id_item(data, header.string_ids.size, header.string_ids.offset, 4, index, "string")?;
+    let mut reader = DexReader::at(data, DexReader::at(data, item).u32()? as usize);
+    // utf-16 length, not the byte length
+    reader.uleb128()?;
+    let start = reader.offset;
+    let len = data.get(start..).and_then(|rest| rest.iter().position(|byte| *byte == 0))
+        .ok_or_else(|| DexError::new(format!("string {} isn't terminated", index)))?;
+    Ok(String::from_utf16_lossy(&decode_mutf8(&data[start..start + len])))
+}
+
+// descriptor of type_ids[`index`], "Lcom/foo/Bar;"
+pub(crate) fn type_at(data: &[u8], header: &DexHeader, index: u32) -> Result<String, DexError> {
+    let item = id_item(data, header.type_ids.size, header.type_ids.offset, 4, index, "type")?;
+    string_at(data, header, DexReader::at(data, item).u32()?)
+}
+
+// descriptors of the classes defined in the dex, in class_defs order
+pub fn class_names(data: &[u8]) -> Result<Vec<String>, DexError> {
+    let header = DexHeader::parse(data)?;
+    (0..header.class_defs.size).map(|index| {
+        let item = id_item(data, header.class_defs.size, header.class_defs.offset, 32, index, "class_def")?;
+        match DexReader::at(data, item).u32()? {
+            NO_INDEX => Err(DexError::new(format!("class_def {} has no type", index))),
+            type_index => type_at(data, &header, type_index)
+        }
+    }).collect()
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use crate::dex::{DexError, DexFile};
+
+// the renamed form of `descriptor` if its class is in `map`: "Lold;" and arrays of it ("[[Lold;"),
+// and the "Lold<" a generic Signature annotation splits off
+fn map_descriptor(descriptor: &str, map: &HashMap<&str, &str>) -> Option<String> {
+    let class = descriptor.trim_start_matches('[');
+    let dimensions = &descriptor[..descriptor.len() - class.len()];
+    if let Some(new_class) = map.get(class) {
+        return Some(format!("{}{}", dimensions, new_class));
+    }
+    let generic = class.strip_suffix('<')?;
+    let new_class = map.get(format!("{};", generic).as_str())?;
+    Some(format!("{}{}<", dimensions, new_class.strip_suffix(';')?))
+}
+
+impl DexFile {
+    // renames classes by descriptor, ("Lcom/foo/Bar;", "Lcom/bar/Bar;"). Every string spelling a mapped
+    // descriptor is rewritten, which covers the type table and the signature annotations; members keep
+    // their names. Returns how many strings changed
+    pub fn apply_mapping(&mut self, map: &[(&str, &str)]) -> Result<usize, DexError> {
+        for (from, to) in map {
+            for descriptor in [from, to] {
+                if !descriptor.starts_with('L') || !descriptor.ends_with(';') || descriptor.len() < 3 {
+                    return Err(DexError::new(format!("{} is not a class descriptor", descriptor)));
+                }
+            }
+        }
+        let map: HashMap<&str, &str> = map.iter().copied().collect();
+        let mut count = 0;
+        for units in &mut self.strings {
+            if let Some(renamed) = map_descriptor(&String::from_utf16_lossy(units), &map) {
+                *units = renamed.encode_utf16().collect();
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+// `data` with its classes renamed by `map`, see DexFile::apply_mapping
+pub fn apply_mapping(data: &[u8], map: &[(&str, &str)]) -> Result<Vec<u8>, DexError> {
+    let mut file = DexFile::parse(data)?;
+    file.apply_mapping(map)?;
+    file.to_bytes()
+}
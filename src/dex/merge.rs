@@ -0,0 +1,56 @@
+use crate::dex::{DexError, DexFile, IndexKind};
+
+impl DexFile {
+    // appends the tables and classes of `other`, duplicates between the two are folded when the dex
+    // is written. A class defined in both is an error
+    pub fn merge(&mut self, other: &DexFile) -> Result<(), DexError> {
+        for class in &other.classes {
+            let name = other.type_descriptor(class.class).unwrap_or_default();
+            if self.classes.iter().any(|item| self.type_descriptor(item.class).as_deref() == Some(name.as_str())) {
+                return Err(DexError::new(format!("{} is defined in both dex files", name)));
+            }
+        }
+        let offsets = [self.strings.len(), self.types.len(), self.protos.len(), self.fields.len(), self.methods.len()].map(|len| len as u32);
+        let shift = |kind: IndexKind, index: &mut u32| *index += match kind {
+            IndexKind::String => offsets[0],
+            IndexKind::Type => offsets[1],
+            IndexKind::Proto => offsets[2],
+            IndexKind::Field => offsets[3],
+            IndexKind::Method => offsets[4],
+            IndexKind::CallSite | IndexKind::MethodHandle => 0
+        };
+        let mut other = other.clone();
+        other.visit_class_indices(&mut |kind, index| shift(kind, index))?;
+        self.strings.append(&mut other.strings);
+        self.types.extend(other.types.iter().map(|string| string + offsets[0]));
+        for mut proto in other.protos {
+            shift(IndexKind::String, &mut proto.shorty);
+            shift(IndexKind::Type, &mut proto.return_type);
+            proto.parameters.iter_mut().for_each(|parameter| shift(IndexKind::Type, parameter));
+            self.protos.push(proto);
+        }
+        for mut field in other.fields {
+            shift(IndexKind::Type, &mut field.class);
+            shift(IndexKind::String, &mut field.name);
+            shift(IndexKind::Type, &mut field.field_type);
+            self.fields.push(field);
+        }
+        for mut method in other.methods {
+            shift(IndexKind::Type, &mut method.class);
+            shift(IndexKind::String, &mut method.name);
+            shift(IndexKind::Proto, &mut method.proto);
+            self.methods.push(method);
+        }
+        self.classes.append(&mut other.classes);
+        self.version = self.version.max(other.version);
+        Ok(())
+    }
+}
+
+// `host` with the classes of `addition` added, as long as the merged references still fit one dex, so a
+// small stub doesn't need a classesN.dex of its own
+pub fn merge(host: &[u8], addition: &[u8]) -> Result<Vec<u8>, DexError> {
+    let mut file = DexFile::parse(host)?;
+    file.merge(&DexFile::parse(addition)?)?;
+    file.to_bytes()
+}
@@ -0,0 +1,95 @@
+mod code;
+mod file;
+pub mod header;
+mod ids;
+mod mapping;
+mod merge;
+mod proguard;
+mod reader;
+mod refs;
+mod scan;
+mod strings;
+mod strip;
+mod value;
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use sha1::{Digest, Sha1};
+
+pub use file::DexFile;
+pub use header::{validate, DexHeader, DexSection};
+pub use ids::class_names;
+pub use mapping::apply_mapping;
+pub use merge::merge;
+pub use proguard::ProguardMapping;
+pub use refs::{ref_counts, RefCounts, MAX_REFS};
+pub use scan::{ScanMatch, ScanTarget};
+pub use strings::ConstString;
+pub use strip::{strip_debug_info, StripReport};
+
+#[derive(Debug)]
+pub struct DexError {
+    reason: String
+}
+
+impl DexError {
+    pub(crate) fn new(reason: String) -> DexError {
+        DexError{
+            reason
+        }
+    }
+}
+
+impl Display for DexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "dex error: {}", self.reason)
+    }
+}
+
+impl Error for DexError {}
+
+// the id table an index points into
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IndexKind {
+    String,
+    Type,
+    Proto,
+    Field,
+    Method,
+    CallSite,
+    MethodHandle
+}
+
+// the checksum field covers everything after itself
+pub(crate) fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before b may overflow
+    for block in data.chunks(5552) {
+        for byte in block {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+// rewrites file_size, the sha-1 signature and the adler32 checksum after the bytes were patched, in that
+// order since each one covers the fields before it
+pub fn fix_checksums(data: &mut [u8]) -> Result<(), DexError> {
+    let header = DexHeader::parse(data)?;
+    let file_size = data.len() as u32;
+    data[32..36].copy_from_slice(&file_size.to_le_bytes());
+    // a container of just this dex grows with it
+    let container = header::header_size(header.version) == 0x78 && header.header_offset == 0;
+    if container && header.container_size == header.file_size && data.len() >= 0x78 {
+        data[112..116].copy_from_slice(&file_size.to_le_bytes());
+    }
+    let signature = Sha1::digest(&data[32..]);
+    data[12..32].copy_from_slice(&signature);
+    let checksum = adler32(&data[12..]);
+    data[8..12].copy_from_slice(&checksum.to_le_bytes());
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use crate::dex::DexError;
+
+// the class part of a ProGuard/R8 mapping.txt, "com.example.Original -> a.b:". Member lines are skipped
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProguardMapping {
+    obfuscated: HashMap<String, String>, // original -> obfuscated
+    original: HashMap<String, String>
+}
+
+// "com.foo.Bar" -> "Lcom/foo/Bar;"
+fn to_descriptor(class_name: &str) -> String {
+    format!("L{};", class_name.replace('.', "/"))
+}
+
+// "Lcom/foo/Bar;" -> "com.foo.Bar", anything else as is
+fn to_class_name(name: &str) -> String {
+    match name.strip_prefix('L').and_then(|name| name.strip_suffix(';')) {
+        Some(name) => name.replace('/', "."),
+        None => String::from(name)
+    }
+}
+
+impl ProguardMapping {
+    pub fn parse(text: &str) -> Result<ProguardMapping, DexError> {
+        let mut mapping = ProguardMapping::default();
+        for (number, line) in text.lines().enumerate() {
+            // members are indented under their class
+            if line.trim().is_empty() || line.starts_with('#') || line.starts_with([' ', '\t']) {
+                continue;
+            }
+            let (original, obfuscated) = line.trim_end().strip_suffix(':')
+                .and_then(|line| line.split_once(" -> "))
+                .ok_or_else(|| DexError::new(format!("mapping line {} is not a class line: {}", number + 1, line)))?;
+            mapping.obfuscated.insert(String::from(original), String::from(obfuscated));
+            mapping.original.insert(String::from(obfuscated), String::from(original));
+        }
+        Ok(mapping)
+    }
+
+    // "com.example.Original" -> "a.b"; descriptors ("Lcom/example/Original;") are translated as
+    // descriptors
+    pub fn obfuscate(&self, name: &str) -> Option<String> {
+        let obfuscated = self.obfuscated.get(&to_class_name(name))?;
+        Some(if name.ends_with(';') { to_descriptor(obfuscated) } else { obfuscated.clone() })
+    }
+
+    // the other way round, "a.b" -> "com.example.Original"
+    pub fn deobfuscate(&self, name: &str) -> Option<String> {
+        let original = self.original.get(&to_class_name(name))?;
+        Some(if name.ends_with(';') { to_descriptor(original) } else { original.clone() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.obfuscated.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.obfuscated.is_empty()
+    }
+}
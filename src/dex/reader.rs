@@ -0,0 +1,133 @@
+use crate::dex::DexError;
+
+// index value meaning "none" in class_defs and, stored as uleb128p1, in debug info
+pub(crate) const NO_INDEX: u32 = 0xffffffff;
+
+// bounds checked little endian reads over a dex, everything past the end is an error instead of a panic
+pub(crate) struct DexReader<'a> {
+    data: &'a [u8],
+    pub(crate) offset: usize
+}
+
+impl<'a> DexReader<'a> {
+    pub(crate) fn at(data: &'a [u8], offset: usize) -> DexReader<'a> {
+        DexReader{
+            data,
+            offset
+        }
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], DexError> {
+        let end = self.offset.checked_add(len).filter(|end| *end <= self.data.len())
+            .ok_or_else(|| DexError::new(format!("read of {} bytes at 0x{:x} out of the file", len, self.offset)))?;
+        let bytes = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, DexError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, DexError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, DexError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub(crate) fn uleb128(&mut self) -> Result<u32, DexError> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DexError::new(format!("uleb128 at 0x{:x} is longer than 5 bytes", self.offset)))
+    }
+
+    pub(crate) fn sleb128(&mut self) -> Result<i32, DexError> {
+        let mut value = 0i32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as i32) << shift;
+            if byte & 0x80 == 0 {
+                // sign extend from the last bit read
+                return Ok(if shift < 25 && byte & 0x40 != 0 { value | (-1i32 << (shift + 7)) } else { value });
+            }
+        }
+        Err(DexError::new(format!("sleb128 at 0x{:x} is longer than 5 bytes", self.offset)))
+    }
+
+    // uleb128 of value + 1, so NO_INDEX fits in one byte
+    pub(crate) fn uleb128p1(&mut self) -> Result<u32, DexError> {
+        Ok(self.uleb128()?.wrapping_sub(1))
+    }
+}
+
+pub(crate) fn push_uleb128(data: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        data.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+pub(crate) fn push_sleb128(data: &mut Vec<u8>, mut value: i32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn push_uleb128p1(data: &mut Vec<u8>, value: u32) {
+    push_uleb128(data, value.wrapping_add(1));
+}
+
+// modified utf-8 to utf-16 units: nulls are 0xc0 0x80 and supplementary characters are surrogate pairs
+// of 3 bytes each, so every unit comes out as is, unpaired surrogates included
+pub(crate) fn decode_mutf8(data: &[u8]) -> Vec<u16> {
+    let mut units: Vec<u16> = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i] as u16;
+        let (unit, len) = match byte {
+            0x00..=0x7f => (byte, 1),
+            0xc0..=0xdf if i + 1 < data.len() => (((byte & 0x1f) << 6) | (data[i + 1] as u16 & 0x3f), 2),
+            0xe0..=0xef if i + 2 < data.len() => (((byte & 0x0f) << 12) | ((data[i + 1] as u16 & 0x3f) << 6) | (data[i + 2] as u16 & 0x3f), 3),
+            _ => (0xfffd, 1)
+        };
+        units.push(unit);
+        i += len;
+    }
+    units
+}
+
+pub(crate) fn encode_mutf8(units: &[u16]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(units.len());
+    for unit in units {
+        match *unit {
+            0x0001..=0x007f => data.push(*unit as u8),
+            0x0000 | 0x0080..=0x07ff => {
+                data.push(0xc0 | (unit >> 6) as u8);
+                data.push(0x80 | (unit & 0x3f) as u8);
+            },
+            _ => {
+                data.push(0xe0 | (unit >> 12) as u8);
+                data.push(0x80 | ((unit >> 6) & 0x3f) as u8);
+                data.push(0x80 | (unit & 0x3f) as u8);
+            }
+        }
+    }
+    data
+}
@@ -0,0 +1,46 @@
+use crate::dex::{validate, DexError};
+
+// the most method, field, type or proto ids one dex can hold, indices are 16 bit
+pub const MAX_REFS: u32 = 0x10000;
+
+// id counts of a dex, the method and field ones are what the 64K limit is about
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RefCounts {
+    pub strings: u32,
+    pub types: u32,
+    pub protos: u32,
+    pub fields: u32,
+    pub methods: u32,
+    pub classes: u32
+}
+
+impl RefCounts {
+    // method references that can still be added before the dex is full
+    pub fn method_headroom(&self) -> u32 {
+        MAX_REFS.saturating_sub(self.methods)
+    }
+
+    pub fn field_headroom(&self) -> u32 {
+        MAX_REFS.saturating_sub(self.fields)
+    }
+
+    // upper bound for a merge, shared references make the real counts lower
+    pub fn fits_with(&self, other: &RefCounts) -> bool {
+        [(self.types, other.types), (self.protos, other.protos), (self.fields, other.fields), (self.methods, other.methods)]
+            .iter()
+            .all(|(a, b)| a + b <= MAX_REFS)
+    }
+}
+
+pub fn ref_counts(data: &[u8]) -> Result<RefCounts, DexError> {
+    let header = validate(data)?;
+    Ok(RefCounts{
+        strings: header.string_ids.size,
+        types: header.type_ids.size,
+        protos: header.proto_ids.size,
+        fields: header.field_ids.size,
+        methods: header.method_ids.size,
+        classes: header.class_defs.size
+    })
+}
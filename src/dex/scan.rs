@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use crate::dex::code::{const_string_index, for_each_instruction, invoked_method_index};
+use crate::dex::DexFile;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ScanTarget {
+    String, // string_ids, which holds class descriptors and member names too
+    Method // method_ids, "Lcom/foo/Bar;->run()V"
+}
+
+// a string or method reference matching one of the scanned patterns
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ScanMatch {
+    pub pattern: String,
+    pub target: ScanTarget,
+    pub value: String,
+    pub used_at: Vec<(String, usize)> // methods and pcs of the const-strings loading it or the invokes calling it
+}
+
+impl DexFile {
+    // every string and method reference containing one of `patterns`, once per pattern it contains.
+    // A match with nothing in used_at is only referenced from the tables (names, types, annotations)
+    // or declared without being called here
+    pub fn scan(&self, patterns: &[&str]) -> Vec<ScanMatch> {
+        let mut used_at: HashMap<(ScanTarget, u32), Vec<(String, usize)>> = HashMap::new();
+        for method in self.classes.iter().flat_map(|class| class.methods()) {
+            let code = match &method.code {
+                Some(code) => code,
+                None => continue
+            };
+            let descriptor = self.method_descriptor(method.method).unwrap_or_default();
+            // code that doesn't decode has no instructions to report
+            let _ = for_each_instruction(&code.insns, |pc, opcode, units| {
+                let key = match (const_string_index(opcode, units), invoked_method_index(opcode, units)) {
+                    (Some(index), _) => (ScanTarget::String, index),
+                    (_, Some(index)) => (ScanTarget::Method, index),
+                    _ => return
+                };
+                used_at.entry(key).or_default().push((descriptor.clone(), pc));
+            });
+        }
+        let strings = (0..self.strings.len() as u32).filter_map(|index| Some((ScanTarget::String, index, self.string(index)?)));
+        let methods = (0..self.methods.len() as u32).filter_map(|index| Some((ScanTarget::Method, index, self.method_descriptor(index)?)));
+        let mut found = vec![];
+        for (target, index, value) in strings.chain(methods) {
+            for pattern in patterns.iter().filter(|pattern| value.contains(*pattern)) {
+                found.push(ScanMatch{
+                    pattern: String::from(*pattern),
+                    target,
+                    value: value.clone(),
+                    used_at: used_at.get(&(target, index)).cloned().unwrap_or_default()
+                });
+            }
+        }
+        found
+    }
+}
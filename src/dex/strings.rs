@@ -0,0 +1,84 @@
+use crate::dex::code::{const_string_index, for_each_instruction};
+use crate::dex::{DexError, DexFile};
+
+// a const-string instruction and the string it loads
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConstString {
+    pub method: String, // "Lcom/foo/Bar;->run()V"
+    pub pc: usize, // in code units
+    pub value: String
+}
+
+impl DexFile {
+    // every const-string, or const-string/jumbo, loading a string that contains `pattern`
+    pub fn find_const_strings(&self, pattern: &str) -> Vec<ConstString> {
+        let mut found = vec![];
+        for method in self.classes.iter().flat_map(|class| class.methods()) {
+            let code = match &method.code {
+                Some(code) => code,
+                None => continue
+            };
+            // code that doesn't decode has no instructions to report
+            let _ = for_each_instruction(&code.insns, |pc, opcode, units| {
+                let value = match const_string_index(opcode, units).and_then(|index| self.string(index)) {
+                    Some(value) if value.contains(pattern) => value,
+                    _ => return
+                };
+                found.push(ConstString{
+                    method: self.method_descriptor(method.method).unwrap_or_default(),
+                    pc,
+                    value
+                });
+            });
+        }
+        found
+    }
+
+    // replaces `from` with `to` in the strings const-string instructions load, returns how many were
+    // changed. The same string used as a name, type or annotation value is left alone; the table is
+    // re-sorted and the string data resized when the dex is written
+    pub fn replace_const_strings(&mut self, from: &str, to: &str) -> Result<usize, DexError> {
+        if from.is_empty() {
+            return Err(DexError::new(String::from("nothing to replace")));
+        }
+        let mut operands: Vec<(usize, usize, usize, u32)> = vec![]; // class, method, pc, string
+        for (class_index, class) in self.classes.iter().enumerate() {
+            for (method_index, method) in class.methods().enumerate() {
+                if let Some(code) = &method.code {
+                    for_each_instruction(&code.insns, |pc, opcode, units| {
+                        if let Some(index) = const_string_index(opcode, units) {
+                            operands.push((class_index, method_index, pc, index));
+                        }
+                    })?;
+                }
+            }
+        }
+        // new strings shift the indices, so they are all added before any operand is rewritten
+        let mut replacements: Vec<(usize, usize, usize, String)> = vec![];
+        for (class_index, method_index, pc, index) in operands {
+            match self.string(index) {
+                Some(value) if value.contains(from) => replacements.push((class_index, method_index, pc, value.replace(from, to))),
+                _ => {}
+            }
+        }
+        for (_, _, _, value) in &replacements {
+            self.insert_string(value)?;
+        }
+        let count = replacements.len();
+        for (class_index, method_index, pc, value) in replacements {
+            let index = self.insert_string(&value)?;
+            let insns = match self.classes[class_index].methods_mut().nth(method_index).and_then(|method| method.code.as_mut()) {
+                Some(code) => &mut code.insns,
+                None => continue
+            };
+            if insns[pc] & 0xff == 0x1b {
+                insns[pc + 2] = (index >> 16) as u16;
+            } else if index > 0xffff {
+                return Err(DexError::new(format!("string {} is past what the const-string at {} can load", index, pc)));
+            }
+            insns[pc + 1] = index as u16;
+        }
+        Ok(count)
+    }
+}
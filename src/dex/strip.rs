@@ -0,0 +1,60 @@
+use crate::dex::reader::NO_INDEX;
+use crate::dex::{DexError, DexFile};
+
+// what strip_debug_info removed from one dex. The sizes are of the whole file, the strings only the
+// debug info used (parameter and local names, source files) are dropped along with it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StripReport {
+    pub debug_infos: usize,
+    pub source_files: usize,
+    pub original_size: usize,
+    pub stripped_size: usize
+}
+
+impl StripReport {
+    pub fn saved_bytes(&self) -> usize {
+        self.original_size.saturating_sub(self.stripped_size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.debug_infos == 0 && self.source_files == 0
+    }
+}
+
+impl DexFile {
+    // drops the debug_info_item of every method and the source file of every class, stack traces lose
+    // their line numbers. Returns the (debug infos, source files) removed
+    pub fn strip_debug_info(&mut self) -> (usize, usize) {
+        let mut debug_infos = 0;
+        let mut source_files = 0;
+        for class in &mut self.classes {
+            if class.source_file != NO_INDEX {
+                class.source_file = NO_INDEX;
+                source_files += 1;
+            }
+            for method in class.methods_mut() {
+                if let Some(code) = &mut method.code {
+                    if code.debug_info.take().is_some() {
+                        debug_infos += 1;
+                    }
+                }
+            }
+        }
+        (debug_infos, source_files)
+    }
+}
+
+// `data` without debug info, see DexFile::strip_debug_info
+pub fn strip_debug_info(data: &[u8]) -> Result<(Vec<u8>, StripReport), DexError> {
+    let mut file = DexFile::parse(data)?;
+    let (debug_infos, source_files) = file.strip_debug_info();
+    let stripped = file.to_bytes()?;
+    let report = StripReport{
+        debug_infos,
+        source_files,
+        original_size: data.len(),
+        stripped_size: stripped.len()
+    };
+    Ok((stripped, report))
+}
@@ -0,0 +1,248 @@
+use crate::dex::reader::{push_uleb128, DexReader};
+use crate::dex::{DexError, IndexKind};
+
+const VALUE_BYTE: u8 = 0x00;
+const VALUE_METHOD_TYPE: u8 = 0x15;
+const VALUE_METHOD_HANDLE: u8 = 0x16;
+const VALUE_STRING: u8 = 0x17;
+const VALUE_TYPE: u8 = 0x18;
+const VALUE_FIELD: u8 = 0x19;
+const VALUE_METHOD: u8 = 0x1a;
+const VALUE_ENUM: u8 = 0x1b;
+const VALUE_ARRAY: u8 = 0x1c;
+const VALUE_ANNOTATION: u8 = 0x1d;
+const VALUE_NULL: u8 = 0x1e;
+const VALUE_BOOLEAN: u8 = 0x1f;
+
+// encoded_value, with the values pointing into the id tables decoded so they can be remapped
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum EncodedValue {
+    Index(u8, u32), // value type, index
+    Array(Vec<EncodedValue>),
+    Annotation(EncodedAnnotation),
+    Other(u8, Vec<u8>) // header byte and the bytes after it, numbers, null and booleans
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EncodedAnnotation {
+    pub(crate) annotation_type: u32,
+    pub(crate) elements: Vec<(u32, EncodedValue)> // name string, value
+}
+
+// annotation_item
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Annotation {
+    pub(crate) visibility: u8,
+    pub(crate) annotation: EncodedAnnotation
+}
+
+// annotations_directory_item with the sets it points at read in
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct AnnotationsDirectory {
+    pub(crate) class_annotations: Option<Vec<Annotation>>,
+    pub(crate) fields: Vec<(u32, Vec<Annotation>)>,
+    pub(crate) methods: Vec<(u32, Vec<Annotation>)>,
+    pub(crate) parameters: Vec<(u32, Vec<Option<Vec<Annotation>>>)>
+}
+
+fn index_kind(value_type: u8) -> Option<IndexKind> {
+    match value_type {
+        VALUE_METHOD_TYPE => Some(IndexKind::Proto),
+        VALUE_METHOD_HANDLE => Some(IndexKind::MethodHandle),
+        VALUE_STRING => Some(IndexKind::String),
+        VALUE_TYPE => Some(IndexKind::Type),
+        VALUE_FIELD | VALUE_ENUM => Some(IndexKind::Field),
+        VALUE_METHOD => Some(IndexKind::Method),
+        _ => None
+    }
+}
+
+impl EncodedValue {
+    pub(crate) fn parse(reader: &mut DexReader) -> Result<EncodedValue, DexError> {
+        let header = reader.u8()?;
+        let (value_type, arg) = (header & 0x1f, (header >> 5) as usize);
+        if index_kind(value_type).is_some() {
+            let bytes = reader.bytes(arg + 1)?;
+            if bytes.len() > 4 {
+                return Err(DexError::new(format!("{} byte index in an encoded value", bytes.len())));
+            }
+            let index = bytes.iter().rev().fold(0u32, |res, byte| (res << 8) | *byte as u32);
+            return Ok(EncodedValue::Index(value_type, index));
+        }
+        match value_type {
+            VALUE_ARRAY => Ok(EncodedValue::Array(parse_array(reader)?)),
+            VALUE_ANNOTATION => Ok(EncodedValue::Annotation(EncodedAnnotation::parse(reader)?)),
+            VALUE_NULL | VALUE_BOOLEAN => Ok(EncodedValue::Other(header, vec![])),
+            VALUE_BYTE => Ok(EncodedValue::Other(header, reader.bytes(1)?.to_vec())),
+            0x02..=0x04 | 0x06 | 0x10 | 0x11 => Ok(EncodedValue::Other(header, reader.bytes(arg + 1)?.to_vec())),
+            _ => Err(DexError::new(format!("unknown encoded value type 0x{:02x}", value_type)))
+        }
+    }
+
+    pub(crate) fn write(&self, data: &mut Vec<u8>) {
+        match self {
+            EncodedValue::Index(value_type, index) => {
+                // as few bytes as the index needs, at least one
+                let len = (4 - index.leading_zeros() as usize / 8).max(1);
+                data.push((((len - 1) as u8) << 5) | value_type);
+                data.extend_from_slice(&index.to_le_bytes()[..len]);
+            },
+            EncodedValue::Array(values) => {
+                data.push(VALUE_ARRAY);
+                write_array(values, data);
+            },
+            EncodedValue::Annotation(annotation) => {
+                data.push(VALUE_ANNOTATION);
+                annotation.write(data);
+            },
+            EncodedValue::Other(header, bytes) => {
+                data.push(*header);
+                data.extend_from_slice(bytes);
+            }
+        }
+    }
+
+    pub(crate) fn visit_indices<F: FnMut(IndexKind, &mut u32)>(&mut self, f: &mut F) {
+        match self {
+            EncodedValue::Index(value_type, index) => {
+                if let Some(kind) = index_kind(*value_type) {
+                    f(kind, index);
+                }
+            },
+            EncodedValue::Array(values) => values.iter_mut().for_each(|value| value.visit_indices(f)),
+            EncodedValue::Annotation(annotation) => annotation.visit_indices(f),
+            EncodedValue::Other(_, _) => {}
+        }
+    }
+}
+
+// encoded_array, also the static_values of a class
+pub(crate) fn parse_array(reader: &mut DexReader) -> Result<Vec<EncodedValue>, DexError> {
+    let size = reader.uleb128()?;
+    (0..size).map(|_| EncodedValue::parse(reader)).collect()
+}
+
+pub(crate) fn write_array(values: &[EncodedValue], data: &mut Vec<u8>) {
+    push_uleb128(data, values.len() as u32);
+    values.iter().for_each(|value| value.write(data));
+}
+
+impl EncodedAnnotation {
+    fn parse(reader: &mut DexReader) -> Result<EncodedAnnotation, DexError> {
+        let annotation_type = reader.uleb128()?;
+        let size = reader.uleb128()?;
+        let elements = (0..size).map(|_| Ok((reader.uleb128()?, EncodedValue::parse(reader)?))).collect::<Result<_, DexError>>()?;
+        Ok(EncodedAnnotation{
+            annotation_type,
+            elements
+        })
+    }
+
+    // elements have to be sorted by name, which moves when strings are remapped
+    fn write(&self, data: &mut Vec<u8>) {
+        let mut elements: Vec<&(u32, EncodedValue)> = self.elements.iter().collect();
+        elements.sort_by_key(|(name, _)| *name);
+        push_uleb128(data, self.annotation_type);
+        push_uleb128(data, elements.len() as u32);
+        for (name, value) in elements {
+            push_uleb128(data, *name);
+            value.write(data);
+        }
+    }
+
+    fn visit_indices<F: FnMut(IndexKind, &mut u32)>(&mut self, f: &mut F) {
+        f(IndexKind::Type, &mut self.annotation_type);
+        for (name, value) in &mut self.elements {
+            f(IndexKind::String, name);
+            value.visit_indices(f);
+        }
+    }
+}
+
+impl Annotation {
+    pub(crate) fn write(&self, data: &mut Vec<u8>) {
+        data.push(self.visibility);
+        self.annotation.write(data);
+    }
+}
+
+fn parse_annotation_set(data: &[u8], offset: u32) -> Result<Vec<Annotation>, DexError> {
+    let mut reader = DexReader::at(data, offset as usize);
+    let size = reader.u32()?;
+    (0..size).map(|_| {
+        let mut item = DexReader::at(data, reader.u32()? as usize);
+        Ok(Annotation{
+            visibility: item.u8()?,
+            annotation: EncodedAnnotation::parse(&mut item)?
+        })
+    }).collect()
+}
+
+fn visit_annotation_set<F: FnMut(IndexKind, &mut u32)>(set: &mut [Annotation], f: &mut F) {
+    set.iter_mut().for_each(|annotation| annotation.annotation.visit_indices(f));
+}
+
+impl AnnotationsDirectory {
+    pub(crate) fn parse(data: &[u8], offset: u32) -> Result<AnnotationsDirectory, DexError> {
+        let mut reader = DexReader::at(data, offset as usize);
+        let class_annotations = match reader.u32()? {
+            0 => None,
+            offset => Some(parse_annotation_set(data, offset)?)
+        };
+        let (field_count, method_count, parameter_count) = (reader.u32()?, reader.u32()?, reader.u32()?);
+        let mut directory = AnnotationsDirectory{
+            class_annotations,
+            ..Default::default()
+        };
+        for _ in 0..field_count {
+            directory.fields.push((reader.u32()?, parse_annotation_set(data, reader.u32()?)?));
+        }
+        for _ in 0..method_count {
+            directory.methods.push((reader.u32()?, parse_annotation_set(data, reader.u32()?)?));
+        }
+        for _ in 0..parameter_count {
+            let method = reader.u32()?;
+            let mut list = DexReader::at(data, reader.u32()? as usize);
+            let size = list.u32()?;
+            let sets = (0..size).map(|_| match list.u32()? {
+                0 => Ok(None),
+                offset => Ok(Some(parse_annotation_set(data, offset)?))
+            }).collect::<Result<_, DexError>>()?;
+            directory.parameters.push((method, sets));
+        }
+        Ok(directory)
+    }
+
+    pub(crate) fn visit_indices<F: FnMut(IndexKind, &mut u32)>(&mut self, f: &mut F) {
+        if let Some(set) = &mut self.class_annotations {
+            visit_annotation_set(set, f);
+        }
+        for (field, set) in &mut self.fields {
+            f(IndexKind::Field, field);
+            visit_annotation_set(set, f);
+        }
+        for (method, set) in &mut self.methods {
+            f(IndexKind::Method, method);
+            visit_annotation_set(set, f);
+        }
+        for (method, sets) in &mut self.parameters {
+            f(IndexKind::Method, method);
+            sets.iter_mut().flatten().for_each(|set| visit_annotation_set(set, f));
+        }
+    }
+
+    // the entries have to be sorted by field and method index
+    pub(crate) fn sort(&mut self) {
+        self.fields.sort_by_key(|(field, _)| *field);
+        self.methods.sort_by_key(|(method, _)| *method);
+        self.parameters.sort_by_key(|(method, _)| *method);
+        let sets = self.class_annotations.iter_mut()
+            .chain(self.fields.iter_mut().map(|(_, set)| set))
+            .chain(self.methods.iter_mut().map(|(_, set)| set))
+            .chain(self.parameters.iter_mut().flat_map(|(_, sets)| sets.iter_mut().flatten()));
+        // and sets by annotation type
+        for set in sets {
+            set.sort_by_key(|annotation| annotation.annotation.annotation_type);
+        }
+    }
+}
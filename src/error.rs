@@ -0,0 +1,61 @@
+use alloc::string::String;
+#[cfg(feature = "zip-headers")]
+use crate::apk_zip::ZipFormatError;
+#[cfg(feature = "zip")]
+use crate::apk_zip::ZipError;
+#[cfg(feature = "sign")]
+use crate::apk_zip::SignError;
+#[cfg(feature = "dex")]
+use crate::dex::DexError;
+#[cfg(feature = "manifest")]
+use crate::manifest::FileFormatError;
+#[cfg(feature = "manifest")]
+use crate::manifest::manifest_editor::ManifestError;
+#[cfg(feature = "manifest")]
+use crate::manifest::merger::MergeError;
+#[cfg(feature = "resources")]
+use crate::resources::ResourceError;
+#[cfg(feature = "plan")]
+use crate::plan::PlanError;
+
+// what the ApkFile facade returns. The module level apis keep their own error types, which all
+// convert into this one, so `?` works across them
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[cfg(feature = "zip-headers")]
+    #[error(transparent)]
+    Zip(#[from] ZipFormatError),
+    #[cfg(feature = "zip")]
+    #[error(transparent)]
+    ZipEdit(#[from] ZipError),
+    #[cfg(feature = "manifest")]
+    #[error(transparent)]
+    Axml(#[from] FileFormatError),
+    #[cfg(feature = "manifest")]
+    #[error(transparent)]
+    Manifest(#[from] ManifestError),
+    #[cfg(feature = "manifest")]
+    #[error(transparent)]
+    ManifestMerge(#[from] MergeError),
+    #[cfg(feature = "resources")]
+    #[error(transparent)]
+    Resource(#[from] ResourceError),
+    #[cfg(feature = "dex")]
+    #[error(transparent)]
+    Dex(#[from] DexError),
+    #[cfg(feature = "sign")]
+    #[error(transparent)]
+    Sign(#[from] SignError),
+    #[cfg(feature = "plan")]
+    #[error(transparent)]
+    Plan(#[from] PlanError),
+    #[cfg(feature = "std")]
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    // an entry the operation needs isn't in the apk
+    #[error("{0} is not in the apk")]
+    MissingEntry(String)
+}
+
+pub type Result<T, E = Error> = core::result::Result<T, E>;
@@ -0,0 +1,244 @@
+// UniFFI interface behind bindings/uniffi, one definition the Kotlin, Swift and Python bindings are
+// generated from. Objects are shared with the foreign side through an Arc, so state sits behind a
+// Mutex. An Apk works like the JNI session: every edit is saved back into its bytes. Panics come out
+// as the bindings' internal error, uniffi catches them
+use std::fmt::Display;
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::apk_zip::{sign_v2, verify_v2, ApkFile, CompressMethod, SigningKey};
+use crate::manifest::components;
+use crate::manifest::manifest_editor::{AndroidManifest, UsesPermission};
+
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum ApkError {
+    #[error("{reason}")]
+    Failed {
+        reason: String
+    }
+}
+
+type Result<T> = std::result::Result<T, ApkError>;
+
+fn reason<E: Display>(err: E) -> ApkError {
+    ApkError::Failed{
+        reason: err.to_string()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // a panic while holding it left nothing half written, every edit replaces the value whole
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[derive(uniffi::Record)]
+pub struct FileEntry {
+    pub name: String,
+    pub stored: bool,
+    pub size: u32,
+    pub compressed_size: u32,
+    pub crc32: u32
+}
+
+#[derive(uniffi::Enum)]
+pub enum ComponentKind {
+    Activity,
+    Service,
+    Receiver
+}
+
+#[derive(uniffi::Record)]
+pub struct Permission {
+    pub name: String,
+    pub max_sdk_version: Option<u32>
+}
+
+// an apk held in memory. align and sign belong last, a later edit rewrites the zip with the default
+// alignment and no signature
+#[derive(uniffi::Object)]
+pub struct Apk {
+    data: Mutex<Vec<u8>>
+}
+
+impl Apk {
+    fn edit<F: FnOnce(&mut ApkFile) -> Result<()>>(&self, f: F) -> Result<()> {
+        let mut data = lock(&self.data);
+        let mut apk = ApkFile::from(&*data).map_err(reason)?;
+        f(&mut apk)?;
+        let mut res = vec![];
+        apk.save(&mut res).map_err(reason)?;
+        drop(apk);
+        *data = res;
+        Ok(())
+    }
+
+    fn read<T, F: FnOnce(&ApkFile) -> Result<T>>(&self, f: F) -> Result<T> {
+        let data = lock(&self.data);
+        f(&ApkFile::from(&*data).map_err(reason)?)
+    }
+}
+
+#[uniffi::export]
+impl Apk {
+    #[uniffi::constructor]
+    pub fn new(data: Vec<u8>) -> Result<Arc<Apk>> {
+        ApkFile::from(&data).map_err(reason)?;
+        Ok(Arc::new(Apk{
+            data: Mutex::new(data)
+        }))
+    }
+
+    pub fn list_files(&self) -> Result<Vec<FileEntry>> {
+        self.read(|apk| Ok(apk.list_files().into_iter().map(|entry| FileEntry{
+            name: entry.name,
+            stored: entry.compress_method == CompressMethod::Stored,
+            size: entry.size,
+            compressed_size: entry.compressed_size,
+            crc32: entry.crc32
+        }).collect()))
+    }
+
+    pub fn get_file(&self, name: String) -> Result<Option<Vec<u8>>> {
+        self.read(|apk| Ok(apk.get_file(&name)))
+    }
+
+    // replaces the entry when there is one
+    pub fn add_file(&self, name: String, data: Vec<u8>, stored: bool) -> Result<()> {
+        self.edit(|apk| {
+            let method = if stored { CompressMethod::Stored } else { CompressMethod::Deflated };
+            apk.add_or_replace_file(&name, data, method);
+            Ok(())
+        })
+    }
+
+    pub fn remove_file(&self, name: String) -> Result<()> {
+        self.edit(|apk| apk.remove_file(&name).map_err(reason))
+    }
+
+    // a copy, edits to it reach the apk through set_manifest
+    pub fn manifest(&self) -> Result<Arc<Manifest>> {
+        let data = self.read(|apk| apk.get_manifest().map_err(reason))?;
+        Manifest::new(data)
+    }
+
+    pub fn set_manifest(&self, manifest: Arc<Manifest>) -> Result<()> {
+        let data = manifest.to_bytes();
+        self.edit(|apk| apk.set_manifest(&data).map_err(reason))
+    }
+
+    pub fn get_app_label(&self, locale: Option<String>) -> Result<Option<String>> {
+        self.read(|apk| apk.get_app_label(locale.as_deref().unwrap_or("")).map_err(reason))
+    }
+
+    pub fn set_app_label(&self, label: String) -> Result<()> {
+        self.edit(|apk| apk.set_app_label(&label, &[]).map_err(reason))
+    }
+
+    // appended as the next classesN.dex
+    pub fn add_dex(&self, data: Vec<u8>) -> Result<()> {
+        self.edit(|apk| apk.add_dex(data).map_err(reason))
+    }
+
+    pub fn align(&self, alignment: u32) -> Result<()> {
+        let mut data = lock(&self.data);
+        let mut res = vec![];
+        ApkFile::from(&*data).map_err(reason)?.save_aligned(&mut res, alignment as usize).map_err(reason)?;
+        *data = res;
+        Ok(())
+    }
+
+    // APK Signature Scheme v2, see apk_zip::sign_v2
+    pub fn sign(&self, signer: Arc<Signer>) -> Result<()> {
+        let mut data = lock(&self.data);
+        *data = sign_v2(&data, &signer.key).map_err(reason)?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        lock(&self.data).clone()
+    }
+}
+
+// a binary AndroidManifest.xml being edited, on its own or out of Apk::manifest
+#[derive(uniffi::Object)]
+pub struct Manifest {
+    manifest: Mutex<AndroidManifest<'static>>
+}
+
+#[uniffi::export]
+impl Manifest {
+    #[uniffi::constructor]
+    pub fn new(data: Vec<u8>) -> Result<Arc<Manifest>> {
+        Ok(Arc::new(Manifest{
+            manifest: Mutex::new(AndroidManifest::from_vec(data).map_err(reason)?)
+        }))
+    }
+
+    pub fn package_name(&self) -> Option<String> {
+        lock(&self.manifest).package().map(String::from)
+    }
+
+    pub fn min_sdk_version(&self) -> Option<u32> {
+        lock(&self.manifest).min_sdk_version()
+    }
+
+    pub fn split(&self) -> Option<String> {
+        lock(&self.manifest).split().map(String::from)
+    }
+
+    pub fn set_split(&self, split: Option<String>) {
+        lock(&self.manifest).set_split(split.as_deref());
+    }
+
+    pub fn set_test_only(&self, test_only: bool) -> Result<()> {
+        lock(&self.manifest).set_test_only(test_only).map_err(reason)
+    }
+
+    pub fn add_component(&self, kind: ComponentKind, class_name: String, exported: Option<bool>) -> Result<()> {
+        let kind = match kind {
+            ComponentKind::Activity => components::ComponentKind::Activity,
+            ComponentKind::Service => components::ComponentKind::Service,
+            ComponentKind::Receiver => components::ComponentKind::Receiver
+        };
+        lock(&self.manifest).add_component(kind, &class_name, exported).map_err(reason)
+    }
+
+    pub fn permissions(&self) -> Vec<Permission> {
+        lock(&self.manifest).permissions().into_iter().map(|permission| Permission{
+            name: permission.name,
+            max_sdk_version: permission.max_sdk_version
+        }).collect()
+    }
+
+    // replaces a request with the same name
+    pub fn add_uses_permission(&self, permission: Permission) {
+        lock(&self.manifest).add_uses_permission(UsesPermission{
+            name: permission.name,
+            max_sdk_version: permission.max_sdk_version
+        });
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        lock(&self.manifest).get_data()
+    }
+}
+
+// a PKCS#8 or PKCS#1 key and its X.509 certificate, DER or PEM
+#[derive(uniffi::Object)]
+pub struct Signer {
+    key: SigningKey
+}
+
+#[uniffi::export]
+impl Signer {
+    #[uniffi::constructor]
+    pub fn new(key: Vec<u8>, certificate: Vec<u8>) -> Result<Arc<Signer>> {
+        Ok(Arc::new(Signer{
+            key: SigningKey::from(&key, &certificate).map_err(reason)?
+        }))
+    }
+}
+
+// the certificates of the v2 signers
+#[uniffi::export]
+pub fn verify_apk(data: Vec<u8>) -> Result<Vec<Vec<u8>>> {
+    verify_v2(&data).map_err(reason)
+}
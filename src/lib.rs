@@ -1,3 +1,55 @@
+// the binary xml and zip header parsers only need alloc, see the std feature
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+#[macro_use]
+#[allow(unused_macros)] // which ones are used depends on the features
+mod trace;
+#[cfg(feature = "zip-headers")]
 pub mod apk_zip;
+// byte helpers shared by the parsers, with some of them left out a few go unused
+#[cfg_attr(not(all(feature = "manifest", feature = "resources", feature = "dex")), allow(dead_code))]
 pub mod utils;
+#[cfg(feature = "manifest")]
 pub mod manifest;
+#[cfg(feature = "resources")]
+pub mod resources;
+#[cfg(feature = "dex")]
+pub mod dex;
+pub mod error;
+// the parsers taking options (zip, binary xml, resource table) all come with manifest
+#[cfg(feature = "manifest")]
+pub mod options;
+#[cfg(feature = "plan")]
+pub mod plan;
+pub mod prelude;
+#[cfg(feature = "jni")]
+mod android;
+#[cfg(feature = "uniffi")]
+mod ffi;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+pub use error::{Error, Result};
+#[cfg(feature = "manifest")]
+pub use options::{ParseOptions, ParseWarning};
+
+// parsed structures are shared between threads (parse once, process concurrently), a field that
+// isn't Send + Sync has to fail the build rather than the users
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    #[cfg(feature = "zip")]
+    assert_send_sync::<apk_zip::ApkFile<'static>>();
+    #[cfg(feature = "manifest")]
+    assert_send_sync::<manifest::manifest_editor::AndroidManifest<'static>>();
+    #[cfg(feature = "resources")]
+    assert_send_sync::<resources::ResourceTable>();
+    #[cfg(feature = "dex")]
+    assert_send_sync::<dex::DexFile>();
+    #[cfg(feature = "dex")]
+    assert_send_sync::<dex::ProguardMapping>();
+    assert_send_sync::<Error>();
+    #[cfg(feature = "sign")]
+    assert_send_sync::<apk_zip::SigningKey>();
+};
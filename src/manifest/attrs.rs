@@ -0,0 +1,109 @@
+// Resource ids of the framework attributes (android:*) used by the manifest editor.
+// Values come from the platform's public.xml, they never change once published.
+
+pub const ANDROID_NAMESPACE: &str = "http://schemas.android.com/apk/res/android";
+pub const TOOLS_NAMESPACE: &str = "http://schemas.android.com/tools";
+
+pub const THEME: u32 = 0x01010000;
+pub const LABEL: u32 = 0x01010001;
+pub const ICON: u32 = 0x01010002;
+pub const NAME: u32 = 0x01010003;
+pub const PERMISSION: u32 = 0x01010006;
+pub const READ_PERMISSION: u32 = 0x01010007;
+pub const WRITE_PERMISSION: u32 = 0x01010008;
+pub const PROTECTION_LEVEL: u32 = 0x01010009;
+pub const PERMISSION_GROUP: u32 = 0x0101000a;
+pub const ENABLED: u32 = 0x0101000e;
+pub const DEBUGGABLE: u32 = 0x0101000f;
+pub const EXPORTED: u32 = 0x01010010;
+pub const PROCESS: u32 = 0x01010011;
+pub const MULTIPROCESS: u32 = 0x01010013;
+pub const AUTHORITIES: u32 = 0x01010018;
+pub const GRANT_URI_PERMISSIONS: u32 = 0x0101001b;
+pub const VALUE: u32 = 0x01010024;
+pub const RESOURCE: u32 = 0x01010025;
+pub const SCHEME: u32 = 0x01010027;
+pub const PATH: u32 = 0x0101002a;
+pub const PATH_PREFIX: u32 = 0x0101002b;
+pub const PATH_PATTERN: u32 = 0x0101002c;
+pub const ID: u32 = 0x010100d0;
+pub const MIN_SDK_VERSION: u32 = 0x0101020c;
+pub const VERSION_CODE: u32 = 0x0101021b;
+pub const VERSION_NAME: u32 = 0x0101021c;
+pub const TARGET_SDK_VERSION: u32 = 0x01010270;
+pub const MAX_SDK_VERSION: u32 = 0x01010271;
+pub const TEST_ONLY: u32 = 0x01010272;
+pub const ANY_DENSITY: u32 = 0x0101026c;
+pub const ALLOW_BACKUP: u32 = 0x01010280;
+pub const SMALL_SCREENS: u32 = 0x01010284;
+pub const NORMAL_SCREENS: u32 = 0x01010285;
+pub const LARGE_SCREENS: u32 = 0x01010286;
+pub const RESIZEABLE: u32 = 0x0101028d;
+pub const XLARGE_SCREENS: u32 = 0x010102bf;
+pub const SCREEN_SIZE: u32 = 0x010102ca;
+pub const SCREEN_DENSITY: u32 = 0x010102cb;
+pub const REQUIRES_SMALLEST_WIDTH_DP: u32 = 0x01010364;
+pub const COMPATIBLE_WIDTH_LIMIT_DP: u32 = 0x01010365;
+pub const LARGEST_WIDTH_LIMIT_DP: u32 = 0x01010366;
+pub const EXTRACT_NATIVE_LIBS: u32 = 0x010104ea;
+pub const USES_CLEARTEXT_TRAFFIC: u32 = 0x010104ec;
+pub const NETWORK_SECURITY_CONFIG: u32 = 0x01010527;
+pub const ROUND_ICON: u32 = 0x0101052c;
+pub const IS_FEATURE_SPLIT: u32 = 0x0101055b;
+pub const SHELL: u32 = 0x01010594;
+pub const REQUIRED_SPLIT_TYPES: u32 = 0x0101064e;
+
+// id of the framework attribute `name` (without the android: prefix), for those listed here
+pub fn by_name(name: &str) -> Option<u32> {
+    match name {
+        "theme" => Some(THEME),
+        "label" => Some(LABEL),
+        "icon" => Some(ICON),
+        "name" => Some(NAME),
+        "permission" => Some(PERMISSION),
+        "readPermission" => Some(READ_PERMISSION),
+        "writePermission" => Some(WRITE_PERMISSION),
+        "protectionLevel" => Some(PROTECTION_LEVEL),
+        "permissionGroup" => Some(PERMISSION_GROUP),
+        "enabled" => Some(ENABLED),
+        "debuggable" => Some(DEBUGGABLE),
+        "exported" => Some(EXPORTED),
+        "process" => Some(PROCESS),
+        "multiprocess" => Some(MULTIPROCESS),
+        "authorities" => Some(AUTHORITIES),
+        "grantUriPermissions" => Some(GRANT_URI_PERMISSIONS),
+        "value" => Some(VALUE),
+        "resource" => Some(RESOURCE),
+        "scheme" => Some(SCHEME),
+        "path" => Some(PATH),
+        "pathPrefix" => Some(PATH_PREFIX),
+        "pathPattern" => Some(PATH_PATTERN),
+        "id" => Some(ID),
+        "minSdkVersion" => Some(MIN_SDK_VERSION),
+        "versionCode" => Some(VERSION_CODE),
+        "versionName" => Some(VERSION_NAME),
+        "targetSdkVersion" => Some(TARGET_SDK_VERSION),
+        "maxSdkVersion" => Some(MAX_SDK_VERSION),
+        "testOnly" => Some(TEST_ONLY),
+        "anyDensity" => Some(ANY_DENSITY),
+        "allowBackup" => Some(ALLOW_BACKUP),
+        "smallScreens" => Some(SMALL_SCREENS),
+        "normalScreens" => Some(NORMAL_SCREENS),
+        "largeScreens" => Some(LARGE_SCREENS),
+        "resizeable" => Some(RESIZEABLE),
+        "xlargeScreens" => Some(XLARGE_SCREENS),
+        "screenSize" => Some(SCREEN_SIZE),
+        "screenDensity" => Some(SCREEN_DENSITY),
+        "requiresSmallestWidthDp" => Some(REQUIRES_SMALLEST_WIDTH_DP),
+        "compatibleWidthLimitDp" => Some(COMPATIBLE_WIDTH_LIMIT_DP),
+        "largestWidthLimitDp" => Some(LARGEST_WIDTH_LIMIT_DP),
+        "extractNativeLibs" => Some(EXTRACT_NATIVE_LIBS),
+        "usesCleartextTraffic" => Some(USES_CLEARTEXT_TRAFFIC),
+        "networkSecurityConfig" => Some(NETWORK_SECURITY_CONFIG),
+        "roundIcon" => Some(ROUND_ICON),
+        "isFeatureSplit" => Some(IS_FEATURE_SPLIT),
+        "shell" => Some(SHELL),
+        "requiredSplitTypes" => Some(REQUIRED_SPLIT_TYPES),
+        _ => None
+    }
+}
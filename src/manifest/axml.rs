@@ -1,97 +1,211 @@
-use std::string::FromUtf16Error;
-use std::collections::HashMap;
-use std::error::Error;
-use std::fmt::{Display, Formatter};
-use std::io::Write;
-use byteorder::{LittleEndian, WriteBytesExt};
+use alloc::string::{String, ToString};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use core::error::Error;
+use core::hash::{Hash, Hasher};
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::fmt::{Display, Formatter};
 use crate::utils::{*};
+use crate::manifest::attrs;
+use crate::manifest::attrs::ANDROID_NAMESPACE;
+use crate::manifest::manifest_editor::ManifestError;
+use crate::manifest::typed_value::TypedValue;
+use crate::options::{ParseContext, ParseOptions, ParseWarning};
 
 const START_TAG: i32 = 0x00100102;
 const END_TAG: i32 = 0x00100103;
 const START_NAMESPACE: i32 = 0x00100100;
 const END_NAMESPACE: i32 = 0x00100101;
+const CDATA: i32 = 0x00100104;
 const STRING_CHUNK: i32 = 0x001C0001;
 const RESOURCE_CHUNK: i32 = 0x00080180;
 const XML_MAGIC: i32 = 0x00080003;
 
+// ResXMLTree_attribute layout written by the editor, parsed files may use a larger attribute size
+const ATTRIBUTE_START: u16 = 0x14;
+const ATTRIBUTE_SIZE: u16 = 0x14;
+
+// Res_value word of string values (size 8, res0 0, data type in the high byte), other types go through TypedValue
+pub(crate) const VALUE_TYPE_STRING: u32 = 0x03000008;
+
+// distinguishes the buffers nodes were parsed from, nodes cloned across manifests must not copy foreign bytes
+static NEXT_SOURCE_ID: AtomicU64 = AtomicU64::new(1);
+
 #[derive(Debug)]
 pub struct FileFormatError{
-    offset: usize
+    pub(crate) offset: usize
 }
 
+// an attribute name with a resource id put into a pool that already has plain strings, the resource
+// map only covers the head of the pool
+#[derive(Debug)]
+pub(crate) struct AttrNameOrderError;
+
 
+#[derive(Clone, Debug)]
 pub struct XmlAttributeValue {
     pub(crate) namespace_uri: Option<String>, // AndroidManifest http://schemas.android.com/apk/res/android
-    pub(crate) name_index: u32,
+    pub(crate) resource_id: u32, // 0 when the attribute name has no entry in the resource map
     pub(crate) name: String,
     pub(crate) value_type: u32,
     pub(crate) string_data: Option<String>,
     pub(crate) data: u32
 }
 
+#[derive(Clone, Debug)]
 pub struct XmlNode {
     pub(crate) tag_name: String,
     pub(crate) attrs: Vec<XmlAttributeValue>,
-    pub(crate) children: Vec<Box<XmlNode>>
+    pub(crate) children: Vec<XmlNode>,
+    pub(crate) origin: Option<NodeOrigin>,
+    namespaces: Vec<XmlNameSpace>, // xmlns declarations opened right before this element and closed after it
+    unknown_chunks: Vec<(usize, Vec<u8>)> // chunks this editor doesn't understand, with the child index they preceded
 }
 
+// Depth first walks in document order. Paths look like "manifest/application[0]/activity[2]", the index
+// counting siblings with the same tag.
+pub struct XmlNodeIter<'a> {
+    stack: Vec<(String, &'a XmlNode)>
+}
 
-pub struct StringChunk<'a> {
-    data: &'a Vec<u8>,
-    chunk_offset: usize,
-    chunk_size: u32,
-    string_count: u32,
-    style_count: u32,
-    string_pool_offset: u32,
-    style_pool_offset: u32,
-    string_index_global_offset: usize,
-    style_index_global_offset: usize
+pub struct XmlNodeIterMut<'a> {
+    stack: Vec<(String, &'a mut XmlNode)>
 }
 
-pub struct ResourceChunk<'a> {
-    data: &'a Vec<u8>,
+// what iter_mut hands out: an element without its children, those are yielded on their own
+pub struct XmlElementMut<'a> {
+    pub tag_name: &'a mut String,
+    pub attrs: &'a mut Vec<XmlAttributeValue>
+}
+
+// the file an element was parsed from and XmlNode::unchanged_subtrees of it, for copying what wasn't edited
+type Original<'a> = (&'a [u8], &'a [(bool, usize)]);
+
+// where a parsed element (start tag to end tag) lives in the original file, and what it looked like
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct NodeOrigin {
+    source_id: u64,
+    start: usize,
+    end: usize,
+    fingerprint: u64
+}
+
+
+pub struct StringChunk {
     chunk_offset: usize,
     chunk_size: u32,
-    chunk_count: u32
+    strings: Vec<String>
+}
+
+pub struct ResourceChunk {
+    resource_ids: Vec<u32>
 }
 
 pub struct XmlContent {
-    namespace_prefix: String,
-    namespace_uri: String,
-    pub(crate) root_node: Box<XmlNode>,
+    pub(crate) root_node: XmlNode,
+    leading_chunks: Vec<Vec<u8>>, // unknown chunks ahead of the document, written after the resource map
+    trailing_chunks: Vec<Vec<u8>>
 }
 
-pub struct XmlNameSpace<'a> {
-    data: &'a Vec<u8>,
-    namespace_offset: usize,
-    line_number: u32,
+#[derive(Clone, Debug, PartialEq)]
+pub struct XmlNameSpace {
     prefix: String,
     uri: String
 }
 
 pub struct AndroidXml<'a> {
-    data: &'a Vec<u8>,
-    pub(crate) string_chunk: Box<StringChunk<'a>>,
-    resource_chunk: Box<ResourceChunk<'a>>,
-    pub(crate) content: Box<XmlContent>
+    data: Cow<'a, [u8]>,
+    source_id: u64,
+    pub(crate) string_chunk: Box<StringChunk>,
+    resource_chunk: Box<ResourceChunk>,
+    pub(crate) content: Box<XmlContent>,
+    warnings: Vec<ParseWarning>
 }
 
 pub struct StringChunkBuilder {
-    string_index_map: HashMap<String,u32>,
-    string_arr: Vec<String>
+    string_index_map: BTreeMap<String,u32>,
+    string_arr: Vec<String>,
+    resource_ids: Vec<u32>
+}
+
+// FNV-1a for the fingerprints, DefaultHasher needs std. They're only compared within a process
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+// reads of the parser, past the end of the buffer is a format error at that offset
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, FileFormatError> {
+    read_leu16(data, offset).ok_or(FileFormatError{ offset })
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, FileFormatError> {
+    read_leu32(data, offset).ok_or(FileFormatError{ offset })
+}
+
+// type of the chunk at `offset`
+fn chunk_type(data: &[u8], offset: usize) -> Result<i32, FileFormatError> {
+    Ok(u32_at(data, offset)? as i32)
+}
+
+// size of the chunk at `offset`, checked against the buffer so a corrupt header can't send the walk out of bounds
+fn get_chunk_size(data: &[u8], offset: usize) -> Result<usize, FileFormatError> {
+    if offset + 8 > data.len() {
+        return Err(FileFormatError{ offset });
+    }
+    let chunk_size = u32_at(data, offset + 4)? as usize;
+    if chunk_size < 8 || offset + chunk_size > data.len() {
+        return Err(FileFormatError{ offset });
+    }
+    Ok(chunk_size)
 }
 
 impl Display for FileFormatError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(f, "file format error at: {}", self.offset)
     }
 }
 
 impl Error for FileFormatError {}
 
+// a length of 0x8000 UTF-16 units and up takes two units, the first with its high bit set
+fn length16_size(len: usize) -> usize {
+    if len > 0x7fff { 4 } else { 2 }
+}
+
+fn push_length16(data: &mut Vec<u8>, len: usize) {
+    if len > 0x7fff {
+        push_leu16(data, 0x8000 | ((len >> 16) & 0x7fff) as u16);
+    }
+    push_leu16(data, (len & 0xffff) as u16);
+}
+
 impl StringChunkBuilder {
     pub fn build(&self) -> Vec<u8> {
-        let mut res: Vec<u8> = Vec::new();
+        // lengths count UTF-16 units, not the bytes of the str
+        let lengths: Vec<usize> = self.string_arr.iter().map(|str_item| str_item.encode_utf16().count()).collect();
+        let string_sizes: Vec<usize> = lengths.iter().map(|len| length16_size(*len) + len * 2 + 2).collect();
+        let capacity = 7 * 4 + lengths.len() * 4 + string_sizes.iter().sum::<usize>() + 3;
+        let mut res: Vec<u8> = Vec::with_capacity(capacity);
         push_le32(&mut res, STRING_CHUNK);
         push_le32(&mut res, 0); // size
         push_leu32(&mut res, self.string_arr.len() as u32);
@@ -100,376 +214,897 @@ impl StringChunkBuilder {
         push_leu32(&mut res, (7 * 4 + self.string_arr.len() * 4) as u32); // string pool offset
         push_leu32(&mut res, 0); // style pool offset
         let mut current_str_offset: u32 = 0;
-        for str_item in &self.string_arr {
+        for size in &string_sizes {
             push_leu32(&mut res, current_str_offset);
-            current_str_offset += (2 + str_item.len()*2 + 2) as u32;
-        }
-        for str_item in &self.string_arr {
-            let str_len = str_item.len();
-            res.push((str_len & 0xff) as u8);
-            res.push(((str_len >> 8) & 0xff) as u8);
-            let str_data: Vec<u16> = str_item.encode_utf16().collect();
-            for ch in str_data {
-                res.push((ch & 0xff) as u8);
-                res.push(((ch >> 8) & 0xff) as u8);
-            }
-            res.push(0);
-            res.push(0);
+            current_str_offset += *size as u32;
         }
-        let align_len = 4 - (res.len() % 4);
-        if align_len < 4 {
-            for i in 0..align_len {
-                res.push(0);
+        for (str_item, len) in self.string_arr.iter().zip(&lengths) {
+            push_length16(&mut res, *len);
+            for ch in str_item.encode_utf16() {
+                push_leu16(&mut res, ch);
             }
+            push_leu16(&mut res, 0);
+        }
+        res.resize(res.len().next_multiple_of(4), 0);
+        let chunk_len = res.len() as u32;
+        res[4..8].copy_from_slice(&chunk_len.to_le_bytes());
+        res
+    }
+
+    pub fn build_resource_chunk(&self) -> Vec<u8> {
+        let mut res: Vec<u8> = Vec::with_capacity(8 + self.resource_ids.len() * 4);
+        push_le32(&mut res, RESOURCE_CHUNK);
+        push_leu32(&mut res, (8 + self.resource_ids.len() * 4) as u32);
+        for id in &self.resource_ids {
+            push_leu32(&mut res, *id);
         }
-        let chunk_len = res.len();
-        res[4] = (chunk_len & 0xff) as u8;
-        res[5] = ((chunk_len >> 8) & 0xff) as u8;
-        res[6] = ((chunk_len >> 16) & 0xff) as u8;
-        res[7] = ((chunk_len >> 24) & 0xff) as u8;
         res
     }
+
     pub(crate) fn put(&mut self, value: &str) -> u32 {
-        if self.string_index_map.contains_key(value) {
-            return self.string_index_map.get(value).unwrap().clone();
+        if let Some(idx) = self.string_index_map.get(value) {
+            return *idx;
         }
-        let res = self.string_index_map.len() as u32;
+        let res = self.string_arr.len() as u32;
         self.string_index_map.insert(String::from(value), res);
         self.string_arr.push(String::from(value));
-        return res;
+        res
     }
 
-    pub fn new() -> StringChunkBuilder {
-        StringChunkBuilder{
-            string_index_map: HashMap::new(),
-            string_arr: Vec::new()
+    // append without deduplication, keeps the index of every original pool entry unchanged
+    fn push_original(&mut self, value: &str, resource_id: Option<u32>) {
+        let idx = self.string_arr.len() as u32;
+        self.string_index_map.entry(String::from(value)).or_insert(idx);
+        self.string_arr.push(String::from(value));
+        if let Some(resource_id) = resource_id {
+            self.resource_ids.push(resource_id);
         }
     }
 
-    pub(crate) fn init(&mut self, string_chunk: &StringChunk) {
-        for i in 0..string_chunk.string_count {
-            self.put(string_chunk.get_string(i).unwrap().as_str());
+    // Attribute names carrying a resource id must sit at the head of the pool, index i maps to resource_ids[i],
+    // so these have to be put before any plain string.
+    pub(crate) fn put_attr_name(&mut self, name: &str, resource_id: u32) -> Result<u32, AttrNameOrderError> {
+        if let Some(idx) = self.get_attr_name_index(name, resource_id) {
+            return Ok(idx);
         }
+        let res = self.string_arr.len() as u32;
+        if res as usize != self.resource_ids.len() {
+            return Err(AttrNameOrderError);
+        }
+        self.string_index_map.entry(String::from(name)).or_insert(res);
+        self.string_arr.push(String::from(name));
+        self.resource_ids.push(resource_id);
+        Ok(res)
     }
 
-    pub fn from_string_chunk(string_chunk: &StringChunk) -> StringChunkBuilder {
-        let mut res = StringChunkBuilder{
-            string_index_map: HashMap::new(),
-            string_arr: Vec::new()
-        };
-        for i in 0..string_chunk.string_count {
-            res.put(string_chunk.get_string(i).unwrap().as_str());
+    pub(crate) fn get_attr_name_index(&self, name: &str, resource_id: u32) -> Option<u32> {
+        if resource_id == 0 {
+            return self.string_index_map.get(name).copied();
         }
-        res
+        self.resource_ids.iter().enumerate()
+            .find(|(idx, id)| **id == resource_id && self.string_arr[*idx] == name)
+            .map(|(idx, _)| idx as u32)
+    }
+
+    pub fn new() -> StringChunkBuilder {
+        StringChunkBuilder{
+            string_index_map: BTreeMap::new(),
+            string_arr: Vec::new(),
+            resource_ids: Vec::new()
+        }
+    }
+}
+
+impl Default for StringChunkBuilder {
+    fn default() -> Self {
+        StringChunkBuilder::new()
     }
 }
 
 impl XmlAttributeValue {
-    pub fn new_attr(idx: u32, name: &str, value: &str, string_chunk_builder: &mut StringChunkBuilder) -> XmlAttributeValue {
+    pub fn new_string_attr(resource_id: u32, name: &str, value: &str) -> XmlAttributeValue {
+        XmlAttributeValue{
+            namespace_uri: Some(ANDROID_NAMESPACE.to_string()),
+            name: String::from(name),
+            resource_id,
+            value_type: VALUE_TYPE_STRING,
+            string_data: Some(String::from(value)),
+            data: 0 // string index, resolved on regenerate
+        }
+    }
+
+    // Any attribute. `resource_id` is the framework attribute id for android:* names (see attrs), 0 otherwise.
+    // A StringRef is an error, its index means nothing outside the pool it was read from: strings go through
+    // new_string
+    pub fn new(namespace_uri: Option<&str>, name: &str, resource_id: u32, value: TypedValue) -> Result<XmlAttributeValue, ManifestError> {
+        if let TypedValue::StringRef(_) = value {
+            return Err(ManifestError::new(format!("string value of {} given as a pool index, use new_string", name)));
+        }
+        let (value_type, data) = value.encode();
+        Ok(XmlAttributeValue{
+            namespace_uri: namespace_uri.map(String::from),
+            name: String::from(name),
+            resource_id,
+            value_type,
+            string_data: None,
+            data
+        })
+    }
+
+    pub fn new_string(namespace_uri: Option<&str>, name: &str, resource_id: u32, value: &str) -> XmlAttributeValue {
         XmlAttributeValue{
-            namespace_uri: Some("http://schemas.android.com/apk/res/android".to_string()),
+            namespace_uri: namespace_uri.map(String::from),
             name: String::from(name),
-            name_index: idx,
-            value_type: 0x3000008,
+            resource_id,
+            value_type: VALUE_TYPE_STRING,
             string_data: Some(String::from(value)),
-            data: string_chunk_builder.put(value)
+            data: 0 // string index, resolved on regenerate
+        }
+    }
+
+    pub fn new_typed_attr(resource_id: u32, name: &str, value: TypedValue) -> XmlAttributeValue {
+        let (value_type, data) = value.encode();
+        XmlAttributeValue{
+            namespace_uri: Some(ANDROID_NAMESPACE.to_string()),
+            name: String::from(name),
+            resource_id,
+            value_type,
+            string_data: None,
+            data
         }
     }
 
-    pub fn new_name_attr(value: &str, string_chunk_builder: &mut StringChunkBuilder) -> XmlAttributeValue {
-        XmlAttributeValue::new_attr(3, "name", value, string_chunk_builder)
+    pub fn new_bool_attr(resource_id: u32, name: &str, value: bool) -> XmlAttributeValue {
+        XmlAttributeValue::new_typed_attr(resource_id, name, TypedValue::Boolean(value))
     }
 
-    pub fn new_authorities_attr(value: &str, string_chunk_builder: &mut StringChunkBuilder) -> XmlAttributeValue {
-        XmlAttributeValue::new_attr(5, "authorities", value, string_chunk_builder)
+    pub fn new_int_attr(resource_id: u32, name: &str, value: u32) -> XmlAttributeValue {
+        XmlAttributeValue::new_typed_attr(resource_id, name, TypedValue::IntDec(value as i32))
+    }
+
+    pub fn new_hex_attr(resource_id: u32, name: &str, value: u32) -> XmlAttributeValue {
+        XmlAttributeValue::new_typed_attr(resource_id, name, TypedValue::IntHex(value))
+    }
+
+    // value is a resource id such as 0x7f0e0001 (@xml/file_paths)
+    pub fn new_reference_attr(resource_id: u32, name: &str, reference: u32) -> XmlAttributeValue {
+        XmlAttributeValue::new_typed_attr(resource_id, name, TypedValue::Reference(reference))
+    }
+
+    pub fn new_name_attr(value: &str) -> XmlAttributeValue {
+        XmlAttributeValue::new_string_attr(attrs::NAME, "name", value)
+    }
+
+    pub fn new_authorities_attr(value: &str) -> XmlAttributeValue {
+        XmlAttributeValue::new_string_attr(attrs::AUTHORITIES, "authorities", value)
+    }
+
+    pub(crate) fn is_same_attr(&self, other: &XmlAttributeValue) -> bool {
+        self.name == other.name && self.namespace_uri == other.namespace_uri
+    }
+
+    pub(crate) fn has_same_value(&self, other: &XmlAttributeValue) -> bool {
+        self.value_type == other.value_type && self.string_data == other.string_data
+            && (self.string_data.is_some() || self.data == other.data)
+    }
+
+    // "android:name" style name for reports
+    pub(crate) fn qualified_name(&self) -> String {
+        match self.namespace_uri.as_deref() {
+            Some(ANDROID_NAMESPACE) => format!("android:{}", self.name),
+            Some(attrs::TOOLS_NAMESPACE) => format!("tools:{}", self.name),
+            _ => self.name.clone()
+        }
+    }
+
+    pub fn namespace_uri(&self) -> Option<&str> {
+        self.namespace_uri.as_deref()
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn resource_id(&self) -> u32 {
+        self.resource_id
+    }
+
+    // the raw Res_value header word and data word
+    pub fn value_type(&self) -> u32 {
+        self.value_type
+    }
+
+    pub fn data(&self) -> u32 {
+        self.data
+    }
+
+    // the raw string, set for string values (and kept by aapt for some typed ones)
+    pub fn string_value(&self) -> Option<&str> {
+        self.string_data.as_deref()
+    }
+
+    pub fn typed_value(&self) -> TypedValue {
+        TypedValue::decode(self.value_type, self.data)
+    }
+
+    pub fn set_string_value(&mut self, value: &str) {
+        self.value_type = VALUE_TYPE_STRING;
+        self.string_data = Some(String::from(value));
+        self.data = 0; // string index, resolved on regenerate
+    }
+
+    pub fn set_typed_value(&mut self, value: TypedValue) {
+        let (value_type, data) = value.encode();
+        self.value_type = value_type;
+        self.data = data;
+        self.string_data = None;
+    }
+
+    pub(crate) fn display_value(&self) -> String {
+        match &self.string_data {
+            Some(s) => s.clone(),
+            None => self.typed_value().to_string()
+        }
     }
 }
 
 impl XmlNode {
 
-    pub fn walk_children<F>(&mut self, mut f: F) where F: FnMut(&mut Box<XmlNode>) {
+    // `attrs` in any order, they're sorted as set_attr keeps them
+    pub fn new(tag_name: &str, mut attrs: Vec<XmlAttributeValue>) -> XmlNode {
+        attrs.sort_by_key(attr_order);
+        XmlNode{
+            tag_name: String::from(tag_name),
+            attrs,
+            children: vec![],
+            origin: None,
+            namespaces: vec![],
+            unknown_chunks: vec![]
+        }
+    }
+
+    // hash of this element alone, combined with the fingerprints of its children
+    fn shallow_fingerprint(&self, child_fingerprints: &[u64]) -> u64 {
+        let mut hasher = Fnv1a::default();
+        self.tag_name.hash(&mut hasher);
+        for namespace in &self.namespaces {
+            namespace.prefix.hash(&mut hasher);
+            namespace.uri.hash(&mut hasher);
+        }
+        for attr in &self.attrs {
+            attr.namespace_uri.hash(&mut hasher);
+            attr.resource_id.hash(&mut hasher);
+            attr.name.hash(&mut hasher);
+            attr.value_type.hash(&mut hasher);
+            attr.string_data.hash(&mut hasher);
+            // the data word of string values is a pool index, not part of the content
+            if attr.value_type != VALUE_TYPE_STRING || attr.string_data.is_none() {
+                attr.data.hash(&mut hasher);
+            }
+        }
+        child_fingerprints.len().hash(&mut hasher);
+        for fingerprint in child_fingerprints {
+            fingerprint.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    // post-order walk with an explicit stack, deep documents must not overflow the call stack
+    fn fingerprint(&self) -> u64 {
+        let mut stack: Vec<(&XmlNode, Vec<u64>)> = vec![(self, Vec::with_capacity(self.children.len()))];
+        loop {
+            let (node, done) = stack.last().map(|(node, child_fingerprints)| (*node, child_fingerprints.len())).unwrap();
+            if done < node.children.len() {
+                let child = &node.children[done];
+                stack.push((child, Vec::with_capacity(child.children.len())));
+                continue;
+            }
+            let (node, child_fingerprints) = stack.pop().unwrap();
+            let fingerprint = node.shallow_fingerprint(&child_fingerprints);
+            match stack.last_mut() {
+                Some((_, parent_fingerprints)) => parent_fingerprints.push(fingerprint),
+                None => return fingerprint
+            }
+        }
+    }
+
+    // For every element in pre-order, whether it and everything below it are as read from `source_id` and
+    // how many elements its subtree has. One bottom-up pass, rather than fingerprinting a subtree again for
+    // each of its ancestors
+    fn unchanged_subtrees(&self, source_id: u64) -> Vec<(bool, usize)> {
+        let mut res: Vec<(bool, usize)> = vec![(false, 1)];
+        // element, its pre-order index and the fingerprints of its finished children
+        let mut stack: Vec<(&XmlNode, usize, Vec<u64>)> = vec![(self, 0, Vec::with_capacity(self.children.len()))];
+        while let Some((node, _, child_fingerprints)) = stack.last() {
+            if let Some(child) = node.children.get(child_fingerprints.len()) {
+                stack.push((child, res.len(), Vec::with_capacity(child.children.len())));
+                res.push((false, 1));
+                continue;
+            }
+            let (node, index, child_fingerprints) = match stack.pop() {
+                Some(top) => top,
+                None => break
+            };
+            let fingerprint = node.shallow_fingerprint(&child_fingerprints);
+            let unchanged = node.origin.is_some_and(|origin| origin.source_id == source_id && origin.fingerprint == fingerprint);
+            res[index] = (unchanged, res.len() - index);
+            if let Some((_, _, parent_fingerprints)) = stack.last_mut() {
+                parent_fingerprints.push(fingerprint);
+            }
+        }
+        res
+    }
+
+    pub fn iter(&self) -> XmlNodeIter<'_> {
+        XmlNodeIter{
+            stack: vec![(self.tag_name.clone(), self)]
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> XmlNodeIterMut<'_> {
+        XmlNodeIterMut{
+            stack: vec![(self.tag_name.clone(), self)]
+        }
+    }
+
+    pub fn tag_name(&self) -> &str {
+        self.tag_name.as_str()
+    }
+
+    pub fn set_tag_name(&mut self, tag_name: &str) {
+        self.tag_name = String::from(tag_name);
+    }
+
+    pub fn attrs(&self) -> &[XmlAttributeValue] {
+        &self.attrs
+    }
+
+    pub fn attrs_mut(&mut self) -> &mut Vec<XmlAttributeValue> {
+        &mut self.attrs
+    }
+
+    pub fn children(&self) -> &[XmlNode] {
+        &self.children
+    }
+
+    pub fn children_mut(&mut self) -> &mut Vec<XmlNode> {
+        &mut self.children
+    }
+
+    // xmlns declarations made on this element
+    pub fn namespaces(&self) -> &[XmlNameSpace] {
+        &self.namespaces
+    }
+
+    pub fn add_namespace(&mut self, prefix: &str, uri: &str) {
+        let namespace = XmlNameSpace{
+            prefix: String::from(prefix),
+            uri: String::from(uri)
+        };
+        if !self.namespaces.contains(&namespace) {
+            self.namespaces.push(namespace);
+        }
+    }
+
+    pub fn get_attr(&self, namespace_uri: Option<&str>, name: &str) -> Option<&XmlAttributeValue> {
+        self.attrs.iter().find(|attr| attr.name == name && attr.namespace_uri.as_deref() == namespace_uri)
+    }
+
+    pub fn get_attr_mut(&mut self, namespace_uri: Option<&str>, name: &str) -> Option<&mut XmlAttributeValue> {
+        self.attrs.iter_mut().find(|attr| attr.name == name && attr.namespace_uri.as_deref() == namespace_uri)
+    }
+
+    pub fn get_attr_by_id(&self, resource_id: u32) -> Option<&XmlAttributeValue> {
+        self.attrs.iter().find(|attr| resource_id != 0 && attr.resource_id == resource_id)
+    }
+
+    pub fn remove_attr(&mut self, namespace_uri: Option<&str>, name: &str) -> Option<XmlAttributeValue> {
+        let index = self.attrs.iter().position(|attr| attr.name == name && attr.namespace_uri.as_deref() == namespace_uri)?;
+        Some(self.attrs.remove(index))
+    }
+
+    pub fn find_child(&self, tag_name: &str) -> Option<&XmlNode> {
+        self.children.iter().find(|child| child.tag_name == tag_name)
+    }
+
+    pub fn walk_children<F>(&mut self, mut f: F) where F: FnMut(&mut XmlNode) {
         for child in &mut self.children {
             f(child);
         }
     }
 
-    pub fn push_child(&mut self, new_child: Box<XmlNode>) {
+    pub fn push_child(&mut self, new_child: XmlNode) {
         self.children.push(new_child);
     }
 
-    fn parse_node_recursion(data: &Vec<u8>, string_chunk: &StringChunk, current_offset: & mut usize) -> Result<Box<XmlNode>, Box<dyn Error>> {
-        let tag_type = get_le32_value(data, *current_offset);
-        // let line_no = get_le32_value(data, *current_offset + 2 * 4);
-        let name_si = get_leu32_value(data, *current_offset + 5 * 4);
+    pub fn find_child_mut(&mut self, tag_name: &str) -> Option<&mut XmlNode> {
+        self.children.iter_mut().find(|child| child.tag_name == tag_name)
+    }
+
+    pub fn remove_children(&mut self, tag_name: &str) {
+        self.children.retain(|child| child.tag_name != tag_name);
+    }
+
+    // Replace an attribute with the same namespace and name, or insert it by resource id, those
+    // without one last. The framework walks an element's attributes alongside the sorted ids it
+    // looks up, one out of order isn't found
+    pub fn set_attr(&mut self, attr: XmlAttributeValue) {
+        match self.attrs.iter_mut().find(|item| item.is_same_attr(&attr)) {
+            Some(item) => *item = attr,
+            None => {
+                let index = self.attrs.iter().position(|item| attr_order(item) > attr_order(&attr)).unwrap_or(self.attrs.len());
+                self.attrs.insert(index, attr);
+            }
+        }
+    }
+
+    // namespace declarations and the start tag of an element, children are filled in by parse_tree
+    fn parse_start(data: &[u8], string_chunk: &StringChunk, resource_chunk: &ResourceChunk, current_offset: &mut usize,
+                   context: &mut ParseContext) -> Result<XmlNode, FileFormatError> {
+        let mut namespaces: Vec<XmlNameSpace> = Vec::new();
+        while get_chunk_size(data, *current_offset).is_ok() && chunk_type(data, *current_offset)? == START_NAMESPACE {
+            namespaces.push(XmlNameSpace::parse(data, string_chunk, current_offset)?);
+        }
+        let tag_offset = *current_offset;
+        let chunk_size = get_chunk_size(data, tag_offset)?;
+        if chunk_type(data, tag_offset)? != START_TAG {
+            return Err(FileFormatError{ offset: tag_offset });
+        }
+        // let line_no = get_le32_value(data, tag_offset + 2 * 4);
+        // ResXMLTree_attrExt follows the chunk header, attributes are located relative to it
+        let ext_offset = tag_offset + u16_at(data, tag_offset + 2)? as usize;
+        let name_si = u32_at(data, ext_offset + 4)?;
+        let attribute_start = u16_at(data, ext_offset + 8)? as usize;
+        let attribute_size = u16_at(data, ext_offset + 10)? as usize;
+        let attr_number = u16_at(data, ext_offset + 12)? as usize;
+        if attribute_size < ATTRIBUTE_SIZE as usize || ext_offset + attribute_start + attr_number * attribute_size > tag_offset + chunk_size {
+            return Err(FileFormatError{ offset: tag_offset });
+        }
         let mut res = XmlNode{
-            tag_name: String::new(),
-            attrs: vec![],
-            children: vec![]
+            tag_name: String::from(string_chunk.get_string(name_si)?),
+            attrs: Vec::with_capacity(attr_number),
+            children: vec![],
+            origin: None,
+            namespaces,
+            unknown_chunks: vec![]
         };
 
-        let tag_name : String;
-        if tag_type == START_TAG {
-            let attr_number = get_le32_value(data, *current_offset + 7 * 4);
-            *current_offset += 9 * 4;
-            tag_name = string_chunk.get_string(name_si)?;
-            res.tag_name = tag_name.clone();
-
-            for _ in 0..attr_number {
-                let namespace_si = get_leu32_value(data, *current_offset);
-                let attr_name_si = get_leu32_value(data, *current_offset + 1 * 4);
-                let attr_raw_value = get_leu32_value(data, *current_offset + 2 * 4);
-                let value_type =  get_leu32_value(data, *current_offset + 3 * 4);
-                let attr_data = get_leu32_value(data, *current_offset + 4 * 4);
-                let attr_name = string_chunk.get_string(attr_name_si)?;
-                *current_offset += 5 * 4;
-
-                res.attrs.push(XmlAttributeValue{
-                    namespace_uri: if namespace_si == 0xffffffff {
-                        None
-                    } else {
-                        Some(string_chunk.get_string(namespace_si)?)
-                    },
-                    name_index: attr_name_si,
-                    name: attr_name,
-                    value_type,
-                    string_data: if attr_raw_value == 0xffffffff {
-                        None
-                    } else {
-                        Some(string_chunk.get_string(attr_raw_value)?)
-                    },
-                    data: attr_data
-                });
-            }
-        } else {
-            return Err(Box::new(FileFormatError{ offset: *current_offset }))
+        for i in 0..attr_number {
+            let attr_offset = ext_offset + attribute_start + i * attribute_size;
+            let namespace_si = u32_at(data, attr_offset)?;
+            let attr_name_si = u32_at(data, attr_offset + 4)?;
+            let attr_raw_value = u32_at(data, attr_offset + 2 * 4)?;
+            let value_type =  u32_at(data, attr_offset + 3 * 4)?;
+            let attr_data = u32_at(data, attr_offset + 4 * 4)?;
+            let attr_name = String::from(string_chunk.get_string(attr_name_si)?);
+
+            res.attrs.push(XmlAttributeValue{
+                namespace_uri: if namespace_si == 0xffffffff {
+                    None
+                } else {
+                    Some(String::from(string_chunk.get_string(namespace_si)?))
+                },
+                resource_id: resource_chunk.get_resource_id(attr_name_si),
+                name: attr_name,
+                value_type,
+                string_data: match string_chunk.strings.get(attr_raw_value as usize) {
+                    _ if attr_raw_value == 0xffffffff => None,
+                    Some(raw_value) => Some(raw_value.clone()),
+                    // only read for string values, obfuscators fill it with junk elsewhere
+                    None if value_type != VALUE_TYPE_STRING && context.tolerate(attr_offset + 8, "attribute raw value out of the string pool") => None,
+                    None => return Err(FileFormatError{ offset: attr_offset + 8 })
+                },
+                data: attr_data
+            });
         }
+        *current_offset = tag_offset + chunk_size;
+        Ok(res)
+    }
+
+    // Iterative parse of the element at current_offset and everything below it, open elements are kept on
+    // an explicit stack so nesting depth is bounded by memory rather than by the call stack.
+    fn parse_tree(data: &[u8], string_chunk: &StringChunk, resource_chunk: &ResourceChunk, source_id: u64, current_offset: &mut usize,
+                  context: &mut ParseContext) -> Result<XmlNode, FileFormatError> {
+        let mut stack: Vec<(XmlNode, usize)> = Vec::new(); // open element and where its chunks start
+        let start_offset = *current_offset;
+        stack.push((XmlNode::parse_start(data, string_chunk, resource_chunk, current_offset, context)?, start_offset));
 
         while *current_offset < data.len() {
-            let current_tag_type = get_le32_value(data, *current_offset);
-            if current_tag_type == START_TAG {
-                res.children.push(XmlNode::parse_node_recursion(data, string_chunk, current_offset)?);
+            let chunk_size = get_chunk_size(data, *current_offset)?;
+            let current_tag_type = chunk_type(data, *current_offset)?;
+            if current_tag_type == START_TAG || current_tag_type == START_NAMESPACE {
+                let start_offset = *current_offset;
+                stack.push((XmlNode::parse_start(data, string_chunk, resource_chunk, current_offset, context)?, start_offset));
             } else if current_tag_type == END_TAG {
-                let current_name_si = get_leu32_value(data, *current_offset + 5 * 4);
+                let header_size = u16_at(data, *current_offset + 2)? as usize;
+                let current_name_si = u32_at(data, *current_offset + header_size + 4)?;
                 let current_name = string_chunk.get_string(current_name_si)?;
-                *current_offset += 6 * 4;
-                if current_name == tag_name {
-                    return Ok(Box::new(res));
+                if current_name != stack.last().unwrap().0.tag_name {
+                    if !context.tolerate(*current_offset, "end tag doesn't match the open element, skipped") {
+                        return Err(FileFormatError{ offset: *current_offset });
+                    }
+                    *current_offset += chunk_size;
+                    continue;
+                }
+                *current_offset += chunk_size;
+                let (mut node, start_offset) = stack.pop().unwrap();
+                // declarations close in reverse order, right after the element
+                for namespace in node.namespaces.iter().rev() {
+                    namespace.parse_end(data, string_chunk, current_offset)?;
+                }
+                // children are complete, their own fingerprints are already known
+                let child_fingerprints: Vec<u64> = node.children.iter()
+                    .map(|child| child.origin.map(|origin| origin.fingerprint).unwrap_or_else(|| child.fingerprint()))
+                    .collect();
+                node.origin = Some(NodeOrigin{
+                    source_id,
+                    start: start_offset,
+                    end: *current_offset,
+                    fingerprint: node.shallow_fingerprint(&child_fingerprints)
+                });
+                match stack.last_mut() {
+                    Some((parent, _)) => parent.children.push(node),
+                    None => return Ok(node)
                 }
             } else {
-                return Err(Box::new(FileFormatError{ offset: *current_offset }));
+                if current_tag_type != END_NAMESPACE && current_tag_type != CDATA {
+                    context.note(*current_offset, &format!("unknown chunk type {:#x} in an element, kept as is", current_tag_type));
+                }
+                let (node, _) = stack.last_mut().unwrap();
+                node.unknown_chunks.push((node.children.len(), data[*current_offset..*current_offset + chunk_size].to_vec()));
+                *current_offset += chunk_size;
             }
         }
 
-        Ok(Box::new(res))
-
-    }
-
-    fn write<W: Write>(&self, mut writer: W, string_chunk_builder: &mut StringChunkBuilder) -> Result<(),std::io::Error> {
-        writer.write_u32::<LittleEndian>(START_TAG as u32)?;
-        writer.write_u32::<LittleEndian>(9 * 4 + (self.attrs.len() * 5 * 4) as u32)?;
-        writer.write_u32::<LittleEndian>(1)?;
-        writer.write_u32::<LittleEndian>(0xFFFFFFFF)?;
-        writer.write_u32::<LittleEndian>(0xFFFFFFFF)?; //namesapce
-        writer.write_u32::<LittleEndian>(string_chunk_builder.put(self.tag_name.as_str()))?;
-        writer.write_u32::<LittleEndian>(0x00140014)?; // flag
-        writer.write_u32::<LittleEndian>(self.attrs.len() as u32)?;
-        writer.write_u32::<LittleEndian>(0)?;
-
-        for attr in &self.attrs {
-            writer.write_u32::<LittleEndian>(match &attr.namespace_uri {
-                Some(namespace_str) => string_chunk_builder.put(namespace_str.as_str()),
-                None => 0xFFFFFFFF
-            })?;
-            writer.write_u32::<LittleEndian>(attr.name_index)?;
-            writer.write_u32::<LittleEndian>(match &attr.string_data {
-                Some(value_str) => string_chunk_builder.put(value_str.as_str()),
-                None => 0xFFFFFFFF
-            })?;
-            writer.write_u32::<LittleEndian>(attr.value_type)?;
-            writer.write_u32::<LittleEndian>(attr.data)?;
+        // truncated document, close whatever is still open
+        if !context.tolerate(*current_offset, "document ends inside an element") {
+            return Err(FileFormatError{ offset: *current_offset });
         }
-
-        for child in &self.children {
-            child.write(&mut writer, string_chunk_builder)?;
+        let (mut node, _) = stack.pop().unwrap();
+        while let Some((mut parent, _)) = stack.pop() {
+            parent.children.push(node);
+            node = parent;
         }
+        Ok(node)
+    }
 
-        writer.write_u32::<LittleEndian>(END_TAG as u32)?;
-        writer.write_u32::<LittleEndian>(6 * 4)?;
-        writer.write_u32::<LittleEndian>(1)?;
-        writer.write_u32::<LittleEndian>(0xFFFFFFFF)?;
-        writer.write_u32::<LittleEndian>(0xFFFFFFFF)?; // namespace
-        writer.write_u32::<LittleEndian>(string_chunk_builder.put(self.tag_name.as_str()))?;
-
+    fn put_attr_names(&self, string_chunk_builder: &mut StringChunkBuilder) -> Result<(), AttrNameOrderError> {
+        let mut stack: Vec<&XmlNode> = vec![self];
+        while let Some(node) = stack.pop() {
+            for attr in &node.attrs {
+                if attr.resource_id != 0 {
+                    string_chunk_builder.put_attr_name(attr.name.as_str(), attr.resource_id)?;
+                }
+            }
+            stack.extend(node.children.iter().rev());
+        }
         Ok(())
     }
 
-    fn regenerate(&self, data: &mut Vec<u8>, string_chunk_builder: &mut StringChunkBuilder) {
+    fn regenerate_start_tag(&self, data: &mut Vec<u8>, string_chunk_builder: &mut StringChunkBuilder) {
+        for namespace in &self.namespaces {
+            namespace.regenerate(data, START_NAMESPACE, string_chunk_builder);
+        }
         push_le32(data, START_TAG);
-        push_leu32(data, 9 * 4 + (self.attrs.len() * 5 * 4) as u32);
+        push_leu32(data, 9 * 4 + (self.attrs.len() * ATTRIBUTE_SIZE as usize) as u32);
         push_leu32(data, 1);
         push_leu32(data, 0xFFFFFFFF);
         push_leu32(data, 0xFFFFFFFF); // namespace
         push_leu32(data, string_chunk_builder.put(self.tag_name.as_str()));
-        push_leu32(data, 0x00140014); // flag
-        push_leu32(data, self.attrs.len() as u32);
-        push_leu32(data, 0);
+        // attributes are always written with the plain layout, right after the 20 byte attrExt
+        push_leu16(data, ATTRIBUTE_START);
+        push_leu16(data, ATTRIBUTE_SIZE);
+        push_leu16(data, self.attrs.len() as u16);
+        // 1-based positions of android:id, class and style, 0 when absent
+        let id_index = self.attrs.iter().position(|attr| attr.resource_id == attrs::ID);
+        let class_index = self.attrs.iter().position(|attr| attr.namespace_uri.is_none() && attr.name == "class");
+        let style_index = self.attrs.iter().position(|attr| attr.namespace_uri.is_none() && attr.name == "style");
+        for index in [id_index, class_index, style_index] {
+            push_leu16(data, index.map_or(0, |idx| idx as u16 + 1));
+        }
 
         for attr in &self.attrs {
             push_leu32(data, match &attr.namespace_uri {
                 Some(namespace_str) => string_chunk_builder.put(namespace_str.as_str()),
                 None => 0xFFFFFFFF
             });
-            push_leu32(data, attr.name_index);
-            match &attr.string_data {
-                Some(value_str) => push_leu32(data, string_chunk_builder.put(value_str.as_str())),
-                None => push_leu32(data, 0xFFFFFFFF)
-            }
+            push_leu32(data, string_chunk_builder.get_attr_name_index(attr.name.as_str(), attr.resource_id)
+                .unwrap_or_else(|| string_chunk_builder.put(attr.name.as_str())));
+            let raw_index = match &attr.string_data {
+                Some(value_str) => string_chunk_builder.put(value_str.as_str()),
+                None => 0xFFFFFFFF
+            };
+            push_leu32(data, raw_index);
             push_leu32(data, attr.value_type);
-            push_leu32(data, attr.data);
+            if attr.value_type == VALUE_TYPE_STRING && raw_index != 0xFFFFFFFF {
+                push_leu32(data, raw_index);
+            } else {
+                push_leu32(data, attr.data);
+            }
         }
 
-        for child in &self.children {
-            child.regenerate(data, string_chunk_builder);
-        }
+    }
 
+    fn regenerate_end_tag(&self, data: &mut Vec<u8>, string_chunk_builder: &mut StringChunkBuilder) {
         push_le32(data, END_TAG);
         push_leu32(data, 6 * 4);
         push_leu32(data, 1);
         push_leu32(data, 0xFFFFFFFF);
         push_leu32(data, 0xFFFFFFFF); // namespace
         push_leu32(data, string_chunk_builder.put(self.tag_name.as_str()));
+        for namespace in self.namespaces.iter().rev() {
+            namespace.regenerate(data, END_NAMESPACE, string_chunk_builder);
+        }
+    }
+
+    // Unknown chunks are written back untouched at their original position among the children (those past the
+    // end of a shortened child list go last). They are opaque, so string indices inside them are not remapped.
+    fn push_unknown_chunks(&self, data: &mut Vec<u8>, child_index: usize) {
+        let is_last = child_index == self.children.len();
+        for (position, chunk) in &self.unknown_chunks {
+            if *position == child_index || (is_last && *position > child_index) {
+                data.extend_from_slice(chunk);
+            }
+        }
+    }
+
+    fn regenerate(&self, data: &mut Vec<u8>, string_chunk_builder: &mut StringChunkBuilder) {
+        // nothing is copied without the original, so nothing can fail
+        self.write_tree(data, None, string_chunk_builder).unwrap_or(());
+    }
+
+    // like regenerate, but unchanged elements are copied from the original file,
+    // only valid while the original string indices are kept
+    fn regenerate_preserving(&self, data: &mut Vec<u8>, origin_data: &[u8], source_id: u64,
+                             string_chunk_builder: &mut StringChunkBuilder) -> Result<(), FileFormatError> {
+        let unchanged = self.unchanged_subtrees(source_id);
+        self.write_tree(data, Some((origin_data, &unchanged)), string_chunk_builder)
+    }
+
+    // the bytes of the element in the original file if `unchanged` says it's as read, an error when they
+    // are not inside it
+    fn copy_original(&self, data: &mut Vec<u8>, original: Option<Original>, index: usize) -> Result<bool, FileFormatError> {
+        match (original, &self.origin) {
+            (Some((origin_data, unchanged)), Some(origin)) if unchanged.get(index).is_some_and(|(unchanged, _)| *unchanged) => {
+                let bytes = origin_data.get(origin.start..origin.end).ok_or(FileFormatError{ offset: origin.start })?;
+                data.extend_from_slice(bytes);
+                Ok(true)
+            },
+            _ => Ok(false)
+        }
+    }
 
+    // depth first with an explicit stack of (element, next child index, pre-order index of that child)
+    fn write_tree(&self, data: &mut Vec<u8>, original: Option<Original>,
+                  string_chunk_builder: &mut StringChunkBuilder) -> Result<(), FileFormatError> {
+        if self.copy_original(data, original, 0)? {
+            return Ok(());
+        }
+        let subtree_size = |index: usize| original.and_then(|(_, unchanged)| unchanged.get(index)).map_or(1, |(_, size)| *size);
+        self.regenerate_start_tag(data, string_chunk_builder);
+        let mut stack: Vec<(&XmlNode, usize, usize)> = vec![(self, 0, 1)];
+        while let Some((node, child_index, index)) = stack.pop() {
+            node.push_unknown_chunks(data, child_index);
+            let child = match node.children.get(child_index) {
+                Some(child) => child,
+                None => {
+                    node.regenerate_end_tag(data, string_chunk_builder);
+                    continue;
+                }
+            };
+            stack.push((node, child_index + 1, index + subtree_size(index)));
+            if !child.copy_original(data, original, index)? {
+                child.regenerate_start_tag(data, string_chunk_builder);
+                stack.push((child, 0, index + 1));
+            }
+        }
+        Ok(())
     }
 
 }
 
+// where an attribute goes among the others of its element
+fn attr_order(attr: &XmlAttributeValue) -> (bool, u32) {
+    (attr.resource_id == 0, attr.resource_id)
+}
+
+fn child_paths<'a, I: Iterator<Item = &'a String>>(path: &str, tag_names: I) -> Vec<String> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    tag_names.map(|tag_name| {
+        let count = counts.entry(tag_name.as_str()).or_insert(0);
+        *count += 1;
+        format!("{}/{}[{}]", path, tag_name, *count - 1)
+    }).collect()
+}
+
+impl<'a> Iterator for XmlNodeIter<'a> {
+    type Item = (String, &'a XmlNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = self.stack.pop()?;
+        let paths = child_paths(path.as_str(), node.children.iter().map(|child| &child.tag_name));
+        self.stack.extend(paths.into_iter().zip(node.children.iter()).rev());
+        Some((path, node))
+    }
+}
+
+impl<'a> Iterator for XmlNodeIterMut<'a> {
+    type Item = (String, XmlElementMut<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = self.stack.pop()?;
+        let paths = child_paths(path.as_str(), node.children.iter().map(|child| &child.tag_name));
+        self.stack.extend(paths.into_iter().zip(node.children.iter_mut()).rev());
+        Some((path, XmlElementMut{
+            tag_name: &mut node.tag_name,
+            attrs: &mut node.attrs
+        }))
+    }
+}
+
+// drop deep trees level by level instead of recursing through every nested Vec
+impl Drop for XmlNode {
+    fn drop(&mut self) {
+        let mut pending: Vec<XmlNode> = core::mem::take(&mut self.children);
+        while let Some(mut node) = pending.pop() {
+            pending.append(&mut node.children);
+        }
+    }
+}
+
 impl XmlContent {
-    fn parse<'a>(data: &'a Vec<u8>, string_chunk: &StringChunk, current_offset: &mut usize) -> Result<Box<XmlContent>, Box<dyn Error>> {
-        let namespace = XmlNameSpace::parse(data, string_chunk, current_offset)?;
-        let root = XmlNode::parse_node_recursion(data, string_chunk, current_offset)?;
-        namespace.valid_end_chunk(data, string_chunk, current_offset)?;
+    fn parse(data: &[u8], string_chunk: &StringChunk, resource_chunk: &ResourceChunk, source_id: u64,
+             leading_chunks: Vec<Vec<u8>>, current_offset: &mut usize, context: &mut ParseContext) -> Result<Box<XmlContent>, FileFormatError> {
+        let root = XmlNode::parse_tree(data, string_chunk, resource_chunk, source_id, current_offset, context)?;
+        let mut trailing_chunks: Vec<Vec<u8>> = Vec::new();
+        while *current_offset < data.len() {
+            let chunk_size = get_chunk_size(data, *current_offset)?;
+            if chunk_type(data, *current_offset)? != END_NAMESPACE {
+                context.note(*current_offset, "chunk after the root element, kept as is");
+            }
+            trailing_chunks.push(data[*current_offset..*current_offset + chunk_size].to_vec());
+            *current_offset += chunk_size;
+        }
         Ok(Box::new(XmlContent{
-            namespace_prefix: namespace.prefix,
-            namespace_uri: namespace.uri,
-            root_node: root
+            root_node: root,
+            leading_chunks,
+            trailing_chunks
         }))
     }
 
-    fn to_data(&self, string_chunk_builder: &mut StringChunkBuilder) -> Vec<u8> {
-        let mut res: Vec<u8> = Vec::new();
-
-        // start namespace
-        push_le32(&mut res, START_NAMESPACE);
-        push_leu32(&mut res, 4 * 6);
-        push_leu32(&mut res, 1); // line number
-        push_leu32(&mut res, 0xFFFFFFFF);
-        push_leu32(&mut res, string_chunk_builder.put(self.namespace_prefix.as_str()));
-        push_leu32(&mut res, string_chunk_builder.put(self.namespace_uri.as_str()));
+    // `capacity` is the size of the document as read, edits rarely change it by much
+    fn to_data_preserving(&self, origin_data: &[u8], source_id: u64, capacity: usize,
+                          string_chunk_builder: &mut StringChunkBuilder) -> Result<Vec<u8>, FileFormatError> {
+        let mut res: Vec<u8> = Vec::with_capacity(capacity);
+        self.leading_chunks.iter().for_each(|chunk| res.extend_from_slice(chunk));
+        self.root_node.regenerate_preserving(&mut res, origin_data, source_id, string_chunk_builder)?;
+        self.trailing_chunks.iter().for_each(|chunk| res.extend_from_slice(chunk));
+        Ok(res)
+    }
 
+    fn to_data(&self, capacity: usize, string_chunk_builder: &mut StringChunkBuilder) -> Vec<u8> {
+        let mut res: Vec<u8> = Vec::with_capacity(capacity);
+        self.leading_chunks.iter().for_each(|chunk| res.extend_from_slice(chunk));
         self.root_node.regenerate(&mut res, string_chunk_builder);
-
-        // end namespace
-        push_le32(&mut res, END_NAMESPACE);
-        push_leu32(&mut res, 4 * 6);
-        push_leu32(&mut res, 1); // line number
-        push_leu32(&mut res, 0xFFFFFFFF);
-        push_leu32(&mut res, string_chunk_builder.put(self.namespace_prefix.as_str()));
-        push_leu32(&mut res, string_chunk_builder.put(self.namespace_uri.as_str()));
+        self.trailing_chunks.iter().for_each(|chunk| res.extend_from_slice(chunk));
         res
     }
 }
 
-impl XmlNameSpace<'_> {
-    fn parse<'a>(data: &'a Vec<u8>,string_chunk: &StringChunk, current_offset: &mut usize) -> Result<Box<XmlNameSpace<'a>>, Box<dyn Error>> {
-        if get_le32_value(data, *current_offset) != START_NAMESPACE {
-            return Err(Box::new(FileFormatError{offset: *current_offset}));
+impl XmlNameSpace {
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    pub fn uri(&self) -> &str {
+        self.uri.as_str()
+    }
+
+    fn parse(data: &[u8], string_chunk: &StringChunk, current_offset: &mut usize) -> Result<XmlNameSpace, FileFormatError> {
+        if chunk_type(data, *current_offset)? != START_NAMESPACE {
+            return Err(FileFormatError{offset: *current_offset});
         }
         let res = XmlNameSpace{
-            data,
-            namespace_offset: *current_offset,
-            line_number: get_leu32_value(data, *current_offset + 2 * 4),
-            prefix: string_chunk.get_string(get_leu32_value(data, *current_offset + 4 * 4))?,
-            uri: string_chunk.get_string(get_leu32_value(data, *current_offset + 5 * 4))?
+            prefix: String::from(string_chunk.get_string(u32_at(data, *current_offset + 4 * 4)?)?),
+            uri: String::from(string_chunk.get_string(u32_at(data, *current_offset + 5 * 4)?)?)
         };
-        *current_offset += get_leu32_value(data, *current_offset + 4) as usize;
-        Ok(Box::new(res))
+        *current_offset += get_chunk_size(data, *current_offset)?;
+        Ok(res)
     }
 
-    fn valid_end_chunk<'a>(&self, data: &'a Vec<u8>,string_chunk: &StringChunk, current_offset: &mut usize) -> Result<(), Box<dyn Error>> {
-        if get_le32_value(data, *current_offset) != END_NAMESPACE {
-            return Err(Box::new(FileFormatError{offset: *current_offset}));
+    // the END_NAMESPACE chunk at current_offset must close this declaration
+    fn parse_end(&self, data: &[u8], string_chunk: &StringChunk, current_offset: &mut usize) -> Result<(), FileFormatError> {
+        let chunk_size = get_chunk_size(data, *current_offset)?;
+        if chunk_type(data, *current_offset)? != END_NAMESPACE {
+            return Err(FileFormatError{offset: *current_offset});
         }
-        let prefix = string_chunk.get_string(get_leu32_value(data, *current_offset + 4 * 4))?;
-        let uri = string_chunk.get_string(get_leu32_value(data, *current_offset + 5 * 4))?;
+        let prefix = string_chunk.get_string(u32_at(data, *current_offset + 4 * 4)?)?;
+        let uri = string_chunk.get_string(u32_at(data, *current_offset + 5 * 4)?)?;
         if prefix != self.prefix || uri != self.uri {
-            return Err(Box::new(FileFormatError{offset: *current_offset}));
+            return Err(FileFormatError{offset: *current_offset});
         }
+        *current_offset += chunk_size;
         Ok(())
     }
+
+    fn regenerate(&self, data: &mut Vec<u8>, chunk_type: i32, string_chunk_builder: &mut StringChunkBuilder) {
+        push_le32(data, chunk_type);
+        push_leu32(data, 4 * 6);
+        push_leu32(data, 1); // line number
+        push_leu32(data, 0xFFFFFFFF);
+        push_leu32(data, string_chunk_builder.put(self.prefix.as_str()));
+        push_leu32(data, string_chunk_builder.put(self.uri.as_str()));
+    }
 }
 
-impl ResourceChunk<'_> {
-    fn parse<'a>(data: &'a Vec<u8>, current_offset: &mut usize) -> Result<Box<ResourceChunk<'a>>,Box<dyn Error>> {
+impl ResourceChunk {
+    fn parse(data: &[u8], current_offset: &mut usize) -> Result<Box<ResourceChunk>,FileFormatError> {
+        if chunk_type(data, *current_offset)? != RESOURCE_CHUNK {
+            return Err(FileFormatError{offset: *current_offset})
+        }
+        let chunk_size = get_chunk_size(data, *current_offset)?;
         let mut res = ResourceChunk{
-            data,
-            chunk_offset: *current_offset,
-            chunk_size: get_leu32_value(data, *current_offset + 4),
-            chunk_count: 0
+            resource_ids: Vec::new()
         };
-        if (get_le32_value(data, *current_offset)) != RESOURCE_CHUNK {
-            return Err(Box::new(FileFormatError{offset: *current_offset}))
+        for i in 0..(chunk_size / 4 - 2) {
+            res.resource_ids.push(u32_at(data, *current_offset + 8 + 4 * i)?);
         }
-        res.chunk_count = res.chunk_size/4 - 2;
-        *current_offset = *current_offset + res.chunk_size as usize;
+        *current_offset += chunk_size;
         Ok(Box::new(res))
     }
-}
 
-impl StringChunk<'_> {
-    fn parse<'a>(data: &'a Vec<u8>, current_offset: &mut usize) -> Result<Box<StringChunk<'a>>,Box<dyn Error>> {
-        let mut res = StringChunk{
-            data,
-            chunk_offset: *current_offset,
-            chunk_size: 0,
-            string_count: 0,
-            style_count: 0,
-            string_pool_offset: 0,
-            style_pool_offset: 0,
-            string_index_global_offset: 0,
-            style_index_global_offset: 0
-        };
-        let chunk_type = get_le32_value(data, *current_offset);
-        if chunk_type != STRING_CHUNK {
-            return Err(Box::new(FileFormatError{offset: *current_offset}));
-        }
-        *current_offset += 4;
-        res.chunk_size = get_leu32_value(data, *current_offset);
-        *current_offset += 4;
-        res.string_count = get_leu32_value(data, *current_offset);
-        *current_offset += 4;
-        res.style_count = get_leu32_value(data, *current_offset);
-        *current_offset += 8; // 4 byte unknown
-        res.string_pool_offset = get_leu32_value(data, *current_offset);
-        *current_offset += 4;
-        res.style_pool_offset = get_leu32_value(data, *current_offset);
-        *current_offset += 4;
-        res.string_index_global_offset = *current_offset;
-        *current_offset += 4;
-        res.style_index_global_offset = *current_offset;
-        *current_offset = res.chunk_offset + (res.chunk_size as usize);
-        Ok(Box::new(res))
+    fn get_resource_id(&self, string_index: u32) -> u32 {
+        match self.resource_ids.get(string_index as usize) {
+            Some(id) => *id,
+            None => 0
+        }
     }
+}
 
-    fn get_string(&self, index: u32) -> Result<String, FromUtf16Error> {
-        let string_offset = (self.string_pool_offset as usize) + self.chunk_offset + get_leu32_value(self.data, self.string_index_global_offset + (4 * index as usize)) as usize;
-        let string_len = (self.data[string_offset as usize] as u16) | ((self.data[(string_offset + 1) as usize] as u16) << 8);
-        let mut utf_16_data : Vec<u16> = Vec::new();
-        for i in 0..string_len {
-            let char_index = (string_offset + 2 + ((i * 2) as usize)) as usize;
-            let c = (self.data[char_index] as u16) | ((self.data[char_index + 1] as u16) << 8);
-            utf_16_data.push(c);
+impl StringChunk {
+    // every string is decoded here, so a broken pool fails the parse instead of a later lookup
+    fn parse(data: &[u8], current_offset: &mut usize) -> Result<Box<StringChunk>, FileFormatError> {
+        let chunk_offset = *current_offset;
+        if chunk_type(data, chunk_offset)? != STRING_CHUNK {
+            return Err(FileFormatError{offset: chunk_offset});
         }
-        String::from_utf16(utf_16_data.as_slice())
+        let chunk_size = get_chunk_size(data, chunk_offset)?;
+        let chunk = &data[chunk_offset..chunk_offset + chunk_size];
+        let at = |offset: usize| FileFormatError{ offset: chunk_offset + offset };
+        let string_count = read_leu32(chunk, 8).ok_or_else(|| at(8))? as usize;
+        let string_pool_offset = read_leu32(chunk, 20).ok_or_else(|| at(20))? as usize;
+        // the string offsets follow the 28 byte header, a count past what the chunk can hold fails below
+        let mut strings: Vec<String> = Vec::with_capacity(string_count.min(chunk_size / 4));
+        let mut utf_16_data: Vec<u16> = Vec::new(); // reused for every string
+        for index in 0..string_count {
+            let index_offset = 28 + 4 * index;
+            let string_offset = string_pool_offset.checked_add(read_leu32(chunk, index_offset).ok_or_else(|| at(index_offset))? as usize)
+                .ok_or_else(|| at(index_offset))?;
+            let mut string_len = read_leu16(chunk, string_offset).ok_or_else(|| at(string_offset))? as usize;
+            let mut data_offset = string_offset + 2;
+            if string_len & 0x8000 != 0 {
+                let low = read_leu16(chunk, data_offset).ok_or_else(|| at(data_offset))? as usize;
+                string_len = ((string_len & 0x7fff) << 16) | low;
+                data_offset += 2;
+            }
+            utf_16_data.clear();
+            utf_16_data.extend(chunk.get(data_offset..data_offset.saturating_add(string_len * 2))
+                .ok_or_else(|| at(string_offset))?
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]])));
+            strings.push(String::from_utf16(&utf_16_data).map_err(|_| at(string_offset))?);
+        }
+        *current_offset = chunk_offset + chunk_size;
+        Ok(Box::new(StringChunk{
+            chunk_offset,
+            chunk_size: chunk_size as u32,
+            strings
+        }))
+    }
+
+    // borrowed, the parse copies only what it keeps: names it compares against are never allocated
+    fn get_string(&self, index: u32) -> Result<&str, FileFormatError> {
+        self.strings.get(index as usize).map(String::as_str).ok_or(FileFormatError{ offset: self.chunk_offset })
     }
 
 }
@@ -496,57 +1131,184 @@ impl XmlNode {
         }
         res.push_str("</");
         res.push_str(self.tag_name.as_str());
-        res.push_str(">");
+        res.push('>');
     }
 }
 
 
 impl AndroidXml<'_> {
-    pub fn from_data(data: &Vec<u8>) -> Result<AndroidXml, Box<dyn Error>> {
-        let mut current_offset : usize = 0;
-        let magic = get_le32_value(data, current_offset);
-        if magic != XML_MAGIC {
-            return Err(Box::new(FileFormatError{offset: 0}))
-        }
-        current_offset += 4;
-        let file_length = get_le32_value(data, current_offset);
-        if file_length as usize != data.len() {
-            return Err(Box::new(FileFormatError{offset: current_offset}))
-        }
-        current_offset += 4;
-        let string_chunk = StringChunk::parse(data, &mut current_offset)?;
-        let resource_chunk = ResourceChunk::parse(data, &mut current_offset)?;
-        let content = XmlContent::parse(data, &string_chunk, &mut current_offset)?;
+    pub fn from_data<T: AsRef<[u8]> + ?Sized>(data: &T) -> Result<AndroidXml<'_>, FileFormatError> {
+        AndroidXml::parse(Cow::Borrowed(data.as_ref()), ParseOptions::default())
+    }
+
+    pub fn from_data_with_options<T: AsRef<[u8]> + ?Sized>(data: &T, options: ParseOptions) -> Result<AndroidXml<'_>, FileFormatError> {
+        AndroidXml::parse(Cow::Borrowed(data.as_ref()), options)
+    }
+
+    // takes the buffer, the result borrows nothing
+    pub fn from_vec(data: Vec<u8>) -> Result<AndroidXml<'static>, FileFormatError> {
+        AndroidXml::parse(Cow::Owned(data), ParseOptions::default())
+    }
+
+    pub fn from_vec_with_options(data: Vec<u8>, options: ParseOptions) -> Result<AndroidXml<'static>, FileFormatError> {
+        AndroidXml::parse(Cow::Owned(data), options)
+    }
+
+    // what a lenient parse let through, always empty for a strict one
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    pub fn into_owned(self) -> AndroidXml<'static> {
+        AndroidXml{
+            data: Cow::Owned(self.data.into_owned()),
+            source_id: self.source_id,
+            string_chunk: self.string_chunk,
+            resource_chunk: self.resource_chunk,
+            content: self.content,
+            warnings: self.warnings
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "axml_parse", level = "debug", skip_all,
+        fields(bytes = buffer.len(), strings = tracing::field::Empty)))]
+    fn parse(buffer: Cow<'_, [u8]>, options: ParseOptions) -> Result<AndroidXml<'_>, FileFormatError> {
+        let mut data: &[u8] = &buffer;
+        let mut context = ParseContext::new(options);
+        if data.len() < 8 {
+            return Err(FileFormatError{offset: 0})
+        }
+        let magic = chunk_type(data, 0)?;
+        // Android finds the first chunk through the header size, packers grow it to trip other parsers
+        if magic & 0xffff != XML_MAGIC & 0xffff
+            || (magic != XML_MAGIC && !context.tolerate(2, "xml header size isn't 8")) {
+            return Err(FileFormatError{offset: 0})
+        }
+        let mut current_offset = u16_at(data, 2)? as usize;
+        let file_length = u32_at(data, 4)? as usize;
+        if current_offset < 8 || file_length < current_offset || file_length > data.len() {
+            return Err(FileFormatError{offset: 4})
+        }
+        if file_length < data.len() {
+            if !context.tolerate(file_length, "data after the end of the xml, ignored") {
+                return Err(FileFormatError{offset: 4})
+            }
+            data = &data[..file_length];
+        }
+        let source_id = NEXT_SOURCE_ID.fetch_add(1, Ordering::Relaxed);
+        // walk the chunks ahead of the document, anything besides the string pool and resource map is kept as is
+        let mut string_chunk: Option<Box<StringChunk>> = None;
+        let mut resource_chunk: Option<Box<ResourceChunk>> = None;
+        let mut leading_chunks: Vec<Vec<u8>> = Vec::new();
+        while current_offset < data.len() {
+            let chunk_type = chunk_type(data, current_offset)?;
+            if chunk_type == START_NAMESPACE || chunk_type == START_TAG {
+                break;
+            }
+            let chunk_size = get_chunk_size(data, current_offset)?;
+            if chunk_type == STRING_CHUNK && string_chunk.is_none() {
+                string_chunk = Some(StringChunk::parse(data, &mut current_offset)?);
+            } else if chunk_type == RESOURCE_CHUNK && resource_chunk.is_none() {
+                resource_chunk = Some(ResourceChunk::parse(data, &mut current_offset)?);
+            } else {
+                context.note(current_offset, &format!("chunk type {:#x} ahead of the root element, kept as is", chunk_type));
+                leading_chunks.push(data[current_offset..current_offset + chunk_size].to_vec());
+                current_offset += chunk_size;
+            }
+        }
+        let string_chunk = string_chunk.ok_or(FileFormatError{ offset: current_offset })?;
+        record!("strings", string_chunk.strings.len());
+        let resource_chunk = resource_chunk.unwrap_or_else(|| Box::new(ResourceChunk{ resource_ids: vec![] }));
+        let content = XmlContent::parse(data, &string_chunk, &resource_chunk, source_id, leading_chunks, &mut current_offset, &mut context)?;
 
         Ok(AndroidXml{
-            data,
+            data: buffer,
+            source_id,
             string_chunk,
             resource_chunk,
-            content
+            content,
+            warnings: context.warnings
         })
     }
 
-    pub fn regenerate(&self,string_chunk_builder: &mut StringChunkBuilder) -> Vec<u8> {
-        let mut res: Vec<u8> = Vec::new();
-        push_le32(&mut res, XML_MAGIC);
+    pub fn root_node(&self) -> &XmlNode {
+        &self.content.root_node
+    }
+
+    pub fn root_node_mut(&mut self) -> &mut XmlNode {
+        &mut self.content.root_node
+    }
+
+    pub fn regenerate(&self) -> Vec<u8> {
+        let mut string_chunk_builder = StringChunkBuilder::new();
+        // keep the original pool order, new attribute names go right after the original resource-mapped ones
+        // only attribute names are in the pool until the plain strings below, none of these is out of order
+        for (name, resource_id) in self.string_chunk.strings.iter().zip(&self.resource_chunk.resource_ids) {
+            string_chunk_builder.put_attr_name(name.as_str(), *resource_id).ok();
+        }
+        self.content.root_node.put_attr_names(&mut string_chunk_builder).ok();
+        for string in &self.string_chunk.strings {
+            string_chunk_builder.put(string.as_str());
+        }
 
-        let content_data = self.content.to_data(string_chunk_builder);
+        let content_data = self.content.to_data(self.content_size(), &mut string_chunk_builder);
         let string_chunk_data = string_chunk_builder.build();
-        let file_size = 4 * 2 + string_chunk_data.len() + self.resource_chunk.chunk_size as usize +
-            content_data.len();
+        let resource_chunk_data = string_chunk_builder.build_resource_chunk();
+        AndroidXml::assemble(&string_chunk_data, &resource_chunk_data, &content_data)
+    }
+
+    // what follows the string pool and the resource map in the file as read
+    fn content_size(&self) -> usize {
+        self.data.len().saturating_sub(8 + self.string_chunk.chunk_size as usize + 8 + self.resource_chunk.resource_ids.len() * 4)
+    }
 
+    fn assemble(string_chunk_data: &[u8], resource_chunk_data: &[u8], content_data: &[u8]) -> Vec<u8> {
+        let file_size = 4 * 2 + string_chunk_data.len() + resource_chunk_data.len() + content_data.len();
+        let mut res: Vec<u8> = Vec::with_capacity(file_size);
+        push_le32(&mut res, XML_MAGIC);
         push_leu32(&mut res, file_size as u32);
-        res.extend(string_chunk_data);
-        for i in 0..self.resource_chunk.chunk_size {
-            res.push(self.data[self.resource_chunk.chunk_offset + i as usize]);
-        }
-        res.extend(content_data);
+        res.extend_from_slice(string_chunk_data);
+        res.extend_from_slice(resource_chunk_data);
+        res.extend_from_slice(content_data);
         res
     }
+
+    // Fidelity mode: original string indices are kept (new strings are appended to the pool) and every element
+    // that was not modified is copied byte for byte, an untouched document comes out identical to its input.
+    pub fn regenerate_preserving(&self) -> Vec<u8> {
+        let mut string_chunk_builder = StringChunkBuilder::new();
+        let resource_count = self.resource_chunk.resource_ids.len();
+        // a resource id past the end of the pool names nothing, it is dropped and the indices regenerated
+        for (name, resource_id) in self.string_chunk.strings.iter().zip(&self.resource_chunk.resource_ids) {
+            string_chunk_builder.push_original(name.as_str(), Some(*resource_id));
+        }
+        let put = self.content.root_node.put_attr_names(&mut string_chunk_builder);
+        if put.is_err() || string_chunk_builder.resource_ids.len() != resource_count {
+            // a new resource mapped attribute name shifts every string index
+            return self.regenerate();
+        }
+        for string in self.string_chunk.strings.iter().skip(resource_count) {
+            string_chunk_builder.push_original(string.as_str(), None);
+        }
+
+        // the original bytes not being where they were parsed from falls back to a full regenerate
+        let content_data = match self.content.to_data_preserving(&self.data, self.source_id, self.content_size(), &mut string_chunk_builder) {
+            Ok(content_data) => content_data,
+            Err(_) => return self.regenerate()
+        };
+        let start = self.string_chunk.chunk_offset;
+        let original_pool = self.data.get(start..start.saturating_add(self.string_chunk.chunk_size as usize));
+        let string_chunk_data = match original_pool {
+            Some(pool) if string_chunk_builder.string_arr.len() == self.string_chunk.strings.len() => Cow::Borrowed(pool),
+            _ => Cow::Owned(string_chunk_builder.build())
+        };
+        let resource_chunk_data = string_chunk_builder.build_resource_chunk();
+        AndroidXml::assemble(&string_chunk_data, &resource_chunk_data, &content_data)
+    }
 }
 
 impl Display for AndroidXml<'_> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         let mut s = String::new();
         self.content.root_node.push_data(&mut s);
         write!(f, "{}", s)
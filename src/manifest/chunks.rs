@@ -0,0 +1,157 @@
+use alloc::format;
+use alloc::vec::Vec;
+use crate::manifest::axml::FileFormatError;
+use crate::manifest::manifest_editor::ManifestError;
+use crate::utils::{push_leu16, push_leu32, read_leu16, read_leu32};
+
+// chunk types of a compiled xml. The parser compares type and header size as one word, these are the type half
+pub const XML: u16 = 0x0003;
+pub const STRING_POOL: u16 = 0x0001;
+pub const RESOURCE_MAP: u16 = 0x0180;
+pub const START_NAMESPACE: u16 = 0x0100;
+pub const END_NAMESPACE: u16 = 0x0101;
+pub const START_ELEMENT: u16 = 0x0102;
+pub const END_ELEMENT: u16 = 0x0103;
+pub const CDATA: u16 = 0x0104;
+
+// one chunk inside the outer xml chunk, as it sits in the file. The chunks of a document are flat, an
+// element is a START_ELEMENT and the matching END_ELEMENT with its children's chunks in between
+#[derive(Clone, Copy, Debug)]
+pub struct XmlChunk<'a> {
+    offset: usize,
+    bytes: &'a [u8]
+}
+
+// what a visitor does with the chunk it was shown
+pub enum ChunkAction {
+    Keep,
+    Remove,
+    Replace(Vec<u8>) // whole chunks, 8 byte prefix included, XmlChunk::encode builds one
+}
+
+pub trait ChunkVisitor {
+    fn visit(&mut self, chunk: &XmlChunk) -> ChunkAction;
+}
+
+impl<F: FnMut(&XmlChunk) -> ChunkAction> ChunkVisitor for F {
+    fn visit(&mut self, chunk: &XmlChunk) -> ChunkAction {
+        self(chunk)
+    }
+}
+
+pub struct XmlChunkIter<'a> {
+    data: &'a [u8],
+    offset: usize,
+    end: usize
+}
+
+impl<'a> XmlChunk<'a> {
+    // of the chunk in the whole file
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn chunk_type(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[0], self.bytes[1]])
+    }
+
+    pub fn header_size(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[2], self.bytes[3]])
+    }
+
+    pub fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    // the type specific header, after the prefix up to header_size
+    pub fn header(&self) -> &'a [u8] {
+        &self.bytes[8..self.header_size() as usize]
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        &self.bytes[self.header_size() as usize..]
+    }
+
+    // the chunk as is, prefix included
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    // builds a chunk with its header size and size filled in, an error when they don't fit their 16
+    // and 32 bits
+    pub fn encode(chunk_type: u16, header: &[u8], payload: &[u8]) -> Result<Vec<u8>, ManifestError> {
+        let header_size = u16::try_from(8 + header.len())
+            .map_err(|_| ManifestError::new(format!("a chunk header of {} bytes is too large", header.len())))?;
+        let size = u32::try_from(8 + header.len() + payload.len())
+            .map_err(|_| ManifestError::new(format!("a chunk of {} bytes is too large", 8 + header.len() + payload.len())))?;
+        let mut res: Vec<u8> = Vec::with_capacity(8 + header.len() + payload.len());
+        push_leu16(&mut res, chunk_type);
+        push_leu16(&mut res, header_size);
+        push_leu32(&mut res, size);
+        res.extend_from_slice(header);
+        res.extend_from_slice(payload);
+        Ok(res)
+    }
+}
+
+impl<'a> Iterator for XmlChunkIter<'a> {
+    type Item = Result<XmlChunk<'a>, FileFormatError>;
+
+    // stops at the first chunk that doesn't fit, after returning its error
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.end {
+            return None;
+        }
+        let offset = self.offset;
+        let header_size = read_leu16(self.data, offset + 2).unwrap_or_default() as usize;
+        let size = read_leu32(self.data, offset + 4).unwrap_or_default() as usize;
+        if header_size < 8 || size < header_size || size > self.end - offset {
+            self.offset = self.end;
+            return Some(Err(FileFormatError{ offset }));
+        }
+        self.offset += size;
+        Some(Ok(XmlChunk{
+            offset,
+            bytes: &self.data[offset..offset + size]
+        }))
+    }
+}
+
+// the chunks of a compiled xml, in file order. Only the outer header is checked here, each chunk
+// is checked as the walk reaches it and none is decoded
+pub fn read_chunks(data: &[u8]) -> Result<XmlChunkIter<'_>, FileFormatError> {
+    let (chunk_type, header_size, size) = match (read_leu16(data, 0), read_leu16(data, 2), read_leu32(data, 4)) {
+        (Some(chunk_type), Some(header_size), Some(size)) => (chunk_type, header_size as usize, size as usize),
+        _ => return Err(FileFormatError{ offset: 0 })
+    };
+    if chunk_type != XML || header_size < 8 || header_size > data.len() {
+        return Err(FileFormatError{ offset: 0 });
+    }
+    if size < header_size || size > data.len() {
+        return Err(FileFormatError{ offset: 4 });
+    }
+    Ok(XmlChunkIter{
+        data,
+        offset: header_size,
+        end: size
+    })
+}
+
+// shows every chunk to `visitor` and puts the file back together from what it returned, with the
+// outer size fixed up. Nothing is checked beyond the chunk bounds, a replacement that breaks the
+// document is written as is. Bytes past the outer chunk are dropped
+pub fn visit_chunks<V: ChunkVisitor + ?Sized>(data: &[u8], visitor: &mut V) -> Result<Vec<u8>, FileFormatError> {
+    let chunks = read_chunks(data)?;
+    let mut res: Vec<u8> = data[..chunks.offset].to_vec();
+    for chunk in chunks {
+        let chunk = chunk?;
+        match visitor.visit(&chunk) {
+            ChunkAction::Keep => res.extend_from_slice(chunk.bytes()),
+            ChunkAction::Remove => {},
+            ChunkAction::Replace(bytes) => res.extend(bytes)
+        }
+    }
+    let size = res.len() as u32;
+    res[4..8].copy_from_slice(&size.to_le_bytes());
+    Ok(res)
+}
@@ -0,0 +1,92 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::manifest::attrs;
+use crate::manifest::axml::XmlNode;
+use crate::manifest::typed_value::TypedValue;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ComponentKind {
+    Activity,
+    ActivityAlias,
+    Service,
+    Receiver,
+    Provider
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct IntentFilter {
+    pub actions: Vec<String>,
+    pub categories: Vec<String>,
+    pub schemes: Vec<String>
+}
+
+// a component declared under <application>, as far as its exposure to other apps is concerned
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Component {
+    pub kind: ComponentKind,
+    pub name: String,
+    pub exported: Option<bool>, // None when unset or not a literal boolean (e.g. @bool/...)
+    pub permission: Option<String>,
+    pub intent_filters: Vec<IntentFilter>
+}
+
+impl ComponentKind {
+    fn from_tag(tag_name: &str) -> Option<ComponentKind> {
+        match tag_name {
+            "activity" => Some(ComponentKind::Activity),
+            "activity-alias" => Some(ComponentKind::ActivityAlias),
+            "service" => Some(ComponentKind::Service),
+            "receiver" => Some(ComponentKind::Receiver),
+            "provider" => Some(ComponentKind::Provider),
+            _ => None
+        }
+    }
+}
+
+fn get_string(node: &XmlNode, resource_id: u32) -> Option<String> {
+    node.attrs.iter()
+        .find(|attr| attr.resource_id == resource_id)
+        .and_then(|attr| attr.string_data.clone())
+}
+
+fn collect_strings(node: &XmlNode, tag_name: &str, resource_id: u32) -> Vec<String> {
+    node.children.iter()
+        .filter(|child| child.tag_name == tag_name)
+        .filter_map(|child| get_string(child, resource_id))
+        .collect()
+}
+
+impl IntentFilter {
+    fn from_node(node: &XmlNode) -> IntentFilter {
+        IntentFilter{
+            actions: collect_strings(node, "action", attrs::NAME),
+            categories: collect_strings(node, "category", attrs::NAME),
+            schemes: collect_strings(node, "data", attrs::SCHEME)
+        }
+    }
+}
+
+impl Component {
+    fn from_node(node: &XmlNode) -> Option<Component> {
+        Some(Component{
+            kind: ComponentKind::from_tag(node.tag_name.as_str())?,
+            name: get_string(node, attrs::NAME).unwrap_or_default(),
+            exported: match node.attrs.iter().find(|attr| attr.resource_id == attrs::EXPORTED).map(|attr| attr.typed_value()) {
+                Some(TypedValue::Boolean(exported)) => Some(exported),
+                _ => None
+            },
+            permission: get_string(node, attrs::PERMISSION),
+            intent_filters: node.children.iter()
+                .filter(|child| child.tag_name == "intent-filter")
+                .map(IntentFilter::from_node)
+                .collect()
+        })
+    }
+}
+
+pub(crate) fn collect(application: &XmlNode) -> Vec<Component> {
+    application.children.iter().filter_map(Component::from_node).collect()
+}
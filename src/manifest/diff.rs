@@ -0,0 +1,108 @@
+use alloc::string::String;
+use alloc::format;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use crate::manifest::attrs::ANDROID_NAMESPACE;
+use crate::manifest::axml::XmlNode;
+
+// `path` identifies an element like "manifest/application/activity[com.foo.Main]/intent-filter[0]":
+// elements with android:name are keyed by it, the others by their position among unnamed siblings of the same tag.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ManifestChange {
+    ElementAdded { path: String },
+    ElementRemoved { path: String },
+    AttributeAdded { path: String, attribute: String, value: String },
+    AttributeRemoved { path: String, attribute: String, value: String },
+    AttributeChanged { path: String, attribute: String, old_value: String, new_value: String }
+}
+
+impl Display for ManifestChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ManifestChange::ElementAdded { path } => write!(f, "+ {}", path),
+            ManifestChange::ElementRemoved { path } => write!(f, "- {}", path),
+            ManifestChange::AttributeAdded { path, attribute, value } => write!(f, "+ {} @{}=\"{}\"", path, attribute, value),
+            ManifestChange::AttributeRemoved { path, attribute, value } => write!(f, "- {} @{}=\"{}\"", path, attribute, value),
+            ManifestChange::AttributeChanged { path, attribute, old_value, new_value } => {
+                write!(f, "~ {} @{}: \"{}\" -> \"{}\"", path, attribute, old_value, new_value)
+            }
+        }
+    }
+}
+
+fn child_keys(node: &XmlNode) -> Vec<String> {
+    let mut res: Vec<String> = Vec::new();
+    for (idx, child) in node.children.iter().enumerate() {
+        let name = child.attrs.iter()
+            .find(|attr| attr.name == "name" && attr.namespace_uri.as_deref() == Some(ANDROID_NAMESPACE))
+            .and_then(|attr| attr.string_data.as_deref());
+        res.push(match name {
+            Some(name) => format!("{}[{}]", child.tag_name, name),
+            None => {
+                let position = node.children[..idx].iter()
+                    .filter(|item| item.tag_name == child.tag_name)
+                    .count();
+                format!("{}[{}]", child.tag_name, position)
+            }
+        });
+    }
+    res
+}
+
+fn diff_attrs(path: &str, old: &XmlNode, new: &XmlNode, changes: &mut Vec<ManifestChange>) {
+    for attr in &old.attrs {
+        match new.attrs.iter().find(|item| item.is_same_attr(attr)) {
+            Some(new_attr) if !new_attr.has_same_value(attr) => changes.push(ManifestChange::AttributeChanged{
+                path: String::from(path),
+                attribute: attr.qualified_name(),
+                old_value: attr.display_value(),
+                new_value: new_attr.display_value()
+            }),
+            Some(_) => {},
+            None => changes.push(ManifestChange::AttributeRemoved{
+                path: String::from(path),
+                attribute: attr.qualified_name(),
+                value: attr.display_value()
+            })
+        }
+    }
+    for attr in &new.attrs {
+        if !old.attrs.iter().any(|item| item.is_same_attr(attr)) {
+            changes.push(ManifestChange::AttributeAdded{
+                path: String::from(path),
+                attribute: attr.qualified_name(),
+                value: attr.display_value()
+            });
+        }
+    }
+}
+
+fn diff_node(path: &str, old: &XmlNode, new: &XmlNode, changes: &mut Vec<ManifestChange>) {
+    diff_attrs(path, old, new, changes);
+    let old_keys = child_keys(old);
+    let new_keys = child_keys(new);
+    for (idx, key) in old_keys.iter().enumerate() {
+        let child_path = format!("{}/{}", path, key);
+        match new_keys.iter().position(|item| item == key) {
+            Some(new_idx) => diff_node(child_path.as_str(), &old.children[idx], &new.children[new_idx], changes),
+            None => changes.push(ManifestChange::ElementRemoved{ path: child_path })
+        }
+    }
+    for key in &new_keys {
+        if !old_keys.contains(key) {
+            changes.push(ManifestChange::ElementAdded{ path: format!("{}/{}", path, key) });
+        }
+    }
+}
+
+pub(crate) fn diff(old: &XmlNode, new: &XmlNode) -> Vec<ManifestChange> {
+    let mut changes: Vec<ManifestChange> = Vec::new();
+    if old.tag_name != new.tag_name {
+        changes.push(ManifestChange::ElementRemoved{ path: old.tag_name.clone() });
+        changes.push(ManifestChange::ElementAdded{ path: new.tag_name.clone() });
+        return changes;
+    }
+    diff_node(old.tag_name.as_str(), old, new, &mut changes);
+    changes
+}
@@ -1,90 +1,744 @@
-use std::error::Error;
-use std::io::Write;
-use crate::manifest::axml::{AndroidXml, StringChunkBuilder, XmlAttributeValue, XmlNode};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use crate::manifest::attrs;
+use crate::manifest::axml::{AndroidXml, FileFormatError, XmlAttributeValue, XmlNode};
+use crate::manifest::components::{self, Component, ComponentKind};
+use crate::manifest::diff::{self, ManifestChange};
+use crate::manifest::typed_value::TypedValue;
+use crate::manifest::placeholders;
+use crate::manifest::validator::{self, ValidationIssue};
+use crate::options::{ParseOptions, ParseWarning};
+
+const COMPONENT_TAGS: [&str; 5] = ["activity", "activity-alias", "service", "receiver", "provider"];
 
 pub struct AndroidManifest<'a> {
     xml: AndroidXml<'a>,
-    string_chunk_builder: StringChunkBuilder,
-    application_node_index: usize
+    application_node_index: Option<usize>,
+    fidelity_mode: bool
+}
+
+#[derive(Debug)]
+pub struct ManifestError {
+    reason: String
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Activity {
     pub class_name: String,
 }
 
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Provider {
     pub class_name: String,
-    pub authorities: String
+    pub authorities: String,
+    pub exported: Option<bool>,
+    pub grant_uri_permissions: Option<bool>,
+    pub process: Option<String>,
+    pub multiprocess: Option<bool>,
+    pub uri_permission_patterns: Vec<PathMatcher>, // <grant-uri-permission> children
+    pub path_permissions: Vec<PathPermission>,
+    pub meta_data: Vec<MetaData>
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum PathMatcher {
+    Path(String),
+    PathPrefix(String),
+    PathPattern(String)
+}
+
+// <path-permission>, a permission scoped to part of the provider's uri space
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PathPermission {
+    pub path: PathMatcher,
+    pub permission: Option<String>,
+    pub read_permission: Option<String>,
+    pub write_permission: Option<String>
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum MetaDataValue {
+    Value(String),
+    Resource(u32) // resource id, e.g. @xml/file_paths for a FileProvider
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MetaData {
+    pub name: String,
+    pub value: MetaDataValue
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ProtectionLevel {
+    Normal = 0,
+    Dangerous = 1,
+    Signature = 2,
+    SignatureOrSystem = 3
+}
+
+// flags that can be or'ed onto a base protection level, e.g. signature|privileged
+pub const PROTECTION_FLAG_PRIVILEGED: u32 = 0x10;
+pub const PROTECTION_FLAG_DEVELOPMENT: u32 = 0x20;
+pub const PROTECTION_FLAG_APPOP: u32 = 0x40;
+pub const PROTECTION_FLAG_PRE23: u32 = 0x80;
+pub const PROTECTION_FLAG_INSTALLER: u32 = 0x100;
+pub const PROTECTION_FLAG_VERIFIER: u32 = 0x200;
+pub const PROTECTION_FLAG_PREINSTALLED: u32 = 0x400;
+pub const PROTECTION_FLAG_SETUP: u32 = 0x800;
+pub const PROTECTION_FLAG_INSTANT: u32 = 0x1000;
+pub const PROTECTION_FLAG_RUNTIME: u32 = 0x2000;
+
+// a <permission> declared by the app
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Permission {
+    pub name: String,
+    pub protection_level: ProtectionLevel,
+    pub protection_flags: u32,
+    pub permission_group: Option<String>
+}
+
+// a <permission-tree>, reserves a name prefix for permissions added at runtime
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PermissionTree {
+    pub name: String
+}
+
+// <uses-permission> or <uses-permission-sdk-23>
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UsesPermission {
+    pub name: String,
+    pub max_sdk_version: Option<u32>
+}
+
+// <supports-screens>, unset fields are left out of the element
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SupportsScreens {
+    pub small_screens: Option<bool>,
+    pub normal_screens: Option<bool>,
+    pub large_screens: Option<bool>,
+    pub xlarge_screens: Option<bool>,
+    pub any_density: Option<bool>,
+    pub resizeable: Option<bool>,
+    pub requires_smallest_width_dp: Option<u32>,
+    pub compatible_width_limit_dp: Option<u32>,
+    pub largest_width_limit_dp: Option<u32>
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ScreenSize {
+    Small = 200,
+    Normal = 300,
+    Large = 400,
+    XLarge = 500
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ScreenDensity {
+    Ldpi = 120,
+    Mdpi = 160,
+    Hdpi = 240,
+    Xhdpi = 320,
+    Xxhdpi = 480,
+    Xxxhdpi = 640
+}
+
+// a <screen> child of <compatible-screens>
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CompatibleScreen {
+    pub screen_size: ScreenSize,
+    pub screen_density: ScreenDensity
+}
+
+impl ManifestError {
+    pub(crate) fn new(reason: String) -> ManifestError {
+        ManifestError{
+            reason
+        }
+    }
+}
+
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "manifest error: {}", self.reason)
+    }
+}
+
+impl Error for ManifestError {}
+
+fn is_process_name_part(part: &str) -> bool {
+    let mut chars = part.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// ":remote" names a process private to the app (the platform prefixes it with the package),
+// other names are global and must look like a package name, e.g. "com.example.shared"
+fn check_process_name(process: &str) -> Result<(), ManifestError> {
+    let valid = match process.strip_prefix(':') {
+        Some(private) => !private.is_empty() && private.split('.').all(is_process_name_part),
+        None => process.contains('.') && process.split('.').all(is_process_name_part)
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(ManifestError{
+            reason: format!("invalid process name \"{}\"", process)
+        })
+    }
+}
+
+impl PathMatcher {
+    fn to_attr(&self) -> XmlAttributeValue {
+        match self {
+            PathMatcher::Path(path) => XmlAttributeValue::new_string_attr(attrs::PATH, "path", path.as_str()),
+            PathMatcher::PathPrefix(path) => XmlAttributeValue::new_string_attr(attrs::PATH_PREFIX, "pathPrefix", path.as_str()),
+            PathMatcher::PathPattern(path) => XmlAttributeValue::new_string_attr(attrs::PATH_PATTERN, "pathPattern", path.as_str())
+        }
+    }
+}
+
+impl PathPermission {
+    fn to_node(&self) -> XmlNode {
+        let mut node = XmlNode::new("path-permission", vec![self.path.to_attr()]);
+        let permissions = [
+            (attrs::PERMISSION, "permission", &self.permission),
+            (attrs::READ_PERMISSION, "readPermission", &self.read_permission),
+            (attrs::WRITE_PERMISSION, "writePermission", &self.write_permission)
+        ];
+        for (resource_id, name, value) in permissions {
+            if let Some(value) = value {
+                node.set_attr(XmlAttributeValue::new_string_attr(resource_id, name, value.as_str()));
+            }
+        }
+        node
+    }
+}
+
+impl MetaData {
+    fn to_node(&self) -> XmlNode {
+        XmlNode::new("meta-data", vec![
+            XmlAttributeValue::new_name_attr(self.name.as_str()),
+            match &self.value {
+                MetaDataValue::Value(value) => XmlAttributeValue::new_string_attr(attrs::VALUE, "value", value.as_str()),
+                MetaDataValue::Resource(id) => XmlAttributeValue::new_reference_attr(attrs::RESOURCE, "resource", *id)
+            }
+        ])
+    }
 }
 
 impl<'a> AndroidManifest<'a> {
-    pub fn from(data: &'a Vec<u8>) -> Result<Self, Box<dyn Error>> {
+    pub fn from<T: AsRef<[u8]> + ?Sized>(data: &'a T) -> Result<Self, FileFormatError> {
+        Ok(AndroidManifest::with_xml(AndroidXml::from_data(data)?))
+    }
+
+    pub fn from_with_options<T: AsRef<[u8]> + ?Sized>(data: &'a T, options: ParseOptions) -> Result<Self, FileFormatError> {
+        Ok(AndroidManifest::with_xml(AndroidXml::from_data_with_options(data, options)?))
+    }
+
+    // takes the buffer, so the manifest can be kept after what it was read from is gone
+    pub fn from_vec(data: Vec<u8>) -> Result<AndroidManifest<'static>, FileFormatError> {
+        Ok(AndroidManifest::with_xml(AndroidXml::from_vec(data)?))
+    }
+
+    pub fn from_vec_with_options(data: Vec<u8>, options: ParseOptions) -> Result<AndroidManifest<'static>, FileFormatError> {
+        Ok(AndroidManifest::with_xml(AndroidXml::from_vec_with_options(data, options)?))
+    }
+
+    // spec violations the parse went past, see ParseOptions
+    pub fn warnings(&self) -> &[ParseWarning] {
+        self.xml.warnings()
+    }
+
+    fn with_xml(xml: AndroidXml<'a>) -> AndroidManifest<'a> {
         let mut res = AndroidManifest{
-            xml: AndroidXml::from_data(data)?,
-            string_chunk_builder: StringChunkBuilder::new(),
-            application_node_index: 0
+            xml,
+            application_node_index: None,
+            fidelity_mode: false
         };
-        for (index, node) in res.xml.content.root_node.children.iter().enumerate() {
-            if node.tag_name == "application" {
-                res.application_node_index = index;
-                break;
+        res.update_application_node_index();
+        res
+    }
+
+    // edits are kept, a borrowed buffer is copied
+    pub fn into_owned(self) -> AndroidManifest<'static> {
+        AndroidManifest{
+            xml: self.xml.into_owned(),
+            application_node_index: self.application_node_index,
+            fidelity_mode: self.fidelity_mode
+        }
+    }
+
+    // must be called after any removal among the root children
+    fn update_application_node_index(&mut self) {
+        self.application_node_index = self.xml.content.root_node.children.iter()
+            .position(|node| node.tag_name == "application");
+    }
+
+    pub fn has_application(&self) -> bool {
+        self.application_node_index.is_some()
+    }
+
+    // the <application> element, created on demand when the manifest has none
+    fn application_mut(&mut self) -> Result<&mut XmlNode, ManifestError> {
+        if self.xml.content.root_node.tag_name != "manifest" {
+            return Err(ManifestError{
+                reason: format!("root element is <{}>, not <manifest>", self.xml.content.root_node.tag_name)
+            });
+        }
+        let index = match self.application_node_index {
+            Some(index) => index,
+            None => {
+                self.xml.content.root_node.push_child(XmlNode::new("application", vec![]));
+                let index = self.xml.content.root_node.children.len() - 1;
+                self.application_node_index = Some(index);
+                index
             }
+        };
+        Ok(&mut self.xml.content.root_node.children[index])
+    }
+
+    pub fn package(&self) -> Option<&str> {
+        self.xml.content.root_node.attrs.iter()
+            .find(|attr| attr.name == "package" && attr.namespace_uri.is_none())
+            .and_then(|attr| attr.string_data.as_deref())
+    }
+
+    // android:minSdkVersion of <uses-sdk>, 1 when it isn't set. None for a codename like "S", which
+    // only preview builds accept
+    pub fn min_sdk_version(&self) -> Option<u32> {
+        let uses_sdk = match self.xml.content.root_node.children.iter().find(|node| node.tag_name == "uses-sdk") {
+            Some(node) => node,
+            None => return Some(1)
+        };
+        let attr = match uses_sdk.get_attr_by_id(attrs::MIN_SDK_VERSION) {
+            Some(attr) => attr,
+            None => return Some(1)
+        };
+        match attr.typed_value() {
+            TypedValue::IntDec(value) => u32::try_from(value).ok(),
+            TypedValue::IntHex(value) => Some(value),
+            _ => attr.string_data.as_deref()?.parse().ok()
         }
-        res.string_chunk_builder.init(&mut res.xml.string_chunk);
-        Ok(res)
     }
 
-    pub fn write<W: Write>(&self, writer: W) -> Result<(), std::io::Error> {
-        // TODO
+    // a component under <application> by class name, ".Foo" declarations match "<package>.Foo"
+    fn component_mut(&mut self, class_name: &str) -> Result<&mut XmlNode, ManifestError> {
+        let package = self.package().unwrap_or_default().to_string();
+        let full_name = |name: &str| if name.starts_with('.') {
+            format!("{}{}", package, name)
+        } else {
+            String::from(name)
+        };
+        let expected = full_name(class_name);
+        let application = match self.application_node_index {
+            Some(index) => &mut self.xml.content.root_node.children[index],
+            None => return Err(ManifestError{ reason: String::from("manifest has no <application>") })
+        };
+        application.children.iter_mut()
+            .filter(|node| COMPONENT_TAGS.contains(&node.tag_name.as_str()))
+            .find(|node| node.get_attr_by_id(attrs::NAME)
+                .and_then(|attr| attr.string_data.as_deref())
+                .is_some_and(|name| full_name(name) == expected))
+            .ok_or_else(|| ManifestError{ reason: format!("no component named {}", class_name) })
+    }
+
+    fn root_attr(&self, namespace_uri: Option<&str>, name: &str) -> Option<&XmlAttributeValue> {
+        self.xml.content.root_node.get_attr(namespace_uri, name)
+    }
+
+    fn set_root_attr(&mut self, attr: Option<XmlAttributeValue>, namespace_uri: Option<&str>, name: &str) {
+        let root = &mut self.xml.content.root_node;
+        match attr {
+            Some(attr) => root.set_attr(attr),
+            None => {
+                root.remove_attr(namespace_uri, name);
+            }
+        }
+    }
+
+    // Split APKs: `split` names the split this manifest belongs to, None for the base APK.
+    pub fn split(&self) -> Option<&str> {
+        self.root_attr(None, "split").and_then(|attr| attr.string_data.as_deref())
+    }
+
+    pub fn set_split(&mut self, split: Option<&str>) {
+        let attr = split.map(|split| XmlAttributeValue::new_string(None, "split", 0, split));
+        self.set_root_attr(attr, None, "split");
+    }
+
+    // dynamic feature module (as opposed to a configuration split)
+    pub fn is_feature_split(&self) -> bool {
+        matches!(self.root_attr(Some(attrs::ANDROID_NAMESPACE), "isFeatureSplit").map(|attr| attr.typed_value()),
+            Some(TypedValue::Boolean(true)))
+    }
+
+    pub fn set_feature_split(&mut self, feature_split: bool) {
+        let attr = XmlAttributeValue::new_bool_attr(attrs::IS_FEATURE_SPLIT, "isFeatureSplit", feature_split);
+        self.set_root_attr(Some(attr), Some(attrs::ANDROID_NAMESPACE), "isFeatureSplit");
+    }
+
+    // the feature split a configuration split (density, abi, language) belongs to
+    pub fn config_for_split(&self) -> Option<&str> {
+        self.root_attr(None, "configForSplit").and_then(|attr| attr.string_data.as_deref())
+    }
+
+    pub fn set_config_for_split(&mut self, split: Option<&str>) {
+        let attr = split.map(|split| XmlAttributeValue::new_string(None, "configForSplit", 0, split));
+        self.set_root_attr(attr, None, "configForSplit");
+    }
+
+    // android:requiredSplitTypes (API 33), split types that must be installed along with this APK
+    pub fn required_split_types(&self) -> Vec<String> {
+        match self.root_attr(Some(attrs::ANDROID_NAMESPACE), "requiredSplitTypes").and_then(|attr| attr.string_data.as_deref()) {
+            Some(types) => types.split(',').map(|item| item.trim()).filter(|item| !item.is_empty()).map(String::from).collect(),
+            None => vec![]
+        }
+    }
+
+    // an empty list removes the attribute
+    pub fn set_required_split_types(&mut self, split_types: &[&str]) {
+        let attr = if split_types.is_empty() {
+            None
+        } else {
+            Some(XmlAttributeValue::new_string_attr(attrs::REQUIRED_SPLIT_TYPES, "requiredSplitTypes", split_types.join(",").as_str()))
+        };
+        self.set_root_attr(attr, Some(attrs::ANDROID_NAMESPACE), "requiredSplitTypes");
+    }
+
+    // Run a component in another process, ":name" for one private to the app.
+    pub fn set_component_process(&mut self, class_name: &str, process: &str) -> Result<(), ManifestError> {
+        check_process_name(process)?;
+        self.component_mut(class_name)?.set_attr(XmlAttributeValue::new_string_attr(attrs::PROCESS, "process", process));
         Ok(())
     }
 
-    pub fn get_data(&mut self) -> Vec<u8> {
-        self.xml.regenerate(&mut self.string_chunk_builder)
-    }
-
-    pub fn add_content_provider(&mut self, cp: Provider) {
-        let application = self.xml.content.root_node.children[self.application_node_index].as_mut();
-        let name_value_index = self.string_chunk_builder.put(cp.class_name.as_str());
-        let authorities_value_index = self.string_chunk_builder.put(cp.authorities.as_str());
-        application.children.push(Box::new(XmlNode{
-            tag_name: String::from("provider"),
-            attrs: vec![XmlAttributeValue{
-                namespace_uri: Some("http://schemas.android.com/apk/res/android".to_string()),
-                name_index: 3,
-                name: "name".to_string(),
-                value_type: 0x3000008,
-                string_data: Some(cp.class_name),
-                data: name_value_index
-            }, XmlAttributeValue{
-                namespace_uri: Some("http://schemas.android.com/apk/res/android".to_string()),
-                name_index: 5,
-                name: "authorities".to_string(),
-                value_type: 0x3000008,
-                string_data: Some(cp.authorities),
-                data: authorities_value_index
-            }],
-            children: vec![]
-        }));
-    }
-
-    pub fn add_activity(&mut self, activity: Activity) {
-        let application = self.xml.content.root_node.children[self.application_node_index].as_mut();
-        let value_index = self.string_chunk_builder.put(activity.class_name.as_str());
-        application.children.push(Box::new(XmlNode{
-            tag_name: String::from("activity"),
-            attrs: vec![XmlAttributeValue{
-                namespace_uri: Some("http://schemas.android.com/apk/res/android".to_string()),
-                name_index: 3,
-                name: "name".to_string(),
-                value_type: 0x3000008,
-                string_data: Some(activity.class_name),
-                data: value_index
-            }],
-            children: vec![]
-        }));
+    // android:multiprocess, lets a provider be instantiated in every client process
+    pub fn set_provider_multiprocess(&mut self, class_name: &str, multiprocess: bool) -> Result<(), ManifestError> {
+        let node = self.component_mut(class_name)?;
+        if node.tag_name != "provider" {
+            return Err(ManifestError{ reason: format!("{} is declared as <{}>, not <provider>", class_name, node.tag_name) });
+        }
+        node.set_attr(XmlAttributeValue::new_bool_attr(attrs::MULTIPROCESS, "multiprocess", multiprocess));
+        Ok(())
     }
 
-}
+    pub fn root_node(&self) -> &XmlNode {
+        &self.xml.content.root_node
+    }
+
+    pub fn xml(&self) -> &AndroidXml<'a> {
+        &self.xml
+    }
 
+    // Replace the whole element tree, e.g. with an edited clone of root_node().
+    pub fn set_root_node(&mut self, root_node: XmlNode) {
+        self.xml.content.root_node = root_node;
+        self.update_application_node_index();
+    }
+
+    // Activities, aliases, services, receivers and providers declared under <application>, in document order.
+    pub fn components(&self) -> Vec<Component> {
+        match self.application_node_index {
+            Some(index) => components::collect(&self.xml.content.root_node.children[index]),
+            None => vec![]
+        }
+    }
+
+    // Semantic checks to run before the manifest is written back, an empty list means no findings.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        validator::validate(&self.xml.content.root_node)
+    }
+
+    // Structural changes needed to turn this manifest into `other`.
+    pub fn diff(&self, other: &AndroidManifest) -> Vec<ManifestChange> {
+        diff::diff(&self.xml.content.root_node, &other.xml.content.root_node)
+    }
+
+    // Substitute ${applicationId} (the package unless overridden in `placeholders`) and the given placeholders
+    // in all string attribute values. An undefined placeholder is an error and leaves the manifest untouched.
+    pub fn substitute_placeholders(&mut self, placeholders: &BTreeMap<String, String>) -> Result<(), ManifestError> {
+        let mut root = self.xml.content.root_node.clone();
+        let mut values = placeholders.clone();
+        if !values.contains_key("applicationId") {
+            if let Some(package) = self.package() {
+                values.insert(String::from("applicationId"), String::from(package));
+            }
+        }
+        placeholders::substitute(&mut root, &values).map_err(|reason| ManifestError{ reason })?;
+        self.set_root_node(root);
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write<W: std::io::Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        writer.write_all(self.get_data().as_slice())
+    }
+
+    // When enabled, get_data keeps untouched elements and the string pool byte-identical to the input
+    // instead of rebuilding the whole document.
+    pub fn set_fidelity_mode(&mut self, enabled: bool) {
+        self.fidelity_mode = enabled;
+    }
 
+    pub fn get_data(&self) -> Vec<u8> {
+        if self.fidelity_mode {
+            self.xml.regenerate_preserving()
+        } else {
+            self.xml.regenerate()
+        }
+    }
+
+    pub fn add_content_provider(&mut self, cp: Provider) -> Result<(), ManifestError> {
+        let mut node = XmlNode::new("provider", vec![
+            XmlAttributeValue::new_name_attr(cp.class_name.as_str()),
+            XmlAttributeValue::new_authorities_attr(cp.authorities.as_str())
+        ]);
+        if let Some(exported) = cp.exported {
+            node.set_attr(XmlAttributeValue::new_bool_attr(attrs::EXPORTED, "exported", exported));
+        }
+        if let Some(grant) = cp.grant_uri_permissions {
+            node.set_attr(XmlAttributeValue::new_bool_attr(attrs::GRANT_URI_PERMISSIONS, "grantUriPermissions", grant));
+        }
+        if let Some(process) = &cp.process {
+            check_process_name(process)?;
+            node.set_attr(XmlAttributeValue::new_string_attr(attrs::PROCESS, "process", process.as_str()));
+        }
+        if let Some(multiprocess) = cp.multiprocess {
+            node.set_attr(XmlAttributeValue::new_bool_attr(attrs::MULTIPROCESS, "multiprocess", multiprocess));
+        }
+        for pattern in &cp.uri_permission_patterns {
+            node.push_child(XmlNode::new("grant-uri-permission", vec![pattern.to_attr()]));
+        }
+        for path_permission in &cp.path_permissions {
+            node.push_child(path_permission.to_node());
+        }
+        for meta_data in &cp.meta_data {
+            node.push_child(meta_data.to_node());
+        }
+        self.application_mut()?.push_child(node);
+        Ok(())
+    }
+
+    pub fn add_activity(&mut self, activity: Activity) -> Result<(), ManifestError> {
+        let application = self.application_mut()?;
+        application.children.push(XmlNode::new("activity", vec![
+            XmlAttributeValue::new_name_attr(activity.class_name.as_str())
+        ]));
+        Ok(())
+    }
+
+    // an <activity>, <service> or <receiver> with just android:name and android:exported. Providers
+    // need authorities and aliases a target, they have their own methods
+    pub fn add_component(&mut self, kind: ComponentKind, class_name: &str, exported: Option<bool>) -> Result<(), ManifestError> {
+        let tag_name = match kind {
+            ComponentKind::Activity => "activity",
+            ComponentKind::Service => "service",
+            ComponentKind::Receiver => "receiver",
+            ComponentKind::ActivityAlias | ComponentKind::Provider => return Err(ManifestError{
+                reason: format!("{:?} can't be added without its required attributes", kind)
+            })
+        };
+        let mut node = XmlNode::new(tag_name, vec![XmlAttributeValue::new_name_attr(class_name)]);
+        if let Some(exported) = exported {
+            node.set_attr(XmlAttributeValue::new_bool_attr(attrs::EXPORTED, "exported", exported));
+        }
+        self.application_mut()?.push_child(node);
+        Ok(())
+    }
+
+    // android:testOnly, such packages can only be installed with `adb install -t`
+    pub fn set_test_only(&mut self, test_only: bool) -> Result<(), ManifestError> {
+        self.application_mut()?.set_attr(XmlAttributeValue::new_bool_attr(attrs::TEST_ONLY, "testOnly", test_only));
+        Ok(())
+    }
+
+    // <profileable android:shell="..."/> under <application>, lets profilers attach to release builds (API 29+)
+    pub fn set_profileable(&mut self, shell: bool) -> Result<(), ManifestError> {
+        let application = self.application_mut()?;
+        if application.find_child_mut("profileable").is_none() {
+            application.push_child(XmlNode::new("profileable", vec![]));
+        }
+        let node = application.find_child_mut("profileable").unwrap();
+        node.set_attr(XmlAttributeValue::new_bool_attr(attrs::SHELL, "shell", shell));
+        Ok(())
+    }
+
+    fn set_application_reference(&mut self, resource_id: u32, name: &str, reference: u32) -> Result<(), ManifestError> {
+        self.application_mut()?.set_attr(XmlAttributeValue::new_reference_attr(resource_id, name, reference));
+        Ok(())
+    }
+
+    // The setters below take resource ids from the app's resources.arsc, e.g. 0x7f080001 for @mipmap/ic_launcher.
+    pub fn set_label(&mut self, label: u32) -> Result<(), ManifestError> {
+        self.set_application_reference(attrs::LABEL, "label", label)
+    }
+
+    pub fn set_icon(&mut self, icon: u32) -> Result<(), ManifestError> {
+        self.set_application_reference(attrs::ICON, "icon", icon)
+    }
+
+    pub fn set_round_icon(&mut self, icon: u32) -> Result<(), ManifestError> {
+        self.set_application_reference(attrs::ROUND_ICON, "roundIcon", icon)
+    }
+
+    pub fn set_theme(&mut self, theme: u32) -> Result<(), ManifestError> {
+        self.set_application_reference(attrs::THEME, "theme", theme)
+    }
+
+    // an @xml/ resource holding the <network-security-config>
+    pub fn set_network_security_config(&mut self, config: u32) -> Result<(), ManifestError> {
+        self.set_application_reference(attrs::NETWORK_SECURITY_CONFIG, "networkSecurityConfig", config)
+    }
+
+    // root level elements conventionally precede <application>
+    fn insert_before_application(&mut self, node: XmlNode) {
+        let root = &mut self.xml.content.root_node;
+        match self.application_node_index {
+            Some(index) => root.children.insert(index, node),
+            None => root.push_child(node)
+        }
+        self.update_application_node_index();
+    }
+
+    // remove root level elements with this tag and android:name
+    fn remove_named_root_child(&mut self, tag_name: &str, name: &str) {
+        self.xml.content.root_node.children.retain(|child| {
+            child.tag_name != tag_name || child.attrs.iter().all(|attr| {
+                attr.resource_id != attrs::NAME || attr.string_data.as_deref() != Some(name)
+            })
+        });
+        self.update_application_node_index();
+    }
+
+    // Declare a custom permission, an existing declaration with the same name is replaced.
+    pub fn add_permission(&mut self, permission: Permission) {
+        self.remove_named_root_child("permission", permission.name.as_str());
+        let mut node_attrs = vec![
+            XmlAttributeValue::new_name_attr(permission.name.as_str()),
+            XmlAttributeValue::new_hex_attr(attrs::PROTECTION_LEVEL, "protectionLevel",
+                                            permission.protection_level as u32 | permission.protection_flags)
+        ];
+        if let Some(group) = &permission.permission_group {
+            node_attrs.push(XmlAttributeValue::new_string_attr(attrs::PERMISSION_GROUP, "permissionGroup", group.as_str()));
+        }
+        self.insert_before_application(XmlNode::new("permission", node_attrs));
+    }
+
+    pub fn add_permission_tree(&mut self, tree: PermissionTree) {
+        self.remove_named_root_child("permission-tree", tree.name.as_str());
+        self.insert_before_application(XmlNode::new("permission-tree", vec![
+            XmlAttributeValue::new_name_attr(tree.name.as_str())
+        ]));
+    }
+
+    fn set_uses_permission(&mut self, tag_name: &str, permission: UsesPermission) {
+        let mut node_attrs = vec![XmlAttributeValue::new_name_attr(permission.name.as_str())];
+        if let Some(max_sdk_version) = permission.max_sdk_version {
+            node_attrs.push(XmlAttributeValue::new_int_attr(attrs::MAX_SDK_VERSION, "maxSdkVersion", max_sdk_version));
+        }
+        self.remove_named_root_child(tag_name, permission.name.as_str());
+        self.insert_before_application(XmlNode::new(tag_name, node_attrs));
+    }
+
+    fn get_uses_permissions(&self, tag_names: &[&str]) -> Vec<UsesPermission> {
+        self.xml.content.root_node.children.iter()
+            .filter(|node| tag_names.contains(&node.tag_name.as_str()))
+            .filter_map(|node| {
+                let name = node.attrs.iter().find(|attr| attr.resource_id == attrs::NAME)?.string_data.clone()?;
+                Some(UsesPermission{
+                    name,
+                    max_sdk_version: node.attrs.iter()
+                        .find(|attr| attr.resource_id == attrs::MAX_SDK_VERSION)
+                        .map(|attr| attr.data)
+                })
+            })
+            .collect()
+    }
+
+    // Request a permission, replacing an existing request with the same name.
+    pub fn add_uses_permission(&mut self, permission: UsesPermission) {
+        self.set_uses_permission("uses-permission", permission);
+    }
+
+    // Request a permission only on API 23+ (runtime permission model), older platforms never see it.
+    pub fn add_uses_permission_sdk_23(&mut self, permission: UsesPermission) {
+        self.set_uses_permission("uses-permission-sdk-23", permission);
+    }
+
+    pub fn uses_permissions_sdk_23(&self) -> Vec<UsesPermission> {
+        self.get_uses_permissions(&["uses-permission-sdk-23"])
+    }
+
+    // Every requested permission, <uses-permission> and <uses-permission-sdk-23> alike, in document order.
+    pub fn permissions(&self) -> Vec<UsesPermission> {
+        self.get_uses_permissions(&["uses-permission", "uses-permission-sdk-23"])
+    }
+
+    // Create or update <supports-screens>, attributes set in `screens` overwrite the existing ones.
+    pub fn set_supports_screens(&mut self, screens: SupportsScreens) {
+        let root = &mut self.xml.content.root_node;
+        if root.find_child_mut("supports-screens").is_none() {
+            root.push_child(XmlNode::new("supports-screens", vec![]));
+        }
+        let node = root.find_child_mut("supports-screens").unwrap();
+        let bool_attrs = [
+            (attrs::SMALL_SCREENS, "smallScreens", screens.small_screens),
+            (attrs::NORMAL_SCREENS, "normalScreens", screens.normal_screens),
+            (attrs::LARGE_SCREENS, "largeScreens", screens.large_screens),
+            (attrs::XLARGE_SCREENS, "xlargeScreens", screens.xlarge_screens),
+            (attrs::ANY_DENSITY, "anyDensity", screens.any_density),
+            (attrs::RESIZEABLE, "resizeable", screens.resizeable)
+        ];
+        for (resource_id, name, value) in bool_attrs {
+            if let Some(value) = value {
+                node.set_attr(XmlAttributeValue::new_bool_attr(resource_id, name, value));
+            }
+        }
+        let int_attrs = [
+            (attrs::REQUIRES_SMALLEST_WIDTH_DP, "requiresSmallestWidthDp", screens.requires_smallest_width_dp),
+            (attrs::COMPATIBLE_WIDTH_LIMIT_DP, "compatibleWidthLimitDp", screens.compatible_width_limit_dp),
+            (attrs::LARGEST_WIDTH_LIMIT_DP, "largestWidthLimitDp", screens.largest_width_limit_dp)
+        ];
+        for (resource_id, name, value) in int_attrs {
+            if let Some(value) = value {
+                node.set_attr(XmlAttributeValue::new_int_attr(resource_id, name, value));
+            }
+        }
+    }
+
+    pub fn remove_supports_screens(&mut self) {
+        self.xml.content.root_node.remove_children("supports-screens");
+        self.update_application_node_index();
+    }
+
+    // Replace <compatible-screens> with the given screen list, an empty list removes the element.
+    pub fn set_compatible_screens(&mut self, screens: &[CompatibleScreen]) {
+        let root = &mut self.xml.content.root_node;
+        root.remove_children("compatible-screens");
+        if screens.is_empty() {
+            self.update_application_node_index();
+            return;
+        }
+        let mut node = XmlNode::new("compatible-screens", vec![]);
+        for screen in screens {
+            node.push_child(XmlNode::new("screen", vec![
+                XmlAttributeValue::new_int_attr(attrs::SCREEN_SIZE, "screenSize", screen.screen_size as u32),
+                XmlAttributeValue::new_int_attr(attrs::SCREEN_DENSITY, "screenDensity", screen.screen_density as u32)
+            ]));
+        }
+        root.push_child(node);
+        self.update_application_node_index();
+    }
+
+}
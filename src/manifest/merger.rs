@@ -0,0 +1,220 @@
+use alloc::string::{String, ToString};
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{Display, Formatter};
+use crate::manifest::attrs::{ANDROID_NAMESPACE, TOOLS_NAMESPACE};
+use crate::manifest::axml::{XmlAttributeValue, XmlNode};
+use crate::manifest::manifest_editor::AndroidManifest;
+
+// elements that may appear only once under their parent, matched by tag alone
+const SINGLETON_TAGS: [&str; 5] = ["application", "uses-sdk", "supports-screens", "compatible-screens", "instrumentation"];
+// elements whose android:name is a class, the only ones where a leading '.' means the package
+const CLASS_NAME_TAGS: [&str; 6] = ["application", "activity", "activity-alias", "service", "receiver", "provider"];
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MergeConflict {
+    pub element: String,
+    pub attribute: String,
+    pub main_value: String,
+    pub overlay_value: String
+}
+
+#[derive(Debug)]
+pub struct MergeError {
+    pub conflicts: Vec<MergeConflict>
+}
+
+#[derive(PartialEq)]
+enum NodeMarker {
+    Merge,
+    MergeOnlyAttributes,
+    Replace,
+    Remove,
+    RemoveAll,
+    Strict
+}
+
+impl Display for MergeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "manifest merge failed with {} conflict(s)", self.conflicts.len())?;
+        for conflict in &self.conflicts {
+            write!(f, "\n  {} {}: \"{}\" vs \"{}\"", conflict.element, conflict.attribute, conflict.main_value, conflict.overlay_value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for MergeError {}
+
+fn get_attr<'a>(node: &'a XmlNode, namespace: &str, name: &str) -> Option<&'a XmlAttributeValue> {
+    node.attrs.iter().find(|attr| attr.name == name && attr.namespace_uri.as_deref() == Some(namespace))
+}
+
+fn get_marker(node: &XmlNode) -> NodeMarker {
+    match get_attr(node, TOOLS_NAMESPACE, "node").and_then(|attr| attr.string_data.as_deref()) {
+        Some("replace") => NodeMarker::Replace,
+        Some("remove") => NodeMarker::Remove,
+        Some("removeAll") => NodeMarker::RemoveAll,
+        Some("strict") => NodeMarker::Strict,
+        Some("merge-only-attributes") => NodeMarker::MergeOnlyAttributes,
+        _ => NodeMarker::Merge
+    }
+}
+
+// attribute names listed in tools:replace, main keeps its value for them
+fn get_replace_list(node: &XmlNode) -> Vec<String> {
+    match get_attr(node, TOOLS_NAMESPACE, "replace").and_then(|attr| attr.string_data.as_deref()) {
+        Some(list) => list.split(',')
+            .map(|item| item.trim())
+            .map(|item| item.strip_prefix("android:").unwrap_or(item).to_string())
+            .collect(),
+        None => vec![]
+    }
+}
+
+fn describe_element(node: &XmlNode) -> String {
+    match get_attr(node, ANDROID_NAMESPACE, "name").and_then(|attr| attr.string_data.as_deref()) {
+        Some(name) => format!("<{} android:name=\"{}\">", node.tag_name, name),
+        None => format!("<{}>", node.tag_name)
+    }
+}
+
+fn same_element(a: &XmlNode, b: &XmlNode) -> bool {
+    a.tag_name == b.tag_name && a.attrs.len() == b.attrs.len() && a.children.len() == b.children.len()
+        && a.attrs.iter().all(|attr| b.attrs.iter().any(|other| attr.is_same_attr(other) && attr.has_same_value(other)))
+        && a.children.iter().zip(b.children.iter()).all(|(x, y)| same_element(x, y))
+}
+
+fn get_package(root: &XmlNode) -> String {
+    root.attrs.iter()
+        .find(|attr| attr.name == "package" && attr.namespace_uri.is_none())
+        .and_then(|attr| attr.string_data.clone())
+        .unwrap_or_default()
+}
+
+// ".Foo" style component names are relative to the package of the manifest declaring them
+fn full_class_name(name: &str, package: &str) -> String {
+    if name.starts_with('.') {
+        format!("{}{}", package, name)
+    } else {
+        String::from(name)
+    }
+}
+
+fn expand_class_names(node: &mut XmlNode, package: &str) {
+    if CLASS_NAME_TAGS.contains(&node.tag_name.as_str()) {
+        for attr in &mut node.attrs {
+            if attr.name == "name" && attr.namespace_uri.as_deref() == Some(ANDROID_NAMESPACE) {
+                if let Some(name) = &attr.string_data {
+                    attr.string_data = Some(full_class_name(name, package));
+                }
+            }
+        }
+    }
+    for child in &mut node.children {
+        expand_class_names(child, package);
+    }
+}
+
+// Elements carrying android:name are keyed by it, singleton elements by their tag,
+// everything else (intent-filter, action, data...) only matches an equivalent element.
+fn is_matching(main: &XmlNode, overlay: &XmlNode, main_package: &str) -> bool {
+    if main.tag_name != overlay.tag_name {
+        return false;
+    }
+    let main_name = get_attr(main, ANDROID_NAMESPACE, "name")
+        .and_then(|attr| attr.string_data.as_deref())
+        .map(|name| match CLASS_NAME_TAGS.contains(&main.tag_name.as_str()) {
+            true => full_class_name(name, main_package),
+            false => String::from(name)
+        });
+    let overlay_name = get_attr(overlay, ANDROID_NAMESPACE, "name")
+        .and_then(|attr| attr.string_data.clone());
+    if main_name.is_some() || overlay_name.is_some() {
+        return main_name == overlay_name;
+    }
+    if SINGLETON_TAGS.contains(&main.tag_name.as_str()) {
+        return true;
+    }
+    same_element(main, overlay)
+}
+
+fn merge_attrs(main: &mut XmlNode, overlay: &XmlNode, strict: bool, conflicts: &mut Vec<MergeConflict>) {
+    let replace_list = get_replace_list(main);
+    for attr in &overlay.attrs {
+        if attr.namespace_uri.as_deref() == Some(TOOLS_NAMESPACE) {
+            continue;
+        }
+        match main.attrs.iter().find(|item| item.is_same_attr(attr)) {
+            Some(existing) => {
+                if !existing.has_same_value(attr) && (strict || !replace_list.contains(&attr.name)) {
+                    conflicts.push(MergeConflict{
+                        element: describe_element(main),
+                        attribute: attr.name.clone(),
+                        main_value: existing.display_value(),
+                        overlay_value: attr.display_value()
+                    });
+                }
+            },
+            None => main.set_attr(attr.clone())
+        }
+    }
+}
+
+fn merge_children(main: &mut XmlNode, overlay: &XmlNode, main_package: &str, conflicts: &mut Vec<MergeConflict>) {
+    for overlay_child in &overlay.children {
+        if main.children.iter().any(|child| child.tag_name == overlay_child.tag_name && get_marker(child) == NodeMarker::RemoveAll) {
+            continue;
+        }
+        match main.children.iter_mut().find(|child| is_matching(child, overlay_child, main_package)) {
+            Some(main_child) => match get_marker(main_child) {
+                NodeMarker::Remove | NodeMarker::RemoveAll | NodeMarker::Replace => {},
+                NodeMarker::Strict => {
+                    merge_attrs(main_child, overlay_child, true, conflicts);
+                    merge_children(main_child, overlay_child, main_package, conflicts);
+                },
+                NodeMarker::MergeOnlyAttributes => merge_attrs(main_child, overlay_child, false, conflicts),
+                NodeMarker::Merge => {
+                    merge_attrs(main_child, overlay_child, false, conflicts);
+                    merge_children(main_child, overlay_child, main_package, conflicts);
+                }
+            },
+            None => main.children.push(overlay_child.clone())
+        }
+    }
+}
+
+// drop elements marked for removal and every tools:* attribute, like the final merger output
+fn clean_tools_markers(node: &mut XmlNode) {
+    node.children.retain(|child| {
+        let marker = get_marker(child);
+        marker != NodeMarker::Remove && marker != NodeMarker::RemoveAll
+    });
+    node.attrs.retain(|attr| attr.namespace_uri.as_deref() != Some(TOOLS_NAMESPACE));
+    for child in &mut node.children {
+        clean_tools_markers(child);
+    }
+}
+
+// Fold a lower priority manifest (library / SDK snippet) into `main`, following the AGP merger rules:
+// elements are matched by android:name (or by tag for singletons), tools:node and tools:replace markers
+// on the main manifest decide the outcome, remaining attribute mismatches are reported as conflicts.
+// On error `main` is left untouched.
+pub fn merge(main: &mut AndroidManifest, overlay: &AndroidManifest) -> Result<(), MergeError> {
+    let mut conflicts: Vec<MergeConflict> = Vec::new();
+    let mut merged = main.root_node().clone();
+    let main_package = get_package(&merged);
+    let mut overlay_root = overlay.root_node().clone();
+    let overlay_package = get_package(&overlay_root);
+    expand_class_names(&mut overlay_root, overlay_package.as_str());
+    merge_children(&mut merged, &overlay_root, main_package.as_str(), &mut conflicts);
+    if !conflicts.is_empty() {
+        return Err(MergeError{ conflicts });
+    }
+    clean_tools_markers(&mut merged);
+    main.set_root_node(merged);
+    Ok(())
+}
@@ -1,2 +1,12 @@
 pub(crate) mod axml;
+pub mod attrs;
+pub mod chunks;
+pub mod components;
+pub mod diff;
 pub mod manifest_editor;
+pub mod merger;
+mod placeholders;
+pub mod typed_value;
+pub mod validator;
+
+pub use axml::{AndroidXml, FileFormatError, XmlAttributeValue, XmlElementMut, XmlNameSpace, XmlNode, XmlNodeIter, XmlNodeIterMut};
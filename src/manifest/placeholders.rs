@@ -0,0 +1,49 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::format;
+use crate::manifest::axml::{XmlNode, VALUE_TYPE_STRING};
+
+// Expand every ${name} in `value`, the error carries the first placeholder without a definition.
+fn expand(value: &str, placeholders: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut res = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start + 2..].find('}') {
+            Some(end) => start + 2 + end,
+            None => break
+        };
+        let key = &rest[start + 2..end];
+        match placeholders.get(key) {
+            Some(replacement) => {
+                res.push_str(&rest[..start]);
+                res.push_str(replacement);
+            },
+            None => return Err(String::from(key))
+        }
+        rest = &rest[end + 1..];
+    }
+    res.push_str(rest);
+    Ok(res)
+}
+
+// Replace placeholders in string typed attribute values of the whole tree, like AGP's manifestPlaceholders.
+// The error names the undefined placeholder and the element using it.
+pub(crate) fn substitute(root: &mut XmlNode, placeholders: &BTreeMap<String, String>) -> Result<(), String> {
+    for (path, element) in root.iter_mut() {
+        for attr in element.attrs.iter_mut() {
+            if attr.value_type != VALUE_TYPE_STRING {
+                continue;
+            }
+            if let Some(value) = &attr.string_data {
+                if !value.contains("${") {
+                    continue;
+                }
+                match expand(value, placeholders) {
+                    Ok(expanded) => attr.string_data = Some(expanded),
+                    Err(key) => return Err(format!("placeholder ${{{}}} used by {} {} is not defined", key, path, attr.qualified_name()))
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use core::fmt::{Display, Formatter};
+
+// Res_value data types, the high byte of the value_type word
+const TYPE_NULL: u8 = 0x00;
+const TYPE_REFERENCE: u8 = 0x01;
+const TYPE_ATTRIBUTE: u8 = 0x02;
+const TYPE_STRING: u8 = 0x03;
+const TYPE_FLOAT: u8 = 0x04;
+const TYPE_DIMENSION: u8 = 0x05;
+const TYPE_FRACTION: u8 = 0x06;
+const TYPE_DYNAMIC_REFERENCE: u8 = 0x07;
+const TYPE_DYNAMIC_ATTRIBUTE: u8 = 0x08;
+const TYPE_INT_DEC: u8 = 0x10;
+const TYPE_INT_HEX: u8 = 0x11;
+const TYPE_INT_BOOLEAN: u8 = 0x12;
+const TYPE_INT_COLOR_ARGB8: u8 = 0x1c;
+const TYPE_INT_COLOR_RGB8: u8 = 0x1d;
+const TYPE_INT_COLOR_ARGB4: u8 = 0x1e;
+const TYPE_INT_COLOR_RGB4: u8 = 0x1f;
+
+const VALUE_SIZE: u32 = 8;
+
+// complex (dimension / fraction) layout: unit in bits 0-3, radix in bits 4-5, signed mantissa in bits 8-31
+const COMPLEX_UNIT_MASK: u32 = 0xf;
+const COMPLEX_RADIX_SHIFT: u32 = 4;
+const COMPLEX_RADIX_MASK: u32 = 0x3;
+const COMPLEX_MANTISSA_SHIFT: u32 = 8;
+const COMPLEX_MANTISSA_MASK: u32 = 0xffffff;
+const COMPLEX_RADIX_23P0: u32 = 0;
+const COMPLEX_RADIX_16P7: u32 = 1;
+const COMPLEX_RADIX_8P15: u32 = 2;
+const COMPLEX_RADIX_0P23: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DimensionUnit {
+    Px = 0,
+    Dp = 1,
+    Sp = 2,
+    Pt = 3,
+    In = 4,
+    Mm = 5
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FractionUnit {
+    Fraction = 0, // 50%
+    Parent = 1 // 50%p
+}
+
+// The (value_type, data) pair of an attribute, decoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypedValue {
+    Null,
+    StringRef(u32), // index into the string pool
+    Reference(u32), // @0x7f0e0001
+    Attribute(u32), // ?0x01010036
+    DynamicReference(u32), // into a shared library, the package byte is mapped at load time
+    DynamicAttribute(u32),
+    Boolean(bool),
+    IntDec(i32),
+    IntHex(u32),
+    Float(f32),
+    Dimension(f32, DimensionUnit),
+    Fraction(f32, FractionUnit),
+    Color(u32), // ARGB, the short #rgb forms are expanded by aapt already
+    Other(u8, u32) // data type without a dedicated variant, kept as is
+}
+
+fn complex_to_float(complex: u32) -> f32 {
+    let mantissa = (complex & (COMPLEX_MANTISSA_MASK << COMPLEX_MANTISSA_SHIFT)) as i32 as f32;
+    let radix_shift = match (complex >> COMPLEX_RADIX_SHIFT) & COMPLEX_RADIX_MASK {
+        COMPLEX_RADIX_23P0 => 0,
+        COMPLEX_RADIX_16P7 => 7,
+        COMPLEX_RADIX_8P15 => 15,
+        _ => 23
+    };
+    mantissa / (1u64 << (COMPLEX_MANTISSA_SHIFT + radix_shift)) as f32
+}
+
+// same precision selection as aapt's floatToComplex
+fn float_to_complex(value: f32) -> u32 {
+    let negative = value < 0.0;
+    let bits = ((value.abs() as f64) * (1u64 << 23) as f64 + 0.5) as u64;
+    let (radix, shift) = if bits & 0x7fffff == 0 {
+        (COMPLEX_RADIX_23P0, 23)
+    } else if bits & 0xffffffffff800000 == 0 {
+        (COMPLEX_RADIX_0P23, 0)
+    } else if bits & 0xffffffff80000000 == 0 {
+        (COMPLEX_RADIX_8P15, 8)
+    } else if bits & 0xffffff8000000000 == 0 {
+        (COMPLEX_RADIX_16P7, 16)
+    } else {
+        (COMPLEX_RADIX_23P0, 23)
+    };
+    let mut mantissa = ((bits >> shift) as u32) & COMPLEX_MANTISSA_MASK;
+    if negative {
+        mantissa = mantissa.wrapping_neg() & COMPLEX_MANTISSA_MASK;
+    }
+    (radix << COMPLEX_RADIX_SHIFT) | (mantissa << COMPLEX_MANTISSA_SHIFT)
+}
+
+impl DimensionUnit {
+    fn from_complex(complex: u32) -> Option<DimensionUnit> {
+        match complex & COMPLEX_UNIT_MASK {
+            0 => Some(DimensionUnit::Px),
+            1 => Some(DimensionUnit::Dp),
+            2 => Some(DimensionUnit::Sp),
+            3 => Some(DimensionUnit::Pt),
+            4 => Some(DimensionUnit::In),
+            5 => Some(DimensionUnit::Mm),
+            _ => None
+        }
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            DimensionUnit::Px => "px",
+            DimensionUnit::Dp => "dp",
+            DimensionUnit::Sp => "sp",
+            DimensionUnit::Pt => "pt",
+            DimensionUnit::In => "in",
+            DimensionUnit::Mm => "mm"
+        }
+    }
+}
+
+impl FractionUnit {
+    fn from_complex(complex: u32) -> Option<FractionUnit> {
+        match complex & COMPLEX_UNIT_MASK {
+            0 => Some(FractionUnit::Fraction),
+            1 => Some(FractionUnit::Parent),
+            _ => None
+        }
+    }
+}
+
+impl TypedValue {
+    // `value_type` is the whole Res_value header word (size, res0, data type) as stored in the attribute
+    pub fn decode(value_type: u32, data: u32) -> TypedValue {
+        let data_type = (value_type >> 24) as u8;
+        match data_type {
+            TYPE_NULL => TypedValue::Null,
+            TYPE_REFERENCE => TypedValue::Reference(data),
+            TYPE_ATTRIBUTE => TypedValue::Attribute(data),
+            TYPE_STRING => TypedValue::StringRef(data),
+            TYPE_DYNAMIC_REFERENCE => TypedValue::DynamicReference(data),
+            TYPE_DYNAMIC_ATTRIBUTE => TypedValue::DynamicAttribute(data),
+            TYPE_FLOAT => TypedValue::Float(f32::from_bits(data)),
+            TYPE_DIMENSION => match DimensionUnit::from_complex(data) {
+                Some(unit) => TypedValue::Dimension(complex_to_float(data), unit),
+                None => TypedValue::Other(data_type, data)
+            },
+            TYPE_FRACTION => match FractionUnit::from_complex(data) {
+                Some(unit) => TypedValue::Fraction(complex_to_float(data), unit),
+                None => TypedValue::Other(data_type, data)
+            },
+            TYPE_INT_DEC => TypedValue::IntDec(data as i32),
+            TYPE_INT_HEX => TypedValue::IntHex(data),
+            TYPE_INT_BOOLEAN => TypedValue::Boolean(data != 0),
+            TYPE_INT_COLOR_ARGB8 | TYPE_INT_COLOR_RGB8 | TYPE_INT_COLOR_ARGB4 | TYPE_INT_COLOR_RGB4 => TypedValue::Color(data),
+            _ => TypedValue::Other(data_type, data)
+        }
+    }
+
+    // inverse of decode, returns (value_type, data)
+    pub fn encode(&self) -> (u32, u32) {
+        let (data_type, data) = match *self {
+            TypedValue::Null => (TYPE_NULL, 0),
+            TypedValue::StringRef(index) => (TYPE_STRING, index),
+            TypedValue::Reference(id) => (TYPE_REFERENCE, id),
+            TypedValue::Attribute(id) => (TYPE_ATTRIBUTE, id),
+            TypedValue::DynamicReference(id) => (TYPE_DYNAMIC_REFERENCE, id),
+            TypedValue::DynamicAttribute(id) => (TYPE_DYNAMIC_ATTRIBUTE, id),
+            TypedValue::Boolean(value) => (TYPE_INT_BOOLEAN, if value { 0xFFFFFFFF } else { 0 }),
+            TypedValue::IntDec(value) => (TYPE_INT_DEC, value as u32),
+            TypedValue::IntHex(value) => (TYPE_INT_HEX, value),
+            TypedValue::Float(value) => (TYPE_FLOAT, value.to_bits()),
+            TypedValue::Dimension(value, unit) => (TYPE_DIMENSION, float_to_complex(value) | unit as u32),
+            TypedValue::Fraction(value, unit) => (TYPE_FRACTION, float_to_complex(value) | unit as u32),
+            TypedValue::Color(argb) => (TYPE_INT_COLOR_ARGB8, argb),
+            TypedValue::Other(data_type, data) => (data_type, data)
+        };
+        (((data_type as u32) << 24) | VALUE_SIZE, data)
+    }
+}
+
+// aapt2 dump style rendering
+impl Display for TypedValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TypedValue::Null => write!(f, "@null"),
+            TypedValue::StringRef(index) => write!(f, "string#{}", index),
+            TypedValue::Reference(id) | TypedValue::DynamicReference(id) => write!(f, "@0x{:08x}", id),
+            TypedValue::Attribute(id) | TypedValue::DynamicAttribute(id) => write!(f, "?0x{:08x}", id),
+            TypedValue::Boolean(value) => write!(f, "{}", value),
+            TypedValue::IntDec(value) => write!(f, "{}", value),
+            TypedValue::IntHex(value) => write!(f, "0x{:08x}", value),
+            TypedValue::Float(value) => write!(f, "{}", value),
+            TypedValue::Dimension(value, unit) => write!(f, "{}{}", value, unit.suffix()),
+            TypedValue::Fraction(value, FractionUnit::Fraction) => write!(f, "{}%", value * 100.0),
+            TypedValue::Fraction(value, FractionUnit::Parent) => write!(f, "{}%p", value * 100.0),
+            TypedValue::Color(argb) => write!(f, "#{:08x}", argb),
+            TypedValue::Other(data_type, data) => write!(f, "(type 0x{:02x})0x{:08x}", data_type, data)
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use alloc::string::String;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use alloc::collections::BTreeSet;
+use core::fmt::{Display, Formatter};
+use crate::manifest::attrs::ANDROID_NAMESPACE;
+use crate::manifest::axml::XmlNode;
+
+const COMPONENT_TAGS: [&str; 5] = ["activity", "activity-alias", "service", "receiver", "provider"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Severity {
+    Warning,
+    Error
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub element: String, // tag, plus android:name when present
+    pub message: String
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let level = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error"
+        };
+        write!(f, "{}: {}: {}", level, self.element, self.message)
+    }
+}
+
+fn get_android_string<'a>(node: &'a XmlNode, name: &str) -> Option<&'a str> {
+    node.attrs.iter()
+        .find(|attr| attr.name == name && attr.namespace_uri.as_deref() == Some(ANDROID_NAMESPACE))
+        .and_then(|attr| attr.string_data.as_deref())
+}
+
+fn has_android_attr(node: &XmlNode, name: &str) -> bool {
+    node.attrs.iter().any(|attr| attr.name == name && attr.namespace_uri.as_deref() == Some(ANDROID_NAMESPACE))
+}
+
+fn describe(node: &XmlNode) -> String {
+    match get_android_string(node, "name") {
+        Some(name) => format!("<{} android:name=\"{}\">", node.tag_name, name),
+        None => format!("<{}>", node.tag_name)
+    }
+}
+
+fn is_java_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || c == '$' => {},
+        _ => return false
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+fn is_valid_package(package: &str) -> bool {
+    let segments: Vec<&str> = package.split('.').collect();
+    segments.len() >= 2 && segments.iter().all(|segment| is_java_identifier(segment))
+}
+
+// accepts "com.foo.Bar", ".Bar" and "Bar" (the latter two are expanded with the package by the platform)
+fn is_resolvable_class_name(name: &str) -> bool {
+    name.strip_prefix('.').unwrap_or(name).split('.').all(is_java_identifier)
+}
+
+fn is_launcher(node: &XmlNode) -> bool {
+    node.children.iter().filter(|child| child.tag_name == "intent-filter").any(|filter| {
+        let has = |tag: &str, value: &str| filter.children.iter()
+            .any(|item| item.tag_name == tag && get_android_string(item, "name") == Some(value));
+        has("action", "android.intent.action.MAIN") && has("category", "android.intent.category.LAUNCHER")
+    })
+}
+
+struct Validator {
+    issues: Vec<ValidationIssue>
+}
+
+impl Validator {
+    fn push(&mut self, severity: Severity, node: &XmlNode, message: String) {
+        self.issues.push(ValidationIssue{
+            severity,
+            element: describe(node),
+            message
+        });
+    }
+
+    fn check_root(&mut self, root: &XmlNode) {
+        if root.tag_name != "manifest" {
+            self.push(Severity::Error, root, String::from("root element must be <manifest>"));
+            return;
+        }
+        let package = root.attrs.iter()
+            .find(|attr| attr.name == "package" && attr.namespace_uri.is_none())
+            .and_then(|attr| attr.string_data.as_deref());
+        match package {
+            None => self.push(Severity::Error, root, String::from("missing package attribute")),
+            Some(package) if !is_valid_package(package) => {
+                self.push(Severity::Error, root, format!("invalid package name \"{}\"", package));
+            },
+            _ => {}
+        }
+        if !root.children.iter().any(|child| child.tag_name == "application") {
+            self.push(Severity::Warning, root, String::from("no <application> element"));
+        }
+    }
+
+    fn check_permissions(&mut self, root: &XmlNode) {
+        let mut requested: BTreeSet<&str> = BTreeSet::new();
+        for node in &root.children {
+            match node.tag_name.as_str() {
+                "uses-permission" | "uses-permission-sdk-23" | "permission" | "permission-tree" | "permission-group" => {},
+                _ => continue
+            }
+            match get_android_string(node, "name") {
+                None | Some("") => self.push(Severity::Error, node, String::from("missing android:name")),
+                Some(name) => {
+                    if name.chars().any(|c| c.is_whitespace()) || name.starts_with('.') || name.ends_with('.') {
+                        self.push(Severity::Error, node, format!("malformed permission name \"{}\"", name));
+                    } else if !name.contains('.') {
+                        self.push(Severity::Warning, node, format!("permission name \"{}\" is not qualified", name));
+                    }
+                    if node.tag_name.starts_with("uses-permission") && !requested.insert(name) {
+                        self.push(Severity::Warning, node, String::from("permission is requested more than once"));
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_components(&mut self, application: &XmlNode) -> bool {
+        let mut has_launcher = false;
+        let activities: BTreeSet<&str> = application.children.iter()
+            .filter(|node| node.tag_name == "activity")
+            .filter_map(|node| get_android_string(node, "name"))
+            .collect();
+        for node in &application.children {
+            if !COMPONENT_TAGS.contains(&node.tag_name.as_str()) {
+                continue;
+            }
+            match get_android_string(node, "name") {
+                None | Some("") => self.push(Severity::Error, node, String::from("missing android:name")),
+                Some(name) if !is_resolvable_class_name(name) => {
+                    self.push(Severity::Error, node, format!("\"{}\" is not a valid class name", name));
+                },
+                _ => {}
+            }
+            match node.tag_name.as_str() {
+                "provider" => {
+                    match get_android_string(node, "authorities") {
+                        Some(authorities) if !authorities.trim().is_empty() => {},
+                        _ => self.push(Severity::Error, node, String::from("provider without android:authorities"))
+                    }
+                },
+                "activity-alias" => {
+                    match get_android_string(node, "targetActivity") {
+                        None => self.push(Severity::Error, node, String::from("missing android:targetActivity")),
+                        Some(target) if !activities.contains(target) => {
+                            self.push(Severity::Error, node, format!("targetActivity \"{}\" is not declared", target));
+                        },
+                        _ => {}
+                    }
+                },
+                _ => {}
+            }
+            if (node.tag_name == "activity" || node.tag_name == "activity-alias") && is_launcher(node) {
+                has_launcher = true;
+            }
+            // intent filters on API 31+ need an explicit exported flag
+            if node.children.iter().any(|child| child.tag_name == "intent-filter") && !has_android_attr(node, "exported") {
+                self.push(Severity::Warning, node, String::from("component with intent-filter has no android:exported"));
+            }
+        }
+        has_launcher
+    }
+}
+
+pub(crate) fn validate(root: &XmlNode) -> Vec<ValidationIssue> {
+    let mut validator = Validator{
+        issues: vec![]
+    };
+    validator.check_root(root);
+    validator.check_permissions(root);
+    // split APKs rely on the base APK's launcher
+    let is_split = root.attrs.iter().any(|attr| attr.name == "split" && attr.namespace_uri.is_none());
+    if let Some(application) = root.children.iter().find(|child| child.tag_name == "application") {
+        if !validator.check_components(application) && !is_split {
+            validator.push(Severity::Warning, application, String::from("no launcher activity (MAIN/LAUNCHER)"));
+        }
+    }
+    validator.issues
+}
@@ -0,0 +1,109 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+// How the zip, binary xml and resource table parsers treat input that breaks the spec but that
+// Android accepts anyway, which packers and obfuscators rely on (bogus local headers, made up
+// compression methods, size fields that don't match). Strict turns each of those into an error,
+// lenient goes on and records a ParseWarning. Input that can't be read either way is an error in both,
+// except for a broken central directory entry when `recover` is set: the zip parser skips it, records
+// a warning and reads the rest, to salvage what's left of a damaged apk
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParseOptions {
+    pub strict: bool,
+    pub recover: bool
+}
+
+// a spec violation a lenient parse went past, `offset` is into the parsed buffer
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ParseWarning {
+    pub offset: usize,
+    pub message: String
+}
+
+// options of a running parse and the warnings it collected
+pub(crate) struct ParseContext {
+    options: ParseOptions,
+    pub(crate) warnings: Vec<ParseWarning>
+}
+
+impl ParseOptions {
+    pub fn strict() -> ParseOptions {
+        ParseOptions{
+            strict: true,
+            recover: false
+        }
+    }
+
+    pub fn lenient() -> ParseOptions {
+        ParseOptions{
+            strict: false,
+            recover: false
+        }
+    }
+
+    // lenient, and broken entries are skipped instead of failing the parse
+    pub fn recovering() -> ParseOptions {
+        ParseOptions{
+            strict: false,
+            recover: true
+        }
+    }
+}
+
+// lenient, as Android itself is
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions::lenient()
+    }
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} at: {}", self.message, self.offset)
+    }
+}
+
+impl ParseContext {
+    pub(crate) fn new(options: ParseOptions) -> ParseContext {
+        ParseContext{
+            options,
+            warnings: vec![]
+        }
+    }
+
+    // false when strict, the caller fails with its own error then. Otherwise the violation is
+    // recorded and the parse carries on
+    pub(crate) fn tolerate(&mut self, offset: usize, message: &str) -> bool {
+        if self.options.strict {
+            return false;
+        }
+        self.warnings.push(ParseWarning{
+            offset,
+            message: String::from(message)
+        });
+        true
+    }
+
+    // something odd but within the spec (an unknown chunk kept as is, a garbage timestamp), recorded
+    // by a lenient parse and let through by both
+    pub(crate) fn note(&mut self, offset: usize, message: &str) {
+        self.tolerate(offset, message);
+    }
+
+    // whether an error at `offset` can be skipped past, recorded when it can. Strict or not, a
+    // recovering parse leaves out what it skipped rather than letting it through. Only zips recover
+    #[cfg(feature = "zip-headers")]
+    pub(crate) fn recover(&mut self, offset: usize, message: &str) -> bool {
+        if !self.options.recover {
+            return false;
+        }
+        self.warnings.push(ParseWarning{
+            offset,
+            message: String::from(message)
+        });
+        true
+    }
+}
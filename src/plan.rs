@@ -0,0 +1,302 @@
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use serde::Deserialize;
+use crate::apk_zip::{ApkFile, CompressMethod, SavePlan, SigningKey};
+use crate::manifest::attrs::{self, ANDROID_NAMESPACE};
+use crate::manifest::components::ComponentKind;
+use crate::manifest::manifest_editor::{AndroidManifest, Provider, UsesPermission};
+use crate::manifest::typed_value::TypedValue;
+use crate::manifest::{XmlAttributeValue, XmlNode};
+use crate::{Error, Result};
+
+// A repack job described as data, read from JSON or TOML:
+//
+//   align = 4
+//   sign = { key = "release.pk8", cert = "release.x509.pem" }
+//
+//   [[steps]]
+//   op = "set_manifest_attribute"
+//   element = "application"
+//   attribute = "android:debuggable"
+//   value = false
+//
+// Steps run in order, then the apk is saved, signed when `sign` is set. Relative paths are resolved
+// against the directory of the plan file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PatchPlan {
+    #[serde(default)]
+    pub steps: Vec<PatchStep>,
+    pub align: Option<usize>,
+    pub sign: Option<SignWith>,
+    #[serde(skip)]
+    base_dir: PathBuf
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum PatchStep {
+    // an entry with the content of `source`, replacing one of the same name, also one an earlier step
+    // added. `source` is relative to the plan's directory and can't leave it
+    AddFile {
+        name: String,
+        source: PathBuf,
+        #[serde(default)]
+        store: bool
+    },
+    RemoveFile {
+        name: String
+    },
+    // `element` is a path of tags below <manifest>, e.g. "application/activity", "" for <manifest>
+    // itself. With `name`, the element whose android:name it is, otherwise the first one
+    SetManifestAttribute {
+        element: String,
+        name: Option<String>,
+        attribute: String, // "android:exported", or without prefix for the unnamespaced ones like "package"
+        value: AttributeValue,
+        resource_id: Option<u32> // for android: attributes the crate has no id of
+    },
+    AddActivity {
+        name: String,
+        exported: Option<bool>
+    },
+    AddService {
+        name: String,
+        exported: Option<bool>
+    },
+    AddReceiver {
+        name: String,
+        exported: Option<bool>
+    },
+    AddProvider {
+        name: String,
+        authorities: String,
+        exported: Option<bool>
+    },
+    AddUsesPermission {
+        name: String,
+        max_sdk_version: Option<u32>
+    },
+    // removes the native libraries of every abi not listed, e.g. keep = ["arm64-v8a"]
+    StripAbis {
+        keep: Vec<String>
+    }
+}
+
+// strings of the form @0x7f0e0001 are references
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    Bool(bool),
+    Int(i32),
+    String(String)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignWith {
+    pub key: PathBuf,
+    pub cert: PathBuf
+}
+
+#[derive(Debug)]
+pub struct PlanError {
+    reason: String
+}
+
+impl Display for PlanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "patch plan error: {}", self.reason)
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+impl PlanError {
+    fn new(reason: String) -> PlanError {
+        PlanError{
+            reason
+        }
+    }
+}
+
+impl PatchPlan {
+    pub fn from_json(text: &str) -> Result<PatchPlan, PlanError> {
+        serde_json::from_str(text).map_err(|err| PlanError::new(err.to_string()))
+    }
+
+    pub fn from_toml(text: &str) -> Result<PatchPlan, PlanError> {
+        toml::from_str(text).map_err(|err| PlanError::new(err.to_string()))
+    }
+
+    // TOML for a .toml file, JSON otherwise. Paths in the plan are taken relative to its directory
+    pub fn from_file(path: &Path) -> Result<PatchPlan> {
+        let text = std::fs::read_to_string(path)?;
+        let mut plan = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => PatchPlan::from_toml(&text)?,
+            _ => PatchPlan::from_json(&text)?
+        };
+        plan.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(plan)
+    }
+
+    fn path(&self, path: &Path) -> PathBuf {
+        self.base_dir.join(path)
+    }
+
+    // `source` of an AddFile, a plan shouldn't be able to pack files from outside its directory
+    fn read_source(&self, source: &Path) -> Result<Vec<u8>> {
+        if !source.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)) {
+            return Err(PlanError::new(format!("{} is outside the plan's directory", source.display())).into());
+        }
+        self.read(source)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let path = self.path(path);
+        std::fs::read(&path).map_err(|err| PlanError::new(format!("{}: {}", path.display(), err)).into())
+    }
+}
+
+impl AttributeValue {
+    fn set_on(&self, attr: &mut XmlAttributeValue) {
+        match self {
+            AttributeValue::Bool(value) => attr.set_typed_value(TypedValue::Boolean(*value)),
+            AttributeValue::Int(value) => attr.set_typed_value(TypedValue::IntDec(*value)),
+            AttributeValue::String(value) => match value.strip_prefix("@0x").and_then(|id| u32::from_str_radix(id, 16).ok()) {
+                Some(id) => attr.set_typed_value(TypedValue::Reference(id)),
+                None => attr.set_string_value(value)
+            }
+        }
+    }
+}
+
+fn find_element<'a>(node: &'a mut XmlNode, path: &[&str], name: Option<&str>) -> Option<&'a mut XmlNode> {
+    let (tag_name, rest) = match path.split_first() {
+        Some(first) => first,
+        None => return match name {
+            Some(name) if node.get_attr(Some(ANDROID_NAMESPACE), "name").and_then(|attr| attr.string_value()) != Some(name) => None,
+            _ => Some(node)
+        }
+    };
+    node.children_mut().iter_mut()
+        .filter(|child| child.tag_name() == *tag_name)
+        .find_map(|child| find_element(child, rest, name))
+}
+
+fn set_manifest_attribute(manifest: &mut AndroidManifest, element: &str, name: Option<&str>, attribute: &str,
+                          value: &AttributeValue, resource_id: Option<u32>) -> Result<()> {
+    let mut root = manifest.root_node().clone();
+    let path: Vec<&str> = element.split('/').filter(|tag_name| !tag_name.is_empty()).collect();
+    let node = find_element(&mut root, &path, name)
+        .ok_or_else(|| PlanError::new(format!("no <{}> element {}in the manifest", element, name.map(|name| format!("named {} ", name)).unwrap_or_default())))?;
+    let (namespace_uri, local_name) = match attribute.strip_prefix("android:") {
+        Some(local_name) => (Some(ANDROID_NAMESPACE), local_name),
+        None => (None, attribute)
+    };
+    let existing = node.get_attr_mut(namespace_uri, local_name);
+    match existing {
+        Some(attr) => value.set_on(attr),
+        None => {
+            // framework attributes are matched by id, one without it is ignored by Android
+            let resource_id = match namespace_uri {
+                Some(_) => resource_id.or_else(|| attrs::by_name(local_name))
+                    .ok_or_else(|| PlanError::new(format!("no known resource id for {}, set resource_id", attribute)))?,
+                None => 0
+            };
+            let mut attr = XmlAttributeValue::new(namespace_uri, local_name, resource_id, TypedValue::Null)?;
+            value.set_on(&mut attr);
+            node.set_attr(attr);
+        }
+    }
+    manifest.set_root_node(root);
+    Ok(())
+}
+
+// the manifest is parsed on the first step that edits it and written back once all steps ran,
+// get_manifest keeps returning the original
+fn manifest<'m>(apk: &ApkFile, manifest: &'m mut Option<AndroidManifest<'static>>) -> Result<&'m mut AndroidManifest<'static>> {
+    if manifest.is_none() {
+        *manifest = Some(AndroidManifest::from_vec(apk.get_manifest()?)?);
+    }
+    Ok(manifest.as_mut().unwrap())
+}
+
+// native libraries live in lib/<abi>/
+fn strip_abis(apk: &mut ApkFile, keep: &[String]) -> Result<()> {
+    for entry in apk.list_files() {
+        let abi = entry.name.strip_prefix("lib/").and_then(|rest| rest.split_once('/')).map(|(abi, _)| abi);
+        if abi.is_some_and(|abi| !keep.iter().any(|item| item == abi)) {
+            apk.remove_file(&entry.name)?;
+        }
+    }
+    Ok(())
+}
+
+// a bad key should fail before any work is done
+fn signing_key(plan: &PatchPlan) -> Result<Option<SigningKey>> {
+    match &plan.sign {
+        Some(sign) => Ok(Some(SigningKey::from(&plan.read(&sign.key)?, &plan.read(&sign.cert)?)?)),
+        None => Ok(None)
+    }
+}
+
+fn run_steps(plan: &PatchPlan, apk: &mut ApkFile) -> Result<()> {
+    let mut edited_manifest = None;
+    for step in &plan.steps {
+        match step {
+            PatchStep::AddFile { name, source, store } => {
+                let data = plan.read_source(source)?;
+                apk.add_or_replace_file(name, data, if *store { CompressMethod::Stored } else { CompressMethod::Deflated });
+            },
+            PatchStep::RemoveFile { name } => apk.remove_file(name)?,
+            PatchStep::SetManifestAttribute { element, name, attribute, value, resource_id } => {
+                let manifest = manifest(apk, &mut edited_manifest)?;
+                set_manifest_attribute(manifest, element, name.as_deref(), attribute, value, *resource_id)?;
+            },
+            PatchStep::AddActivity { name, exported } => manifest(apk, &mut edited_manifest)?.add_component(ComponentKind::Activity, name, *exported)?,
+            PatchStep::AddService { name, exported } => manifest(apk, &mut edited_manifest)?.add_component(ComponentKind::Service, name, *exported)?,
+            PatchStep::AddReceiver { name, exported } => manifest(apk, &mut edited_manifest)?.add_component(ComponentKind::Receiver, name, *exported)?,
+            PatchStep::AddProvider { name, authorities, exported } => manifest(apk, &mut edited_manifest)?.add_content_provider(Provider{
+                class_name: name.clone(),
+                authorities: authorities.clone(),
+                exported: *exported,
+                ..Provider::default()
+            })?,
+            PatchStep::AddUsesPermission { name, max_sdk_version } => manifest(apk, &mut edited_manifest)?.add_uses_permission(UsesPermission{
+                name: name.clone(),
+                max_sdk_version: *max_sdk_version
+            }),
+            PatchStep::StripAbis { keep } => strip_abis(apk, keep)?
+        }
+    }
+    if let Some(manifest) = edited_manifest {
+        apk.set_manifest(manifest.get_data())?;
+    }
+    Ok(())
+}
+
+// runs the steps of `plan` on `apk` and writes the result. When a step fails nothing is written and
+// `apk` is left as it was
+pub fn apply<W: Write>(plan: &PatchPlan, apk: &mut ApkFile, mut writer: W) -> Result<()> {
+    let key = signing_key(plan)?;
+    apk.transaction(|apk| run_steps(plan, apk))?;
+    let mut data = vec![];
+    apk.save_aligned(&mut data, plan.align.unwrap_or(apk.config().align))?;
+    if let Some(key) = key {
+        data = crate::apk_zip::sign_v2(&data, &key)?;
+    }
+    writer.write_all(&data).map_err(Error::from)
+}
+
+// runs the steps and reports what apply would write instead of writing it, then rolls them back so
+// `apk` is left as it was whether they succeeded or not. The signature of the plan reflects save(), the
+// one `sign` adds isn't in it
+pub fn dry_run(plan: &PatchPlan, apk: &mut ApkFile) -> Result<SavePlan> {
+    signing_key(plan)?;
+    apk.begin();
+    let res = run_steps(plan, apk).and_then(|_| apk.plan_aligned(plan.align.unwrap_or(apk.config().align)));
+    apk.rollback()?;
+    res
+}
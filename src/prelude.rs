@@ -0,0 +1,24 @@
+// `use apk_editor::prelude::*` for the types most edits need, each behind the feature that brings it
+pub use crate::error::{Error, Result};
+#[cfg(feature = "manifest")]
+pub use crate::options::{ParseOptions, ParseWarning};
+#[cfg(feature = "zip")]
+pub use crate::apk_zip::{ApkFile, EditEvent, EditorConfig, EntryMeta, EntryTransformer, FileInfo, SavePlan, ZipEditor};
+#[cfg(feature = "zip-headers")]
+pub use crate::apk_zip::{CompressMethod, ZipEntry, ZipFile, ZipFormatError, ZipIndex};
+#[cfg(feature = "sign")]
+pub use crate::apk_zip::{SignError, SigningKey};
+#[cfg(feature = "manifest")]
+pub use crate::manifest::{AndroidXml, FileFormatError, XmlAttributeValue, XmlNode};
+#[cfg(feature = "manifest")]
+pub use crate::manifest::chunks::{ChunkAction, ChunkVisitor, XmlChunk};
+#[cfg(feature = "manifest")]
+pub use crate::manifest::manifest_editor::{AndroidManifest, ManifestError};
+#[cfg(feature = "manifest")]
+pub use crate::manifest::typed_value::TypedValue;
+#[cfg(feature = "resources")]
+pub use crate::resources::{ResourceError, ResourceTable};
+#[cfg(feature = "dex")]
+pub use crate::dex::{DexError, DexFile};
+#[cfg(feature = "plan")]
+pub use crate::plan::{PatchPlan, PlanError};
@@ -0,0 +1,474 @@
+use std::fmt::{Display, Formatter};
+use crate::utils::{get_leu16_value, get_leu32_value, push_leu16, push_leu32};
+
+const CONFIG_SIZE: usize = 64;
+
+pub const DENSITY_DEFAULT: u16 = 0;
+pub const DENSITY_MEDIUM: u16 = 160;
+pub const DENSITY_ANY: u16 = 0xfffe;
+pub const DENSITY_NONE: u16 = 0xffff;
+
+const SCREEN_SIZE_MASK: u8 = 0x0f;
+const SCREEN_LONG_MASK: u8 = 0x30;
+const LAYOUT_DIR_MASK: u8 = 0xc0;
+const SCREEN_ROUND_MASK: u8 = 0x03;
+const WIDE_COLOR_GAMUT_MASK: u8 = 0x03;
+const HDR_MASK: u8 = 0x0c;
+const UI_MODE_TYPE_MASK: u8 = 0x0f;
+const UI_MODE_NIGHT_MASK: u8 = 0x30;
+const KEYS_HIDDEN_MASK: u8 = 0x03;
+const NAV_HIDDEN_MASK: u8 = 0x0c;
+const KEYS_HIDDEN_NO: u8 = 1;
+const KEYS_HIDDEN_SOFT: u8 = 3;
+
+// (qualifier, value) for the fields that are picked from a fixed set, in aapt order within each group
+const DENSITIES: [(&str, u16); 9] = [("ldpi", 120), ("mdpi", 160), ("tvdpi", 213), ("hdpi", 240), ("xhdpi", 320),
+    ("xxhdpi", 480), ("xxxhdpi", 640), ("anydpi", DENSITY_ANY), ("nodpi", DENSITY_NONE)];
+const LAYOUT_DIRS: [(&str, u8); 2] = [("ldltr", 0x40), ("ldrtl", 0x80)];
+const SCREEN_SIZES: [(&str, u8); 4] = [("small", 1), ("normal", 2), ("large", 3), ("xlarge", 4)];
+const SCREEN_LONGS: [(&str, u8); 2] = [("notlong", 0x10), ("long", 0x20)];
+const SCREEN_ROUNDS: [(&str, u8); 2] = [("notround", 1), ("round", 2)];
+const WIDE_COLOR_GAMUTS: [(&str, u8); 2] = [("nowidecg", 1), ("widecg", 2)];
+const HDRS: [(&str, u8); 2] = [("lowdr", 4), ("highdr", 8)];
+const ORIENTATIONS: [(&str, u8); 3] = [("port", 1), ("land", 2), ("square", 3)];
+const UI_MODE_TYPES: [(&str, u8); 6] = [("desk", 2), ("car", 3), ("television", 4), ("appliance", 5), ("watch", 6), ("vrheadset", 7)];
+const UI_MODE_NIGHTS: [(&str, u8); 2] = [("notnight", 0x10), ("night", 0x20)];
+const TOUCHSCREENS: [(&str, u8); 3] = [("notouch", 1), ("stylus", 2), ("finger", 3)];
+const KEYS_HIDDENS: [(&str, u8); 3] = [("keysexposed", 1), ("keyshidden", 2), ("keyssoft", 3)];
+const KEYBOARDS: [(&str, u8); 3] = [("nokeys", 1), ("qwerty", 2), ("12key", 3)];
+const NAV_HIDDENS: [(&str, u8); 2] = [("navexposed", 4), ("navhidden", 8)];
+const NAVIGATIONS: [(&str, u8); 4] = [("nonav", 1), ("dpad", 2), ("trackball", 3), ("wheel", 4)];
+
+// Typed ResTable_config, the qualifiers of one configuration ("fr-rCA-xxhdpi-v21"). Zero means unset
+// everywhere, the flag style fields keep their ResTable_config bit layout.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResourceConfig {
+    pub mcc: u16,
+    pub mnc: u16,
+    pub language: String,
+    pub region: String,
+    pub orientation: u8,
+    pub touchscreen: u8,
+    pub density: u16,
+    pub keyboard: u8,
+    pub navigation: u8,
+    pub input_flags: u8, // keys hidden | nav hidden
+    pub screen_width: u16,
+    pub screen_height: u16,
+    pub sdk_version: u16,
+    pub minor_version: u16,
+    pub screen_layout: u8, // size | long | layout direction
+    pub ui_mode: u8, // type | night
+    pub smallest_screen_width_dp: u16,
+    pub screen_width_dp: u16,
+    pub screen_height_dp: u16,
+    pub locale_script: String,
+    pub locale_variant: String,
+    pub screen_layout2: u8, // round
+    pub color_mode: u8 // wide color gamut | hdr
+}
+
+// two bytes, or three letters packed into them when the high bit is set
+fn unpack_locale_part(data: [u8; 2], base: u8) -> String {
+    if data[0] & 0x80 == 0 {
+        return data.iter().take_while(|c| **c != 0).map(|c| *c as char).collect();
+    }
+    let first = data[1] & 0x1f;
+    let second = ((data[1] & 0xe0) >> 5) | ((data[0] & 0x03) << 3);
+    let third = (data[0] & 0x7c) >> 2;
+    [first, second, third].iter().map(|c| (base + c) as char).collect()
+}
+
+fn pack_locale_part(value: &str, base: u8) -> [u8; 2] {
+    let bytes = value.as_bytes();
+    match bytes.len() {
+        2 => [bytes[0], bytes[1]],
+        3 => {
+            let first = bytes[0].wrapping_sub(base) & 0x1f;
+            let second = bytes[1].wrapping_sub(base) & 0x1f;
+            let third = bytes[2].wrapping_sub(base) & 0x1f;
+            [0x80 | (third << 2) | (second >> 3), (second << 5) | first]
+        },
+        _ => [0, 0]
+    }
+}
+
+fn fixed_string(data: &[u8]) -> String {
+    data.iter().take_while(|c| **c != 0).map(|c| *c as char).collect()
+}
+
+fn push_fixed_string(data: &mut Vec<u8>, value: &str, len: usize) {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.resize(len, 0);
+    data.extend_from_slice(&bytes);
+}
+
+fn find_qualifier<T: Copy + PartialEq>(table: &[(&str, T)], name: &str) -> Option<T> {
+    table.iter().find(|(qualifier, _)| *qualifier == name).map(|(_, value)| *value)
+}
+
+fn qualifier_name<T: Copy + PartialEq>(table: &[(&'static str, T)], value: T) -> Option<&'static str> {
+    table.iter().find(|(_, item)| *item == value).map(|(qualifier, _)| *qualifier)
+}
+
+// "sw600dp", "w720dp", "v21"... the number between `prefix` and `suffix`
+fn parse_number(part: &str, prefix: &str, suffix: &str) -> Option<u16> {
+    let number = part.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    if number.is_empty() || !number.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+// a set flag field only matches the same value
+fn flag_matches(value: u8, requested: u8) -> bool {
+    value == 0 || value == requested
+}
+
+// both sides already match, so the one that is set at all is the more specific
+fn set_field_wins<T: PartialEq + Default>(value: T, other: T) -> Option<bool> {
+    if value == other {
+        None
+    } else {
+        Some(value != T::default())
+    }
+}
+
+impl ResourceConfig {
+    // reads a ResTable_config of any size, fields past the end stay unset
+    pub fn parse(data: &[u8]) -> ResourceConfig {
+        let mut full = data.to_vec();
+        full.resize(full.len().max(CONFIG_SIZE), 0);
+        let size = if data.len() >= 4 { (get_leu32_value(data, 0) as usize).min(data.len()) } else { 0 };
+        full[size.clamp(4, CONFIG_SIZE)..CONFIG_SIZE].fill(0);
+        ResourceConfig{
+            mcc: get_leu16_value(&full, 4),
+            mnc: get_leu16_value(&full, 6),
+            language: unpack_locale_part([full[8], full[9]], b'a'),
+            region: unpack_locale_part([full[10], full[11]], b'0'),
+            orientation: full[12],
+            touchscreen: full[13],
+            density: get_leu16_value(&full, 14),
+            keyboard: full[16],
+            navigation: full[17],
+            input_flags: full[18],
+            screen_width: get_leu16_value(&full, 20),
+            screen_height: get_leu16_value(&full, 22),
+            sdk_version: get_leu16_value(&full, 24),
+            minor_version: get_leu16_value(&full, 26),
+            screen_layout: full[28],
+            ui_mode: full[29],
+            smallest_screen_width_dp: get_leu16_value(&full, 30),
+            screen_width_dp: get_leu16_value(&full, 32),
+            screen_height_dp: get_leu16_value(&full, 34),
+            locale_script: fixed_string(&full[36..40]),
+            locale_variant: fixed_string(&full[40..48]),
+            screen_layout2: full[48],
+            color_mode: full[49]
+        }
+    }
+
+    // 64 byte ResTable_config
+    pub fn to_data(&self) -> Vec<u8> {
+        let mut res: Vec<u8> = Vec::with_capacity(CONFIG_SIZE);
+        push_leu32(&mut res, CONFIG_SIZE as u32);
+        push_leu16(&mut res, self.mcc);
+        push_leu16(&mut res, self.mnc);
+        res.extend_from_slice(&pack_locale_part(&self.language, b'a'));
+        res.extend_from_slice(&pack_locale_part(&self.region, b'0'));
+        res.push(self.orientation);
+        res.push(self.touchscreen);
+        push_leu16(&mut res, self.density);
+        res.push(self.keyboard);
+        res.push(self.navigation);
+        res.push(self.input_flags);
+        res.push(0);
+        push_leu16(&mut res, self.screen_width);
+        push_leu16(&mut res, self.screen_height);
+        push_leu16(&mut res, self.sdk_version);
+        push_leu16(&mut res, self.minor_version);
+        res.push(self.screen_layout);
+        res.push(self.ui_mode);
+        push_leu16(&mut res, self.smallest_screen_width_dp);
+        push_leu16(&mut res, self.screen_width_dp);
+        push_leu16(&mut res, self.screen_height_dp);
+        push_fixed_string(&mut res, &self.locale_script, 4);
+        push_fixed_string(&mut res, &self.locale_variant, 8);
+        res.push(self.screen_layout2);
+        res.push(self.color_mode);
+        res.resize(CONFIG_SIZE, 0);
+        res
+    }
+
+    // aapt style qualifiers, "" is the default configuration, e.g. "zh-rCN-xxhdpi", "b+sr+Latn", "sw600dp-land-v13"
+    pub fn from_qualifiers(qualifiers: &str) -> Option<ResourceConfig> {
+        let mut res = ResourceConfig::default();
+        if qualifiers.is_empty() {
+            return Some(res);
+        }
+        let mut parts = qualifiers.split('-').peekable();
+        // the locale comes first, only mcc/mnc may precede it
+        let mut accepts_locale = true;
+        while let Some(part) = parts.next() {
+            let lower = part.to_ascii_lowercase();
+            let lower = lower.as_str();
+            let locale_allowed = accepts_locale;
+            accepts_locale = lower.starts_with("mcc") || lower.starts_with("mnc");
+            if let Some(value) = parse_number(lower, "mcc", "") {
+                res.mcc = value;
+            } else if let Some(value) = parse_number(lower, "mnc", "") {
+                res.mnc = if value == 0 { 0xffff } else { value };
+            } else if let Some(tag) = part.strip_prefix("b+").filter(|_| locale_allowed) {
+                // BCP 47: language, then an optional 4 letter script and a 2 letter or 3 digit region
+                let mut subtags = tag.split('+');
+                res.language = subtags.next()?.to_ascii_lowercase();
+                for subtag in subtags {
+                    match subtag.len() {
+                        4 => res.locale_script = subtag.to_string(),
+                        2 | 3 => res.region = subtag.to_ascii_uppercase(),
+                        5..=8 => res.locale_variant = subtag.to_string(),
+                        _ => return None
+                    }
+                }
+            } else if locale_allowed && (part.len() == 2 || part.len() == 3) && part.bytes().all(|c| c.is_ascii_lowercase())
+                && find_qualifier(&UI_MODE_TYPES, lower).is_none() {
+                res.language = part.to_string();
+                if let Some(region) = parts.peek().and_then(|next| next.strip_prefix('r')) {
+                    if region.len() == 2 || region.len() == 3 {
+                        res.region = region.to_ascii_uppercase();
+                        parts.next();
+                    }
+                }
+            } else if let Some(value) = find_qualifier(&LAYOUT_DIRS, lower) {
+                res.screen_layout |= value;
+            } else if let Some(value) = parse_number(lower, "sw", "dp") {
+                res.smallest_screen_width_dp = value;
+            } else if let Some(value) = parse_number(lower, "w", "dp") {
+                res.screen_width_dp = value;
+            } else if let Some(value) = parse_number(lower, "h", "dp") {
+                res.screen_height_dp = value;
+            } else if let Some(value) = find_qualifier(&SCREEN_SIZES, lower) {
+                res.screen_layout |= value;
+            } else if let Some(value) = find_qualifier(&SCREEN_LONGS, lower) {
+                res.screen_layout |= value;
+            } else if let Some(value) = find_qualifier(&SCREEN_ROUNDS, lower) {
+                res.screen_layout2 |= value;
+            } else if let Some(value) = find_qualifier(&WIDE_COLOR_GAMUTS, lower) {
+                res.color_mode |= value;
+            } else if let Some(value) = find_qualifier(&HDRS, lower) {
+                res.color_mode |= value;
+            } else if let Some(value) = find_qualifier(&ORIENTATIONS, lower) {
+                res.orientation = value;
+            } else if let Some(value) = find_qualifier(&UI_MODE_TYPES, lower) {
+                res.ui_mode |= value;
+            } else if let Some(value) = find_qualifier(&UI_MODE_NIGHTS, lower) {
+                res.ui_mode |= value;
+            } else if let Some(value) = find_qualifier(&DENSITIES, lower) {
+                res.density = value;
+            } else if let Some(value) = parse_number(lower, "", "dpi") {
+                res.density = value;
+            } else if let Some(value) = find_qualifier(&TOUCHSCREENS, lower) {
+                res.touchscreen = value;
+            } else if let Some(value) = find_qualifier(&KEYS_HIDDENS, lower) {
+                res.input_flags |= value;
+            } else if let Some(value) = find_qualifier(&KEYBOARDS, lower) {
+                res.keyboard = value;
+            } else if let Some(value) = find_qualifier(&NAV_HIDDENS, lower) {
+                res.input_flags |= value;
+            } else if let Some(value) = find_qualifier(&NAVIGATIONS, lower) {
+                res.navigation = value;
+            } else if let Some((width, height)) = lower.split_once('x').and_then(|(w, h)| Some((parse_number(w, "", "")?, parse_number(h, "", "")?))) {
+                res.screen_width = width.max(height);
+                res.screen_height = width.min(height);
+            } else if let Some(value) = parse_number(lower, "v", "") {
+                res.sdk_version = value;
+            } else {
+                return None;
+            }
+        }
+        Some(res)
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == ResourceConfig::default()
+    }
+
+    // whether resources in this configuration can be used on a device described by `requested`,
+    // everything set here has to be satisfied by it
+    pub fn matches(&self, requested: &ResourceConfig) -> bool {
+        if (self.mcc != 0 && self.mcc != requested.mcc) || (self.mnc != 0 && self.mnc != requested.mnc) {
+            return false;
+        }
+        if !self.language.is_empty() {
+            if self.language != requested.language {
+                return false;
+            }
+            if (!self.region.is_empty() && self.region != requested.region)
+                || (!self.locale_script.is_empty() && self.locale_script != requested.locale_script)
+                || (!self.locale_variant.is_empty() && self.locale_variant != requested.locale_variant) {
+                return false;
+            }
+        }
+        let at_most = |value: u16, requested: u16| value == 0 || value <= requested;
+        let keys_hidden = self.input_flags & KEYS_HIDDEN_MASK;
+        let requested_keys_hidden = requested.input_flags & KEYS_HIDDEN_MASK;
+        // a request for exposed keys still counts as matching keyssoft
+        let keys_hidden_matches = flag_matches(keys_hidden, requested_keys_hidden)
+            || (keys_hidden == KEYS_HIDDEN_NO && requested_keys_hidden == KEYS_HIDDEN_SOFT);
+        flag_matches(self.screen_layout & LAYOUT_DIR_MASK, requested.screen_layout & LAYOUT_DIR_MASK)
+            && at_most(self.smallest_screen_width_dp, requested.smallest_screen_width_dp)
+            && at_most(self.screen_width_dp, requested.screen_width_dp)
+            && at_most(self.screen_height_dp, requested.screen_height_dp)
+            && at_most((self.screen_layout & SCREEN_SIZE_MASK) as u16, (requested.screen_layout & SCREEN_SIZE_MASK) as u16)
+            && flag_matches(self.screen_layout & SCREEN_LONG_MASK, requested.screen_layout & SCREEN_LONG_MASK)
+            && flag_matches(self.screen_layout2 & SCREEN_ROUND_MASK, requested.screen_layout2 & SCREEN_ROUND_MASK)
+            && flag_matches(self.color_mode & WIDE_COLOR_GAMUT_MASK, requested.color_mode & WIDE_COLOR_GAMUT_MASK)
+            && flag_matches(self.color_mode & HDR_MASK, requested.color_mode & HDR_MASK)
+            && flag_matches(self.orientation, requested.orientation)
+            && flag_matches(self.ui_mode & UI_MODE_TYPE_MASK, requested.ui_mode & UI_MODE_TYPE_MASK)
+            && flag_matches(self.ui_mode & UI_MODE_NIGHT_MASK, requested.ui_mode & UI_MODE_NIGHT_MASK)
+            && flag_matches(self.touchscreen, requested.touchscreen)
+            && keys_hidden_matches
+            && flag_matches(self.keyboard, requested.keyboard)
+            && flag_matches(self.input_flags & NAV_HIDDEN_MASK, requested.input_flags & NAV_HIDDEN_MASK)
+            && flag_matches(self.navigation, requested.navigation)
+            && at_most(self.screen_width, requested.screen_width)
+            && at_most(self.screen_height, requested.screen_height)
+            && at_most(self.sdk_version, requested.sdk_version)
+            && (self.minor_version == 0 || self.minor_version == requested.minor_version)
+    }
+
+    // closest density to the requested one, scaling down counts as twice as good as scaling up
+    pub(crate) fn is_density_better(&self, other: &ResourceConfig, requested: &ResourceConfig) -> Option<bool> {
+        if self.density == other.density {
+            return None;
+        }
+        if self.density == DENSITY_ANY || other.density == DENSITY_ANY {
+            return Some(self.density == DENSITY_ANY);
+        }
+        let or_medium = |density: u16| if density == DENSITY_DEFAULT { DENSITY_MEDIUM as i64 } else { density as i64 };
+        let (this, that, wanted) = (or_medium(self.density), or_medium(other.density), or_medium(requested.density));
+        if this == that {
+            return None;
+        }
+        let (high, low, i_am_bigger) = if this > that { (this, that, true) } else { (that, this, false) };
+        if wanted >= high {
+            return Some(i_am_bigger);
+        }
+        if low >= wanted {
+            return Some(!i_am_bigger);
+        }
+        if (2 * low - wanted) * high > wanted * wanted {
+            Some(!i_am_bigger)
+        } else {
+            Some(i_am_bigger)
+        }
+    }
+
+    // ResTable_config::isBetterThan, both configurations are expected to match `requested`
+    pub fn is_better_than(&self, other: &ResourceConfig, requested: &ResourceConfig) -> bool {
+        let larger_wins = |value: u16, other: u16| if value == other { None } else { Some(value > other) };
+        let decisions = [
+            set_field_wins(self.mcc, other.mcc),
+            set_field_wins(self.mnc, other.mnc),
+            set_field_wins(self.language.as_str(), other.language.as_str()),
+            set_field_wins(self.region.as_str(), other.region.as_str()),
+            set_field_wins(self.locale_script.as_str(), other.locale_script.as_str()),
+            set_field_wins(self.locale_variant.as_str(), other.locale_variant.as_str()),
+            set_field_wins(self.screen_layout & LAYOUT_DIR_MASK, other.screen_layout & LAYOUT_DIR_MASK),
+            larger_wins(self.smallest_screen_width_dp, other.smallest_screen_width_dp),
+            larger_wins(self.screen_width_dp, other.screen_width_dp),
+            larger_wins(self.screen_height_dp, other.screen_height_dp),
+            larger_wins((self.screen_layout & SCREEN_SIZE_MASK) as u16, (other.screen_layout & SCREEN_SIZE_MASK) as u16),
+            set_field_wins(self.screen_layout & SCREEN_LONG_MASK, other.screen_layout & SCREEN_LONG_MASK),
+            set_field_wins(self.screen_layout2 & SCREEN_ROUND_MASK, other.screen_layout2 & SCREEN_ROUND_MASK),
+            set_field_wins(self.color_mode & HDR_MASK, other.color_mode & HDR_MASK),
+            set_field_wins(self.color_mode & WIDE_COLOR_GAMUT_MASK, other.color_mode & WIDE_COLOR_GAMUT_MASK),
+            set_field_wins(self.orientation, other.orientation),
+            set_field_wins(self.ui_mode & UI_MODE_TYPE_MASK, other.ui_mode & UI_MODE_TYPE_MASK),
+            set_field_wins(self.ui_mode & UI_MODE_NIGHT_MASK, other.ui_mode & UI_MODE_NIGHT_MASK),
+            self.is_density_better(other, requested),
+            set_field_wins(self.touchscreen, other.touchscreen),
+            set_field_wins(self.input_flags & KEYS_HIDDEN_MASK, other.input_flags & KEYS_HIDDEN_MASK),
+            set_field_wins(self.keyboard, other.keyboard),
+            set_field_wins(self.input_flags & NAV_HIDDEN_MASK, other.input_flags & NAV_HIDDEN_MASK),
+            set_field_wins(self.navigation, other.navigation),
+            larger_wins(self.screen_width, other.screen_width),
+            larger_wins(self.screen_height, other.screen_height),
+            larger_wins(self.sdk_version, other.sdk_version),
+            set_field_wins(self.minor_version, other.minor_version)
+        ];
+        decisions.into_iter().flatten().next().unwrap_or(false)
+    }
+}
+
+impl Display for ResourceConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        if self.mcc != 0 {
+            parts.push(format!("mcc{}", self.mcc));
+        }
+        if self.mnc != 0 {
+            parts.push(if self.mnc == 0xffff { String::from("mnc00") } else { format!("mnc{}", self.mnc) });
+        }
+        if !self.language.is_empty() {
+            if self.locale_script.is_empty() && self.locale_variant.is_empty() {
+                parts.push(self.language.clone());
+                if !self.region.is_empty() {
+                    parts.push(format!("r{}", self.region));
+                }
+            } else {
+                let mut tag = format!("b+{}", self.language);
+                for subtag in [&self.locale_script, &self.region, &self.locale_variant] {
+                    if !subtag.is_empty() {
+                        tag.push('+');
+                        tag.push_str(subtag);
+                    }
+                }
+                parts.push(tag);
+            }
+        }
+        parts.extend(qualifier_name(&LAYOUT_DIRS, self.screen_layout & LAYOUT_DIR_MASK).map(String::from));
+        if self.smallest_screen_width_dp != 0 {
+            parts.push(format!("sw{}dp", self.smallest_screen_width_dp));
+        }
+        if self.screen_width_dp != 0 {
+            parts.push(format!("w{}dp", self.screen_width_dp));
+        }
+        if self.screen_height_dp != 0 {
+            parts.push(format!("h{}dp", self.screen_height_dp));
+        }
+        let named = [
+            qualifier_name(&SCREEN_SIZES, self.screen_layout & SCREEN_SIZE_MASK),
+            qualifier_name(&SCREEN_LONGS, self.screen_layout & SCREEN_LONG_MASK),
+            qualifier_name(&SCREEN_ROUNDS, self.screen_layout2 & SCREEN_ROUND_MASK),
+            qualifier_name(&WIDE_COLOR_GAMUTS, self.color_mode & WIDE_COLOR_GAMUT_MASK),
+            qualifier_name(&HDRS, self.color_mode & HDR_MASK),
+            qualifier_name(&ORIENTATIONS, self.orientation),
+            qualifier_name(&UI_MODE_TYPES, self.ui_mode & UI_MODE_TYPE_MASK),
+            qualifier_name(&UI_MODE_NIGHTS, self.ui_mode & UI_MODE_NIGHT_MASK)
+        ];
+        parts.extend(named.into_iter().flatten().map(String::from));
+        if self.density != 0 {
+            parts.push(match qualifier_name(&DENSITIES, self.density) {
+                Some(name) => String::from(name),
+                None => format!("{}dpi", self.density)
+            });
+        }
+        let named = [
+            qualifier_name(&TOUCHSCREENS, self.touchscreen),
+            qualifier_name(&KEYS_HIDDENS, self.input_flags & KEYS_HIDDEN_MASK),
+            qualifier_name(&KEYBOARDS, self.keyboard),
+            qualifier_name(&NAV_HIDDENS, self.input_flags & NAV_HIDDEN_MASK),
+            qualifier_name(&NAVIGATIONS, self.navigation)
+        ];
+        parts.extend(named.into_iter().flatten().map(String::from));
+        if self.screen_width != 0 || self.screen_height != 0 {
+            parts.push(format!("{}x{}", self.screen_width, self.screen_height));
+        }
+        if self.sdk_version != 0 {
+            parts.push(format!("v{}", self.sdk_version));
+        }
+        write!(f, "{}", parts.join("-"))
+    }
+}
@@ -0,0 +1,72 @@
+use crate::resources::{patch_chunk_size, read_chunk_header, ResourceError, ResourcePackage, ResourceTable, RES_TABLE_LIBRARY_TYPE};
+use crate::utils::{get_leu16_value, get_leu32_value, push_leu16, push_leu32};
+
+const LIBRARY_NAME_LEN: usize = 128; // u16 units
+const LIBRARY_ENTRY_SIZE: usize = 4 + LIBRARY_NAME_LEN * 2;
+
+// (build time package id, package name) pairs of a ResTable_lib chunk, None if it is malformed
+pub(crate) fn parse_library(chunk: &[u8]) -> Option<Vec<(u32, String)>> {
+    let (_, header_size, chunk_size) = read_chunk_header(chunk, 0).ok()?;
+    if header_size < 12 {
+        return None;
+    }
+    let count = get_leu32_value(chunk, 8) as usize;
+    if header_size + count * LIBRARY_ENTRY_SIZE > chunk_size {
+        return None;
+    }
+    Some((0..count).map(|i| {
+        let offset = header_size + i * LIBRARY_ENTRY_SIZE;
+        let units: Vec<u16> = (0..LIBRARY_NAME_LEN)
+            .map(|unit| get_leu16_value(chunk, offset + 4 + unit * 2))
+            .take_while(|unit| *unit != 0)
+            .collect();
+        (get_leu32_value(chunk, offset), String::from_utf16_lossy(&units))
+    }).collect())
+}
+
+pub(crate) fn write_library(data: &mut Vec<u8>, libraries: &[(u32, String)]) {
+    let start = data.len();
+    push_leu16(data, RES_TABLE_LIBRARY_TYPE);
+    push_leu16(data, 12);
+    push_leu32(data, 0);
+    push_leu32(data, libraries.len() as u32);
+    for (package_id, name) in libraries {
+        push_leu32(data, *package_id);
+        let name: Vec<u16> = name.encode_utf16().collect();
+        for i in 0..LIBRARY_NAME_LEN {
+            push_leu16(data, name.get(i).copied().unwrap_or(0));
+        }
+    }
+    patch_chunk_size(data, start);
+}
+
+impl ResourcePackage {
+    // the shared libraries this package references, with the package id their DynamicReference values
+    // carry. A shared library lists itself too, under id 0 or its own id
+    pub fn libraries(&self) -> &[(u32, String)] {
+        &self.libraries
+    }
+
+    pub fn library_name(&self, package_id: u32) -> Option<&str> {
+        self.libraries.iter().find(|(id, _)| *id == package_id).map(|(_, name)| name.as_str())
+    }
+}
+
+impl ResourceTable {
+    // maps the shared library `name` to `package_id` in the dynamic reference table of the app package (the
+    // first one), replacing its previous id
+    pub fn set_library(&mut self, package_id: u32, name: &str) -> Result<(), ResourceError> {
+        if package_id > 0xff || name.is_empty() || name.encode_utf16().count() >= LIBRARY_NAME_LEN {
+            return Err(ResourceError::new(format!("bad library 0x{:02x} {}", package_id, name)));
+        }
+        let package = self.packages.first_mut().ok_or_else(|| ResourceError::new(String::from("no package to add to")))?;
+        if package.libraries.iter().any(|(id, other)| *id == package_id && other != name) {
+            return Err(ResourceError::new(format!("package id 0x{:02x} already maps to another library", package_id)));
+        }
+        match package.libraries.iter_mut().find(|(_, other)| other == name) {
+            Some(library) => library.0 = package_id,
+            None => package.libraries.push((package_id, String::from(name)))
+        }
+        Ok(())
+    }
+}
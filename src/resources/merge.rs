@@ -0,0 +1,134 @@
+use crate::manifest::typed_value::TypedValue;
+use crate::resources::{BagItem, EntryValue, ResourceEntry, ResourceError, ResourcePackage, ResourceTable, StringPool};
+
+// the same value with string references moved from `from` into `to`. Spans of styled strings are not carried over
+fn move_strings(value: &EntryValue, from: &StringPool, to: &mut StringPool) -> EntryValue {
+    let mut move_data = |value_type: u32, data: u32| match (TypedValue::decode(value_type, data), from.get(data)) {
+        (TypedValue::StringRef(_), Some(text)) => to.add(text),
+        _ => data
+    };
+    match value {
+        EntryValue::Simple{ value_type, data } => EntryValue::Simple{
+            value_type: *value_type,
+            data: move_data(*value_type, *data)
+        },
+        EntryValue::Complex{ parent, items } => EntryValue::Complex{
+            parent: *parent,
+            items: items.iter().map(|item| BagItem{
+                name: item.name,
+                value_type: item.value_type,
+                data: move_data(item.value_type, item.data)
+            }).collect()
+        }
+    }
+}
+
+// the type ids of `other` have to come out the same here, the split's files and code refer to them
+fn check_type_ids(package: &ResourcePackage, other: &ResourcePackage) -> Result<(), ResourceError> {
+    let mut next_index = package.type_strings.len() as u32;
+    for other_type in &other.types {
+        let name = other.type_name(other_type.id)
+            .ok_or_else(|| ResourceError::new(format!("unnamed type 0x{:02x} in package 0x{:02x}", other_type.id, other.id)))?;
+        let index = package.type_strings.find(name).unwrap_or_else(|| {
+            next_index += 1;
+            next_index - 1
+        });
+        if index + 1 + package.type_id_offset != other_type.id as u32 {
+            return Err(ResourceError::new(format!("type {} has id 0x{:02x} in package 0x{:02x}, 0x{:02x} in the merged table",
+                name, index + 1 + package.type_id_offset, package.id, other_type.id)));
+        }
+    }
+    Ok(())
+}
+
+impl ResourceTable {
+    // folds `other`, e.g. the table of a config split, into this one: packages are matched by id, types by
+    // name and entries keep their ids. Where both tables define an entry for the same configuration the
+    // value already here is kept, the ids for which the two differed are returned
+    pub fn merge(&mut self, other: &ResourceTable) -> Result<Vec<u32>, ResourceError> {
+        for other_package in &other.packages {
+            if let Some(package) = self.get_package(other_package.id) {
+                check_type_ids(package, other_package)?;
+            }
+        }
+
+        let mut conflicts: Vec<u32> = Vec::new();
+        for other_package in &other.packages {
+            let package_index = match self.packages.iter().position(|package| package.id == other_package.id) {
+                Some(index) => index,
+                None => {
+                    self.packages.push(ResourcePackage{
+                        id: other_package.id,
+                        name: other_package.name.clone(),
+                        type_strings: other_package.type_strings.clone(),
+                        key_strings: other_package.key_strings.clone(),
+                        last_public_type: other_package.last_public_type,
+                        last_public_key: other_package.last_public_key,
+                        type_id_offset: other_package.type_id_offset,
+                        types: vec![],
+                        libraries: vec![],
+                        unknown_chunks: vec![],
+                        layout: vec![]
+                    });
+                    self.packages.len() - 1
+                }
+            };
+            let package = &mut self.packages[package_index];
+            for other_type in &other_package.types {
+                let type_name = other_package.type_name(other_type.id).unwrap_or_default();
+                for other_config in &other_type.configs {
+                    let entries: Vec<(usize, ResourceEntry)> = other_config.entries.iter().enumerate()
+                        .filter_map(|(entry_id, entry)| Some((entry_id, entry.as_ref()?)))
+                        .map(|(entry_id, entry)| (entry_id, ResourceEntry{
+                            flags: entry.flags,
+                            key: package.key_strings.add(other_package.key_name(entry.key).unwrap_or_default()),
+                            value: move_strings(&entry.value, &other.string_pool, &mut self.string_pool)
+                        }))
+                        .collect();
+                    let resource_type = package.get_or_create_named_type(type_name);
+                    if resource_type.spec_flags.len() < other_type.spec_flags.len() {
+                        resource_type.spec_flags.resize(other_type.spec_flags.len(), 0);
+                        let entry_count = resource_type.spec_flags.len();
+                        for config in &mut resource_type.configs {
+                            config.entries.resize(entry_count, None);
+                        }
+                    }
+                    let type_id = resource_type.id as u32;
+                    let config = resource_type.get_or_create_config(&other_config.config());
+                    for (entry_id, entry) in entries {
+                        match &config.entries[entry_id] {
+                            None => config.entries[entry_id] = Some(entry),
+                            Some(existing) if *existing != entry => {
+                                let id = (other_package.id << 24) | (type_id << 16) | entry_id as u32;
+                                if !conflicts.contains(&id) {
+                                    conflicts.push(id);
+                                }
+                            },
+                            _ => {}
+                        }
+                    }
+                }
+                let resource_type = package.get_or_create_named_type(type_name);
+                for (flags, other_flags) in resource_type.spec_flags.iter_mut().zip(other_type.spec_flags.iter()) {
+                    *flags |= other_flags;
+                }
+            }
+            for library in &other_package.libraries {
+                if !package.libraries.contains(library) {
+                    package.libraries.push(library.clone());
+                }
+            }
+            for chunk in &other_package.unknown_chunks {
+                if !package.unknown_chunks.contains(chunk) {
+                    package.unknown_chunks.push(chunk.clone());
+                }
+            }
+        }
+        for chunk in &other.unknown_chunks {
+            if !self.unknown_chunks.contains(chunk) {
+                self.unknown_chunks.push(chunk.clone());
+            }
+        }
+        Ok(conflicts)
+    }
+}
@@ -0,0 +1,85 @@
+mod library;
+mod merge;
+mod names;
+#[cfg(feature = "zip")] // deflate for the png IDAT, which only the zip feature brings
+pub mod nine_patch;
+mod obfuscate;
+pub mod overlayable;
+pub(crate) mod remap;
+pub mod config;
+pub mod shrink;
+mod staged_alias;
+mod strip;
+pub mod string_pool;
+pub mod table;
+pub mod usage;
+mod validate;
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::utils::{get_leu16_value, get_leu32_value};
+
+pub use config::ResourceConfig;
+pub use overlayable::{Overlayable, OverlayablePolicy};
+pub use shrink::ShrinkReport;
+pub use string_pool::StringPool;
+pub use table::{BagItem, EntryValue, ResourceEntry, ResourcePackage, ResourceTable, ResourceType, ResourceTypeConfig};
+pub use usage::UsageReport;
+
+// ResChunk_header types used by the resource table
+pub(crate) const RES_STRING_POOL_TYPE: u16 = 0x0001;
+pub(crate) const RES_TABLE_TYPE: u16 = 0x0002;
+pub(crate) const RES_TABLE_PACKAGE_TYPE: u16 = 0x0200;
+pub(crate) const RES_TABLE_TYPE_TYPE: u16 = 0x0201;
+pub(crate) const RES_TABLE_TYPE_SPEC_TYPE: u16 = 0x0202;
+pub(crate) const RES_TABLE_LIBRARY_TYPE: u16 = 0x0203;
+pub(crate) const RES_TABLE_OVERLAYABLE_TYPE: u16 = 0x0204;
+pub(crate) const RES_TABLE_OVERLAYABLE_POLICY_TYPE: u16 = 0x0205;
+pub(crate) const RES_TABLE_STAGED_ALIAS_TYPE: u16 = 0x0206;
+
+#[derive(Debug)]
+pub struct ResourceError {
+    reason: String
+}
+
+impl ResourceError {
+    pub(crate) fn new(reason: String) -> ResourceError {
+        ResourceError{
+            reason
+        }
+    }
+
+    pub(crate) fn format(offset: usize, reason: &str) -> ResourceError {
+        ResourceError{
+            reason: format!("{} at offset {}", reason, offset)
+        }
+    }
+}
+
+impl Display for ResourceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "resource table error: {}", self.reason)
+    }
+}
+
+impl Error for ResourceError {}
+
+// type, header size and total size of the chunk at `offset`, checked against the buffer
+pub(crate) fn read_chunk_header(data: &[u8], offset: usize) -> Result<(u16, usize, usize), ResourceError> {
+    if offset + 8 > data.len() {
+        return Err(ResourceError::format(offset, "truncated chunk header"));
+    }
+    let chunk_type = get_leu16_value(data, offset);
+    let header_size = get_leu16_value(data, offset + 2) as usize;
+    let chunk_size = get_leu32_value(data, offset + 4) as usize;
+    if header_size < 8 || chunk_size < header_size || offset + chunk_size > data.len() {
+        return Err(ResourceError::format(offset, "bad chunk size"));
+    }
+    Ok((chunk_type, header_size, chunk_size))
+}
+
+// fill in the size field of the chunk starting at `start` once everything after it has been written
+pub(crate) fn patch_chunk_size(data: &mut [u8], start: usize) {
+    let size = (data.len() - start) as u32;
+    data[start + 4..start + 8].copy_from_slice(&size.to_le_bytes());
+}
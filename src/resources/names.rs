@@ -0,0 +1,131 @@
+use crate::manifest::typed_value::TypedValue;
+use crate::resources::{ResourceConfig, ResourceEntry, ResourcePackage, ResourceTable};
+
+// 0xPPTTEEEE
+fn split_id(id: u32) -> (u32, u8, u16) {
+    (id >> 24, ((id >> 16) & 0xff) as u8, (id & 0xffff) as u16)
+}
+
+impl ResourcePackage {
+    // key of the entry in whichever configuration defines it first
+    fn entry_key(&self, type_id: u8, entry_id: u16) -> Option<u32> {
+        self.get_type(type_id)?
+            .configs()
+            .iter()
+            .find_map(|config| config.entry(entry_id))
+            .map(|entry| entry.key())
+    }
+
+    fn find_entry_id(&self, type_id: u8, name: &str) -> Option<u16> {
+        let key = self.key_strings.find(name)?;
+        self.get_type(type_id)?
+            .configs()
+            .iter()
+            .find_map(|config| config.entries().iter().position(|entry| entry.as_ref().is_some_and(|entry| entry.key() == key)))
+            .map(|idx| idx as u16)
+    }
+
+    fn find_type_id(&self, name: &str) -> Option<u8> {
+        self.types.iter().map(|item| item.id()).find(|id| self.type_name(*id) == Some(name))
+    }
+}
+
+impl ResourceTable {
+    // 0x7f0a0012 -> "string/app_name"
+    pub fn resource_name(&self, id: u32) -> Option<String> {
+        let (package_id, type_id, entry_id) = split_id(id);
+        let package = self.get_package(package_id)?;
+        let key = package.entry_key(type_id, entry_id)?;
+        Some(format!("{}/{}", package.type_name(type_id)?, package.key_name(key)?))
+    }
+
+    // "string/app_name", "@string/app_name" or "@android:string/ok" -> id, packages other than the
+    // first one have to be named
+    pub fn resource_id(&self, name: &str) -> Option<u32> {
+        let name = name.strip_prefix(['@', '?']).unwrap_or(name);
+        let (package, name) = match name.split_once(':') {
+            Some((package_name, name)) => (self.packages.iter().find(|package| package.name() == package_name)?, name),
+            None => (self.packages.first()?, name)
+        };
+        let (type_name, entry_name) = name.split_once('/')?;
+        let type_id = package.find_type_id(type_name)?;
+        let entry_id = package.find_entry_id(type_id, entry_name)?;
+        Some((package.id() << 24) | ((type_id as u32) << 16) | entry_id as u32)
+    }
+
+    // value of `id` on a device described by `config`, picked like the framework does: the best of the
+    // configurations that match
+    pub fn resolve(&self, id: u32, config: &ResourceConfig) -> Option<&ResourceEntry> {
+        let (package_id, type_id, entry_id) = split_id(id);
+        let mut best: Option<(ResourceConfig, &ResourceEntry)> = None;
+        for item in self.get_package(package_id)?.get_type(type_id)?.configs() {
+            let entry = match item.entry(entry_id) {
+                Some(entry) => entry,
+                None => continue
+            };
+            let candidate = item.config();
+            if !candidate.matches(config) {
+                continue;
+            }
+            if best.as_ref().is_none_or(|(best_config, _)| candidate.is_better_than(best_config, config)) {
+                best = Some((candidate, entry));
+            }
+        }
+        best.map(|(_, entry)| entry)
+    }
+
+    // every configuration `id` has a value in
+    pub fn variants(&self, id: u32) -> Vec<(ResourceConfig, &ResourceEntry)> {
+        let (package_id, type_id, entry_id) = split_id(id);
+        let resource_type = match self.get_package(package_id).and_then(|package| package.get_type(type_id)) {
+            Some(resource_type) => resource_type,
+            None => return vec![]
+        };
+        resource_type.configs()
+            .iter()
+            .filter_map(|item| Some((item.config(), item.entry(entry_id)?)))
+            .collect()
+    }
+
+    // value of `id` for `config` with references, and staged ids, followed to the end of the chain
+    // (@null stays Reference(0)), None for bags, missing entries and reference loops
+    pub fn resolve_value(&self, id: u32, config: &ResourceConfig) -> Option<TypedValue> {
+        let id = self.finalized_id(id).unwrap_or(id);
+        let mut visited: Vec<u32> = vec![id];
+        let mut value = self.resolve(id, config)?.typed_value()?;
+        while let TypedValue::Reference(next @ 1..) = value {
+            let next = self.finalized_id(next).unwrap_or(next);
+            if visited.contains(&next) {
+                return None;
+            }
+            visited.push(next);
+            value = self.resolve(next, config)?.typed_value()?;
+        }
+        Some(value)
+    }
+
+    // the global pool string behind a StringRef value
+    pub fn string_value(&self, value: &TypedValue) -> Option<&str> {
+        match value {
+            TypedValue::StringRef(index) => self.string_pool.get(*index),
+            _ => None
+        }
+    }
+
+    // like the TypedValue Display, but with references spelled out by name: "@string/app_name"
+    pub fn format_value(&self, value: &TypedValue) -> String {
+        let (prefix, id) = match value {
+            TypedValue::Reference(id) => ('@', *id),
+            TypedValue::Attribute(id) => ('?', *id),
+            _ => return value.to_string()
+        };
+        let name = match self.resource_name(id) {
+            Some(name) => name,
+            None => return value.to_string()
+        };
+        match self.packages.first() {
+            Some(package) if package.id() == id >> 24 => format!("{}{}", prefix, name),
+            _ => format!("{}{}:{}", prefix, self.get_package(id >> 24).map(|package| package.name()).unwrap_or_default(), name)
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::io::{Read, Write};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use crate::resources::ResourceError;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+const NINE_PATCH_CHUNK: [u8; 4] = *b"npTc";
+const NINE_PATCH_HEADER_SIZE: usize = 32; // Res_png_9patch without the div and color arrays
+const NO_COLOR: u32 = 0x00000001; // region is not one solid color, always safe to claim
+// decoded RGBA bytes of the largest source decode takes, far beyond any real 9-patch
+const MAX_DECODED_SIZE: usize = 64 * 1024 * 1024;
+
+// the 1px frame of a source 9-patch
+const TICK: [u8; 4] = [0, 0, 0, 0xff];
+const LAYOUT_BOUNDS_TICK: [u8; 4] = [0xff, 0, 0, 0xff]; // optical bounds, not stretch or padding
+const OPAQUE_WHITE: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8> // RGBA, row by row
+}
+
+impl Image {
+    fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let offset = (y * self.width + x) * 4;
+        [self.pixels[offset], self.pixels[offset + 1], self.pixels[offset + 2], self.pixels[offset + 3]]
+    }
+}
+
+// (type, body) of every chunk, None when the data isn't a well formed png
+fn png_chunks(data: &[u8]) -> Option<Vec<([u8; 4], &[u8])>> {
+    if !data.starts_with(&PNG_SIGNATURE) {
+        return None;
+    }
+    let mut res: Vec<([u8; 4], &[u8])> = Vec::new();
+    let mut offset = PNG_SIGNATURE.len();
+    while offset < data.len() {
+        let len = u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        let kind: [u8; 4] = data.get(offset + 4..offset + 8)?.try_into().ok()?;
+        let body_end = (offset + 8).checked_add(len)?;
+        res.push((kind, data.get(offset + 8..body_end)?));
+        offset = body_end.checked_add(4)?; // the crc after the body
+        if &kind == b"IEND" {
+            break;
+        }
+    }
+    Some(res)
+}
+
+fn push_chunk(data: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    data.extend_from_slice(kind);
+    data.extend_from_slice(body);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(body);
+    data.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// 8 bit non interlaced pngs of any color type, which is what 9-patch sources are in practice
+fn decode(png: &[u8]) -> Result<Image, ResourceError> {
+    let bad_png = |reason: &str| ResourceError::new(format!("9-patch: {}", reason));
+    let chunks = png_chunks(png).ok_or_else(|| bad_png("not a png"))?;
+    let header = chunks.iter().find(|(kind, _)| kind == b"IHDR").map(|(_, body)| *body)
+        .filter(|body| body.len() >= 13)
+        .ok_or_else(|| bad_png("missing IHDR"))?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let (depth, color_type, interlace) = (header[8], header[9], header[12]);
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(bad_png("unknown color type"))
+    };
+    if depth != 8 || interlace != 0 {
+        return Err(bad_png("only 8 bit non interlaced pngs are supported"));
+    }
+    let palette = chunks.iter().find(|(kind, _)| kind == b"PLTE").map(|(_, body)| *body).unwrap_or_default();
+    let transparency = chunks.iter().find(|(kind, _)| kind == b"tRNS").map(|(_, body)| *body).unwrap_or_default();
+
+    // a filter byte before every row
+    let stride = width.checked_mul(channels);
+    let raw_size = stride.and_then(|stride| (stride + 1).checked_mul(height));
+    let decoded_size = width.checked_mul(height).and_then(|size| size.checked_mul(4));
+    let (stride, raw_size, decoded_size) = match (stride, raw_size, decoded_size) {
+        (Some(stride), Some(raw_size), Some(decoded_size)) if decoded_size <= MAX_DECODED_SIZE => (stride, raw_size, decoded_size),
+        _ => return Err(bad_png(&format!("{}x{} is too large", width, height)))
+    };
+
+    let compressed: Vec<u8> = chunks.iter().filter(|(kind, _)| kind == b"IDAT").flat_map(|(_, body)| body.iter().copied()).collect();
+    let mut raw: Vec<u8> = Vec::new();
+    ZlibDecoder::new(compressed.as_slice()).take(raw_size as u64).read_to_end(&mut raw).map_err(|_| bad_png("corrupt image data"))?;
+    if raw.len() < raw_size {
+        return Err(bad_png("truncated image data"));
+    }
+
+    let mut pixels: Vec<u8> = Vec::with_capacity(decoded_size);
+    let mut previous: Vec<u8> = vec![0; stride];
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let mut row = raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)].to_vec();
+        for x in 0..stride {
+            let a = if x >= channels { row[x - channels] } else { 0 };
+            let (b, c) = (previous[x], if x >= channels { previous[x - channels] } else { 0 });
+            row[x] = row[x].wrapping_add(match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(bad_png("unknown row filter"))
+            });
+        }
+        for pixel in row.chunks_exact(channels) {
+            match color_type {
+                0 => {
+                    let alpha = if transparency.len() >= 2 && transparency[1] == pixel[0] { 0 } else { 0xff };
+                    pixels.extend_from_slice(&[pixel[0], pixel[0], pixel[0], alpha]);
+                },
+                2 => pixels.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 0xff]),
+                3 => {
+                    let index = pixel[0] as usize;
+                    let color = palette.get(index * 3..index * 3 + 3).ok_or_else(|| bad_png("palette index out of range"))?;
+                    pixels.extend_from_slice(color);
+                    pixels.push(transparency.get(index).copied().unwrap_or(0xff));
+                },
+                4 => pixels.extend_from_slice(&[pixel[0], pixel[0], pixel[0], pixel[1]]),
+                _ => pixels.extend_from_slice(pixel)
+            }
+        }
+        previous = row;
+    }
+    Ok(Image{
+        width,
+        height,
+        pixels
+    })
+}
+
+// an RGBA png holding `image`, with `nine_patch` as npTc ahead of the image data like aapt does
+fn encode(image: &Image, nine_patch: &[u8]) -> Vec<u8> {
+    let mut header: Vec<u8> = Vec::with_capacity(13);
+    header.extend_from_slice(&(image.width as u32).to_be_bytes());
+    header.extend_from_slice(&(image.height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    for row in image.pixels.chunks_exact(image.width * 4) {
+        // writing into a Vec can't fail
+        let _ = encoder.write_all(&[0]);
+        let _ = encoder.write_all(row);
+    }
+    let compressed = encoder.finish().unwrap_or_default();
+
+    let mut res: Vec<u8> = PNG_SIGNATURE.to_vec();
+    push_chunk(&mut res, b"IHDR", &header);
+    push_chunk(&mut res, &NINE_PATCH_CHUNK, nine_patch);
+    push_chunk(&mut res, b"IDAT", &compressed);
+    push_chunk(&mut res, b"IEND", &[]);
+    res
+}
+
+// [start, end) runs of ticks along one edge of the frame
+fn tick_regions<I: Iterator<Item = [u8; 4]>>(edge: &str, pixels: I) -> Result<Vec<(u32, u32)>, ResourceError> {
+    let mut res: Vec<(u32, u32)> = Vec::new();
+    let mut start: Option<u32> = None;
+    let mut len = 0;
+    for (i, pixel) in pixels.enumerate() {
+        let is_tick = match pixel {
+            TICK => true,
+            LAYOUT_BOUNDS_TICK | OPAQUE_WHITE => false,
+            [_, _, _, 0] => false,
+            _ => return Err(ResourceError::new(format!("9-patch: pixel {} of the {} edge is neither a black tick nor blank", i, edge)))
+        };
+        match (is_tick, start) {
+            (true, None) => start = Some(i as u32),
+            (false, Some(region_start)) => {
+                res.push((region_start, i as u32));
+                start = None;
+            },
+            _ => {}
+        }
+        len = i as u32 + 1;
+    }
+    if let Some(region_start) = start {
+        res.push((region_start, len));
+    }
+    Ok(res)
+}
+
+// segments the divs cut `size` into, edges that start or end on a div don't add an empty one
+fn region_count(divs: &[u32], size: u32) -> usize {
+    let mut res = divs.len() + 1;
+    if divs.first() == Some(&0) {
+        res -= 1;
+    }
+    if divs.last() == Some(&size) {
+        res -= 1;
+    }
+    res
+}
+
+// true for a png already run through aapt, carrying the serialized 9-patch in an npTc chunk
+pub fn is_compiled(png: &[u8]) -> bool {
+    png_chunks(png).is_some_and(|chunks| chunks.iter().any(|(kind, _)| *kind == NINE_PATCH_CHUNK))
+}
+
+// checks the npTc chunk of a compiled 9-patch against the image it comes with
+pub fn validate(png: &[u8]) -> Result<(), ResourceError> {
+    let bad_chunk = |reason: &str| ResourceError::new(format!("9-patch: {}", reason));
+    let chunks = png_chunks(png).ok_or_else(|| bad_chunk("not a png"))?;
+    let header = chunks.iter().find(|(kind, _)| kind == b"IHDR").map(|(_, body)| *body)
+        .filter(|body| body.len() >= 13)
+        .ok_or_else(|| bad_chunk("missing IHDR"))?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let chunk = chunks.iter().find(|(kind, _)| *kind == NINE_PATCH_CHUNK).map(|(_, body)| *body)
+        .ok_or_else(|| bad_chunk("no npTc chunk"))?;
+    if chunk.len() < NINE_PATCH_HEADER_SIZE {
+        return Err(bad_chunk("npTc chunk too short"));
+    }
+    let (x_count, y_count, color_count) = (chunk[1] as usize, chunk[2] as usize, chunk[3] as usize);
+    if chunk.len() != NINE_PATCH_HEADER_SIZE + (x_count + y_count + color_count) * 4 {
+        return Err(bad_chunk("npTc size doesn't match its div and color counts"));
+    }
+    let read = |index: usize| {
+        let offset = NINE_PATCH_HEADER_SIZE + index * 4;
+        u32::from_be_bytes([chunk[offset], chunk[offset + 1], chunk[offset + 2], chunk[offset + 3]])
+    };
+    let x_divs: Vec<u32> = (0..x_count).map(read).collect();
+    let y_divs: Vec<u32> = (0..y_count).map(|i| read(x_count + i)).collect();
+    for (divs, size, axis) in [(&x_divs, width, "x"), (&y_divs, height, "y")] {
+        if !divs.len().is_multiple_of(2) || divs.windows(2).any(|pair| pair[0] > pair[1]) || divs.last().is_some_and(|div| *div > size) {
+            return Err(bad_chunk(&format!("{} divs out of order or outside the image", axis)));
+        }
+    }
+    Ok(())
+}
+
+// turns a source 9-patch, content framed by a 1px border of black ticks, into what aapt ships: the
+// border stripped and the stretch regions and padding stored in an npTc chunk
+pub fn compile(png: &[u8]) -> Result<Vec<u8>, ResourceError> {
+    let source = decode(png)?;
+    if source.width < 3 || source.height < 3 {
+        return Err(ResourceError::new(String::from("9-patch: image too small for a frame")));
+    }
+    let (width, height) = (source.width - 2, source.height - 2);
+    let top = tick_regions("top", (1..=width).map(|x| source.pixel(x, 0)))?;
+    let left = tick_regions("left", (1..=height).map(|y| source.pixel(0, y)))?;
+    let bottom = tick_regions("bottom", (1..=width).map(|x| source.pixel(x, source.height - 1)))?;
+    let right = tick_regions("right", (1..=height).map(|y| source.pixel(source.width - 1, y)))?;
+    if top.is_empty() || left.is_empty() {
+        return Err(ResourceError::new(String::from("9-patch: no stretch region marked on the top or left edge")));
+    }
+    if bottom.len() > 1 || right.len() > 1 {
+        return Err(ResourceError::new(String::from("9-patch: more than one padding region on the bottom or right edge")));
+    }
+    // without padding ticks the content area is the first stretch region
+    let (padding_left, padding_right) = bottom.first().or(top.first())
+        .map(|(start, end)| (*start, width as u32 - end))
+        .unwrap_or_default();
+    let (padding_top, padding_bottom) = right.first().or(left.first())
+        .map(|(start, end)| (*start, height as u32 - end))
+        .unwrap_or_default();
+    let x_divs: Vec<u32> = top.iter().flat_map(|(start, end)| [*start, *end]).collect();
+    let y_divs: Vec<u32> = left.iter().flat_map(|(start, end)| [*start, *end]).collect();
+    let color_count = region_count(&x_divs, width as u32) * region_count(&y_divs, height as u32);
+    if x_divs.len() > u8::MAX as usize || y_divs.len() > u8::MAX as usize || color_count > u8::MAX as usize {
+        return Err(ResourceError::new(String::from("9-patch: too many stretch regions")));
+    }
+
+    // Res_png_9patch, big endian; the array offsets are left 0 and recomputed by the loader
+    let mut chunk: Vec<u8> = vec![0, x_divs.len() as u8, y_divs.len() as u8, color_count as u8];
+    chunk.extend_from_slice(&[0; 8]);
+    for padding in [padding_left, padding_right, padding_top, padding_bottom] {
+        chunk.extend_from_slice(&padding.to_be_bytes());
+    }
+    chunk.extend_from_slice(&[0; 4]);
+    for div in x_divs.iter().chain(y_divs.iter()) {
+        chunk.extend_from_slice(&div.to_be_bytes());
+    }
+    for _ in 0..color_count {
+        chunk.extend_from_slice(&NO_COLOR.to_be_bytes());
+    }
+
+    let mut pixels: Vec<u8> = Vec::with_capacity(width * height * 4);
+    for y in 1..=height {
+        let offset = (y * source.width + 1) * 4;
+        pixels.extend_from_slice(&source.pixels[offset..offset + width * 4]);
+    }
+    Ok(encode(&Image{ width, height, pixels }, &chunk))
+}
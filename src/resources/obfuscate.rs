@@ -0,0 +1,65 @@
+use std::collections::{BTreeSet, HashMap};
+use crate::manifest::typed_value::TypedValue;
+use crate::resources::ResourceTable;
+
+// a, b, ... z, aa, ab ...
+fn short_name(mut index: usize) -> String {
+    let mut res: Vec<u8> = Vec::new();
+    loop {
+        res.insert(0, b'a' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    String::from_utf8(res).unwrap_or_default()
+}
+
+impl ResourceTable {
+    // global pool indices of the strings entries point at
+    fn referenced_strings(&self) -> BTreeSet<u32> {
+        let mut res: BTreeSet<u32> = BTreeSet::new();
+        let entries = self.packages.iter()
+            .flat_map(|package| package.types.iter())
+            .flat_map(|resource_type| resource_type.configs.iter())
+            .flat_map(|config| config.entries.iter().flatten());
+        for entry in entries {
+            if let Some(TypedValue::StringRef(index)) = entry.typed_value() {
+                res.insert(index);
+            }
+        }
+        res
+    }
+
+    // AndResGuard style: every res/<dir>/<name>.<ext> file path becomes r/<a>/<b>.<ext>, one short
+    // directory per original directory, skipping the paths `is_taken` says are in use already. Returns
+    // (old, new) so the zip entries can follow
+    pub fn obfuscate_paths<F: Fn(&str) -> bool>(&mut self, is_taken: F) -> Vec<(String, String)> {
+        let mut dirs: HashMap<String, (String, usize)> = HashMap::new();
+        let mut res: Vec<(String, String)> = Vec::new();
+        for index in self.referenced_strings() {
+            let path = match self.string_pool.get(index) {
+                Some(path) if path.starts_with("res/") => path.to_string(),
+                _ => continue
+            };
+            let (dir, file_name) = match path.rsplit_once('/') {
+                Some(item) => item,
+                None => continue
+            };
+            let dir_count = dirs.len();
+            let (short_dir, file_count) = dirs.entry(dir.to_string()).or_insert_with(|| (short_name(dir_count), 0));
+            // keep the whole extension, ".9.png" included
+            let ext = file_name.split_once('.').map(|(_, ext)| format!(".{}", ext)).unwrap_or_default();
+            let new_path = loop {
+                let new_path = format!("r/{}/{}{}", short_dir, short_name(*file_count), ext);
+                *file_count += 1;
+                if !is_taken(&new_path) {
+                    break new_path;
+                }
+            };
+            self.string_pool.set(index, new_path.as_str());
+            res.push((path, new_path));
+        }
+        res
+    }
+}
@@ -0,0 +1,91 @@
+use crate::resources::{read_chunk_header, ResourcePackage, ResourceTable};
+use crate::resources::{RES_TABLE_OVERLAYABLE_POLICY_TYPE, RES_TABLE_OVERLAYABLE_TYPE};
+use crate::utils::{get_leu16_value, get_leu32_value};
+
+const OVERLAYABLE_NAME_LEN: usize = 256; // u16 units, for both the name and the actor
+
+// ResTable_overlayable_policy_header flags, which overlays may replace the resources
+pub const POLICY_PUBLIC: u32 = 0x00000001;
+pub const POLICY_SYSTEM_PARTITION: u32 = 0x00000002;
+pub const POLICY_VENDOR_PARTITION: u32 = 0x00000004;
+pub const POLICY_PRODUCT_PARTITION: u32 = 0x00000008;
+pub const POLICY_SIGNATURE: u32 = 0x00000010;
+pub const POLICY_ODM_PARTITION: u32 = 0x00000020;
+pub const POLICY_OEM_PARTITION: u32 = 0x00000040;
+pub const POLICY_ACTOR_SIGNATURE: u32 = 0x00000080;
+pub const POLICY_CONFIG_SIGNATURE: u32 = 0x00000100;
+
+// an <overlayable> block: resources runtime resource overlays may target, grouped by policy
+#[derive(Clone, Debug, PartialEq)]
+pub struct Overlayable {
+    pub name: String,
+    pub actor: String,
+    pub policies: Vec<OverlayablePolicy>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OverlayablePolicy {
+    pub flags: u32,
+    pub ids: Vec<u32>
+}
+
+fn read_utf16(chunk: &[u8], offset: usize) -> String {
+    let units: Vec<u16> = (0..OVERLAYABLE_NAME_LEN)
+        .map(|i| get_leu16_value(chunk, offset + i * 2))
+        .take_while(|unit| *unit != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+// None when the chunk is not a well formed overlayable chunk
+fn parse_overlayable(chunk: &[u8]) -> Option<Overlayable> {
+    let (chunk_type, header_size, chunk_size) = read_chunk_header(chunk, 0).ok()?;
+    if chunk_type != RES_TABLE_OVERLAYABLE_TYPE || header_size < 8 + OVERLAYABLE_NAME_LEN * 4 {
+        return None;
+    }
+    let mut policies: Vec<OverlayablePolicy> = Vec::new();
+    let mut offset = header_size;
+    while offset < chunk_size {
+        let (child_type, child_header_size, child_size) = read_chunk_header(chunk, offset).ok()?;
+        if child_type == RES_TABLE_OVERLAYABLE_POLICY_TYPE && child_header_size >= 16 {
+            let count = get_leu32_value(chunk, offset + 12) as usize;
+            if child_header_size + count * 4 > child_size {
+                return None;
+            }
+            policies.push(OverlayablePolicy{
+                flags: get_leu32_value(chunk, offset + 8),
+                ids: (0..count).map(|i| get_leu32_value(chunk, offset + child_header_size + i * 4)).collect()
+            });
+        }
+        offset += child_size;
+    }
+    Some(Overlayable{
+        name: read_utf16(chunk, 8),
+        actor: read_utf16(chunk, 8 + OVERLAYABLE_NAME_LEN * 2),
+        policies
+    })
+}
+
+impl ResourcePackage {
+    // decoded from the chunks kept as is, so rewriting the table leaves them untouched
+    pub fn overlayables(&self) -> Vec<Overlayable> {
+        self.unknown_chunks.iter().filter_map(|chunk| parse_overlayable(chunk)).collect()
+    }
+}
+
+impl ResourceTable {
+    // policy flags of every overlayable block listing `id`, 0 when overlays can't replace it
+    pub fn overlayable_policy(&self, id: u32) -> u32 {
+        self.get_package(id >> 24)
+            .map(|package| package.overlayables())
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|overlayable| overlayable.policies.iter())
+            .filter(|policy| policy.ids.contains(&id))
+            .fold(0, |flags, policy| flags | policy.flags)
+    }
+
+    pub fn is_overlayable(&self, id: u32) -> bool {
+        self.overlayable_policy(id) != 0
+    }
+}
@@ -0,0 +1,110 @@
+use crate::manifest::typed_value::TypedValue;
+use crate::resources::{read_chunk_header, BagItem, EntryValue, ResourceError, ResourceTable};
+use crate::resources::{RES_TABLE_OVERLAYABLE_POLICY_TYPE, RES_TABLE_OVERLAYABLE_TYPE, RES_TABLE_STAGED_ALIAS_TYPE};
+use crate::utils::get_leu32_value;
+
+pub(crate) fn remap_id(id: u32, from: u32, to: u32) -> u32 {
+    if id >> 24 == from && id != 0 {
+        (to << 24) | (id & 0x00ffffff)
+    } else {
+        id
+    }
+}
+
+// `value` with references into package `from` pointed at package `to`, anything else unchanged
+pub(crate) fn remap_value(value: TypedValue, from: u32, to: u32) -> TypedValue {
+    match value {
+        TypedValue::Reference(id) => TypedValue::Reference(remap_id(id, from, to)),
+        TypedValue::Attribute(id) => TypedValue::Attribute(remap_id(id, from, to)),
+        TypedValue::DynamicReference(id) => TypedValue::DynamicReference(remap_id(id, from, to)),
+        TypedValue::DynamicAttribute(id) => TypedValue::DynamicAttribute(remap_id(id, from, to)),
+        _ => value
+    }
+}
+
+fn remap_word(data: &mut [u8], offset: usize, from: u32, to: u32) {
+    let id = remap_id(get_leu32_value(&*data, offset), from, to);
+    data[offset..offset + 4].copy_from_slice(&id.to_le_bytes());
+}
+
+// the ids listed by overlayable policies and staged aliases, rewritten in place
+fn remap_chunk(chunk: &mut [u8], from: u32, to: u32) {
+    match read_chunk_header(chunk, 0) {
+        Ok((RES_TABLE_OVERLAYABLE_TYPE, header_size, chunk_size)) => {
+            let mut offset = header_size;
+            while let Ok((child_type, child_header_size, child_size)) = read_chunk_header(chunk, offset) {
+                if child_type == RES_TABLE_OVERLAYABLE_POLICY_TYPE && child_header_size >= 16 {
+                    let count = (get_leu32_value(&*chunk, offset + 12) as usize).min((child_size - child_header_size) / 4);
+                    for i in 0..count {
+                        remap_word(chunk, offset + child_header_size + i * 4, from, to);
+                    }
+                }
+                offset += child_size;
+                if offset >= chunk_size {
+                    break;
+                }
+            }
+        },
+        Ok((RES_TABLE_STAGED_ALIAS_TYPE, header_size, chunk_size)) if header_size >= 12 => {
+            let count = (get_leu32_value(&*chunk, 8) as usize).min((chunk_size - header_size) / 8);
+            for i in 0..count * 2 {
+                remap_word(chunk, header_size + i * 4, from, to);
+            }
+        },
+        _ => {}
+    }
+}
+
+impl ResourceTable {
+    // moves package `from` to id `to` (0x7f -> 0x7e...) and points every reference, bag attribute,
+    // overlayable and staged alias id at the new package. Compiled xml files are not part of the table,
+    // see ApkFile::remap_package_id
+    pub fn remap_package_id(&mut self, from: u32, to: u32) -> Result<(), ResourceError> {
+        if !(0x02..=0xff).contains(&to) {
+            return Err(ResourceError::new(format!("bad package id 0x{:02x}", to)));
+        }
+        if self.get_package(from).is_none() {
+            return Err(ResourceError::new(format!("no package 0x{:02x}", from)));
+        }
+        if from != to && self.get_package(to).is_some() {
+            return Err(ResourceError::new(format!("package id 0x{:02x} is already taken", to)));
+        }
+        // only ids are rewritten, decoding and encoding other data could change it (a dimension's radix)
+        let remap = |value_type: u32, data: u32| match remap_value(TypedValue::decode(value_type, data), from, to) {
+            TypedValue::Reference(id) | TypedValue::Attribute(id) | TypedValue::DynamicReference(id) | TypedValue::DynamicAttribute(id) => id,
+            _ => data
+        };
+        for package in &mut self.packages {
+            if package.id == from {
+                package.id = to;
+            }
+            for entry in package.types.iter_mut()
+                .flat_map(|resource_type| resource_type.configs.iter_mut())
+                .flat_map(|config| config.entries.iter_mut().flatten()) {
+                entry.value = match &entry.value {
+                    EntryValue::Simple{ value_type, data } => EntryValue::Simple{
+                        value_type: *value_type,
+                        data: remap(*value_type, *data)
+                    },
+                    EntryValue::Complex{ parent, items } => EntryValue::Complex{
+                        parent: remap_id(*parent, from, to),
+                        items: items.iter().map(|item| BagItem{
+                            name: remap_id(item.name, from, to),
+                            value_type: item.value_type,
+                            data: remap(item.value_type, item.data)
+                        }).collect()
+                    }
+                };
+            }
+            for library in &mut package.libraries {
+                if library.0 == from {
+                    library.0 = to;
+                }
+            }
+            for chunk in &mut package.unknown_chunks {
+                remap_chunk(chunk, from, to);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::{BTreeSet, HashSet};
+use crate::manifest::typed_value::TypedValue;
+use crate::resources::{EntryValue, ResourceEntry, ResourceTable};
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ShrinkReport {
+    pub removed_ids: Vec<u32>,
+    pub removed_files: Vec<String>,
+    pub reclaimed_bytes: usize // table bytes, plus the stored size of removed files when run on an apk
+}
+
+fn push_reference(res: &mut Vec<u32>, value: TypedValue) {
+    if let TypedValue::Reference(id) | TypedValue::Attribute(id) | TypedValue::DynamicReference(id) | TypedValue::DynamicAttribute(id) = value {
+        if id != 0 {
+            res.push(id);
+        }
+    }
+}
+
+// ids an entry points at: its value, or for a bag the parent, the attribute names and the item values
+fn entry_references(entry: &ResourceEntry) -> Vec<u32> {
+    let mut res: Vec<u32> = Vec::new();
+    match &entry.value {
+        EntryValue::Simple{ value_type, data } => push_reference(&mut res, TypedValue::decode(*value_type, *data)),
+        EntryValue::Complex{ parent, items } => {
+            push_reference(&mut res, TypedValue::Reference(*parent));
+            for item in items {
+                res.push(item.name);
+                push_reference(&mut res, item.typed_value());
+            }
+        }
+    }
+    res
+}
+
+impl ResourceTable {
+    // blanks every entry not reachable from `roots`, following references between entries and, through
+    // `file_references`, the ids used inside file resources (layouts, drawables xml...). Ids stay stable
+    pub fn shrink<F: FnMut(&str) -> Vec<u32>>(&mut self, roots: &[u32], mut file_references: F) -> ShrinkReport {
+        // a table that can't be written has no size to compare
+        let size_before = self.to_data().map_or(0, |data| data.len());
+        let mut reachable: HashSet<u32> = HashSet::new();
+        let mut pending: Vec<u32> = roots.to_vec();
+        while let Some(id) = pending.pop() {
+            if !reachable.insert(id) {
+                continue;
+            }
+            let (package_id, type_id, entry_id) = (id >> 24, ((id >> 16) & 0xff) as u8, (id & 0xffff) as usize);
+            let resource_type = match self.get_package(package_id).and_then(|package| package.get_type(type_id)) {
+                Some(resource_type) => resource_type,
+                None => continue
+            };
+            for entry in resource_type.configs.iter().filter_map(|config| config.entries.get(entry_id)?.as_ref()) {
+                pending.extend(entry_references(entry));
+                if let Some(path) = self.entry_file(entry) {
+                    pending.extend(file_references(path));
+                }
+            }
+        }
+
+        let mut removed_ids: BTreeSet<u32> = BTreeSet::new();
+        let mut removed_paths: BTreeSet<String> = BTreeSet::new();
+        let mut kept_paths: HashSet<String> = HashSet::new();
+        for package in &self.packages {
+            for resource_type in &package.types {
+                for config in &resource_type.configs {
+                    for (entry_id, entry) in config.entries.iter().enumerate() {
+                        let entry = match entry {
+                            Some(entry) => entry,
+                            None => continue
+                        };
+                        let id = (package.id << 24) | ((resource_type.id as u32) << 16) | entry_id as u32;
+                        let path = self.entry_file(entry).map(String::from);
+                        if reachable.contains(&id) {
+                            kept_paths.extend(path);
+                        } else {
+                            removed_ids.insert(id);
+                            removed_paths.extend(path);
+                        }
+                    }
+                }
+            }
+        }
+
+        for package in &mut self.packages {
+            let package_id = package.id;
+            for resource_type in &mut package.types {
+                let type_id = resource_type.id as u32;
+                for config in &mut resource_type.configs {
+                    for (entry_id, entry) in config.entries.iter_mut().enumerate() {
+                        if removed_ids.contains(&((package_id << 24) | (type_id << 16) | entry_id as u32)) {
+                            *entry = None;
+                        }
+                    }
+                }
+                resource_type.configs.retain(|config| config.entries.iter().any(|entry| entry.is_some()));
+            }
+        }
+
+        ShrinkReport{
+            removed_ids: removed_ids.into_iter().collect(),
+            removed_files: removed_paths.into_iter().filter(|path| !kept_paths.contains(path)).collect(),
+            reclaimed_bytes: size_before.saturating_sub(self.to_data().map_or(0, |data| data.len()))
+        }
+    }
+}
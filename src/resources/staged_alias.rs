@@ -0,0 +1,33 @@
+use crate::resources::{read_chunk_header, ResourcePackage, ResourceTable, RES_TABLE_STAGED_ALIAS_TYPE};
+use crate::utils::get_leu32_value;
+
+impl ResourcePackage {
+    // (staged id, finalized id) pairs of the staged alias chunks: resources added during a platform's
+    // development that received their final id once the SDK was finalized
+    pub fn staged_aliases(&self) -> Vec<(u32, u32)> {
+        let mut res: Vec<(u32, u32)> = Vec::new();
+        for chunk in &self.unknown_chunks {
+            let (header_size, chunk_size) = match read_chunk_header(chunk, 0) {
+                Ok((RES_TABLE_STAGED_ALIAS_TYPE, header_size, chunk_size)) if header_size >= 12 => (header_size, chunk_size),
+                _ => continue
+            };
+            let count = (get_leu32_value(chunk, 8) as usize).min((chunk_size - header_size) / 8);
+            res.extend((0..count).map(|i| {
+                let offset = header_size + i * 8;
+                (get_leu32_value(chunk, offset), get_leu32_value(chunk, offset + 4))
+            }));
+        }
+        res
+    }
+}
+
+impl ResourceTable {
+    // the finalized id a staged id was given, None when `id` isn't staged
+    pub fn finalized_id(&self, id: u32) -> Option<u32> {
+        self.get_package(id >> 24)?
+            .staged_aliases()
+            .into_iter()
+            .find(|(staged, _)| *staged == id)
+            .map(|(_, finalized)| finalized)
+    }
+}
@@ -0,0 +1,263 @@
+use crate::resources::{read_chunk_header, ResourceError, RES_STRING_POOL_TYPE};
+use crate::resources::patch_chunk_size;
+use crate::utils::{get_leu16_value, get_leu32_value, push_leu16, push_leu32};
+
+const SORTED_FLAG: u32 = 1 << 0;
+const UTF8_FLAG: u32 = 1 << 8;
+const SPAN_END: u32 = 0xFFFFFFFF;
+
+// A ResStringPool, fully decoded. Unlike the lazy AXML pool this one is owned and can be rewritten.
+#[derive(Clone)]
+pub struct StringPool {
+    strings: Vec<String>,
+    styles: Vec<Vec<StringSpan>>, // spans of the first styles.len() strings
+    utf8: bool,
+    sorted: bool
+}
+
+// markup on part of a styled string, e.g. <b> over chars first_char..=last_char
+#[derive(Clone, Debug, PartialEq)]
+pub struct StringSpan {
+    pub name: u32, // pool index of the tag name
+    pub first_char: u32,
+    pub last_char: u32
+}
+
+// UTF-8 pools store lengths in one byte, or two with the high bit set
+fn decode_length8(data: &[u8], offset: &mut usize) -> Option<usize> {
+    let first = *data.get(*offset)? as usize;
+    *offset += 1;
+    if first & 0x80 == 0 {
+        return Some(first);
+    }
+    let second = *data.get(*offset)? as usize;
+    *offset += 1;
+    Some(((first & 0x7f) << 8) | second)
+}
+
+// UTF-16 pools store lengths in one u16, or two with the high bit set
+fn decode_length16(data: &[u8], offset: &mut usize) -> Option<usize> {
+    if *offset + 2 > data.len() {
+        return None;
+    }
+    let first = get_leu16_value(data, *offset) as usize;
+    *offset += 2;
+    if first & 0x8000 == 0 {
+        return Some(first);
+    }
+    if *offset + 2 > data.len() {
+        return None;
+    }
+    let second = get_leu16_value(data, *offset) as usize;
+    *offset += 2;
+    Some(((first & 0x7fff) << 16) | second)
+}
+
+// two bytes hold 15 bits, a longer string has no UTF-8 encoding in the pool
+fn encode_length8(data: &mut Vec<u8>, len: usize) -> Result<(), ResourceError> {
+    if len > 0x7fff {
+        return Err(ResourceError::new(format!("a string of length {} is too long for a UTF-8 pool", len)));
+    }
+    if len > 0x7f {
+        data.push(0x80 | ((len >> 8) & 0x7f) as u8);
+    }
+    data.push((len & 0xff) as u8);
+    Ok(())
+}
+
+fn encode_length16(data: &mut Vec<u8>, len: usize) {
+    if len > 0x7fff {
+        push_leu16(data, 0x8000 | ((len >> 16) & 0x7fff) as u16);
+    }
+    push_leu16(data, (len & 0xffff) as u16);
+}
+
+fn encode_string(data: &mut Vec<u8>, value: &str, utf8: bool) -> Result<(), ResourceError> {
+    if utf8 {
+        encode_length8(data, value.encode_utf16().count())?;
+        encode_length8(data, value.len())?;
+        data.extend_from_slice(value.as_bytes());
+        data.push(0);
+    } else {
+        let units: Vec<u16> = value.encode_utf16().collect();
+        encode_length16(data, units.len());
+        for unit in units {
+            push_leu16(data, unit);
+        }
+        push_leu16(data, 0);
+    }
+    Ok(())
+}
+
+fn decode_string(chunk: &[u8], mut offset: usize, utf8: bool) -> Option<String> {
+    if utf8 {
+        decode_length8(chunk, &mut offset)?; // length in UTF-16 units
+        let byte_len = decode_length8(chunk, &mut offset)?;
+        let bytes = chunk.get(offset..offset + byte_len)?;
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    } else {
+        let len = decode_length16(chunk, &mut offset)?;
+        let bytes = chunk.get(offset..offset + len * 2)?;
+        let units: Vec<u16> = bytes.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+        Some(String::from_utf16_lossy(&units))
+    }
+}
+
+fn decode_spans(chunk: &[u8], mut offset: usize) -> Option<Vec<StringSpan>> {
+    let mut res: Vec<StringSpan> = Vec::new();
+    loop {
+        if offset + 4 > chunk.len() {
+            return None;
+        }
+        let name = get_leu32_value(chunk, offset);
+        if name == SPAN_END {
+            return Some(res);
+        }
+        if offset + 12 > chunk.len() {
+            return None;
+        }
+        res.push(StringSpan{
+            name,
+            first_char: get_leu32_value(chunk, offset + 4),
+            last_char: get_leu32_value(chunk, offset + 8)
+        });
+        offset += 12;
+    }
+}
+
+impl StringPool {
+    pub(crate) fn parse(data: &[u8], offset: usize) -> Result<StringPool, ResourceError> {
+        let (chunk_type, header_size, chunk_size) = read_chunk_header(data, offset)?;
+        if chunk_type != RES_STRING_POOL_TYPE || header_size < 28 {
+            return Err(ResourceError::format(offset, "expected a string pool"));
+        }
+        let chunk = &data[offset..offset + chunk_size];
+        let string_count = get_leu32_value(chunk, 8) as usize;
+        let style_count = get_leu32_value(chunk, 12) as usize;
+        let flags = get_leu32_value(chunk, 16);
+        let strings_start = get_leu32_value(chunk, 20) as usize;
+        let styles_start = get_leu32_value(chunk, 24) as usize;
+        if header_size + (string_count + style_count) * 4 > chunk_size || style_count > string_count {
+            return Err(ResourceError::format(offset, "string pool index out of bounds"));
+        }
+        let utf8 = flags & UTF8_FLAG != 0;
+
+        let mut strings: Vec<String> = Vec::with_capacity(string_count);
+        for i in 0..string_count {
+            let string_offset = strings_start + get_leu32_value(chunk, header_size + i * 4) as usize;
+            match decode_string(chunk, string_offset, utf8) {
+                Some(value) => strings.push(value),
+                None => return Err(ResourceError::format(offset + string_offset, "bad string pool entry"))
+            }
+        }
+        let mut styles: Vec<Vec<StringSpan>> = Vec::with_capacity(style_count);
+        for i in 0..style_count {
+            let style_offset = styles_start + get_leu32_value(chunk, header_size + (string_count + i) * 4) as usize;
+            match decode_spans(chunk, style_offset) {
+                Some(spans) => styles.push(spans),
+                None => return Err(ResourceError::format(offset + style_offset, "bad string pool style"))
+            }
+        }
+        Ok(StringPool{
+            strings,
+            styles,
+            utf8,
+            sorted: flags & SORTED_FLAG != 0
+        })
+    }
+
+    pub fn to_data(&self) -> Result<Vec<u8>, ResourceError> {
+        let mut string_data: Vec<u8> = Vec::new();
+        let mut string_offsets: Vec<u32> = Vec::with_capacity(self.strings.len());
+        for value in &self.strings {
+            string_offsets.push(string_data.len() as u32);
+            encode_string(&mut string_data, value, self.utf8)?;
+        }
+        while !string_data.len().is_multiple_of(4) {
+            string_data.push(0);
+        }
+        let mut style_data: Vec<u8> = Vec::new();
+        let mut style_offsets: Vec<u32> = Vec::with_capacity(self.styles.len());
+        for spans in &self.styles {
+            style_offsets.push(style_data.len() as u32);
+            for span in spans {
+                push_leu32(&mut style_data, span.name);
+                push_leu32(&mut style_data, span.first_char);
+                push_leu32(&mut style_data, span.last_char);
+            }
+            push_leu32(&mut style_data, SPAN_END);
+        }
+        if !self.styles.is_empty() {
+            // the style array is closed by an extra pair of end markers
+            push_leu32(&mut style_data, SPAN_END);
+            push_leu32(&mut style_data, SPAN_END);
+        }
+
+        let header_size = 28;
+        let strings_start = header_size + (string_offsets.len() + style_offsets.len()) * 4;
+        let mut flags = 0;
+        if self.utf8 {
+            flags |= UTF8_FLAG;
+        }
+        if self.sorted {
+            flags |= SORTED_FLAG;
+        }
+        let mut res: Vec<u8> = Vec::with_capacity(strings_start + string_data.len() + style_data.len());
+        push_leu16(&mut res, RES_STRING_POOL_TYPE);
+        push_leu16(&mut res, header_size as u16);
+        push_leu32(&mut res, 0); // patched below
+        push_leu32(&mut res, self.strings.len() as u32);
+        push_leu32(&mut res, self.styles.len() as u32);
+        push_leu32(&mut res, flags);
+        push_leu32(&mut res, strings_start as u32);
+        push_leu32(&mut res, if self.styles.is_empty() { 0 } else { (strings_start + string_data.len()) as u32 });
+        for offset in string_offsets.iter().chain(style_offsets.iter()) {
+            push_leu32(&mut res, *offset);
+        }
+        res.extend_from_slice(&string_data);
+        res.extend_from_slice(&style_data);
+        patch_chunk_size(&mut res, 0);
+        Ok(res)
+    }
+
+    pub fn get(&self, index: u32) -> Option<&str> {
+        self.strings.get(index as usize).map(|value| value.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    pub fn is_utf8(&self) -> bool {
+        self.utf8
+    }
+
+    pub fn styles(&self, index: u32) -> &[StringSpan] {
+        self.styles.get(index as usize).map(|spans| spans.as_slice()).unwrap_or_default()
+    }
+
+    pub fn find(&self, value: &str) -> Option<u32> {
+        self.strings.iter().position(|item| item == value).map(|idx| idx as u32)
+    }
+
+    pub(crate) fn set(&mut self, index: u32, value: &str) {
+        if let Some(item) = self.strings.get_mut(index as usize) {
+            *item = String::from(value);
+            self.sorted = false;
+        }
+    }
+
+    // index of `value`, appended if the pool doesn't have it yet
+    pub fn add(&mut self, value: &str) -> u32 {
+        if let Some(index) = self.find(value) {
+            return index;
+        }
+        self.strings.push(String::from(value));
+        self.sorted = false;
+        (self.strings.len() - 1) as u32
+    }
+}
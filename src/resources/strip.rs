@@ -0,0 +1,96 @@
+use std::collections::{BTreeSet, HashSet};
+use crate::resources::config::{DENSITY_ANY, DENSITY_DEFAULT, DENSITY_NONE};
+use crate::resources::{ResourceConfig, ResourceError, ResourceTable, ResourceType};
+
+// entries of `resource_type` that lose out to another density for every one of `densities`, as
+// (config index, entry id). Default, anydpi and nodpi variants are never dropped
+fn losing_densities(resource_type: &ResourceType, densities: &[ResourceConfig]) -> Vec<(usize, usize)> {
+    let configs: Vec<ResourceConfig> = resource_type.configs.iter().map(|item| item.config()).collect();
+    let is_bucket = |config: &ResourceConfig| ![DENSITY_DEFAULT, DENSITY_ANY, DENSITY_NONE].contains(&config.density);
+    let mut res: Vec<(usize, usize)> = Vec::new();
+    for entry_id in 0..resource_type.spec_flags.len() {
+        let candidates: Vec<usize> = (0..configs.len())
+            .filter(|idx| is_bucket(&configs[*idx]) && resource_type.configs[*idx].entries.get(entry_id).is_some_and(|entry| entry.is_some()))
+            .collect();
+        let mut kept: HashSet<usize> = HashSet::new();
+        for candidate in &candidates {
+            // a variant only competes with the ones differing from it in density alone
+            let mut group_key = configs[*candidate].clone();
+            group_key.density = DENSITY_DEFAULT;
+            for density in densities {
+                let best = candidates.iter()
+                    .filter(|idx| ResourceConfig{ density: DENSITY_DEFAULT, ..configs[**idx].clone() } == group_key)
+                    .reduce(|best, idx| if configs[*idx].is_density_better(&configs[*best], density) == Some(true) { idx } else { best });
+                kept.extend(best.copied());
+            }
+        }
+        res.extend(candidates.into_iter().filter(|idx| !kept.contains(idx)).map(|idx| (idx, entry_id)));
+    }
+    res
+}
+
+impl ResourceTable {
+    // drops every configuration whose locale isn't listed, like resConfigs. "zh" keeps all of
+    // zh-rCN, zh-rTW..., "zh-rTW" only that region. Returns how many type chunks went away, an error
+    // naming the first of `locales` that isn't a locale, before anything is dropped
+    pub fn keep_locales(&mut self, locales: &[&str]) -> Result<usize, ResourceError> {
+        let kept: Vec<ResourceConfig> = locales.iter().map(|locale| match ResourceConfig::from_qualifiers(locale) {
+            Some(config) if !config.language.is_empty() => Ok(config),
+            _ => Err(ResourceError::new(format!("{} is not a locale", locale)))
+        }).collect::<Result<_, ResourceError>>()?;
+        let mut removed = 0;
+        for resource_type in self.packages.iter_mut().flat_map(|package| package.types.iter_mut()) {
+            let count = resource_type.configs.len();
+            resource_type.configs.retain(|item| {
+                let config = item.config();
+                config.language.is_empty() || kept.iter().any(|locale| {
+                    locale.language == config.language && (locale.region.is_empty() || locale.region == config.region)
+                })
+            });
+            resource_type.configs.retain(|item| item.entries.iter().any(|entry| entry.is_some()));
+            removed += count - resource_type.configs.len();
+        }
+        Ok(removed)
+    }
+
+    // keeps, for drawables and mipmaps, only the density variants that would be picked on one of
+    // `densities` ("xxhdpi", "480dpi"...). Returns the res/ files no entry points at any more
+    pub fn keep_densities(&mut self, densities: &[&str]) -> Vec<String> {
+        let densities: Vec<ResourceConfig> = densities.iter()
+            .filter_map(|density| ResourceConfig::from_qualifiers(density))
+            .filter(|config| config.density != DENSITY_DEFAULT)
+            .collect();
+        if densities.is_empty() {
+            return vec![];
+        }
+        let mut removed_files: BTreeSet<String> = BTreeSet::new();
+        for package_index in 0..self.packages.len() {
+            for type_index in 0..self.packages[package_index].types.len() {
+                let package = &self.packages[package_index];
+                let resource_type = &package.types[type_index];
+                if !matches!(package.type_name(resource_type.id), Some("drawable" | "mipmap")) {
+                    continue;
+                }
+                let losing = losing_densities(resource_type, &densities);
+                for (config_index, entry_id) in &losing {
+                    if let Some(path) = resource_type.configs[*config_index].entries[*entry_id].as_ref().and_then(|entry| self.entry_file(entry)) {
+                        removed_files.insert(path.to_string());
+                    }
+                }
+                let resource_type = &mut self.packages[package_index].types[type_index];
+                for (config_index, entry_id) in losing {
+                    resource_type.configs[config_index].entries[entry_id] = None;
+                }
+                resource_type.configs.retain(|item| item.entries.iter().any(|entry| entry.is_some()));
+            }
+        }
+
+        let still_used: HashSet<&str> = self.packages.iter()
+            .flat_map(|package| package.types.iter())
+            .flat_map(|resource_type| resource_type.configs.iter())
+            .flat_map(|config| config.entries.iter().flatten())
+            .filter_map(|entry| self.entry_file(entry))
+            .collect();
+        removed_files.into_iter().filter(|path| !still_used.contains(path.as_str())).collect()
+    }
+}
@@ -0,0 +1,745 @@
+use crate::manifest::typed_value::TypedValue;
+use crate::options::{ParseContext, ParseOptions, ParseWarning};
+use crate::resources::{patch_chunk_size, read_chunk_header, ResourceConfig, ResourceError, StringPool};
+use crate::resources::library::{parse_library, write_library};
+use crate::resources::{RES_STRING_POOL_TYPE, RES_TABLE_LIBRARY_TYPE, RES_TABLE_OVERLAYABLE_POLICY_TYPE, RES_TABLE_OVERLAYABLE_TYPE, RES_TABLE_PACKAGE_TYPE,
+                        RES_TABLE_STAGED_ALIAS_TYPE, RES_TABLE_TYPE, RES_TABLE_TYPE_SPEC_TYPE, RES_TABLE_TYPE_TYPE};
+use crate::utils::{get_leu16_value, get_leu32_value, push_leu16, push_leu32};
+
+const NO_ENTRY: u32 = 0xFFFFFFFF;
+const NO_ENTRY16: u16 = 0xFFFF;
+const PACKAGE_NAME_LEN: usize = 128; // u16 units
+const PACKAGE_HEADER_SIZE: usize = 288;
+const TYPE_SPEC_HEADER_SIZE: usize = 16;
+const TYPE_HEADER_SIZE: usize = 20; // up to the config, whose first field is its size
+
+// ResTable_type flags
+pub(crate) const TYPE_FLAG_SPARSE: u8 = 0x01;
+pub(crate) const TYPE_FLAG_OFFSET16: u8 = 0x02;
+
+// ResTable_entry flags
+pub(crate) const ENTRY_FLAG_COMPLEX: u16 = 0x0001;
+pub(crate) const ENTRY_FLAG_PUBLIC: u16 = 0x0002;
+pub(crate) const ENTRY_FLAG_COMPACT: u16 = 0x0008;
+
+// Parsed resources.arsc: the global value string pool and the packages, each holding its types.
+pub struct ResourceTable {
+    pub(crate) string_pool: StringPool,
+    pub(crate) packages: Vec<ResourcePackage>,
+    pub(crate) unknown_chunks: Vec<Vec<u8>>, // top level chunks other than the pool and packages, kept as is
+    warnings: Vec<ParseWarning>
+}
+
+pub struct ResourcePackage {
+    pub(crate) id: u32,
+    pub(crate) name: String,
+    pub(crate) type_strings: StringPool,
+    pub(crate) key_strings: StringPool,
+    pub(crate) last_public_type: u32,
+    pub(crate) last_public_key: u32,
+    pub(crate) type_id_offset: u32,
+    pub(crate) types: Vec<ResourceType>,
+    pub(crate) libraries: Vec<(u32, String)>, // dynamic reference table, see library.rs
+    pub(crate) unknown_chunks: Vec<Vec<u8>>, // overlayable, staged alias... chunks, kept as is
+    pub(crate) layout: Vec<PackageChunk> // the order the chunks after the pools came in, written back in it
+}
+
+// one of the chunks after the pools of a package as read. Types added since go after them, and so do a
+// library chunk or unknown chunks the package didn't have
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum PackageChunk {
+    Type(u8), // its spec and configurations, where the first of them was
+    Library,
+    Unknown(usize) // index into unknown_chunks
+}
+
+// every configuration of one resource type (string, drawable...), ids 0xPPTTEEEE share the TT
+pub struct ResourceType {
+    pub(crate) id: u8,
+    pub(crate) spec_flags: Vec<u32>, // ResTable_typeSpec, configuration change flags per entry
+    pub(crate) configs: Vec<ResourceTypeConfig>
+}
+
+// a ResTable_type chunk: the values of one type for one configuration (default, -zh, -xxhdpi...)
+pub struct ResourceTypeConfig {
+    pub(crate) config: Vec<u8>, // raw ResTable_config
+    pub(crate) flags: u8, // offset array format (sparse, 16 bit) of the input, reused when writing
+    pub(crate) entries: Vec<Option<ResourceEntry>> // indexed by entry id, None where this config has no value
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceEntry {
+    pub(crate) flags: u16,
+    pub(crate) key: u32, // index into the package key pool
+    pub(crate) value: EntryValue
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntryValue {
+    Simple { value_type: u32, data: u32 }, // Res_value, value_type holds the size/res0/dataType word
+    Complex { parent: u32, items: Vec<BagItem> } // ResTable_map_entry: style, array, plurals...
+}
+
+// a ResTable_map: attribute of a style, ^index_N of an array, quantity of a plural... and its Res_value
+#[derive(Clone, Debug, PartialEq)]
+pub struct BagItem {
+    pub name: u32,
+    pub value_type: u32,
+    pub data: u32
+}
+
+impl BagItem {
+    pub fn typed_value(&self) -> TypedValue {
+        TypedValue::decode(self.value_type, self.data)
+    }
+}
+
+fn parse_entry(chunk: &[u8], offset: usize) -> Option<ResourceEntry> {
+    if offset + 8 > chunk.len() {
+        return None;
+    }
+    let flags = get_leu16_value(chunk, offset + 2);
+    if flags & ENTRY_FLAG_COMPACT != 0 {
+        // u16 key, flags with the value's dataType in the high byte, then the value data
+        return Some(ResourceEntry{
+            flags: flags & 0xff,
+            key: get_leu16_value(chunk, offset) as u32,
+            value: EntryValue::Simple{
+                value_type: ((flags as u32 >> 8) << 24) | 8,
+                data: get_leu32_value(chunk, offset + 4)
+            }
+        });
+    }
+    let size = get_leu16_value(chunk, offset) as usize;
+    let key = get_leu32_value(chunk, offset + 4);
+    let value = if flags & ENTRY_FLAG_COMPLEX != 0 {
+        if size < 16 || offset + 16 > chunk.len() {
+            return None;
+        }
+        let count = get_leu32_value(chunk, offset + 12) as usize;
+        let maps = chunk.get(offset + size..offset + size + count * 12)?;
+        EntryValue::Complex{
+            parent: get_leu32_value(chunk, offset + 8),
+            items: maps.chunks_exact(12).map(|map| BagItem{
+                name: get_leu32_value(map, 0),
+                value_type: get_leu32_value(map, 4),
+                data: get_leu32_value(map, 8)
+            }).collect()
+        }
+    } else {
+        if offset + size + 8 > chunk.len() {
+            return None;
+        }
+        EntryValue::Simple{
+            value_type: get_leu32_value(chunk, offset + size),
+            data: get_leu32_value(chunk, offset + size + 4)
+        }
+    };
+    Some(ResourceEntry{
+        flags,
+        key,
+        value
+    })
+}
+
+// entries read in the compact form are written back compact as long as they still fit it
+fn write_entry(data: &mut Vec<u8>, entry: &ResourceEntry) {
+    match &entry.value {
+        EntryValue::Simple{ value_type, data: value } if entry.flags & ENTRY_FLAG_COMPACT != 0 && entry.key <= u16::MAX as u32 && value_type & 0x00ffffff == 8 => {
+            push_leu16(data, entry.key as u16);
+            push_leu16(data, (entry.flags & 0xff & !ENTRY_FLAG_COMPLEX) | ((value_type >> 24) << 8) as u16);
+            push_leu32(data, *value);
+        },
+        EntryValue::Simple{ value_type, data: value } => {
+            push_leu16(data, 8);
+            push_leu16(data, entry.flags & !(ENTRY_FLAG_COMPLEX | ENTRY_FLAG_COMPACT));
+            push_leu32(data, entry.key);
+            push_leu32(data, *value_type);
+            push_leu32(data, *value);
+        },
+        EntryValue::Complex{ parent, items } => {
+            push_leu16(data, 16);
+            push_leu16(data, (entry.flags | ENTRY_FLAG_COMPLEX) & !ENTRY_FLAG_COMPACT);
+            push_leu32(data, entry.key);
+            push_leu32(data, *parent);
+            push_leu32(data, items.len() as u32);
+            for item in items {
+                push_leu32(data, item.name);
+                push_leu32(data, item.value_type);
+                push_leu32(data, item.data);
+            }
+        }
+    }
+}
+
+fn parse_package_name(chunk: &[u8]) -> String {
+    let units: Vec<u16> = (0..PACKAGE_NAME_LEN)
+        .map(|i| get_leu16_value(chunk, 12 + i * 2))
+        .take_while(|unit| *unit != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+impl ResourceTypeConfig {
+    fn parse(chunk: &[u8], offset: usize, header_size: usize) -> Result<ResourceTypeConfig, ResourceError> {
+        if header_size < TYPE_HEADER_SIZE + 4 || chunk.len() < header_size {
+            return Err(ResourceError::format(offset, "bad type chunk header"));
+        }
+        let flags = chunk[9];
+        let entry_count = get_leu32_value(chunk, 12) as usize;
+        let entries_start = get_leu32_value(chunk, 16) as usize;
+        let config_size = get_leu32_value(chunk, TYPE_HEADER_SIZE) as usize;
+        let offset_size = if flags & TYPE_FLAG_OFFSET16 != 0 && flags & TYPE_FLAG_SPARSE == 0 { 2 } else { 4 };
+        let offsets_end = entry_count.checked_mul(offset_size).and_then(|size| size.checked_add(header_size));
+        if config_size > header_size - TYPE_HEADER_SIZE || offsets_end.is_none_or(|end| end > chunk.len()) {
+            return Err(ResourceError::format(offset, "bad type chunk header"));
+        }
+        // (entry id, offset from entries_start) of every entry present
+        let offsets: Vec<(usize, usize)> = (0..entry_count).filter_map(|i| {
+            let position = header_size + i * offset_size;
+            if flags & TYPE_FLAG_SPARSE != 0 {
+                // ResTable_sparseTypeEntry: entry id and offset / 4
+                Some((get_leu16_value(chunk, position) as usize, get_leu16_value(chunk, position + 2) as usize * 4))
+            } else if offset_size == 2 {
+                let entry_offset = get_leu16_value(chunk, position);
+                (entry_offset != NO_ENTRY16).then_some((i, entry_offset as usize * 4))
+            } else {
+                let entry_offset = get_leu32_value(chunk, position);
+                (entry_offset != NO_ENTRY).then_some((i, entry_offset as usize))
+            }
+        }).collect();
+
+        let mut entries: Vec<Option<ResourceEntry>> = vec![None; if flags & TYPE_FLAG_SPARSE != 0 { 0 } else { entry_count }];
+        for (entry_id, entry_offset) in offsets {
+            if entry_id >= entries.len() {
+                entries.resize(entry_id + 1, None);
+            }
+            let entry_offset = entries_start + entry_offset;
+            match parse_entry(chunk, entry_offset) {
+                Some(entry) => entries[entry_id] = Some(entry),
+                None => return Err(ResourceError::format(offset + entry_offset, "bad entry"))
+            }
+        }
+        Ok(ResourceTypeConfig{
+            config: chunk[TYPE_HEADER_SIZE..TYPE_HEADER_SIZE + config_size].to_vec(),
+            flags: flags & (TYPE_FLAG_SPARSE | TYPE_FLAG_OFFSET16),
+            entries
+        })
+    }
+
+    pub(crate) fn write(&self, data: &mut Vec<u8>, type_id: u8) {
+        let mut entry_data: Vec<u8> = Vec::new();
+        let mut offsets: Vec<Option<usize>> = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            offsets.push(entry.as_ref().map(|entry| {
+                let entry_offset = entry_data.len();
+                write_entry(&mut entry_data, entry);
+                entry_offset
+            }));
+        }
+        // the compact formats store offset / 4 in 16 bits, fall back to plain offsets when the data is too large
+        let fits_u16 = entry_data.len() / 4 < NO_ENTRY16 as usize;
+        let flags = if fits_u16 && self.entries.len() <= u16::MAX as usize { self.flags } else { 0 };
+        let offset_table: Vec<u8> = if flags & TYPE_FLAG_SPARSE != 0 {
+            let mut table: Vec<u8> = Vec::new();
+            for (entry_id, entry_offset) in offsets.iter().enumerate() {
+                if let Some(entry_offset) = entry_offset {
+                    push_leu16(&mut table, entry_id as u16);
+                    push_leu16(&mut table, (entry_offset / 4) as u16);
+                }
+            }
+            table
+        } else if flags & TYPE_FLAG_OFFSET16 != 0 {
+            let mut table: Vec<u8> = Vec::new();
+            for entry_offset in &offsets {
+                push_leu16(&mut table, entry_offset.map(|value| (value / 4) as u16).unwrap_or(NO_ENTRY16));
+            }
+            table.resize(table.len().next_multiple_of(4), 0);
+            table
+        } else {
+            let mut table: Vec<u8> = Vec::new();
+            for entry_offset in &offsets {
+                push_leu32(&mut table, entry_offset.map(|value| value as u32).unwrap_or(NO_ENTRY));
+            }
+            table
+        };
+        let entry_count = if flags & TYPE_FLAG_SPARSE != 0 { offsets.iter().flatten().count() } else { offsets.len() };
+
+        let start = data.len();
+        let header_size = (20 + self.config.len()).next_multiple_of(4);
+        push_leu16(data, RES_TABLE_TYPE_TYPE);
+        push_leu16(data, header_size as u16);
+        push_leu32(data, 0);
+        data.push(type_id);
+        data.push(flags);
+        push_leu16(data, 0);
+        push_leu32(data, entry_count as u32);
+        push_leu32(data, (header_size + offset_table.len()) as u32);
+        data.extend_from_slice(&self.config);
+        data.resize(start + header_size, 0);
+        data.extend_from_slice(&offset_table);
+        data.extend_from_slice(&entry_data);
+        patch_chunk_size(data, start);
+    }
+
+    // everything after the size field zeroed
+    pub fn is_default(&self) -> bool {
+        self.config.iter().skip(4).all(|byte| *byte == 0)
+    }
+
+    pub fn config(&self) -> ResourceConfig {
+        ResourceConfig::parse(&self.config)
+    }
+
+    pub fn config_data(&self) -> &[u8] {
+        &self.config
+    }
+
+    pub fn entries(&self) -> &[Option<ResourceEntry>] {
+        &self.entries
+    }
+
+    pub fn entry(&self, entry_id: u16) -> Option<&ResourceEntry> {
+        self.entries.get(entry_id as usize).and_then(|entry| entry.as_ref())
+    }
+}
+
+impl ResourceEntry {
+    pub fn key(&self) -> u32 {
+        self.key
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.flags & ENTRY_FLAG_PUBLIC != 0
+    }
+
+    pub fn value(&self) -> &EntryValue {
+        &self.value
+    }
+
+    // None for complex (bag) entries
+    pub fn typed_value(&self) -> Option<TypedValue> {
+        match self.value {
+            EntryValue::Simple{ value_type, data } => Some(TypedValue::decode(value_type, data)),
+            EntryValue::Complex{ .. } => None
+        }
+    }
+
+    // style / theme parent, 0 when there is none. None for simple entries
+    pub fn parent(&self) -> Option<u32> {
+        match self.value {
+            EntryValue::Complex{ parent, .. } => Some(parent),
+            EntryValue::Simple{ .. } => None
+        }
+    }
+
+    // the name/value pairs of a bag, empty for simple entries
+    pub fn bag_items(&self) -> &[BagItem] {
+        match &self.value {
+            EntryValue::Complex{ items, .. } => items,
+            EntryValue::Simple{ .. } => &[]
+        }
+    }
+}
+
+impl ResourceType {
+    fn write(&self, data: &mut Vec<u8>) {
+        let start = data.len();
+        push_leu16(data, RES_TABLE_TYPE_SPEC_TYPE);
+        push_leu16(data, 16);
+        push_leu32(data, 0);
+        data.push(self.id);
+        data.push(0);
+        push_leu16(data, self.configs.len() as u16);
+        push_leu32(data, self.spec_flags.len() as u32);
+        for flags in &self.spec_flags {
+            push_leu32(data, *flags);
+        }
+        patch_chunk_size(data, start);
+        for config in &self.configs {
+            config.write(data, self.id);
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    // appends an entry id to the type, every configuration starts without a value for it
+    fn add_entry(&mut self) -> u16 {
+        let entry_id = self.spec_flags.len();
+        self.spec_flags.push(0);
+        for config in &mut self.configs {
+            config.entries.resize(entry_id + 1, None);
+        }
+        entry_id as u16
+    }
+
+    pub(crate) fn get_or_create_config(&mut self, config: &ResourceConfig) -> &mut ResourceTypeConfig {
+        let index = match self.configs.iter().position(|item| item.config() == *config) {
+            Some(index) => index,
+            None => {
+                self.configs.push(ResourceTypeConfig{
+                    config: config.to_data(),
+                    flags: 0,
+                    entries: vec![None; self.spec_flags.len()]
+                });
+                self.configs.len() - 1
+            }
+        };
+        &mut self.configs[index]
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.spec_flags.len()
+    }
+
+    pub fn configs(&self) -> &[ResourceTypeConfig] {
+        &self.configs
+    }
+}
+
+impl ResourcePackage {
+    fn parse(data: &[u8], offset: usize, context: &mut ParseContext) -> Result<ResourcePackage, ResourceError> {
+        let (_, header_size, chunk_size) = read_chunk_header(data, offset)?;
+        if header_size < 284 {
+            return Err(ResourceError::format(offset, "bad package header"));
+        }
+        let chunk = &data[offset..offset + chunk_size];
+        let type_strings_offset = get_leu32_value(chunk, 268) as usize;
+        let key_strings_offset = get_leu32_value(chunk, 276) as usize;
+        let mut res = ResourcePackage{
+            id: get_leu32_value(chunk, 8),
+            name: parse_package_name(chunk),
+            type_strings: StringPool::parse(chunk, type_strings_offset)?,
+            key_strings: StringPool::parse(chunk, key_strings_offset)?,
+            last_public_type: get_leu32_value(chunk, 272),
+            last_public_key: get_leu32_value(chunk, 280),
+            type_id_offset: if header_size >= PACKAGE_HEADER_SIZE { get_leu32_value(chunk, 284) } else { 0 },
+            types: vec![],
+            libraries: vec![],
+            unknown_chunks: vec![],
+            layout: vec![]
+        };
+
+        let mut current_offset = header_size;
+        while current_offset < chunk_size {
+            let (chunk_type, child_header_size, child_size) = read_chunk_header(chunk, current_offset)?;
+            let child = &chunk[current_offset..current_offset + child_size];
+            // type ids start at 1, a 0 can't be addressed by any resource id
+            let bad_type_id = matches!(chunk_type, RES_TABLE_TYPE_SPEC_TYPE | RES_TABLE_TYPE_TYPE) && child.get(8) == Some(&0);
+            if bad_type_id && !context.tolerate(offset + current_offset, "type chunk with id 0, kept as an unknown chunk") {
+                return Err(ResourceError::format(offset + current_offset, "type id 0"));
+            }
+            let slot = match chunk_type {
+                _ if bad_type_id || !matches!(chunk_type, RES_TABLE_TYPE_SPEC_TYPE | RES_TABLE_TYPE_TYPE | RES_TABLE_LIBRARY_TYPE) => {
+                    PackageChunk::Unknown(res.unknown_chunks.len())
+                },
+                RES_TABLE_LIBRARY_TYPE => PackageChunk::Library,
+                // a chunk too short for its id is rejected below
+                _ => PackageChunk::Type(child.get(8).copied().unwrap_or_default())
+            };
+            let is_pool = chunk_type == RES_STRING_POOL_TYPE && (current_offset == type_strings_offset || current_offset == key_strings_offset);
+            if !is_pool && !res.layout.contains(&slot) {
+                res.layout.push(slot);
+            }
+            match chunk_type {
+                _ if bad_type_id => res.unknown_chunks.push(child.to_vec()),
+                _ if is_pool => {},
+                RES_TABLE_TYPE_SPEC_TYPE => {
+                    if child_header_size < TYPE_SPEC_HEADER_SIZE || child.len() < child_header_size {
+                        return Err(ResourceError::format(offset + current_offset, "bad type spec chunk"));
+                    }
+                    let entry_count = get_leu32_value(child, 12) as usize;
+                    let flags_end = entry_count.checked_mul(4).and_then(|size| size.checked_add(child_header_size));
+                    if flags_end.is_none_or(|end| end > child_size) {
+                        return Err(ResourceError::format(offset + current_offset, "bad type spec chunk"));
+                    }
+                    let resource_type = res.get_or_create_type(child[8]);
+                    resource_type.spec_flags = (0..entry_count)
+                        .map(|i| get_leu32_value(child, child_header_size + i * 4))
+                        .collect();
+                },
+                RES_TABLE_TYPE_TYPE => {
+                    let config = ResourceTypeConfig::parse(child, offset + current_offset, child_header_size)?;
+                    res.get_or_create_type(child[8]).configs.push(config);
+                },
+                RES_TABLE_LIBRARY_TYPE => match parse_library(child) {
+                    Some(libraries) => res.libraries.extend(libraries),
+                    None => return Err(ResourceError::format(offset + current_offset, "bad library chunk"))
+                },
+                RES_TABLE_OVERLAYABLE_TYPE | RES_TABLE_OVERLAYABLE_POLICY_TYPE | RES_TABLE_STAGED_ALIAS_TYPE => res.unknown_chunks.push(child.to_vec()),
+                _ => {
+                    context.note(offset + current_offset, &format!("unknown package chunk type {:#x}, kept as is", chunk_type));
+                    res.unknown_chunks.push(child.to_vec())
+                }
+            }
+            current_offset += child_size;
+        }
+        // sparse chunks only cover up to their last entry
+        for resource_type in &mut res.types {
+            let entry_count = resource_type.spec_flags.len();
+            for config in &mut resource_type.configs {
+                if config.entries.len() < entry_count {
+                    config.entries.resize(entry_count, None);
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    fn write(&self, data: &mut Vec<u8>) -> Result<(), ResourceError> {
+        let start = data.len();
+        let type_strings = self.type_strings.to_data()?;
+        push_leu16(data, RES_TABLE_PACKAGE_TYPE);
+        push_leu16(data, PACKAGE_HEADER_SIZE as u16);
+        push_leu32(data, 0);
+        push_leu32(data, self.id);
+        let name: Vec<u16> = self.name.encode_utf16().collect();
+        for i in 0..PACKAGE_NAME_LEN {
+            push_leu16(data, name.get(i).copied().unwrap_or(0));
+        }
+        push_leu32(data, PACKAGE_HEADER_SIZE as u32);
+        push_leu32(data, self.last_public_type);
+        push_leu32(data, (PACKAGE_HEADER_SIZE + type_strings.len()) as u32);
+        push_leu32(data, self.last_public_key);
+        push_leu32(data, self.type_id_offset);
+        data.extend_from_slice(&type_strings);
+        data.extend_from_slice(&self.key_strings.to_data()?);
+        let types = self.types.iter().map(|resource_type| PackageChunk::Type(resource_type.id));
+        let unknown_chunks = (0..self.unknown_chunks.len()).map(PackageChunk::Unknown);
+        let added = types.chain([PackageChunk::Library]).chain(unknown_chunks).filter(|slot| !self.layout.contains(slot));
+        for slot in self.layout.iter().copied().chain(added) {
+            match slot {
+                PackageChunk::Type(type_id) => if let Some(resource_type) = self.get_type(type_id) {
+                    resource_type.write(data);
+                },
+                PackageChunk::Library => if !self.libraries.is_empty() {
+                    write_library(data, &self.libraries);
+                },
+                PackageChunk::Unknown(index) => if let Some(chunk) = self.unknown_chunks.get(index) {
+                    data.extend_from_slice(chunk);
+                }
+            }
+        }
+        patch_chunk_size(data, start);
+        Ok(())
+    }
+
+    pub(crate) fn get_or_create_named_type(&mut self, name: &str) -> &mut ResourceType {
+        let type_id = match self.types.iter().find(|item| self.type_name(item.id) == Some(name)) {
+            Some(item) => item.id,
+            None => (self.type_strings.add(name) + 1 + self.type_id_offset) as u8
+        };
+        self.get_or_create_type(type_id)
+    }
+
+    fn get_or_create_type(&mut self, id: u8) -> &mut ResourceType {
+        let index = match self.types.iter().position(|item| item.id == id) {
+            Some(index) => index,
+            None => {
+                self.types.push(ResourceType{
+                    id,
+                    spec_flags: vec![],
+                    configs: vec![]
+                });
+                self.types.len() - 1
+            }
+        };
+        &mut self.types[index]
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn types(&self) -> &[ResourceType] {
+        &self.types
+    }
+
+    pub fn get_type(&self, type_id: u8) -> Option<&ResourceType> {
+        self.types.iter().find(|item| item.id == type_id)
+    }
+
+    // "string", "drawable"... type ids are 1-based indices into the type pool, shifted by typeIdOffset
+    pub fn type_name(&self, type_id: u8) -> Option<&str> {
+        self.type_strings.get((type_id as u32).checked_sub(1 + self.type_id_offset)?)
+    }
+
+    pub fn key_name(&self, key: u32) -> Option<&str> {
+        self.key_strings.get(key)
+    }
+}
+
+impl ResourceTable {
+    pub fn from<T: AsRef<[u8]> + ?Sized>(data: &T) -> Result<ResourceTable, ResourceError> {
+        ResourceTable::parse_table(data.as_ref(), ParseOptions::default())
+    }
+
+    pub fn from_with_options<T: AsRef<[u8]> + ?Sized>(data: &T, options: ParseOptions) -> Result<ResourceTable, ResourceError> {
+        ResourceTable::parse_table(data.as_ref(), options)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "arsc_parse", level = "debug", skip_all,
+        fields(bytes = data.len(), packages = tracing::field::Empty)))]
+    fn parse_table(data: &[u8], options: ParseOptions) -> Result<ResourceTable, ResourceError> {
+        let mut context = ParseContext::new(options);
+        let (chunk_type, header_size, chunk_size) = read_chunk_header(data, 0)?;
+        if chunk_type != RES_TABLE_TYPE || header_size < 12 {
+            return Err(ResourceError::format(0, "not a resource table"));
+        }
+        if chunk_size != data.len() && !context.tolerate(4, "table size doesn't match the data, the rest is ignored") {
+            return Err(ResourceError::format(4, "table size doesn't match the data"));
+        }
+        let mut string_pool: Option<StringPool> = None;
+        let mut packages: Vec<ResourcePackage> = Vec::new();
+        let mut unknown_chunks: Vec<Vec<u8>> = Vec::new();
+        let mut current_offset = header_size;
+        while current_offset < chunk_size {
+            let (child_type, _, child_size) = read_chunk_header(data, current_offset)?;
+            match child_type {
+                RES_STRING_POOL_TYPE if string_pool.is_none() => string_pool = Some(StringPool::parse(data, current_offset)?),
+                RES_STRING_POOL_TYPE if !context.tolerate(current_offset, "second global string pool, kept as an unknown chunk") => {
+                    return Err(ResourceError::format(current_offset, "second global string pool"));
+                },
+                RES_TABLE_PACKAGE_TYPE => packages.push(ResourcePackage::parse(data, current_offset, &mut context)?),
+                _ => {
+                    context.note(current_offset, &format!("unknown chunk type {:#x}, kept as is", child_type));
+                    unknown_chunks.push(data[current_offset..current_offset + child_size].to_vec())
+                }
+            }
+            current_offset += child_size;
+        }
+        record!("packages", packages.len());
+        if get_leu32_value(data, 8) as usize != packages.len() && !context.tolerate(8, "package count doesn't match the packages") {
+            return Err(ResourceError::format(8, "package count doesn't match the packages"));
+        }
+        Ok(ResourceTable{
+            string_pool: string_pool.ok_or_else(|| ResourceError::format(header_size, "missing global string pool"))?,
+            packages,
+            unknown_chunks,
+            warnings: context.warnings
+        })
+    }
+
+    // what a lenient parse let through, always empty for a strict one
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    // serialize back to a resources.arsc, entries are laid out afresh so offsets may differ from the input.
+    // An error when a string is too long for the encoding of its pool
+    pub fn to_data(&self) -> Result<Vec<u8>, ResourceError> {
+        let mut res: Vec<u8> = Vec::new();
+        push_leu16(&mut res, RES_TABLE_TYPE);
+        push_leu16(&mut res, 12);
+        push_leu32(&mut res, 0);
+        push_leu32(&mut res, self.packages.len() as u32);
+        res.extend_from_slice(&self.string_pool.to_data()?);
+        for package in &self.packages {
+            package.write(&mut res)?;
+        }
+        for chunk in &self.unknown_chunks {
+            res.extend_from_slice(chunk);
+        }
+        patch_chunk_size(&mut res, 0);
+        Ok(res)
+    }
+
+    // new entry `type_name`/`name` in the first package holding a string from the global pool in each of
+    // `configs` (the default configuration when empty), returns the new resource id
+    fn add_string_entry(&mut self, type_name: &str, name: &str, value: &str, configs: &[ResourceConfig]) -> Result<u32, ResourceError> {
+        if self.resource_id(&format!("{}/{}", type_name, name)).is_some() {
+            return Err(ResourceError::new(format!("{}/{} already exists", type_name, name)));
+        }
+        let value_index = self.string_pool.add(value);
+        let package = self.packages.first_mut().ok_or_else(|| ResourceError::new(String::from("no package to add to")))?;
+        let package_id = package.id;
+        let key = package.key_strings.add(name);
+        let resource_type = package.get_or_create_named_type(type_name);
+        let entry_id = resource_type.add_entry();
+        let (value_type, data) = TypedValue::StringRef(value_index).encode();
+        let entry = ResourceEntry{
+            flags: 0,
+            key,
+            value: EntryValue::Simple{ value_type, data }
+        };
+
+        let default_config = [ResourceConfig::default()];
+        let configs = if configs.is_empty() { &default_config } else { configs };
+        for config in configs {
+            resource_type.get_or_create_config(config).entries[entry_id as usize] = Some(entry.clone());
+        }
+        Ok((package_id << 24) | ((resource_type.id as u32) << 16) | entry_id as u32)
+    }
+
+    pub fn add_string(&mut self, name: &str, value: &str, configs: &[ResourceConfig]) -> Result<u32, ResourceError> {
+        self.add_string_entry("string", name, value, configs)
+    }
+
+    // registers res/`type_name`/`file_name` ("drawable", "icon.png") in the default configuration,
+    // returns the new id and the zip path the file has to be stored under
+    pub fn add_file(&mut self, type_name: &str, file_name: &str) -> Result<(u32, String), ResourceError> {
+        let name = file_name.split('.').next().unwrap_or_default();
+        if name.is_empty() || file_name.contains('/') {
+            return Err(ResourceError::new(format!("bad resource file name {}", file_name)));
+        }
+        let path = format!("res/{}/{}", type_name, file_name);
+        let id = self.add_string_entry(type_name, name, path.as_str(), &[])?;
+        Ok((id, path))
+    }
+
+    // points `id` at `value` in `config`, adding the configuration if the type doesn't have it yet
+    pub fn set_string(&mut self, id: u32, value: &str, config: &ResourceConfig) -> Result<(), ResourceError> {
+        let (key, flags) = match self.variants(id).first() {
+            Some((_, entry)) => (entry.key, entry.flags),
+            None => return Err(ResourceError::new(format!("no resource 0x{:08x}", id)))
+        };
+        let value_index = self.string_pool.add(value);
+        let (value_type, data) = TypedValue::StringRef(value_index).encode();
+        let resource_type = self.packages.iter_mut()
+            .find(|package| package.id == id >> 24)
+            .and_then(|package| package.types.iter_mut().find(|item| item.id as u32 == (id >> 16) & 0xff))
+            .ok_or_else(|| ResourceError::new(format!("no resource 0x{:08x}", id)))?;
+        resource_type.get_or_create_config(config).entries[(id & 0xffff) as usize] = Some(ResourceEntry{
+            flags: flags & !ENTRY_FLAG_COMPLEX,
+            key,
+            value: EntryValue::Simple{ value_type, data }
+        });
+        Ok(())
+    }
+
+    // renames the app package (the first one), the name field holds at most 127 UTF-16 units
+    pub fn set_package_name(&mut self, name: &str) -> Result<(), ResourceError> {
+        if name.is_empty() || name.encode_utf16().count() >= PACKAGE_NAME_LEN {
+            return Err(ResourceError::new(format!("bad package name {}", name)));
+        }
+        let package = self.packages.first_mut().ok_or_else(|| ResourceError::new(String::from("no package to rename")))?;
+        package.name = String::from(name);
+        Ok(())
+    }
+
+    // the res/ path a file resource entry points at
+    pub(crate) fn entry_file(&self, entry: &ResourceEntry) -> Option<&str> {
+        match entry.typed_value()? {
+            TypedValue::StringRef(index) => self.string_pool.get(index).filter(|value| value.starts_with("res/")),
+            _ => None
+        }
+    }
+
+    pub fn string_pool(&self) -> &StringPool {
+        &self.string_pool
+    }
+
+    pub fn packages(&self) -> &[ResourcePackage] {
+        &self.packages
+    }
+
+    pub fn get_package(&self, package_id: u32) -> Option<&ResourcePackage> {
+        self.packages.iter().find(|package| package.id == package_id)
+    }
+}
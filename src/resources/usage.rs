@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use crate::resources::ResourceTable;
+
+const LARGEST_FILES: usize = 20;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UsageReport {
+    pub types: Vec<TypeUsage>,
+    pub largest_images: Vec<(String, usize)>, // drawable/mipmap files by size, biggest first
+    pub duplicate_files: Vec<Vec<String>> // files with identical content, each group sorted
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TypeUsage {
+    pub name: String, // "string", "drawable"...
+    pub entry_count: usize,
+    pub configs: Vec<ConfigUsage>
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConfigUsage {
+    pub config: String, // qualifiers, "" for the default configuration
+    pub entry_count: usize, // entries with a value in this configuration
+    pub table_bytes: usize, // size of its chunk in resources.arsc
+    pub file_bytes: usize // files the entries point at, as reported by `file_info`
+}
+
+impl ResourceTable {
+    // sizes per type and configuration. `file_info` gives the (stored size, crc32) of a res/ file, files
+    // sharing both are reported as duplicates
+    pub fn usage_report<F: FnMut(&str) -> Option<(usize, u32)>>(&self, mut file_info: F) -> UsageReport {
+        let mut types: Vec<TypeUsage> = Vec::new();
+        let mut files: HashMap<String, (usize, u32)> = HashMap::new();
+        let mut images: Vec<(String, usize)> = Vec::new();
+        for package in &self.packages {
+            for resource_type in &package.types {
+                let name = package.type_name(resource_type.id).unwrap_or_default();
+                let mut configs: Vec<ConfigUsage> = Vec::new();
+                for config in &resource_type.configs {
+                    let mut chunk: Vec<u8> = Vec::new();
+                    config.write(&mut chunk, resource_type.id);
+                    let mut file_bytes = 0;
+                    for entry in config.entries.iter().flatten() {
+                        let path = match self.entry_file(entry) {
+                            Some(path) => path,
+                            None => continue
+                        };
+                        let info = match files.get(path) {
+                            Some(info) => Some(*info),
+                            None => file_info(path)
+                        };
+                        if let Some((size, crc)) = info {
+                            file_bytes += size;
+                            if files.insert(String::from(path), (size, crc)).is_none() && (name == "drawable" || name == "mipmap") {
+                                images.push((String::from(path), size));
+                            }
+                        }
+                    }
+                    configs.push(ConfigUsage{
+                        config: config.config().to_string(),
+                        entry_count: config.entries.iter().flatten().count(),
+                        table_bytes: chunk.len(),
+                        file_bytes
+                    });
+                }
+                types.push(TypeUsage{
+                    name: String::from(name),
+                    entry_count: resource_type.entry_count(),
+                    configs
+                });
+            }
+        }
+
+        images.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        images.truncate(LARGEST_FILES);
+        let mut same_content: HashMap<(usize, u32), Vec<String>> = HashMap::new();
+        for (path, info) in files {
+            same_content.entry(info).or_default().push(path);
+        }
+        let mut duplicate_files: Vec<Vec<String>> = same_content.into_values().filter(|paths| paths.len() > 1).collect();
+        for paths in &mut duplicate_files {
+            paths.sort();
+        }
+        duplicate_files.sort();
+        UsageReport{
+            types,
+            largest_images: images,
+            duplicate_files
+        }
+    }
+}
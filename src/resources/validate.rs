@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use crate::manifest::typed_value::TypedValue;
+use crate::manifest::validator::{Severity, ValidationIssue};
+use crate::resources::{EntryValue, ResourceEntry, ResourceTable};
+
+// the ids and pool strings an entry value refers to, parent and items included for bags
+fn entry_values(entry: &ResourceEntry) -> Vec<TypedValue> {
+    match &entry.value {
+        EntryValue::Simple{ value_type, data } => vec![TypedValue::decode(*value_type, *data)],
+        EntryValue::Complex{ parent, items } => {
+            let mut res = vec![TypedValue::Reference(*parent)];
+            res.extend(items.iter().map(|item| item.typed_value()));
+            res
+        }
+    }
+}
+
+impl ResourceTable {
+    fn describe(&self, id: u32) -> String {
+        match self.resource_name(id) {
+            Some(name) => format!("{} (0x{:08x})", name, id),
+            None => format!("0x{:08x}", id)
+        }
+    }
+
+    // problems that make a rebuilt table unreliable: references to ids missing from a package of this
+    // table, file entries whose path `file_exists` rejects and configurations disagreeing with their type.
+    // Two entries of a configuration sharing a key is only a warning, obfuscators give many entries one name
+    pub fn validate<F: Fn(&str) -> bool>(&self, file_exists: F) -> Vec<ValidationIssue> {
+        let mut issues: Vec<ValidationIssue> = Vec::new();
+        let mut push = |severity: Severity, element: String, message: String| issues.push(ValidationIssue{
+            severity,
+            element,
+            message
+        });
+        for package in &self.packages {
+            for resource_type in &package.types {
+                let type_element = format!("type 0x{:02x} of package 0x{:02x}", resource_type.id, package.id);
+                if package.type_name(resource_type.id).is_none() {
+                    push(Severity::Error, type_element.clone(), String::from("no name in the type pool"));
+                }
+                let mut reported: Vec<u32> = Vec::new();
+                for (index, config) in resource_type.configs.iter().enumerate() {
+                    let mut keys: HashMap<u32, u16> = HashMap::new(); // key -> first entry id of the config using it
+                    if config.entries.len() > resource_type.entry_count() {
+                        push(Severity::Error, type_element.clone(), format!("configuration \"{}\" has {} entries, the type {}",
+                            config.config(), config.entries.len(), resource_type.entry_count()));
+                    }
+                    if resource_type.configs[..index].iter().any(|item| item.config == config.config) {
+                        push(Severity::Error, type_element.clone(), format!("configuration \"{}\" appears twice", config.config()));
+                    }
+                    for (entry_id, entry) in config.entries.iter().enumerate() {
+                        let entry = match entry {
+                            Some(entry) => entry,
+                            None => continue
+                        };
+                        let id = (package.id << 24) | ((resource_type.id as u32) << 16) | entry_id as u32;
+                        if package.key_name(entry.key).is_none() {
+                            push(Severity::Error, format!("0x{:08x}", id), String::from("key out of the key pool"));
+                        }
+                        match keys.get(&entry.key) {
+                            None => {
+                                keys.insert(entry.key, entry_id as u16);
+                            },
+                            Some(first) if !reported.contains(&id) => {
+                                reported.push(id);
+                                push(Severity::Warning, self.describe(id), format!("key also used by 0x{:08x}",
+                                    (id & 0xffff0000) | *first as u32));
+                            },
+                            _ => {}
+                        }
+                        for value in entry_values(entry) {
+                            match value {
+                                TypedValue::Reference(target) | TypedValue::Attribute(target)
+                                | TypedValue::DynamicReference(target) | TypedValue::DynamicAttribute(target)
+                                    if target != 0 && self.get_package(target >> 24).is_some() && self.variants(target).is_empty() => {
+                                    push(Severity::Error, self.describe(id), format!("dangling reference to 0x{:08x}", target));
+                                },
+                                TypedValue::StringRef(index) => match self.string_pool.get(index) {
+                                    None => push(Severity::Error, self.describe(id), format!("string {} out of the pool", index)),
+                                    Some(path) if path.starts_with("res/") && !file_exists(path) => {
+                                        push(Severity::Error, self.describe(id), format!("file {} is missing", path));
+                                    },
+                                    _ => {}
+                                },
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        issues
+    }
+}
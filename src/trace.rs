@@ -0,0 +1,21 @@
+// tracing calls that compile to nothing without the tracing feature. Spans come from
+// cfg_attr(feature = "tracing", tracing::instrument(..)) on the stage functions, subscribers get
+// their durations from when the span is entered and closed
+
+// sets a field the instrument attribute declared as tracing::field::Empty on the current span. The
+// value is evaluated either way, so it doesn't go unused without the feature
+macro_rules! record {
+    ($field:literal, $value:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record($field, $value);
+        #[cfg(not(feature = "tracing"))]
+        let _ = $value;
+    };
+}
+
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
@@ -1,30 +1,33 @@
-pub(crate) fn get_le32_value(data: &Vec<u8>, offset: usize) -> i32 {
-    (data[offset] as i32) | ((data[offset + 1] as i32) << 8)
-        | ((data[offset + 2] as i32) << 16) | ((data[offset + 3] as i32) << 24)
-}
+use alloc::vec::Vec;
 
+// 0 past the end: callers check sizes first, one that misses a check reads zeros rather than panicking
 pub(crate) fn get_leu32_value<I: AsRef<[u8]>>(data: I, offset: usize) -> u32 {
-    let data = data.as_ref();
-    (data[offset] as u32) | ((data[offset + 1] as u32) << 8)
-        | ((data[offset + 2] as u32) << 16) | ((data[offset + 3] as u32) << 24)
+    read_leu32(data.as_ref(), offset).unwrap_or(0)
 }
 
-
 pub(crate) fn get_leu16_value<I: AsRef<[u8]>>(data: I, offset: usize) -> u16 {
-    let data = data.as_ref();
-    (data[offset] as u16) | ((data[offset + 1] as u16) << 8)
+    read_leu16(data.as_ref(), offset).unwrap_or(0)
+}
+
+// bounds checked reads for parsers of untrusted input, None past the end instead of a panic
+pub(crate) fn read_leu16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+pub(crate) fn read_leu32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
 }
 
 pub(crate) fn push_le32 (data: &mut Vec<u8>, value: i32) {
-    data.push((value & 0xff) as u8);
-    data.push(((value >> 8) & 0xff) as u8);
-    data.push(((value >> 16) & 0xff) as u8);
-    data.push(((value >> 24) & 0xff) as u8);
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn push_leu16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_le_bytes());
 }
 
 pub fn push_leu32(data: &mut Vec<u8>, value: u32) {
-    data.push((value & 0xff) as u8);
-    data.push(((value >> 8) & 0xff) as u8);
-    data.push(((value >> 16) & 0xff) as u8);
-    data.push(((value >> 24) & 0xff) as u8);
+    data.extend_from_slice(&value.to_le_bytes());
 }
@@ -0,0 +1,67 @@
+// fixtures of the integration tests, each test crate uses some of them
+#![allow(dead_code)]
+#[cfg(feature = "zip")]
+use apk_editor::apk_zip::{ApkFile, CompressMethod, ZipEditor};
+
+// a small binary AndroidManifest.xml: uses-sdk, uses-permission, and an application with an activity
+// and a service
+pub const MANIFEST: &[u8] = include_bytes!("../data/AndroidManifest.xml");
+
+pub fn chunk(chunk_type: u16, header_size: u16, rest: &[u8]) -> Vec<u8> {
+    let mut data = vec![];
+    data.extend_from_slice(&chunk_type.to_le_bytes());
+    data.extend_from_slice(&header_size.to_le_bytes());
+    data.extend_from_slice(&(8 + rest.len() as u32).to_le_bytes());
+    data.extend_from_slice(rest);
+    data
+}
+
+pub fn words(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+// an empty utf-8 string pool
+pub fn string_pool() -> Vec<u8> {
+    chunk(0x0001, 28, &words(&[0, 0, 0x100, 0, 0]))
+}
+
+// resources.arsc of one package 0x7f, empty pools and `children` after them
+pub fn table(children: &[u8]) -> Vec<u8> {
+    let pool = string_pool();
+    let mut package = words(&[0x7f]);
+    package.resize(package.len() + 256, 0); // name
+    package.extend(words(&[288, 0, 288 + pool.len() as u32, 0, 0]));
+    package.extend_from_slice(&pool);
+    package.extend_from_slice(&pool);
+    package.extend_from_slice(children);
+    let mut rest = words(&[1]);
+    rest.extend(string_pool());
+    rest.extend(chunk(0x0200, 288, &package));
+    chunk(0x0002, 12, &rest)
+}
+
+// a zip of (name, data), resources.arsc stored as aapt does and the rest deflated
+#[cfg(feature = "zip")]
+pub fn zip_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut editor = ZipEditor::new();
+    for (name, data) in entries {
+        let method = if *name == "resources.arsc" { CompressMethod::Stored } else { CompressMethod::Deflated };
+        editor.append_file(data.to_vec(), String::from(*name), method);
+    }
+    let mut res = vec![];
+    editor.finish(None, &mut res, 4).unwrap();
+    res
+}
+
+#[cfg(feature = "zip")]
+pub fn apk_of(entries: &[(&str, &[u8])]) -> ApkFile<'static> {
+    ApkFile::from_vec(zip_of(entries)).unwrap()
+}
+
+// `apk` saved and read back
+#[cfg(feature = "zip")]
+pub fn saved(apk: &mut ApkFile) -> ApkFile<'static> {
+    let mut data = vec![];
+    apk.save(&mut data).unwrap();
+    ApkFile::from_vec(data).unwrap()
+}
@@ -0,0 +1,206 @@
+#![cfg(feature = "zip")]
+use apk_editor::apk_zip::ApkFile;
+use apk_editor::dex::{self, DexFile};
+
+mod common;
+use common::{apk_of, saved, MANIFEST};
+
+// classes.dex loads "https://api.example.com/v1", "util" and "running", hook.dex loads "hooked"
+const CLASSES: &[u8] = include_bytes!("data/classes.dex");
+const HOOK: &[u8] = include_bytes!("data/hook.dex");
+
+fn apk() -> ApkFile<'static> {
+    apk_of(&[("AndroidManifest.xml", MANIFEST), ("classes.dex", CLASSES)])
+}
+
+fn const_strings(apk: &ApkFile) -> Vec<(String, String)> {
+    apk.find_const_strings("").into_iter().map(|(name, item)| (name, item.value)).collect()
+}
+
+#[test]
+fn replace_const_strings_keeps_earlier_edits() {
+    let mut apk = apk();
+    assert_eq!(apk.replace_const_strings("util", "UTIL").unwrap(), 1);
+    assert_eq!(apk.replace_const_strings("running", "RUNNING").unwrap(), 1);
+    let values: Vec<String> = const_strings(&saved(&mut apk)).into_iter().map(|(_, value)| value).collect();
+    assert_eq!(values, ["https://api.example.com/v1", "UTIL", "RUNNING"]);
+}
+
+#[test]
+fn replace_const_strings_sees_added_dexes() {
+    let mut apk = apk();
+    apk.add_dex(HOOK).unwrap();
+    assert!(const_strings(&apk).contains(&(String::from("classes2.dex"), String::from("hooked"))));
+    assert_eq!(apk.replace_const_strings("hooked", "patched").unwrap(), 1);
+    assert!(const_strings(&saved(&mut apk)).contains(&(String::from("classes2.dex"), String::from("patched"))));
+}
+
+#[test]
+fn strip_debug_info_keeps_earlier_edits() {
+    let mut apk = apk();
+    apk.add_dex(HOOK).unwrap();
+    assert_eq!(apk.replace_const_strings("util", "UTIL").unwrap(), 1);
+    let reports = apk.strip_debug_info().unwrap();
+    let names: Vec<&str> = reports.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["classes.dex", "classes2.dex"]);
+    assert!(!reports[0].1.is_empty(), "classes.dex has debug info to strip");
+    assert!(const_strings(&saved(&mut apk)).contains(&(String::from("classes.dex"), String::from("UTIL"))));
+}
+
+// an empty 041 dex: the 0x78 byte header and a map list of the header and itself
+fn empty_v41() -> Vec<u8> {
+    let mut data = vec![0u8; 0x78];
+    data[0..8].copy_from_slice(b"dex\n041\0");
+    for (offset, value) in [(36, 0x78u32), (40, 0x12345678), (52, 0x78), (104, 28), (108, 0x78)] {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    for value in [2u32, 0, 1, 0, 0x1000, 1, 0x78] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    dex::fix_checksums(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn version_41_has_a_longer_header() {
+    let data = empty_v41();
+    let header = dex::validate(&data).unwrap();
+    assert_eq!((header.header_size, header.container_size, header.header_offset), (0x78, data.len() as u32, 0));
+    let written = DexFile::parse(&data).unwrap().to_bytes().unwrap();
+    assert_eq!(dex::validate(&written).unwrap().header_size, 0x78);
+
+    let mut data = empty_v41();
+    data[0x74..0x78].copy_from_slice(&0x10u32.to_le_bytes());
+    dex::fix_checksums(&mut data).unwrap();
+    assert!(dex::validate(&data).is_err());
+    assert!(dex::validate(CLASSES).is_ok());
+}
+
+#[test]
+fn merge_adds_the_classes_of_the_other_dex() {
+    let merged = dex::merge(CLASSES, HOOK).unwrap();
+    dex::validate(&merged).unwrap();
+    let mut names = dex::class_names(CLASSES).unwrap();
+    names.extend(dex::class_names(HOOK).unwrap());
+    names.sort();
+    let mut merged_names = dex::class_names(&merged).unwrap();
+    merged_names.sort();
+    assert_eq!(merged_names, names);
+
+    let mut apk = apk_of(&[("AndroidManifest.xml", MANIFEST), ("classes.dex", &merged)]);
+    let values: Vec<String> = const_strings(&saved(&mut apk)).into_iter().map(|(_, value)| value).collect();
+    assert!(values.contains(&String::from("util")) && values.contains(&String::from("hooked")), "{:?}", values);
+}
+
+#[test]
+fn merge_rejects_a_class_defined_twice() {
+    let err = dex::merge(CLASSES, CLASSES).unwrap_err();
+    assert!(err.to_string().contains("is defined in both dex files"), "{}", err);
+}
+
+fn uleb128(data: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+// a 035 dex of one class `descriptor` with `count` static int fields f0, f1...
+fn fields_dex(descriptor: &str, count: u32) -> Vec<u8> {
+    let strings: Vec<String> = [String::from(descriptor), String::from("I")].into_iter()
+        .chain((0..count).map(|index| format!("f{}", index))).collect();
+    let string_ids = 0x70u32;
+    let type_ids = string_ids + 4 * strings.len() as u32;
+    let field_ids = type_ids + 8;
+    let class_defs = field_ids + 8 * count;
+    let data_offset = class_defs + 32;
+
+    let mut data_section = vec![];
+    let mut string_offsets = vec![];
+    for string in &strings {
+        string_offsets.push(data_offset + data_section.len() as u32);
+        uleb128(&mut data_section, string.len() as u32);
+        data_section.extend_from_slice(string.as_bytes());
+        data_section.push(0);
+    }
+    let class_data = data_offset + data_section.len() as u32;
+    for size in [count, 0, 0, 0] {
+        uleb128(&mut data_section, size);
+    }
+    for index in 0..count {
+        uleb128(&mut data_section, if index == 0 { 0 } else { 1 });
+        uleb128(&mut data_section, 0x9);
+    }
+    while data_section.len() % 4 != 0 {
+        data_section.push(0);
+    }
+    let map_offset = data_offset + data_section.len() as u32;
+
+    let mut data = vec![0u8; 0x70];
+    data[0..8].copy_from_slice(b"dex\n035\0");
+    let header = [
+        (36, 0x70), (40, 0x12345678), (52, map_offset), (56, strings.len() as u32), (60, string_ids), (64, 2), (68, type_ids),
+        (80, count), (84, field_ids), (96, 1), (100, class_defs), (104, data_section.len() as u32 + 4 + 5 * 12), (108, data_offset)
+    ];
+    for (offset, value) in header {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    string_offsets.iter().for_each(|offset| data.extend_from_slice(&offset.to_le_bytes()));
+    for string in [0u32, 1] {
+        data.extend_from_slice(&string.to_le_bytes());
+    }
+    for index in 0..count {
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&(index + 2).to_le_bytes());
+    }
+    for value in [0u32, 1, u32::MAX, 0, u32::MAX, 0, class_data, 0] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&data_section);
+    data.extend_from_slice(&5u32.to_le_bytes());
+    let map = [(0u16, 1, 0), (1, strings.len() as u32, string_ids), (2, 2, type_ids), (4, count, field_ids), (6, 1, class_defs)];
+    for (item_type, size, offset) in map {
+        data.extend_from_slice(&(item_type as u32).to_le_bytes());
+        data.extend_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(&offset.to_le_bytes());
+    }
+    dex::fix_checksums(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn merge_needs_the_references_to_fit_one_dex() {
+    let host = fields_dex("LHost;", 40000);
+    let addition = fields_dex("LAddition;", 40000);
+    let counts = dex::ref_counts(&dex::merge(&host, &fields_dex("LSmall;", 100)).unwrap()).unwrap();
+    assert_eq!((counts.fields, counts.classes), (40100, 2));
+
+    let err = dex::merge(&host, &addition).unwrap_err();
+    assert!(err.to_string().contains("80000 field references, at most 65536 fit in a dex"), "{}", err);
+}
+
+#[test]
+fn apply_mapping_renames_a_class_everywhere_it_is_spelled() {
+    let map = [("Lcom/example/app/Util;", "Lz/a;")];
+    let mut file = DexFile::parse(CLASSES).unwrap();
+    assert_eq!(file.apply_mapping(&map).unwrap(), 1);
+    assert_eq!(file.apply_mapping(&map).unwrap(), 0);
+    let names = dex::class_names(&file.to_bytes().unwrap()).unwrap();
+    assert!(names.contains(&String::from("Lz/a;")), "{:?}", names);
+    assert!(!names.contains(&String::from("Lcom/example/app/Util;")), "{:?}", names);
+
+    // hook.dex calls Util, mapped the same way its reference follows the rename
+    let merged = dex::merge(&dex::apply_mapping(CLASSES, &map).unwrap(), &dex::apply_mapping(HOOK, &map).unwrap()).unwrap();
+    assert!(dex::class_names(&merged).unwrap().contains(&String::from("Lcom/hook/Loader;")));
+    assert!(!merged.windows(8).any(|window| window == b"app/Util"), "the old descriptor is gone");
+}
+
+#[test]
+fn apply_mapping_takes_only_class_descriptors() {
+    for map in [[("com.example.app.Util", "Lz/a;")], [("Lcom/example/app/Util;", "L;")]] {
+        let err = dex::apply_mapping(CLASSES, &map).unwrap_err();
+        assert!(err.to_string().contains("is not a class descriptor"), "{}", err);
+    }
+}
@@ -0,0 +1,400 @@
+#![cfg(feature = "manifest")]
+use apk_editor::manifest::chunks::{self, XmlChunk};
+use apk_editor::manifest::components::{ComponentKind, IntentFilter};
+use apk_editor::manifest::merger::merge;
+use apk_editor::manifest::manifest_editor::{AndroidManifest, PathMatcher, PathPermission, Provider, UsesPermission};
+use apk_editor::manifest::typed_value::TypedValue;
+use apk_editor::manifest::{attrs, AndroidXml, XmlAttributeValue, XmlNode};
+use std::collections::BTreeMap;
+
+mod common;
+use common::MANIFEST;
+
+// resource ids ascending, the attributes without one after them
+fn assert_sorted(node: &XmlNode) {
+    let ids: Vec<u32> = node.attrs().iter().map(|attr| attr.resource_id()).collect();
+    let mut sorted = ids.clone();
+    sorted.sort_by_key(|id| (*id == 0, *id));
+    assert_eq!(ids, sorted, "attributes of <{}>", node.tag_name());
+}
+
+fn application<'a>(xml: &'a AndroidXml) -> &'a XmlNode {
+    xml.root_node().find_child("application").unwrap()
+}
+
+#[test]
+fn regenerate_keeps_non_ascii_and_long_strings() {
+    let long = "é".repeat(0x9000);
+    let values = ["Ünïcødé 日本 😀", long.as_str(), "plain"];
+    let mut xml = AndroidXml::from_data(MANIFEST).unwrap();
+    let node = xml.root_node_mut().find_child_mut("application").unwrap();
+    for (index, value) in values.iter().enumerate() {
+        node.set_attr(XmlAttributeValue::new_string(None, &format!("custom{}", index), 0, value));
+    }
+
+    let regenerated = AndroidXml::from_vec(xml.regenerate()).unwrap();
+    let node = application(&regenerated);
+    for (index, value) in values.iter().enumerate() {
+        let attr = node.attrs().iter().find(|attr| attr.name() == format!("custom{}", index)).unwrap();
+        assert_eq!(attr.string_value(), Some(*value));
+    }
+}
+
+#[test]
+fn set_attr_inserts_by_resource_id() {
+    let mut xml = AndroidXml::from_data(MANIFEST).unwrap();
+    let node = xml.root_node_mut().find_child_mut("application").unwrap();
+    node.set_attr(XmlAttributeValue::new_string(None, "custom", 0, "x"));
+    node.set_attr(XmlAttributeValue::new_bool_attr(attrs::ALLOW_BACKUP, "allowBackup", false));
+    node.set_attr(XmlAttributeValue::new_reference_attr(attrs::THEME, "theme", 0x7f0c0001));
+    node.set_attr(XmlAttributeValue::new_bool_attr(attrs::ENABLED, "enabled", true));
+
+    let regenerated = AndroidXml::from_vec(xml.regenerate()).unwrap();
+    let node = application(&regenerated);
+    assert_sorted(node);
+    let names: Vec<&str> = node.attrs().iter().map(|attr| attr.name()).collect();
+    assert_eq!(names, ["theme", "label", "icon", "enabled", "debuggable", "allowBackup", "custom"]);
+}
+
+#[test]
+fn new_node_sorts_its_attributes() {
+    let node = XmlNode::new("provider", vec![
+        XmlAttributeValue::new_authorities_attr("com.example.provider"),
+        XmlAttributeValue::new_bool_attr(attrs::EXPORTED, "exported", false),
+        XmlAttributeValue::new_name_attr(".Provider")
+    ]);
+    assert_sorted(&node);
+}
+
+#[test]
+fn provider_attributes_are_sorted() {
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    manifest.add_content_provider(Provider {
+        class_name: ".Provider".to_string(),
+        authorities: "com.example.provider".to_string(),
+        exported: Some(false),
+        grant_uri_permissions: Some(true),
+        process: Some(":remote".to_string()),
+        multiprocess: Some(true),
+        uri_permission_patterns: vec![PathMatcher::PathPrefix("/shared".to_string())],
+        path_permissions: vec![PathPermission {
+            path: PathMatcher::Path("/private".to_string()),
+            permission: None,
+            read_permission: Some("com.example.READ".to_string()),
+            write_permission: Some("com.example.WRITE".to_string())
+        }],
+        meta_data: Vec::new()
+    }).unwrap();
+
+    let regenerated = AndroidXml::from_vec(manifest.get_data()).unwrap();
+    let provider = application(&regenerated).find_child("provider").unwrap();
+    assert_sorted(provider);
+    let path_permission = provider.find_child("path-permission").unwrap();
+    assert_sorted(path_permission);
+    let names: Vec<&str> = path_permission.attrs().iter().map(|attr| attr.name()).collect();
+    assert_eq!(names, ["readPermission", "writePermission", "path"]);
+}
+
+#[test]
+fn test_only_goes_before_higher_ids() {
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    manifest.set_round_icon(0x7f0d0001).unwrap();
+    manifest.set_test_only(true).unwrap();
+
+    let regenerated = AndroidXml::from_vec(manifest.get_data()).unwrap();
+    let node = application(&regenerated);
+    assert_sorted(node);
+    let names: Vec<&str> = node.attrs().iter().map(|attr| attr.name()).collect();
+    assert_eq!(names, ["label", "icon", "debuggable", "testOnly", "roundIcon"]);
+}
+
+#[test]
+fn icon_and_theme_setters_keep_order() {
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    manifest.set_round_icon(0x7f0d0001).unwrap();
+    manifest.set_theme(0x7f0c0001).unwrap();
+    manifest.set_icon(0x7f0d0002).unwrap();
+
+    let regenerated = AndroidXml::from_vec(manifest.get_data()).unwrap();
+    let node = application(&regenerated);
+    assert_sorted(node);
+    let names: Vec<&str> = node.attrs().iter().map(|attr| attr.name()).collect();
+    assert_eq!(names, ["theme", "label", "icon", "debuggable", "roundIcon"]);
+    let icon = node.attrs().iter().find(|attr| attr.resource_id() == attrs::ICON).unwrap();
+    assert_eq!(icon.typed_value(), TypedValue::Reference(0x7f0d0002));
+}
+
+#[test]
+fn feature_split_goes_before_package() {
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    manifest.set_split(Some("feature_camera"));
+    manifest.set_feature_split(true);
+    assert!(manifest.is_feature_split());
+
+    let regenerated = AndroidXml::from_vec(manifest.get_data()).unwrap();
+    let root = regenerated.root_node();
+    assert_sorted(root);
+    let names: Vec<&str> = root.attrs().iter().map(|attr| attr.name()).collect();
+    assert_eq!(names, ["versionCode", "versionName", "isFeatureSplit", "package", "split"]);
+}
+
+// a library manifest: package com.example.lib, its application declaring a theme, testOnly and a
+// <meta-data> whose name starts with '.'
+fn library_manifest() -> AndroidManifest<'static> {
+    let mut library = AndroidManifest::from_vec(MANIFEST.to_vec()).unwrap();
+    let mut root = library.root_node().clone();
+    root.set_attr(XmlAttributeValue::new_string(None, "package", 0, "com.example.lib"));
+    let node = root.find_child_mut("application").unwrap();
+    node.set_attr(XmlAttributeValue::new_reference_attr(attrs::THEME, "theme", 0x7f0c0001));
+    node.set_attr(XmlAttributeValue::new_bool_attr(attrs::TEST_ONLY, "testOnly", true));
+    node.push_child(XmlNode::new("meta-data", vec![
+        XmlAttributeValue::new_name_attr(".flavor"),
+        XmlAttributeValue::new_string_attr(attrs::VALUE, "value", "free")
+    ]));
+    library.set_root_node(root);
+    library
+}
+
+#[test]
+fn merge_inserts_overlay_attributes_sorted() {
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    manifest.set_round_icon(0x7f0d0001).unwrap();
+    merge(&mut manifest, &library_manifest()).unwrap();
+
+    let regenerated = AndroidXml::from_vec(manifest.get_data()).unwrap();
+    let node = application(&regenerated);
+    assert_sorted(node);
+    let names: Vec<&str> = node.attrs().iter().map(|attr| attr.name()).collect();
+    assert_eq!(names, ["theme", "label", "icon", "debuggable", "testOnly", "roundIcon"]);
+}
+
+#[test]
+fn merge_expands_only_class_names() {
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    merge(&mut manifest, &library_manifest()).unwrap();
+
+    let node = manifest.root_node().find_child("application").unwrap();
+    let meta_data = node.find_child("meta-data").unwrap();
+    assert_eq!(meta_data.attrs()[0].string_value(), Some(".flavor"));
+    let services: Vec<&str> = node.children().iter()
+        .filter(|child| child.tag_name() == "service")
+        .filter_map(|child| child.attrs()[0].string_value())
+        .collect();
+    assert_eq!(services, [".MyService", "com.example.lib.MyService"]);
+}
+
+#[test]
+fn set_label_goes_before_icon() {
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    let mut root = manifest.root_node().clone();
+    root.find_child_mut("application").unwrap().remove_attr(Some(attrs::ANDROID_NAMESPACE), "label");
+    manifest.set_root_node(root);
+    manifest.set_label(0x7f0e0001).unwrap();
+
+    let regenerated = AndroidXml::from_vec(manifest.get_data()).unwrap();
+    let node = application(&regenerated);
+    assert_sorted(node);
+    let names: Vec<&str> = node.attrs().iter().map(|attr| attr.name()).collect();
+    assert_eq!(names, ["label", "icon", "debuggable"]);
+}
+
+#[test]
+fn encode_rejects_oversized_header() {
+    assert!(XmlChunk::encode(chunks::CDATA, &[0; 65527], &[]).is_ok());
+    let err = XmlChunk::encode(chunks::CDATA, &[0; 65528], &[]).expect_err("header size overflows u16");
+    assert!(err.to_string().contains("too large"), "{}", err);
+}
+
+#[test]
+fn substitute_placeholders_takes_a_btree_map() {
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    let mut root = manifest.root_node().clone();
+    root.find_child_mut("application").unwrap().push_child(XmlNode::new("meta-data", vec![
+        XmlAttributeValue::new_name_attr("channel"),
+        XmlAttributeValue::new_string_attr(attrs::VALUE, "value", "${applicationId}.${channel}")
+    ]));
+    manifest.set_root_node(root);
+    manifest.substitute_placeholders(&BTreeMap::from([(String::from("channel"), String::from("beta"))])).unwrap();
+    let meta_data = manifest.root_node().find_child("application").unwrap().find_child("meta-data").unwrap();
+    assert_eq!(meta_data.attrs()[1].string_value(), Some("com.example.app.beta"));
+}
+
+#[test]
+fn regenerate_preserving_copies_unchanged_elements() {
+    let mut xml = AndroidXml::from_vec(MANIFEST.to_vec()).unwrap();
+    assert_eq!(xml.regenerate_preserving(), MANIFEST);
+
+    // siblings before and after the edited element, and elements below an edited one
+    let application = xml.root_node_mut().find_child_mut("application").unwrap();
+    application.push_child(XmlNode::new("receiver", vec![XmlAttributeValue::new_name_attr(".Late")]));
+    let service = application.children_mut().iter_mut().find(|child| child.tag_name() == "service").unwrap();
+    service.set_attr(XmlAttributeValue::new_name_attr(".OtherService"));
+    let preserved = AndroidXml::from_vec(xml.regenerate_preserving()).unwrap();
+    let regenerated = AndroidXml::from_vec(xml.regenerate()).unwrap();
+    assert_eq!(preserved.to_string(), regenerated.to_string());
+    assert!(preserved.to_string().contains(".OtherService"));
+}
+
+#[test]
+fn new_rejects_string_pool_indices() {
+    let err = XmlAttributeValue::new(None, "label", 0, TypedValue::StringRef(3)).expect_err("a pool index is rejected");
+    assert!(err.to_string().contains("new_string"), "{}", err);
+    let attr = XmlAttributeValue::new(None, "count", 0, TypedValue::IntDec(3)).unwrap();
+    assert_eq!(attr.typed_value(), TypedValue::IntDec(3));
+}
+
+fn issues(root: XmlNode) -> Vec<String> {
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    manifest.set_root_node(root);
+    manifest.validate().iter().map(|issue| issue.to_string()).collect()
+}
+
+#[test]
+fn validate_reports_broken_elements() {
+    assert!(AndroidManifest::from(MANIFEST).unwrap().validate().is_empty());
+
+    let mut root = AndroidManifest::from(MANIFEST).unwrap().root_node().clone();
+    root.remove_attr(None, "package");
+    for name in ["android.permission.INTERNET", "CAMERA"] {
+        root.push_child(XmlNode::new("uses-permission", vec![XmlAttributeValue::new_name_attr(name)]));
+    }
+    let application = root.find_child_mut("application").unwrap();
+    application.push_child(XmlNode::new("activity-alias", vec![
+        XmlAttributeValue::new_name_attr(".Alias"),
+        XmlAttributeValue::new_string_attr(0x01010202, "targetActivity", ".Missing")
+    ]));
+    application.push_child(XmlNode::new("provider", vec![XmlAttributeValue::new_name_attr(".Files")]));
+    let mut receiver = XmlNode::new("receiver", vec![XmlAttributeValue::new_name_attr(".Boot")]);
+    receiver.push_child(XmlNode::new("intent-filter", vec![]));
+    application.push_child(receiver);
+    application.push_child(XmlNode::new("service", vec![XmlAttributeValue::new_name_attr("com.example.2Bad")]));
+
+    assert_eq!(issues(root), [
+        "error: <manifest>: missing package attribute",
+        "warning: <uses-permission android:name=\"android.permission.INTERNET\">: permission is requested more than once",
+        "warning: <uses-permission android:name=\"CAMERA\">: permission name \"CAMERA\" is not qualified",
+        "error: <activity-alias android:name=\".Alias\">: targetActivity \".Missing\" is not declared",
+        "error: <provider android:name=\".Files\">: provider without android:authorities",
+        "warning: <receiver android:name=\".Boot\">: component with intent-filter has no android:exported",
+        "error: <service android:name=\"com.example.2Bad\">: \"com.example.2Bad\" is not a valid class name"
+    ]);
+}
+
+#[test]
+fn validate_wants_a_launcher_unless_split() {
+    let mut root = AndroidManifest::from(MANIFEST).unwrap().root_node().clone();
+    root.find_child_mut("application").unwrap().children_mut()[0].remove_children("intent-filter");
+    assert_eq!(issues(root.clone()), ["warning: <application>: no launcher activity (MAIN/LAUNCHER)"]);
+
+    root.set_attr(XmlAttributeValue::new_string(None, "split", 0, "config.xxhdpi"));
+    assert!(issues(root).is_empty());
+}
+
+#[test]
+fn diff_lists_element_and_attribute_changes() {
+    let old = AndroidManifest::from(MANIFEST).unwrap();
+    let regenerated = AndroidXml::from_data(MANIFEST).unwrap().regenerate();
+    assert!(old.diff(&AndroidManifest::from(&regenerated).unwrap()).is_empty());
+
+    let mut root = old.root_node().clone();
+    root.set_attr(XmlAttributeValue::new_string_attr(attrs::VERSION_NAME, "versionName", "2.0"));
+    let application = root.find_child_mut("application").unwrap();
+    application.remove_attr(Some(attrs::ANDROID_NAMESPACE), "debuggable");
+    application.set_attr(XmlAttributeValue::new_bool_attr(attrs::ALLOW_BACKUP, "allowBackup", false));
+    application.remove_children("service");
+    application.children_mut()[0].remove_children("intent-filter");
+    application.push_child(XmlNode::new("receiver", vec![XmlAttributeValue::new_name_attr(".Boot")]));
+    let mut new = AndroidManifest::from(MANIFEST).unwrap();
+    new.set_root_node(root);
+
+    let changes: Vec<String> = old.diff(&new).iter().map(|change| change.to_string()).collect();
+    assert_eq!(changes, [
+        "~ manifest @android:versionName: \"1.0\" -> \"2.0\"",
+        "- manifest/application[0] @android:debuggable=\"true\"",
+        "+ manifest/application[0] @android:allowBackup=\"false\"",
+        "- manifest/application[0]/activity[com.example.app.MainActivity]/intent-filter[0]",
+        "- manifest/application[0]/service[.MyService]",
+        "+ manifest/application[0]/receiver[.Boot]"
+    ]);
+}
+
+#[test]
+fn diff_keys_named_elements_by_name() {
+    let old = AndroidManifest::from(MANIFEST).unwrap();
+    let mut root = old.root_node().clone();
+    root.find_child_mut("application").unwrap().children_mut().reverse();
+    let mut new = AndroidManifest::from(MANIFEST).unwrap();
+    new.set_root_node(root);
+    assert!(old.diff(&new).is_empty());
+}
+
+fn permission(name: &str, max_sdk_version: Option<u32>) -> UsesPermission {
+    UsesPermission{
+        name: String::from(name),
+        max_sdk_version
+    }
+}
+
+#[test]
+fn permissions_lists_both_tags_in_document_order() {
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    assert_eq!(manifest.permissions(), [permission("android.permission.INTERNET", None)]);
+    manifest.add_uses_permission_sdk_23(permission("android.permission.CAMERA", None));
+    manifest.add_uses_permission(permission("android.permission.READ_CONTACTS", Some(28)));
+    manifest.add_uses_permission(permission("android.permission.INTERNET", Some(30)));
+
+    let data = manifest.get_data();
+    let manifest = AndroidManifest::from(&data).unwrap();
+    assert_eq!(manifest.permissions(), [
+        permission("android.permission.CAMERA", None),
+        permission("android.permission.READ_CONTACTS", Some(28)),
+        permission("android.permission.INTERNET", Some(30))
+    ]);
+    assert_eq!(manifest.uses_permissions_sdk_23(), [permission("android.permission.CAMERA", None)]);
+}
+
+#[test]
+fn components_reports_exposure_in_document_order() {
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    manifest.add_component(ComponentKind::Receiver, ".Boot", Some(false)).unwrap();
+    let err = manifest.add_component(ComponentKind::ActivityAlias, ".Alias", None).unwrap_err();
+    assert!(err.to_string().contains("can't be added without its required attributes"), "{}", err);
+
+    let mut root = manifest.root_node().clone();
+    let mut filter = XmlNode::new("intent-filter", vec![]);
+    filter.push_child(XmlNode::new("action", vec![XmlAttributeValue::new_name_attr("android.intent.action.VIEW")]));
+    filter.push_child(XmlNode::new("data", vec![XmlAttributeValue::new_string_attr(attrs::SCHEME, "scheme", "https")]));
+    let mut alias = XmlNode::new("activity-alias", vec![
+        XmlAttributeValue::new_name_attr(".Link"),
+        XmlAttributeValue::new_reference_attr(attrs::EXPORTED, "exported", 0x7f050000)
+    ]);
+    alias.push_child(filter);
+    root.find_child_mut("application").unwrap().push_child(alias);
+    manifest.set_root_node(root);
+
+    let components = manifest.components();
+    let summary: Vec<(ComponentKind, &str, Option<bool>, Option<&str>)> = components.iter()
+        .map(|component| (component.kind, component.name.as_str(), component.exported, component.permission.as_deref()))
+        .collect();
+    assert_eq!(summary, [
+        (ComponentKind::Activity, "com.example.app.MainActivity", Some(true), None),
+        (ComponentKind::Service, ".MyService", None, Some("com.example.PERM")),
+        (ComponentKind::Receiver, ".Boot", Some(false), None),
+        (ComponentKind::ActivityAlias, ".Link", None, None)
+    ]);
+    assert_eq!(components[0].intent_filters, [IntentFilter{
+        actions: vec![String::from("android.intent.action.MAIN")],
+        categories: vec![String::from("android.intent.category.LAUNCHER")],
+        schemes: vec![]
+    }]);
+    assert_eq!(components[3].intent_filters, [IntentFilter{
+        actions: vec![String::from("android.intent.action.VIEW")],
+        categories: vec![],
+        schemes: vec![String::from("https")]
+    }]);
+
+    let mut root = manifest.root_node().clone();
+    root.remove_children("application");
+    manifest.set_root_node(root);
+    assert!(manifest.components().is_empty());
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "zip")]
+use apk_editor::resources::nine_patch;
+
+// a png signature and an IHDR for an 8 bit RGBA image, no image data
+fn png_header(width: u32, height: u32) -> Vec<u8> {
+    let mut data = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+    data.extend_from_slice(&13u32.to_be_bytes());
+    data.extend_from_slice(b"IHDR");
+    data.extend_from_slice(&width.to_be_bytes());
+    data.extend_from_slice(&height.to_be_bytes());
+    data.extend_from_slice(&[8, 6, 0, 0, 0]);
+    data.extend_from_slice(&[0; 4]); // crc, not checked
+    data
+}
+
+#[test]
+fn nine_patch_dimensions_are_capped() {
+    for (width, height) in [(u32::MAX, u32::MAX), (65536, 65536), (1 << 30, 3)] {
+        let err = nine_patch::compile(&png_header(width, height)).unwrap_err();
+        assert!(err.to_string().contains("too large"), "{}", err);
+    }
+}
+
+#[test]
+fn nine_patch_chunk_length_past_the_end() {
+    let mut png = png_header(3, 3);
+    png.extend_from_slice(&u32::MAX.to_be_bytes());
+    png.extend_from_slice(b"IDAT");
+    assert!(!nine_patch::is_compiled(&png));
+    assert!(nine_patch::compile(&png).unwrap_err().to_string().contains("not a png"));
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "plan")]
+use apk_editor::apk_zip::ApkFile;
+use apk_editor::manifest::AndroidXml;
+use apk_editor::plan::{self, PatchPlan};
+
+mod common;
+use common::{apk_of, MANIFEST};
+
+// an apk holding only the manifest of tests/data
+fn apk() -> ApkFile<'static> {
+    apk_of(&[("AndroidManifest.xml", MANIFEST)])
+}
+
+fn apply(json: &str) -> apk_editor::Result<ApkFile<'static>> {
+    let plan = PatchPlan::from_json(json)?;
+    let mut data = vec![];
+    plan::apply(&plan, &mut apk(), &mut data)?;
+    ApkFile::from_vec(data)
+}
+
+#[test]
+fn set_manifest_attribute_inserts_sorted() {
+    let apk = apply(r#"{"steps": [
+        {"op": "set_manifest_attribute", "element": "application", "attribute": "android:allowBackup", "value": false},
+        {"op": "set_manifest_attribute", "element": "application", "attribute": "android:theme", "value": "@0x7f0c0001"}
+    ]}"#).unwrap();
+    let xml = AndroidXml::from_vec(apk.get_manifest().unwrap()).unwrap();
+    let application = xml.root_node().find_child("application").unwrap();
+    let names: Vec<&str> = application.attrs().iter().map(|attr| attr.name()).collect();
+    assert_eq!(names, ["theme", "label", "icon", "debuggable", "allowBackup"]);
+}
+
+#[test]
+fn add_file_twice_keeps_one_entry() {
+    let apk = apply(r#"{"steps": [
+        {"op": "add_file", "name": "assets/config", "source": "Cargo.toml"},
+        {"op": "add_file", "name": "assets/config", "source": "tests/data/AndroidManifest.xml", "store": true}
+    ]}"#).unwrap();
+    let names: Vec<String> = apk.list_files().into_iter().map(|file| file.name).collect();
+    assert_eq!(names, ["AndroidManifest.xml", "assets/config"]);
+    assert_eq!(apk.get_file("assets/config").unwrap(), MANIFEST);
+}
+
+#[test]
+fn add_file_source_stays_in_plan_directory() {
+    for source in ["../Cargo.toml", "tests/../../Cargo.toml", "/etc/hostname"] {
+        let json = format!(r#"{{"steps": [{{"op": "add_file", "name": "assets/config", "source": "{}"}}]}}"#, source);
+        let err = apply(&json).err().expect(source);
+        assert!(err.to_string().contains("outside the plan's directory"), "{}", err);
+    }
+}
+
+#[test]
+fn dry_run_leaves_the_apk_unchanged() {
+    let plan = PatchPlan::from_json(r#"{"steps": [
+        {"op": "add_file", "name": "assets/config", "source": "Cargo.toml"}
+    ]}"#).unwrap();
+    let mut apk = apk();
+    let save_plan = plan::dry_run(&plan, &mut apk).unwrap();
+    assert!(save_plan.entries.iter().any(|entry| entry.name == "assets/config"));
+    let names: Vec<String> = apk.list_files().into_iter().map(|file| file.name).collect();
+    assert_eq!(names, ["AndroidManifest.xml"]);
+    let mut data = vec![];
+    apk.save(&mut data).unwrap();
+    assert!(ApkFile::from_vec(data).unwrap().get_file("assets/config").is_none());
+
+    let plan = PatchPlan::from_json(r#"{"steps": [
+        {"op": "add_file", "name": "assets/config", "source": "Cargo.toml"},
+        {"op": "add_file", "name": "assets/other", "source": "missing"}
+    ]}"#).unwrap();
+    assert!(plan::dry_run(&plan, &mut apk).is_err());
+    assert!(apk.rollback().is_err(), "no transaction is left open");
+    let names: Vec<String> = apk.list_files().into_iter().map(|file| file.name).collect();
+    assert_eq!(names, ["AndroidManifest.xml"]);
+}
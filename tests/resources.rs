@@ -0,0 +1,114 @@
+#![cfg(feature = "resources")]
+use apk_editor::manifest::validator::Severity;
+use apk_editor::resources::table::{EntryValue, ResourceTable};
+
+mod common;
+use common::{chunk, table, words};
+
+fn parse_error(children: &[u8]) -> String {
+    ResourceTable::from(&table(children)).err().expect("a malformed chunk is rejected").to_string()
+}
+
+#[test]
+fn empty_package_parses() {
+    let table = ResourceTable::from(&table(&[])).unwrap();
+    assert_eq!(table.packages()[0].id(), 0x7f);
+}
+
+#[test]
+fn type_chunk_with_short_header() {
+    // no room for the entry count, the entries start or the config size
+    assert!(parse_error(&chunk(0x0201, 8, &[])).contains("bad type chunk header"));
+    assert!(parse_error(&chunk(0x0201, 20, &words(&[1, 0, 20]))).contains("bad type chunk header"));
+}
+
+#[test]
+fn type_chunk_with_oversized_counts() {
+    // a config larger than the header, and more entries than the chunk has offsets for
+    assert!(parse_error(&chunk(0x0201, 28, &words(&[1, 0, 28, 0xffffffff, 0, 0]))).contains("bad type chunk header"));
+    assert!(parse_error(&chunk(0x0201, 28, &words(&[1, 0xffffffff, 28, 8, 0, 0]))).contains("bad type chunk header"));
+}
+
+#[test]
+fn type_spec_chunk_with_short_header() {
+    assert!(parse_error(&chunk(0x0202, 8, &[])).contains("bad type spec chunk"));
+    assert!(parse_error(&chunk(0x0202, 12, &words(&[1]))).contains("bad type spec chunk"));
+    assert!(parse_error(&chunk(0x0202, 16, &words(&[1, 0xffffffff]))).contains("bad type spec chunk"));
+}
+
+// type 1 with one config holding simple entries of the given (data type, data)
+fn type_chunk(values: &[(u8, u32)]) -> Vec<u8> {
+    let entries_start = 28 + values.len() as u32 * 4;
+    let mut rest = words(&[1, values.len() as u32, entries_start, 8, 0]);
+    rest.extend(words(&(0..values.len() as u32).map(|index| index * 16).collect::<Vec<_>>()));
+    for (data_type, data) in values {
+        rest.extend(words(&[8, 0, ((*data_type as u32) << 24) | 8, *data]));
+    }
+    chunk(0x0201, 28, &rest)
+}
+
+#[test]
+fn remap_leaves_non_reference_data_alone() {
+    // 1.0dp written with the 16p7 radix, aapt would pick 23p0 for it
+    let dimension = (128 << 8) | (1 << 4) | 1;
+    let children = type_chunk(&[(0x01, 0x7f010001), (0x02, 0x7f020002), (0x05, dimension), (0x10, 0x7f000001)]);
+    let mut table = ResourceTable::from(&table(&children)).unwrap();
+    table.remap_package_id(0x7f, 0x7e).unwrap();
+    let data: Vec<u32> = table.packages()[0].types()[0].configs()[0].entries().iter().map(|entry| match entry.as_ref().unwrap().value() {
+        EntryValue::Simple{ data, .. } => *data,
+        EntryValue::Complex{ .. } => panic!("a simple entry")
+    }).collect();
+    assert_eq!(data, [0x7e010001, 0x7e020002, dimension, 0x7f000001]);
+}
+
+#[test]
+fn keep_locales_rejects_what_is_not_a_locale() {
+    let mut table = ResourceTable::from(&table(&type_chunk(&[(0x10, 1)]))).unwrap();
+    for locales in [&["en", "not a locale"][..], &["xxhdpi"]] {
+        let err = table.keep_locales(locales).expect_err("an unparseable locale is rejected");
+        assert!(err.to_string().contains("is not a locale"), "{}", err);
+    }
+    assert_eq!(table.keep_locales(&["en", "zh-rTW"]).unwrap(), 0);
+}
+
+#[test]
+fn shared_keys_are_a_warning() {
+    // both entries use key 0, as an obfuscator's collapsed names would
+    let table = ResourceTable::from(&table(&type_chunk(&[(0x10, 1), (0x10, 2)]))).unwrap();
+    let shared: Vec<Severity> = table.validate(|_| true).into_iter()
+        .filter(|issue| issue.message.contains("key also used by"))
+        .map(|issue| issue.severity)
+        .collect();
+    assert_eq!(shared, [Severity::Warning]);
+}
+
+#[test]
+fn to_data_rejects_strings_too_long_for_utf8() {
+    let mut table = ResourceTable::from(&table(&[])).unwrap();
+    table.add_string("long", &"a".repeat(0x8000), &[]).unwrap();
+    let err = table.to_data().expect_err("a utf-8 length has 15 bits");
+    assert!(err.to_string().contains("too long"), "{}", err);
+}
+
+#[test]
+fn package_chunks_keep_their_order() {
+    // a dynamic reference table for package 2 ahead of the types
+    let mut library = words(&[1, 2]);
+    library.resize(library.len() + 256, 0);
+    let mut children = chunk(0x0203, 12, &library);
+    children.extend(type_chunk(&[(0x10, 1)]));
+    let data = ResourceTable::from(&table(&children)).unwrap().to_data().unwrap();
+    // walk the chunks of the package past its header and two pools
+    let read = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+    let package = 12 + read(16);
+    let mut offset = package + 288;
+    offset += read(offset + 4);
+    offset += read(offset + 4);
+    let mut chunk_types = vec![];
+    while offset < package + read(package + 4) {
+        chunk_types.push(read(offset) & 0xffff);
+        offset += read(offset + 4);
+    }
+    // the type spec is written with its configurations
+    assert_eq!(chunk_types, [0x0203, 0x0202, 0x0201]);
+}
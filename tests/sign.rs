@@ -0,0 +1,55 @@
+#![cfg(feature = "sign")]
+use apk_editor::apk_zip::{sign_v2, verify_v2, SigningKey};
+
+mod common;
+use common::zip_of;
+
+const KEY: &[u8] = include_bytes!("data/sign_key.pem");
+const CERTIFICATE: &[u8] = include_bytes!("data/sign_cert.pem");
+
+// a zip of two entries
+fn unsigned() -> Vec<u8> {
+    zip_of(&[("assets/a", &[1; 100]), ("assets/b", &[2; 1000])])
+}
+
+// where the central directory starts, from its end record
+fn central_directory(apk: &[u8]) -> usize {
+    let end = apk.len() - 22;
+    u32::from_le_bytes(apk[end + 16..end + 20].try_into().unwrap()) as usize
+}
+
+#[test]
+fn sign_then_verify() {
+    let key = SigningKey::from(KEY, CERTIFICATE).unwrap();
+    let signed = sign_v2(&unsigned(), &key).unwrap();
+    let certificates = verify_v2(&signed).unwrap();
+    assert_eq!(certificates.len(), 1);
+    // signing again replaces the block rather than adding one
+    assert_eq!(sign_v2(&signed, &key).unwrap(), signed);
+}
+
+#[test]
+fn verify_rejects_changes_and_unsigned_apks() {
+    let key = SigningKey::from(KEY, CERTIFICATE).unwrap();
+    let mut signed = sign_v2(&unsigned(), &key).unwrap();
+    assert!(verify_v2(&unsigned()).unwrap_err().to_string().contains("no APK Signing Block"));
+    signed[40] ^= 1;
+    assert!(verify_v2(&signed).unwrap_err().to_string().contains("changed after signing"));
+}
+
+#[test]
+fn signing_block_too_small_for_its_magic() {
+    // a block whose size field is 16, both size fields are the same word
+    let apk = unsigned();
+    let cd = central_directory(&apk);
+    let mut block = 16u64.to_le_bytes().to_vec();
+    block.extend(b"APK Sig Block 42");
+    let mut malformed = apk[..cd].to_vec();
+    malformed.extend(&block);
+    malformed.extend(&apk[cd..]);
+    let end = malformed.len() - 22;
+    malformed[end + 16..end + 20].copy_from_slice(&((cd + block.len()) as u32).to_le_bytes());
+    for err in [verify_v2(&malformed).unwrap_err(), sign_v2(&malformed, &SigningKey::from(KEY, CERTIFICATE).unwrap()).unwrap_err()] {
+        assert!(err.to_string().contains("malformed APK Signing Block"), "{}", err);
+    }
+}
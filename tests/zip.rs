@@ -0,0 +1,446 @@
+#![cfg(feature = "zip")]
+use apk_editor::apk_zip::{ApkFile, CompressMethod, CompressionCache, Compressor, EditEvent, EditorConfig, Flate2Compressor, ZipEditor, ZipFile, ZipIndex};
+use apk_editor::manifest::AndroidXml;
+use apk_editor::manifest::chunks::{self, ChunkAction, XmlChunk};
+use apk_editor::manifest::typed_value::TypedValue;
+use apk_editor::manifest::manifest_editor::{AndroidManifest, UsesPermission};
+use apk_editor::resources::table::ResourceTable;
+use apk_editor::ParseOptions;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::{apk_of, saved, table, zip_of, MANIFEST};
+
+// a zip of one deflated entry, `data` in it
+fn zip(data: Vec<u8>) -> Vec<u8> {
+    zip_of(&[("assets/data", &data)])
+}
+
+#[test]
+fn disk_cache_drops_damaged_blobs() {
+    let dir = std::env::temp_dir().join(format!("apk-editor-cache-{}", std::process::id()));
+    let cache = Arc::new(CompressionCache::on_disk(&dir).unwrap());
+    let data: Vec<u8> = (0..1 << 16).map(|i| (i % 251) as u8).collect();
+    let build = || {
+        let mut editor = ZipEditor::new();
+        editor.set_compression_cache(cache.clone());
+        editor.append_file(data.clone(), String::from("assets/data"), CompressMethod::Deflated);
+        let mut res = vec![];
+        editor.finish(None, &mut res, 4).unwrap();
+        res
+    };
+    let first = build();
+    assert!(build() == first, "the cached blob is the one just compressed");
+    assert_eq!((cache.hits(), cache.misses()), (1, 1));
+    // cut every blob short, they are compressed again rather than written truncated
+    for file in std::fs::read_dir(&dir).unwrap() {
+        let path = file.unwrap().path();
+        let blob = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &blob[..blob.len() - 1]).unwrap();
+    }
+    assert!(build() == first, "a damaged blob is compressed again");
+    assert_eq!((cache.hits(), cache.misses()), (1, 2));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// the uncompressed size in the local header and the central directory
+fn set_declared_size(zip: &mut [u8], size: u32) {
+    for (magic, offset) in [(0x04034b50u32, 22), (0x02014b50u32, 24)] {
+        let start = zip.windows(4).position(|window| window == magic.to_le_bytes()).unwrap();
+        zip[start + offset..start + offset + 4].copy_from_slice(&size.to_le_bytes());
+    }
+}
+
+#[test]
+fn inflate_stops_at_declared_size() {
+    let mut data = zip(vec![0; 1 << 20]);
+    set_declared_size(&mut data, 1024);
+    assert_eq!(ZipFile::from(&data).unwrap().get_uncompress_data("assets/data"), None);
+
+    let mut data = zip(vec![0; 1 << 20]);
+    set_declared_size(&mut data, 1 << 20);
+    assert_eq!(ZipFile::from(&data).unwrap().get_uncompress_data("assets/data").map(|data| data.len()), Some(1 << 20));
+}
+
+#[test]
+fn editor_errors_say_why() {
+    let data = zip(vec![1, 2, 3]);
+    let zip = ZipFile::from(&data).unwrap();
+    let mut editor = ZipEditor::from(&zip);
+    let err = editor.edit_file(&zip, "assets/other", vec![]).expect_err("there is no such entry");
+    assert!(err.to_string().contains("assets/other is not in the zip"), "{}", err);
+    assert!(editor.rename_current_file("assets/other", "assets/new").is_err());
+    assert!(editor.commit().expect_err("nothing to commit").to_string().contains("no transaction is open"));
+    editor.begin();
+    editor.remove_file(&zip, "assets/data").unwrap();
+    editor.rollback().unwrap();
+    assert!(editor.has_file("assets/data"));
+}
+
+#[test]
+fn too_many_entries_for_zip32() {
+    let mut editor = ZipEditor::new();
+    for index in 0..0x10000 {
+        editor.append_file(vec![], format!("{}", index), CompressMethod::Stored);
+    }
+    let err = editor.finish(None, &mut vec![], 4).expect_err("65536 entries need zip64");
+    assert!(err.to_string().contains("entry count 65536 needs zip64"), "{}", err);
+}
+
+// a zip of two stored entries, `assets/a` and `name`
+fn zip_of_two(name: &str) -> Vec<u8> {
+    let mut editor = ZipEditor::new();
+    editor.append_file(b"first".to_vec(), String::from("assets/a"), CompressMethod::Stored);
+    editor.append_file(b"second".to_vec(), String::from(name), CompressMethod::Stored);
+    let mut res = vec![];
+    editor.finish(None, &mut res, 4).unwrap();
+    res
+}
+
+#[test]
+fn duplicate_names_are_rejected() {
+    let data = zip_of_two("assets/a");
+    for options in [ParseOptions::strict(), ParseOptions::lenient(), ParseOptions::recovering()] {
+        let err = ZipFile::from_with_options(&data, options).err().expect("duplicates are an error");
+        assert!(err.to_string().contains("duplicate entry name"), "{}", err);
+        assert!(ZipIndex::from_with_options(&data, options).is_err());
+    }
+    let data = zip_of_two("assets/b");
+    assert_eq!(ZipFile::from(&data).unwrap().names().collect::<Vec<_>>(), ["assets/a", "assets/b"]);
+    assert!(ZipIndex::from(&data).unwrap().contains("assets/b"));
+}
+
+#[test]
+fn set_manifest_needs_a_manifest() {
+    let mut apk = ApkFile::from_vec(zip(vec![1, 2, 3])).unwrap();
+    let err = apk.set_manifest(MANIFEST).expect_err("there is no manifest to replace");
+    assert!(err.to_string().contains("AndroidManifest.xml is not in the apk"), "{}", err);
+}
+
+#[test]
+fn add_or_replace_file_keeps_one_entry() {
+    let mut apk = apk_of(&[("assets/a", b"old")]);
+    apk.add_or_replace_file("assets/a", b"new", CompressMethod::Deflated);
+    apk.add_or_replace_file("assets/b", b"one", CompressMethod::Stored);
+    apk.add_or_replace_file("assets/b", b"two", CompressMethod::Stored);
+
+    let apk = saved(&mut apk);
+    assert_eq!(apk.get_file("assets/a").unwrap(), b"new");
+    assert_eq!(apk.get_file("assets/b").unwrap(), b"two");
+}
+
+#[test]
+fn plan_parses_manifests_with_the_apk_options() {
+    let mut manifest = Vec::from(MANIFEST);
+    manifest.extend_from_slice(&[0; 4]);
+    for (options, parses) in [(ParseOptions::strict(), false), (ParseOptions::lenient(), true)] {
+        let mut apk = ApkFile::from_vec_with_options(zip_of(&[("AndroidManifest.xml", MANIFEST)]), options).unwrap();
+        apk.set_manifest(&manifest).unwrap();
+        assert_eq!(apk.plan().is_ok(), parses);
+    }
+}
+
+#[test]
+fn manifest_changes_are_parsed_with_the_apk_options() {
+    let mut manifest = AndroidManifest::from_vec(Vec::from(MANIFEST)).unwrap();
+    manifest.set_test_only(true).unwrap();
+    let mut data = vec![];
+    manifest.write(&mut data).unwrap();
+    data.extend_from_slice(&[0; 4]);
+    for (options, reported) in [(ParseOptions::strict(), 0), (ParseOptions::lenient(), 1)] {
+        let mut apk = ApkFile::from_vec_with_options(zip_of(&[("AndroidManifest.xml", MANIFEST)]), options).unwrap();
+        let counts = Arc::new(Mutex::new(vec![]));
+        let seen = counts.clone();
+        apk.on_event(move |event| if let EditEvent::ManifestChanged{changes} = event {
+            seen.lock().unwrap().push(changes.len());
+        });
+        apk.set_manifest(&data).unwrap();
+        assert_eq!(*counts.lock().unwrap(), [reported]);
+    }
+}
+
+fn chunk_types(data: &[u8]) -> Vec<u16> {
+    chunks::read_chunks(data).unwrap().map(|chunk| chunk.unwrap().chunk_type()).collect()
+}
+
+#[test]
+fn patch_xml_chunks_keeps_earlier_edits() {
+    let mut apk = apk_of(&[("AndroidManifest.xml", MANIFEST)]);
+    assert!(chunk_types(MANIFEST).contains(&chunks::END_NAMESPACE));
+
+    apk.patch_xml_chunks("AndroidManifest.xml", &mut |chunk: &XmlChunk| match chunk.chunk_type() {
+        chunks::END_NAMESPACE => ChunkAction::Remove,
+        _ => ChunkAction::Keep
+    }).unwrap();
+    let mut seen = vec![];
+    apk.patch_xml_chunks("AndroidManifest.xml", &mut |chunk: &XmlChunk| {
+        seen.push(chunk.chunk_type());
+        ChunkAction::Keep
+    }).unwrap();
+    assert!(!seen.contains(&chunks::END_NAMESPACE));
+
+    let manifest = saved(&mut apk).get_manifest().unwrap();
+    assert!(!chunk_types(&manifest).contains(&chunks::END_NAMESPACE));
+}
+
+// finish_from over `data`, which keeps its one entry as is
+fn stream_copy(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let zip = ZipFile::read_central_directory(Cursor::new(data), ParseOptions::strict()).unwrap();
+    let mut res = vec![];
+    ZipEditor::from(&zip).finish_from(Cursor::new(data), &mut res, 4)?;
+    Ok(res)
+}
+
+#[test]
+fn stream_copy_checks_local_headers() {
+    let data = zip(b"some data".to_vec());
+    assert_eq!(ZipFile::from(&stream_copy(&data).unwrap()).unwrap().get_uncompress_data("assets/data").unwrap(), b"some data");
+
+    // a longer name, which would move the data start, and a name of the same length
+    for (offset, value) in [(26, &[12u8, 0][..]), (30, b"x")] {
+        let mut data = zip(b"some data".to_vec());
+        data[offset..offset + value.len()].copy_from_slice(value);
+        assert!(stream_copy(&data).unwrap_err().to_string().contains("bad local file header"));
+    }
+    let mut data = zip(b"some data".to_vec());
+    data[18..22].copy_from_slice(&0u32.to_le_bytes());
+    assert!(stream_copy(&data).unwrap_err().to_string().contains("bad local file header"));
+}
+
+#[test]
+fn obfuscate_renames_added_files_around_taken_paths() {
+    let mut apk = apk_of(&[("AndroidManifest.xml", MANIFEST), ("resources.arsc", &table(&[])), ("r/a/a.png", b"taken")]);
+
+    let mut table = ResourceTable::from(&apk.get_resources().unwrap()).unwrap();
+    apk.add_resource_file(&mut table, "drawable", "icon.png", b"icon").unwrap();
+    let renames = apk.obfuscate_resource_paths(&mut table);
+    assert_eq!(renames, [(String::from("res/drawable/icon.png"), String::from("r/a/b.png"))]);
+    apk.set_resources(table.to_data().unwrap()).unwrap();
+
+    let apk = saved(&mut apk);
+    assert_eq!(apk.get_file("r/a/a.png").unwrap(), b"taken");
+    assert_eq!(apk.get_file("r/a/b.png").unwrap(), b"icon");
+    assert!(apk.get_file("res/drawable/icon.png").is_none());
+}
+
+#[test]
+fn set_app_label_keeps_earlier_edits() {
+    let mut apk = apk_of(&[("AndroidManifest.xml", MANIFEST), ("resources.arsc", &table(&[]))]);
+
+    let mut table = ResourceTable::from(&apk.get_resources().unwrap()).unwrap();
+    let kept = table.add_string("kept", "kept", &[]).unwrap();
+    apk.set_resources(table.to_data().unwrap()).unwrap();
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    manifest.add_uses_permission(UsesPermission{ name: String::from("android.permission.CAMERA"), max_sdk_version: None });
+    apk.set_manifest(manifest.get_data()).unwrap();
+    apk.set_app_label("Renamed", &[("fr", "Renommé")]).unwrap();
+
+    let apk = saved(&mut apk);
+    let table = ResourceTable::from(&apk.get_resources().unwrap()).unwrap();
+    assert_eq!(table.resource_id("string/kept"), Some(kept));
+    let manifest = AndroidManifest::from_vec(apk.get_manifest().unwrap()).unwrap();
+    assert!(manifest.permissions().iter().any(|permission| permission.name == "android.permission.CAMERA"));
+    assert_eq!(apk.get_app_label("").unwrap().as_deref(), Some("Renamed"));
+    assert_eq!(apk.get_app_label("fr").unwrap().as_deref(), Some("Renommé"));
+    let err = apk.get_app_label("not a locale").expect_err("a locale that doesn't parse is an error");
+    assert!(err.to_string().contains("bad locale not a locale"), "{}", err);
+}
+
+// the packages the references in the attributes of a compiled xml point into
+fn referenced_packages(data: Vec<u8>) -> Vec<u32> {
+    let xml = AndroidXml::from_vec(data).unwrap();
+    let mut res: Vec<u32> = xml.root_node().iter()
+        .flat_map(|(_, element)| element.attrs().iter().map(|attr| attr.typed_value()))
+        .filter_map(|value| match value {
+            TypedValue::Reference(id) => Some(id >> 24),
+            _ => None
+        })
+        .collect();
+    res.sort();
+    res.dedup();
+    res
+}
+
+#[test]
+fn remap_package_id_covers_edited_and_added_xml() {
+    assert!(referenced_packages(MANIFEST.to_vec()).contains(&0x7f));
+    let mut apk = apk_of(&[("AndroidManifest.xml", MANIFEST), ("resources.arsc", &table(&[]))]);
+
+    let mut manifest = AndroidManifest::from(MANIFEST).unwrap();
+    manifest.add_uses_permission(UsesPermission{ name: String::from("android.permission.CAMERA"), max_sdk_version: None });
+    apk.set_manifest(manifest.get_data()).unwrap();
+    apk.add_file("res/xml/added.xml", MANIFEST, CompressMethod::Deflated);
+    let mut table = ResourceTable::from(&apk.get_resources().unwrap()).unwrap();
+    apk.remap_package_id(&mut table, 0x7f, 0x7e).unwrap();
+
+    let apk = saved(&mut apk);
+    for path in ["AndroidManifest.xml", "res/xml/added.xml"] {
+        let packages = referenced_packages(apk.get_file(path).unwrap());
+        assert!(packages.contains(&0x7e) && !packages.contains(&0x7f), "{} {:x?}", path, packages);
+    }
+    let manifest = AndroidManifest::from_vec(apk.get_manifest().unwrap()).unwrap();
+    assert!(manifest.permissions().iter().any(|permission| permission.name == "android.permission.CAMERA"));
+}
+
+#[test]
+fn shrink_resources_follows_added_xml() {
+    let mut apk = apk_of(&[("AndroidManifest.xml", MANIFEST), ("resources.arsc", &table(&[]))]);
+
+    let mut table = ResourceTable::from(&apk.get_resources().unwrap()).unwrap();
+    let icon = apk.add_resource_file(&mut table, "drawable", "icon.png", b"icon").unwrap();
+    apk.add_resource_file(&mut table, "drawable", "unused.png", b"unused").unwrap();
+    // an xml only reachable from keep_ids, the icon only from it
+    let mut xml = AndroidXml::from_vec(MANIFEST.to_vec()).unwrap();
+    xml.root_node_mut().attrs_mut()[0].set_typed_value(TypedValue::Reference(icon));
+    let uses = apk.add_resource_file(&mut table, "xml", "uses.xml", xml.regenerate()).unwrap();
+    let report = apk.shrink_resources(&mut table, &[uses]);
+    assert_eq!(report.removed_files, ["res/drawable/unused.png"]);
+    let apk = saved(&mut apk);
+    assert_eq!(apk.get_file("res/drawable/icon.png").unwrap(), b"icon");
+    assert!(apk.get_file("res/drawable/unused.png").is_none());
+}
+
+const HOOK: &[u8] = include_bytes!("data/hook.dex");
+
+#[test]
+fn failed_transaction_leaves_the_apk_as_it_was() {
+    let mut apk = apk_of(&[("AndroidManifest.xml", MANIFEST), ("assets/a", b"a")]);
+    let err = apk.transaction(|apk| {
+        apk.add_file("assets/b", b"b", CompressMethod::Deflated);
+        apk.add_dex(HOOK)?;
+        apk.remove_file("assets/a")?;
+        apk.remove_file("assets/missing")
+    }).unwrap_err();
+    assert!(err.to_string().contains("assets/missing"), "{}", err);
+
+    // the dex count is back too, so the next dex is classes.dex again
+    apk.add_dex(HOOK).unwrap();
+    let reloaded = saved(&mut apk);
+    assert_eq!(reloaded.get_file("assets/a").unwrap(), b"a");
+    assert!(reloaded.get_file("assets/b").is_none());
+    assert_eq!(reloaded.get_file("classes.dex").unwrap(), HOOK);
+    assert!(reloaded.get_file("classes2.dex").is_none());
+
+    assert_eq!(apk.transaction(|apk| apk.remove_file("assets/a").map(|_| 7)).unwrap(), 7);
+    assert!(saved(&mut apk).get_file("assets/a").is_none());
+}
+
+#[test]
+fn transactions_nest() {
+    let mut apk = apk_of(&[("AndroidManifest.xml", MANIFEST)]);
+    apk.begin();
+    apk.add_file("assets/a", b"a", CompressMethod::Deflated);
+    apk.begin();
+    apk.add_file("assets/b", b"b", CompressMethod::Deflated);
+    apk.rollback().unwrap();
+    apk.commit().unwrap();
+    for err in [apk.commit().unwrap_err(), apk.rollback().unwrap_err()] {
+        assert!(err.to_string().contains("no transaction is open"), "{}", err);
+    }
+
+    let apk = saved(&mut apk);
+    assert_eq!(apk.get_file("assets/a").unwrap(), b"a");
+    assert!(apk.get_file("assets/b").is_none());
+}
+
+#[test]
+fn index_reads_entries_by_name() {
+    let data = zip_of(&[("assets/z", b"zzz"), ("resources.arsc", b"table"), ("assets/a", b"aaa")]);
+    let index = ZipIndex::from(&data).unwrap();
+    assert_eq!((index.len(), index.is_empty()), (3, false));
+    assert_eq!(index.names().collect::<Vec<_>>(), ["assets/z", "resources.arsc", "assets/a"]);
+    assert!(index.contains("assets/a") && !index.contains("assets"));
+    assert_eq!(index.get_uncompress_data("assets/z").unwrap(), b"zzz");
+    assert_eq!(index.get_compress_data("resources.arsc").unwrap(), Some(&b"table"[..]));
+    assert!(index.get_uncompress_data("missing").is_none());
+    assert!(index.entry("missing").unwrap().is_none());
+    assert_eq!(index.to_zip_file().unwrap().names().collect::<Vec<_>>(), index.names().collect::<Vec<_>>());
+}
+
+#[test]
+fn index_checks_an_entry_when_it_is_read() {
+    let mut data = zip_of(&[("assets/z", b"zzz"), ("assets/a", b"aaa")]);
+    data[0] ^= 0xff;
+    assert!(ZipFile::from(&data).is_err());
+    let index = ZipIndex::from(&data).unwrap();
+    assert!(index.entry("assets/z").is_err());
+    assert!(index.get_uncompress_data("assets/z").is_none());
+    assert_eq!(index.get_uncompress_data("assets/a").unwrap(), b"aaa");
+}
+
+// an apk of `threads` with forty added entries, half of them deflated
+fn apk_with_threads(threads: usize) -> ApkFile<'static> {
+    let config = EditorConfig{
+        threads,
+        ..EditorConfig::default()
+    };
+    let mut apk = ApkFile::from_vec_with_config(zip_of(&[("AndroidManifest.xml", MANIFEST)]), config).unwrap();
+    for index in 0..40 {
+        let data: Vec<u8> = (0..index * 997).map(|byte| (byte % 251) as u8 ^ index as u8).collect();
+        let method = if index % 2 == 0 { CompressMethod::Deflated } else { CompressMethod::Stored };
+        apk.add_file(&format!("assets/{}", index), data, method);
+    }
+    apk
+}
+
+#[test]
+fn save_is_the_same_on_any_number_of_threads() {
+    let mut expected = vec![];
+    apk_with_threads(1).save(&mut expected).unwrap();
+    for threads in [2, 8] {
+        let mut data = vec![];
+        apk_with_threads(threads).save(&mut data).unwrap();
+        assert!(data == expected, "{} threads", threads);
+    }
+    let apk = ApkFile::from_vec(expected).unwrap();
+    assert_eq!(apk.get_file("assets/39").unwrap().len(), 39 * 997);
+}
+
+struct Failing {
+    panic: bool
+}
+
+impl Compressor for Failing {
+    fn deflate(&self, data: &[u8], level: u32) -> std::io::Result<Vec<u8>> {
+        if data.len() > 30000 {
+            if self.panic {
+                panic!("compressor bug");
+            }
+            return Err(std::io::Error::other("compressor gave up"));
+        }
+        Flate2Compressor::new().deflate(data, level)
+    }
+
+    fn id(&self) -> &str {
+        "failing"
+    }
+}
+
+#[test]
+fn save_reports_a_failing_compressor() {
+    for (threads, panic, reason) in [(1, false, "compressor gave up"), (4, false, "compressor gave up"), (4, true, "the compressor panicked")] {
+        let mut apk = apk_with_threads(threads);
+        apk.set_compressor(Failing{ panic });
+        let err = apk.save(&mut vec![]).unwrap_err();
+        assert!(err.to_string().contains(reason), "{} threads: {}", threads, err);
+    }
+}
+
+#[test]
+fn large_added_entries_are_streamed_behind_a_data_descriptor() {
+    let big: Vec<u8> = (0..5usize << 20).map(|index| (index % 7 + index / 4096 % 13) as u8).collect();
+    let data = zip(big.clone());
+    assert_eq!(u16::from_le_bytes([data[6], data[7]]) & 0x8, 0x8, "data descriptor flag");
+    assert!(data[14..26].iter().all(|byte| *byte == 0), "crc and sizes are in the descriptor");
+    let zip_file = ZipFile::from_with_options(&data, ParseOptions::strict()).unwrap();
+    assert!(zip_file.get_uncompress_data("assets/data").unwrap() == big);
+    assert!(ZipIndex::from(&data).unwrap().get_uncompress_data("assets/data").unwrap() == big);
+    let copied = stream_copy(&data).unwrap();
+    assert!(ZipFile::from(&copied).unwrap().get_uncompress_data("assets/data").unwrap() == big);
+
+    // stored ones are written whole, their header has the sizes
+    let data = zip_of(&[("resources.arsc", &big)]);
+    assert_eq!(u16::from_le_bytes([data[6], data[7]]) & 0x8, 0);
+    assert_eq!(u32::from_le_bytes([data[22], data[23], data[24], data[25]]), 5 << 20);
+}